@@ -0,0 +1,32 @@
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn git_commit() -> Option<String> {
+    Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+}
+
+fn main() {
+    // Builds without git history (e.g. in Docker) can pass the commit in instead
+    let commit = std::env::var("POLLUX_GIT_COMMIT")
+        .ok()
+        .filter(|commit| !commit.is_empty())
+        .or_else(git_commit)
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let built_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time is before the unix epoch!")
+        .as_secs();
+
+    println!("cargo:rustc-env=POLLUX_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=POLLUX_BUILD_TIMESTAMP={}", built_at);
+    println!("cargo:rerun-if-env-changed=POLLUX_GIT_COMMIT");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
@@ -0,0 +1,17 @@
+--
+-- Table structure for table `SyncRuns`
+--
+
+CREATE TABLE `SyncRuns` (
+  `id` int(10) unsigned NOT NULL AUTO_INCREMENT,
+  `platform` varchar(100) NOT NULL,
+  `started_at` datetime NOT NULL,
+  `finished_at` datetime NOT NULL,
+  `duration_ms` int(10) unsigned NOT NULL,
+  `events_fetched` int(10) unsigned NOT NULL DEFAULT 0,
+  `events_inserted` int(10) unsigned NOT NULL DEFAULT 0,
+  `status` varchar(20) NOT NULL,
+  `error_message` text DEFAULT NULL,
+  PRIMARY KEY (`id`),
+  KEY `SyncRuns_started_at_IDX` (`started_at`)
+) ENGINE=InnoDB AUTO_INCREMENT=1 DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_uca1400_ai_ci;
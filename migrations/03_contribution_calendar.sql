@@ -0,0 +1,16 @@
+--
+-- Table structure for table `ContributionCalendar`
+--
+-- Per-day contribution counts imported from the platforms' calendars.
+-- These are less precise than GitEvents (no project, no action),
+-- so they are kept separately and merged into the daily stats on read.
+--
+
+CREATE TABLE `ContributionCalendar` (
+  `platform` varchar(100) NOT NULL,
+  `day` date NOT NULL,
+  `count` int(10) unsigned NOT NULL,
+  `imported_at` datetime NOT NULL,
+  PRIMARY KEY (`platform`, `day`),
+  CONSTRAINT `ContributionCalendar_GitPlatforms_FK` FOREIGN KEY (`platform`) REFERENCES `GitPlatforms` (`name`) ON DELETE CASCADE ON UPDATE CASCADE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_uca1400_ai_ci;
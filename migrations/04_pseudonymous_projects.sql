@@ -0,0 +1,9 @@
+--
+-- Pseudonymous projects stand in for private projects when POLLUX_PRIVATE_EVENTS is anonymized.
+-- Their platform_project_id is derived from a keyed hash, so it must not clash with real projects.
+--
+
+ALTER TABLE `GitProjects`
+  ADD COLUMN `pseudonymous` tinyint(1) NOT NULL DEFAULT 0,
+  DROP INDEX `GitProjects_UNIQUE`,
+  ADD UNIQUE KEY `GitProjects_UNIQUE` (`platform`,`platform_project_id`,`pseudonymous`);
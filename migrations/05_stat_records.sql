@@ -0,0 +1,15 @@
+--
+-- Table structure for table `StatRecords`
+--
+-- All-time records (busiest day/week/hour, longest session), kept up to date while
+-- events are inserted, so reading them doesn't need a full scan of Events.
+--
+
+CREATE TABLE `StatRecords` (
+  `record` varchar(20) NOT NULL,
+  `value` int(10) unsigned NOT NULL,
+  `started_at` datetime NOT NULL,
+  `ended_at` datetime NOT NULL,
+  `updated_at` datetime NOT NULL,
+  PRIMARY KEY (`record`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_uca1400_ai_ci;
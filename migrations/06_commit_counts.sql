@@ -0,0 +1,7 @@
+--
+-- Number of commits of push events, NULL for event types without commits
+-- (and for events synced before commit counts were stored).
+--
+
+ALTER TABLE `GitEvents`
+  ADD COLUMN `commit_count` int(10) unsigned DEFAULT NULL;
@@ -0,0 +1,6 @@
+--
+-- Event listings and stats filter on the timestamp, joins to GitEvents and
+-- GitProjects go through their primary keys and GitEvents_GitProjects_FK.
+--
+
+CREATE INDEX `Events_timestamp_IDX` ON `Events` (`timestamp`);
@@ -0,0 +1,14 @@
+--
+-- Table structure for table `HttpCache`
+--
+-- ETags of fetched API pages, so the first sync after a restart can be answered
+-- with 304 Not Modified instead of downloading everything again.
+--
+
+CREATE TABLE `HttpCache` (
+  `platform` varchar(100) NOT NULL,
+  `url` varchar(512) NOT NULL,
+  `etag` varchar(255) NOT NULL,
+  `fetched_at` datetime NOT NULL,
+  PRIMARY KEY (`platform`, `url`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_uca1400_ai_ci;
@@ -0,0 +1,8 @@
+--
+-- Rate limit headroom of the platform API as last seen during a sync run,
+-- NULL for platforms which don't report it.
+--
+
+ALTER TABLE `SyncRuns`
+  ADD COLUMN `rate_limit_remaining` int(10) unsigned DEFAULT NULL,
+  ADD COLUMN `rate_limit_reset_at` datetime DEFAULT NULL;
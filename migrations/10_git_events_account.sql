@@ -0,0 +1,8 @@
+--
+-- Account whose feed contained the event, NULL for platforms with a single account.
+-- Events are only deduplicated within one account, so two accounts pushing to the
+-- same project in the same second are both counted.
+--
+
+ALTER TABLE `GitEvents`
+  ADD COLUMN `account` varchar(100) DEFAULT NULL;
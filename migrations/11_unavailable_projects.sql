@@ -0,0 +1,7 @@
+--
+-- Projects which were deleted (or renamed) before their events were synced.
+-- Their events still count, the stored url is only a guess and isn't served.
+--
+
+ALTER TABLE `GitProjects`
+  ADD COLUMN `unavailable_since` datetime DEFAULT NULL;
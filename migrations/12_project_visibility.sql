@@ -0,0 +1,7 @@
+--
+-- Visibility reported by the platform (public, internal or private), NULL if it doesn't tell.
+-- Lets responses treat non-public projects differently, e.g. by anonymizing them.
+--
+
+ALTER TABLE `GitProjects`
+  ADD COLUMN `visibility` varchar(16) DEFAULT NULL;
@@ -0,0 +1,7 @@
+--
+-- Branch or tag a push went to, e.g. `main`. Only known for GitLab push events,
+-- NULL for everything else and for pushes GitLab doesn't name a ref for.
+--
+
+ALTER TABLE `GitEvents`
+  ADD COLUMN `git_ref` varchar(255) DEFAULT NULL;
@@ -0,0 +1,8 @@
+--
+-- What a GitLab event acted on, e.g. `Issue` or `MergeRequest`. For comments the
+-- commented issue or merge request, so both kinds of comments can be told apart.
+-- NULL for pushes and for everything not coming from GitLab.
+--
+
+ALTER TABLE `GitEvents`
+  ADD COLUMN `target_type` varchar(64) DEFAULT NULL;
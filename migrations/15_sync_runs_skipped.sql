@@ -0,0 +1,8 @@
+--
+-- Fetched events a sync run didn't insert, by the reason they were skipped.
+--
+
+ALTER TABLE `SyncRuns`
+  ADD COLUMN `skipped_unknown_action` int(10) unsigned NOT NULL DEFAULT 0,
+  ADD COLUMN `skipped_duplicates` int(10) unsigned NOT NULL DEFAULT 0,
+  ADD COLUMN `skipped_project_errors` int(10) unsigned NOT NULL DEFAULT 0;
@@ -0,0 +1,41 @@
+--
+-- Events are unique per project, action, second, commit count and account. The database
+-- rejects duplicates with `GitEvents_UNIQUE`, instead of every insert looking for a match.
+--
+-- `timestamp` is a copy of `Events`.`timestamp`, set along with it, so the key can cover it.
+-- NULL never collides in a unique key, so the commit count and account are part of it as
+-- `*_key` with NULL as 0 and ''. Events stored without a commit count or account (before
+-- they were stored) can't be given theirs here, syncs still match them with any count and
+-- account before inserting (see `git_platform::stored_keys`).
+--
+
+ALTER TABLE `GitEvents`
+  ADD COLUMN `timestamp` datetime DEFAULT NULL,
+  ADD COLUMN `commit_count_key` int(10) unsigned AS (IFNULL(`commit_count`, 0)) STORED,
+  ADD COLUMN `account_key` varchar(100) AS (IFNULL(`account`, '')) STORED;
+
+UPDATE `GitEvents` AS gevt, `Events` AS evt
+  SET gevt.`timestamp` = evt.`timestamp`
+  WHERE gevt.`id` = evt.`id`;
+
+--
+-- One-off cleanup of duplicates stored so far, the first of them is kept.
+-- Deleting their `Events` rows cascades to `GitEvents`.
+--
+
+CREATE TEMPORARY TABLE `DuplicateGitEvents` AS
+  SELECT DISTINCT dup.`id`
+  FROM `GitEvents` AS dup, `GitEvents` AS kept
+  WHERE dup.`project_fk` = kept.`project_fk`
+    AND dup.`action_fk` = kept.`action_fk`
+    AND dup.`timestamp` = kept.`timestamp`
+    AND dup.`commit_count_key` = kept.`commit_count_key`
+    AND dup.`account_key` = kept.`account_key`
+    AND dup.`id` > kept.`id`;
+
+DELETE FROM `Events` WHERE `id` IN (SELECT `id` FROM `DuplicateGitEvents`);
+
+DROP TEMPORARY TABLE `DuplicateGitEvents`;
+
+ALTER TABLE `GitEvents`
+  ADD UNIQUE KEY `GitEvents_UNIQUE` (`project_fk`, `action_fk`, `timestamp`, `commit_count_key`, `account_key`);
@@ -0,0 +1,20 @@
+--
+-- Table structure for table `EventArchive`
+--
+-- Every fetched event as pollux received it, before it's mapped or filtered, so events
+-- skipped for an unknown action can be reprocessed once their action is mapped.
+-- `payload` is zstd compressed JSON, `payload_hash` the SHA-256 of the JSON. Events fetched
+-- again by overlapping syncs are archived once.
+--
+
+CREATE TABLE `EventArchive` (
+  `id` int(10) unsigned NOT NULL AUTO_INCREMENT,
+  `platform` varchar(100) NOT NULL,
+  `external_id` varchar(100) DEFAULT NULL,
+  `received_at` datetime NOT NULL,
+  `payload_hash` binary(32) NOT NULL,
+  `payload` mediumblob NOT NULL,
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `EventArchive_UNIQUE` (`platform`, `payload_hash`),
+  KEY `EventArchive_received_at` (`received_at`)
+) ENGINE=InnoDB AUTO_INCREMENT=1 DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_uca1400_ai_ci;
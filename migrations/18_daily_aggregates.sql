@@ -0,0 +1,19 @@
+--
+-- Table structure for table `DailyAggregates`
+--
+-- Events deleted because they're older than `POLLUX_RETENTION_DAYS`, rolled up per
+-- platform, day and action. The calendar and timeseries add them to the counts of the
+-- remaining events, so they keep the long-range history at day resolution.
+--
+
+CREATE TABLE `DailyAggregates` (
+  `platform` varchar(100) NOT NULL,
+  `day` date NOT NULL,
+  `action_fk` int(10) unsigned NOT NULL,
+  `event_count` int(10) unsigned NOT NULL,
+  -- Push events count with their number of commits, all other events as one
+  `commit_count` int(10) unsigned NOT NULL,
+  PRIMARY KEY (`platform`, `day`, `action_fk`),
+  CONSTRAINT `DailyAggregates_GitPlatforms_FK` FOREIGN KEY (`platform`) REFERENCES `GitPlatforms` (`name`) ON DELETE CASCADE ON UPDATE CASCADE,
+  CONSTRAINT `DailyAggregates_GitActions_FK` FOREIGN KEY (`action_fk`) REFERENCES `GitActions` (`id`) ON DELETE CASCADE ON UPDATE CASCADE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_uca1400_ai_ci;
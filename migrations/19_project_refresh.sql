@@ -0,0 +1,11 @@
+--
+-- Names, urls and visibilities of projects are looked up again once in a while, see
+-- `POLLUX_PROJECT_REFRESH_DAYS`, so renamed or transferred projects don't stay stale.
+-- `refreshed_at` is when the platform was last asked, `updated_at` when anything changed.
+-- Existing projects count as refreshed now, they're looked up after the first interval.
+--
+
+ALTER TABLE `GitProjects`
+  ADD COLUMN `refreshed_at` datetime NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  ADD COLUMN `updated_at` datetime DEFAULT NULL,
+  ADD KEY `GitProjects_refreshed_at` (`platform`, `refreshed_at`);
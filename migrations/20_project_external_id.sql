@@ -0,0 +1,9 @@
+--
+-- Id of an ingested project as its source sent it. `platform_project_id` is only 32 bits
+-- derived from it, so two ids can map onto the same one. Ingest compares this column to
+-- tell them apart and rejects the second project instead of merging both.
+-- NULL for synced projects, and for ingested ones until they're ingested again.
+--
+
+ALTER TABLE `GitProjects`
+  ADD COLUMN `external_id` varchar(255) DEFAULT NULL;
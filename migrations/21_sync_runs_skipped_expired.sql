@@ -0,0 +1,6 @@
+--
+-- Fetched events a sync run didn't insert because they're older than the retention.
+--
+
+ALTER TABLE `SyncRuns`
+  ADD COLUMN `skipped_expired` int(10) unsigned NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+--
+-- Fetched events a sync run left out on purpose or couldn't use, e.g. filtered ones or unparseable dates.
+--
+
+ALTER TABLE `SyncRuns`
+  ADD COLUMN `skipped_other` int(10) unsigned NOT NULL DEFAULT 0;
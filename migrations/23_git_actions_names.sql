@@ -0,0 +1,8 @@
+--
+-- Actions are named after what was done: comments, reviews and releases become commented,
+-- reviewed and released. Renaming keeps their ids, stored events and aggregates keep theirs.
+--
+
+UPDATE `GitActions` SET `name` = 'commented' WHERE `name` = 'comments';
+UPDATE `GitActions` SET `name` = 'reviewed' WHERE `name` = 'review';
+UPDATE `GitActions` SET `name` = 'released' WHERE `name` = 'release';
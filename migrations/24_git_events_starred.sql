@@ -0,0 +1,16 @@
+--
+-- GitHub's WatchEvents (starring a repository) were stored as project management, they're
+-- starred now. Those were the only GitHub events stored as project management, before the
+-- account of GitHub events was stored.
+--
+
+INSERT IGNORE INTO `GitActions` (`name`) VALUES ('starred');
+
+UPDATE `GitEvents` AS gevt
+  JOIN `GitProjects` AS gpro ON gpro.`id` = gevt.`project_fk`
+  JOIN `GitActions` AS legacy ON legacy.`id` = gevt.`action_fk`
+  JOIN `GitActions` AS starred ON starred.`name` = 'starred'
+  SET gevt.`action_fk` = starred.`id`
+  WHERE gpro.`platform` = 'Github'
+    AND legacy.`name` = 'project-management'
+    AND gevt.`account` IS NULL;
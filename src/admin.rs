@@ -0,0 +1,134 @@
+use crate::config::{self, ConfigError, EnvConfig};
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome},
+    Request,
+};
+
+/// Who may use admin routes like `force-sync`, managed as Rocket state
+#[derive(Debug, Clone, Default)]
+pub struct AdminConfig {
+    pub token: Option<String>,
+    pub dev_mode: bool,
+}
+
+impl AdminConfig {
+    /// `POLLUX_ADMIN_TOKEN`, or `POLLUX_ADMIN_TOKEN_FILE`
+    pub fn from_env() -> Result<AdminConfig, ConfigError> {
+        let mut env = EnvConfig::from_env();
+        let dev_mode = config::var("POLLUX_ENABLE_DEV_MODE");
+        let config = AdminConfig {
+            token: env.optional_secret("POLLUX_ADMIN_TOKEN"),
+            dev_mode: dev_mode.is_some_and(|dev_mode| dev_mode.eq_ignore_ascii_case("true")),
+        };
+        env.build(config)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AdminError {
+    MissingToken,
+    InvalidToken,
+    NotAllowedInProd,
+}
+
+/// Request guard for admin routes.
+///
+/// Requests carrying `Authorization: Bearer <POLLUX_ADMIN_TOKEN>` are always allowed.
+/// Requests without the header are only allowed in dev mode.
+pub struct AdminAccess;
+
+/// Compares without bailing out at the first difference, so the token can't be guessed via timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn check_access(config: &AdminConfig, authorization: Option<&str>) -> Result<AdminAccess, (Status, AdminError)> {
+    match (authorization, &config.token) {
+        (Some(header), Some(token)) => {
+            let provided = header.strip_prefix("Bearer ").unwrap_or_default().trim();
+            if constant_time_eq(provided.as_bytes(), token.as_bytes()) {
+                Ok(AdminAccess)
+            } else {
+                Err((Status::Forbidden, AdminError::InvalidToken))
+            }
+        }
+        _ if config.dev_mode => Ok(AdminAccess),
+        (None, Some(_)) => Err((Status::Unauthorized, AdminError::MissingToken)),
+        (_, None) => Err((Status::Forbidden, AdminError::NotAllowedInProd)),
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminAccess {
+    type Error = AdminError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let default_config = AdminConfig::default();
+        let config = request.rocket().state::<AdminConfig>().unwrap_or(&default_config);
+
+        match check_access(config, request.headers().get_one("Authorization")) {
+            Ok(access) => Outcome::Success(access),
+            Err((status, err)) => {
+                warn!("Denied access to admin route {}: {:?}", request.uri(), err);
+                Outcome::Error((status, err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(token: Option<&str>, dev_mode: bool) -> AdminConfig {
+        AdminConfig {
+            token: token.map(str::to_string),
+            dev_mode,
+        }
+    }
+
+    #[test]
+    fn constant_time_eq_compares_whole_input() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+
+    #[test]
+    fn valid_token_is_accepted_without_dev_mode() {
+        assert!(check_access(&config(Some("secret"), false), Some("Bearer secret")).is_ok());
+    }
+
+    #[test]
+    fn missing_token_is_unauthorized() {
+        assert_eq!(
+            check_access(&config(Some("secret"), false), None).err(),
+            Some((Status::Unauthorized, AdminError::MissingToken))
+        );
+    }
+
+    #[test]
+    fn wrong_token_is_forbidden_even_in_dev_mode() {
+        for dev_mode in [false, true] {
+            assert_eq!(
+                check_access(&config(Some("secret"), dev_mode), Some("Bearer guess")).err(),
+                Some((Status::Forbidden, AdminError::InvalidToken))
+            );
+        }
+    }
+
+    #[test]
+    fn dev_mode_is_a_fallback() {
+        assert!(check_access(&config(None, true), None).is_ok());
+        assert!(check_access(&config(Some("secret"), true), None).is_ok());
+        assert_eq!(
+            check_access(&config(None, false), None).err(),
+            Some((Status::Forbidden, AdminError::NotAllowedInProd))
+        );
+    }
+}
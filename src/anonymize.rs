@@ -0,0 +1,169 @@
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome},
+    Request,
+};
+
+use crate::{
+    config::{ConfigError, EnvConfig},
+    visibility::Pseudonymizer,
+};
+
+/// Without managed config there is no secret, so only plain responses are possible
+static UNMANAGED_CONFIG: AnonymizeConfig = AnonymizeConfig {
+    always: false,
+    pseudonymizer: None,
+};
+
+/// Whether read endpoints replace project names and urls by aliases, see `Pseudonymizer::project_alias`.
+/// Counts and timestamps are never touched, admin routes always see the real projects.
+#[derive(Debug, Clone)]
+pub struct AnonymizeConfig {
+    /// Anonymize every response, not only those requested with `?anonymize=true`
+    pub always: bool,
+    /// `None` if no secret is configured, then only real names can be served
+    pub pseudonymizer: Option<Pseudonymizer>,
+}
+
+impl AnonymizeConfig {
+    /// `POLLUX_PSEUDONYM_SECRET` (or `POLLUX_PSEUDONYM_SECRET_FILE`) is required with `POLLUX_ANONYMIZE_PROJECTS`
+    pub fn from_env() -> Result<AnonymizeConfig, ConfigError> {
+        AnonymizeConfig::from(EnvConfig::from_env())
+    }
+
+    fn from(mut env: EnvConfig) -> Result<AnonymizeConfig, ConfigError> {
+        let always = env.parsed("POLLUX_ANONYMIZE_PROJECTS", false);
+        let secret = match always {
+            true => Some(env.secret("POLLUX_PSEUDONYM_SECRET")),
+            false => env.optional_secret("POLLUX_PSEUDONYM_SECRET"),
+        };
+
+        env.build(AnonymizeConfig {
+            always,
+            pseudonymizer: secret.map(|secret| Pseudonymizer::new(&secret)),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AnonymizeError {
+    InvalidParameter,
+    MissingSecret,
+}
+
+fn resolve(config: &AnonymizeConfig, requested: bool) -> Result<Option<&Pseudonymizer>, AnonymizeError> {
+    if !config.always && !requested {
+        return Ok(None);
+    }
+    config.pseudonymizer.as_ref().map(Some).ok_or(AnonymizeError::MissingSecret)
+}
+
+/// Request guard for read routes, holding the pseudonymizer if the response has to be anonymized.
+///
+/// `?anonymize=true` opts in per request, `?anonymize=false` can't opt out of the global mode.
+pub struct Anonymization<'r>(pub Option<&'r Pseudonymizer>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Anonymization<'r> {
+    type Error = AnonymizeError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let requested = match request.query_value::<bool>("anonymize") {
+            None => false,
+            Some(Ok(requested)) => requested,
+            Some(Err(_)) => return Outcome::Error((Status::BadRequest, AnonymizeError::InvalidParameter)),
+        };
+
+        let config = request.rocket().state::<AnonymizeConfig>().unwrap_or(&UNMANAGED_CONFIG);
+        match resolve(config, requested) {
+            Ok(pseudonymizer) => Outcome::Success(Anonymization(pseudonymizer)),
+            Err(err) => {
+                warn!("Can't anonymize {}: {:?}", request.uri(), err);
+                Outcome::Error((Status::BadRequest, err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::local::asynchronous::Client;
+
+    use super::*;
+
+    fn config(always: bool, secret: Option<&str>) -> AnonymizeConfig {
+        AnonymizeConfig {
+            always,
+            pseudonymizer: secret.map(Pseudonymizer::new),
+        }
+    }
+
+    fn env(vars: &'static [(&'static str, &'static str)]) -> EnvConfig {
+        EnvConfig::from(|name| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string()))
+    }
+
+    #[test]
+    fn anonymizing_always_needs_a_secret() {
+        let message = AnonymizeConfig::from(env(&[("POLLUX_ANONYMIZE_PROJECTS", "true")])).unwrap_err().to_string();
+        assert!(message.contains("POLLUX_PSEUDONYM_SECRET is missing"), "{}", message);
+
+        let message = AnonymizeConfig::from(env(&[("POLLUX_ANONYMIZE_PROJECTS", "sometimes")])).unwrap_err().to_string();
+        assert!(message.contains("POLLUX_ANONYMIZE_PROJECTS is invalid"), "{}", message);
+
+        let config = AnonymizeConfig::from(env(&[("POLLUX_ANONYMIZE_PROJECTS", "true"), ("POLLUX_PSEUDONYM_SECRET", "secret")]));
+        assert!(config.unwrap().pseudonymizer.is_some());
+        assert!(AnonymizeConfig::from(env(&[])).unwrap().pseudonymizer.is_none());
+    }
+
+    #[test]
+    fn plain_unless_requested_or_always() {
+        assert!(resolve(&config(false, Some("secret")), false).unwrap().is_none());
+        assert!(resolve(&config(false, Some("secret")), true).unwrap().is_some());
+        assert!(resolve(&config(true, Some("secret")), false).unwrap().is_some());
+    }
+
+    #[test]
+    fn anonymizing_needs_a_secret() {
+        assert!(resolve(&config(false, None), false).unwrap().is_none());
+        assert_eq!(resolve(&config(false, None), true).err(), Some(AnonymizeError::MissingSecret));
+    }
+
+    #[get("/alias")]
+    fn alias(anonymization: Anonymization<'_>) -> String {
+        anonymization
+            .0
+            .map(|pseudonymizer| pseudonymizer.project_alias(1))
+            .unwrap_or("2tefan/pollux".to_string())
+    }
+
+    async fn body(client: &Client, path: &str) -> String {
+        client.get(path).dispatch().await.into_string().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn query_parameter_opts_in() {
+        let rocket = rocket::build()
+            .manage(config(false, Some("secret")))
+            .mount("/", routes![alias]);
+        let client = Client::untracked(rocket).await.unwrap();
+
+        assert_eq!(body(&client, "/alias").await, "2tefan/pollux");
+        assert_eq!(body(&client, "/alias?anonymize=false").await, "2tefan/pollux");
+        assert_eq!(
+            body(&client, "/alias?anonymize=true").await,
+            Pseudonymizer::new("secret").project_alias(1)
+        );
+        assert_eq!(client.get("/alias?anonymize=maybe").dispatch().await.status(), Status::BadRequest);
+    }
+
+    #[tokio::test]
+    async fn global_mode_can_not_be_opted_out_of() {
+        let rocket = rocket::build()
+            .manage(config(true, Some("secret")))
+            .mount("/", routes![alias]);
+        let client = Client::untracked(rocket).await.unwrap();
+
+        assert_ne!(body(&client, "/alias").await, "2tefan/pollux");
+        assert_ne!(body(&client, "/alias?anonymize=false").await, "2tefan/pollux");
+    }
+}
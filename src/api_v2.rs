@@ -0,0 +1,212 @@
+use chrono::{DateTime, Utc};
+use rocket::{serde::json::Json, Route};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    anonymize::Anonymization,
+    database, parse_since_date,
+    queries::{self, EventFilter, GitEventRow},
+    visibility::Pseudonymizer,
+};
+
+static DEFAULT_EVENTS_LIMIT: u32 = 100;
+static MAX_EVENTS_LIMIT: u32 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ProjectV2 {
+    pub id: u64,
+    pub name: String,
+    /// `null` if the project isn't available on its platform anymore
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ActionV2 {
+    pub id: u64,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct EventV2 {
+    /// Stable across requests, the id of the event in the database
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    /// `null` if the project is missing
+    pub platform: Option<String>,
+    /// `null` if the project is missing
+    pub project: Option<ProjectV2>,
+    pub action: ActionV2,
+    /// Only known for push events
+    pub commit_count: Option<u32>,
+}
+
+impl From<GitEventRow> for EventV2 {
+    fn from(row: GitEventRow) -> Self {
+        let project = match (row.project_id, row.project_name, row.url) {
+            (Some(id), Some(name), url) => Some(ProjectV2 { id, name, url }),
+            _ => None,
+        };
+
+        EventV2 {
+            id: row.id,
+            timestamp: row.timestamp,
+            platform: project.as_ref().and(row.platform),
+            project,
+            action: ActionV2 {
+                id: row.action_id,
+                name: row.action,
+            },
+            commit_count: row.commit_count,
+        }
+    }
+}
+
+impl EventV2 {
+    /// Replaces the project name by its alias and drops the url, everything else stays accurate
+    fn anonymize(&mut self, pseudonymizer: &Pseudonymizer) {
+        if let Some(project) = self.project.as_mut() {
+            project.name = pseudonymizer.project_alias(project.id);
+            project.url = Some(String::new());
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Pagination {
+    pub limit: u32,
+    pub offset: u32,
+    /// Number of items matching the request, over all pages
+    pub total: u64,
+    /// Offset of the next page, `null` on the last one
+    pub next_offset: Option<u32>,
+}
+
+impl Pagination {
+    fn new(limit: u32, offset: u32, total: u64) -> Self {
+        let next = offset as u64 + limit as u64;
+        Pagination {
+            limit,
+            offset,
+            total,
+            next_offset: (next < total).then_some(next as u32),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    pub pagination: Pagination,
+}
+
+#[get("/git-events?<since>&<limit>&<offset>")]
+async fn get_git_events(
+    since: Option<&str>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    anonymization: Anonymization<'_>,
+) -> Json<Page<EventV2>> {
+    let filter = EventFilter {
+        since: parse_since_date(since),
+    };
+    let limit = limit.unwrap_or(DEFAULT_EVENTS_LIMIT).clamp(1, MAX_EVENTS_LIMIT);
+    let offset = offset.unwrap_or(0);
+
+    let db = database::Database::get_or_init().await;
+    let pool = db.get_read_pool().await;
+
+    let (rows, total) = tokio::join!(
+        queries::fetch_event_rows(&pool, filter, Some((limit, offset))),
+        queries::count_events(&pool, filter)
+    );
+    let mut data: Vec<EventV2> = rows.into_iter().map(EventV2::from).collect();
+    if let Some(pseudonymizer) = anonymization.0 {
+        data.iter_mut().for_each(|event| event.anonymize(pseudonymizer));
+    }
+    Json(Page {
+        data,
+        pagination: Pagination::new(limit, offset, total),
+    })
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![get_git_events]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queries::GitEvents;
+
+    fn row(project: bool) -> GitEventRow {
+        GitEventRow {
+            id: 17,
+            timestamp: DateTime::from_timestamp(1_714_557_600, 0).unwrap(),
+            action_id: 2,
+            action: "commit".to_string(),
+            commit_count: Some(3),
+            git_ref: None,
+            target_type: None,
+            project_id: project.then_some(5),
+            project_name: project.then(|| "2tefan/pollux".to_string()),
+            platform: project.then(|| "Gitlab".to_string()),
+            url: project.then(|| "https://gitlab.com/2tefan/pollux".to_string()),
+        }
+    }
+
+    #[test]
+    fn v2_maps_the_same_data_as_v1() {
+        let v1 = serde_json::to_value(GitEvents::from(row(true))).unwrap();
+        let v2 = serde_json::to_value(EventV2::from(row(true))).unwrap();
+
+        assert_eq!(v2["timestamp"], v1["timestamp"]);
+        assert_eq!(v2["timestamp"], "2024-05-01T10:00:00Z");
+        assert_eq!(v2["platform"], v1["platform"]);
+        assert_eq!(v2["project"]["name"], v1["project_name"]);
+        assert_eq!(v2["project"]["url"], v1["url"]);
+        assert_eq!(v2["project"]["id"], v1["project"]["id"]);
+        assert_eq!(v2["action"]["name"], v1["action"]);
+        assert_eq!(v2["commit_count"], v1["commit_count"]);
+        // Only v2 identifies events and actions
+        assert_eq!(v2["id"], 17);
+        assert_eq!(v2["action"]["id"], 2);
+        assert!(v1.get("id").is_none());
+    }
+
+    #[test]
+    fn missing_project_is_null_in_both_versions() {
+        let v1 = serde_json::to_value(GitEvents::from(row(false))).unwrap();
+        let v2 = serde_json::to_value(EventV2::from(row(false))).unwrap();
+
+        assert_eq!(v1["project"], serde_json::Value::Null);
+        assert_eq!(v2["project"], serde_json::Value::Null);
+        assert_eq!(v2["platform"], serde_json::Value::Null);
+        assert_eq!(v1["platform"], "");
+    }
+
+    #[test]
+    fn anonymized_events_leak_no_project_name() {
+        let pseudonymizer = Pseudonymizer::new("secret");
+        let mut event = EventV2::from(row(true));
+        event.anonymize(&pseudonymizer);
+
+        let payload = serde_json::to_string(&event).unwrap();
+        assert!(!payload.contains("pollux"), "{}", payload);
+        assert_eq!(event.project.as_ref().unwrap().name, pseudonymizer.project_alias(5));
+        // Same alias as in v1
+        let mut v1 = GitEvents::from(row(true));
+        v1.anonymize(&pseudonymizer);
+        assert_eq!(serde_json::to_value(v1).unwrap()["project_name"], pseudonymizer.project_alias(5));
+        assert_eq!(event.commit_count, Some(3));
+        assert_eq!(event.timestamp, row(true).timestamp);
+    }
+
+    #[test]
+    fn last_page_has_no_next_offset() {
+        assert_eq!(Pagination::new(100, 0, 250).next_offset, Some(100));
+        assert_eq!(Pagination::new(100, 200, 250).next_offset, None);
+        assert_eq!(Pagination::new(100, 0, 100).next_offset, None);
+        assert_eq!(Pagination::new(100, 0, 0).next_offset, None);
+    }
+}
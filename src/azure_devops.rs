@@ -0,0 +1,872 @@
+use crate::{
+    config::{self, ConfigError, EnvConfig},
+    credentials::{self, CredentialCheck, TokenInfo},
+    event_archive::{self, EventSource},
+    git_platform::{self, EventDetails, GitEventAPI, GitPlatform, GitProject, InsertEvents, NewEvent, PlatformError, Skipped, SyncCache, SyncResult},
+    http_client,
+    pagination::{self, FetchedEvents, PaginationGuard},
+    platform_registry::{BoxFuture, PlatformRunner},
+    project_filter::ProjectFilter,
+    retry::{env_parse, RetryPolicy},
+    sync_runs::SyncReport,
+    visibility::ProjectVisibility,
+};
+
+use std::sync::Arc;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{error, log_enabled, warn, Level};
+use once_cell::sync::OnceCell;
+use reqwest::{header::HeaderMap, RequestBuilder, StatusCode, Url};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sqlx::{MySql, Transaction};
+use tokio::sync::Mutex;
+
+static AZURE_DEVOPS: OnceCell<Arc<Mutex<AzureDevops>>> = OnceCell::new();
+static AZURE_DEVOPS_URL: &str = "https://dev.azure.com";
+/// Identities are looked up on a different host
+static AZURE_DEVOPS_IDENTITIES_URL: &str = "https://vssps.dev.azure.com";
+static API_VERSION: &str = "7.1";
+static FALLBACK_AZDO_PER_PAGE: u32 = 100;
+static FALLBACK_RATE_LIMIT_RETRIES: u32 = 3;
+/// Azure DevOps has no feed to page through to its end, so the initial sync looks back this far
+static INITIAL_SYNC_DAYS: i64 = 90;
+static FALLBACK_SYNC_OVERLAP_HOURS: i64 = 24;
+
+/// Body of all listings
+#[derive(Debug, Deserialize)]
+struct AzureList<T> {
+    value: Vec<T>,
+}
+
+/// One entry of `GET {org}/_apis/projects`
+#[derive(Debug, Deserialize)]
+struct AzureProject {
+    name: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AzureRepoProject {
+    pub name: String,
+    /// `private` or `public`, repositories share the visibility of their project
+    #[serde(default)]
+    pub visibility: Option<String>,
+}
+
+/// One entry of `GET {org}/{project}/_apis/git/repositories`
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AzureRepo {
+    /// A uuid, Azure DevOps has no numeric ids
+    pub id: String,
+    pub name: String,
+    pub web_url: String,
+    pub project: AzureRepoProject,
+}
+
+impl AzureRepo {
+    /// e.g. `pollux/backend`, matched by `ProjectFilter`
+    pub fn full_name(&self) -> String {
+        format!("{}/{}", self.project.name, self.name)
+    }
+
+    pub fn visibility(&self) -> ProjectVisibility {
+        match self.project.visibility.as_deref() {
+            Some("public") => ProjectVisibility::Public,
+            _ => ProjectVisibility::Private,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AzureIdentity {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AzureRefUpdate {
+    name: String,
+}
+
+/// One entry of `GET .../repositories/{id}/pushes`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AzurePush {
+    date: String,
+    #[serde(default)]
+    ref_updates: Vec<AzureRefUpdate>,
+}
+
+/// One entry of `GET .../repositories/{id}/pullrequests`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AzurePullRequest {
+    /// `active`, `completed` or `abandoned`
+    status: String,
+    created_by: AzureIdentity,
+    creation_date: String,
+    #[serde(default)]
+    closed_by: Option<AzureIdentity>,
+    #[serde(default)]
+    closed_date: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AzureConnectionData {
+    authenticated_user: AzureAuthenticatedUser,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AzureAuthenticatedUser {
+    provider_display_name: String,
+}
+
+/// A push or pull request of the user, Azure DevOps has no feed of them
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AzureDevopsEvent {
+    /// Named like Azure DevOps' service hook events, e.g. `git.push` or `git.pullrequest.merged`
+    pub action_name: String,
+    pub created: String,
+    pub repo: AzureRepo,
+    pub git_ref: Option<String>,
+    pub target_type: Option<String>,
+}
+
+impl GitEventAPI for AzureDevopsEvent {}
+
+#[derive(Debug)]
+pub struct AzureDevops {
+    organization: String,
+    pat: String,
+    /// Pushes and pull requests are filtered by the identity with this email
+    user_email: String,
+    /// Synced projects, all projects of the organization if empty
+    projects: Vec<String>,
+    base_url: String,
+    identities_url: String,
+    per_page: u32,
+    /// Compared with the visibility of the project a repository belongs to, repositories have none of their own
+    include_visibilities: Vec<ProjectVisibility>,
+    project_filter: ProjectFilter,
+    retry_policy: RetryPolicy,
+    rate_limit_retries: u32,
+    /// Pushes this long before the last sync are listed again, in case they showed up late
+    sync_overlap: chrono::Duration,
+    /// Looked up once for `user_email`
+    user_id: Option<String>,
+}
+
+impl GitPlatform for AzureDevops {
+    const GIT_PLATFORM_ID: &'static str = "AzureDevops";
+    type GitEventAPI = AzureDevopsEvent;
+
+    fn init_from_env_vars() -> Result<Self, ConfigError> {
+        let mut env = EnvConfig::from_env();
+        let azure_devops = AzureDevops {
+            organization: env.required("AZDO_ORG"),
+            pat: env.secret("AZDO_PAT"),
+            user_email: env.required("AZDO_USER_EMAIL"),
+            projects: config::var("AZDO_PROJECTS").map(|input| parse_projects(&input)).unwrap_or_default(),
+            base_url: AZURE_DEVOPS_URL.to_string(),
+            identities_url: AZURE_DEVOPS_IDENTITIES_URL.to_string(),
+            per_page: env_parse("POLLUX_AZDO_PER_PAGE", FALLBACK_AZDO_PER_PAGE).max(1),
+            include_visibilities: AzureDevops::include_visibilities_from_env(),
+            project_filter: ProjectFilter::from_env(),
+            retry_policy: RetryPolicy::from_env(),
+            rate_limit_retries: env_parse("POLLUX_AZDO_RATE_LIMIT_RETRIES", FALLBACK_RATE_LIMIT_RETRIES),
+            sync_overlap: chrono::Duration::hours(env_parse(
+                "POLLUX_AZDO_SYNC_OVERLAP_HOURS",
+                FALLBACK_SYNC_OVERLAP_HOURS,
+            )),
+            user_id: None,
+        };
+        env.build(azure_devops)
+    }
+
+    async fn get_events(&mut self) -> Result<FetchedEvents<Self::GitEventAPI>, PlatformError> {
+        let since = match AzureDevops::get_last_sync_timestamp().await {
+            Some(last_sync) => last_sync - self.sync_overlap,
+            None => {
+                info!("Initial run! Fetching last {} days from Azure DevOps...", INITIAL_SYNC_DAYS);
+                Utc::now() - chrono::Duration::days(INITIAL_SYNC_DAYS)
+            }
+        };
+        self.get_events_since(since).await
+    }
+
+    async fn update_provider(&mut self) -> Result<SyncResult, PlatformError> {
+        info!("Updating events from Azure DevOps ({})...", self.organization);
+        let fetched = self.get_events().await?;
+        let complete = fetched.truncated.is_none();
+        let result = self.insert_events(&fetched.events, EventSource::Fetched, complete).await;
+        Ok(SyncResult {
+            truncated: fetched.truncated,
+            ..result
+        })
+    }
+
+    async fn insert_archived_events(&self, events: Vec<AzureDevopsEvent>) -> Result<SyncResult, PlatformError> {
+        Ok(self.insert_events(&events, EventSource::Archive, true).await)
+    }
+}
+
+impl PlatformRunner for AzureDevops {
+    fn sync(&mut self) -> BoxFuture<'_, SyncReport> {
+        Box::pin(git_platform::sync_and_record(self))
+    }
+
+    fn check_credentials(&mut self) -> BoxFuture<'_, Vec<CredentialCheck>> {
+        Box::pin(async { vec![AzureDevops::validate_credentials(self).await] })
+    }
+
+    fn reprocess(&mut self) -> BoxFuture<'_, SyncReport> {
+        Box::pin(event_archive::reprocess(self))
+    }
+}
+
+impl InsertEvents for AzureDevops {
+    async fn to_new_event<'e>(
+        &self,
+        tx: &mut Transaction<'static, MySql>,
+        cache: &mut SyncCache,
+        event: &'e AzureDevopsEvent,
+    ) -> Result<NewEvent<'e>, Skipped<'e>> {
+        let datetime = Self::parse_datetime(&event.created)?;
+        let action_name =
+            Self::map_action_name(&event.action_name).ok_or(Skipped::UnknownAction(&event.action_name))?;
+        let project_id = self.project_of(tx, cache, &event.repo).await;
+        let action_id = cache.action(tx, action_name).await;
+
+        Ok(NewEvent {
+            datetime,
+            action_id,
+            project_id,
+            commit_count: None,
+            account: None,
+            details: EventDetails {
+                git_ref: event.git_ref.as_deref(),
+                target_type: event.target_type.as_deref(),
+            },
+        })
+    }
+}
+
+/// Comma-separated project names, blanks are ignored
+pub fn parse_projects(input: &str) -> Vec<String> {
+    input
+        .split(',')
+        .map(str::trim)
+        .filter(|project| !project.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl AzureDevops {
+    /// Set up if `AZDO_ORG`, `AZDO_PAT` and `AZDO_USER_EMAIL` are given
+    /// Any of its credentials is set, `init_from_env_vars` reports the missing ones
+    pub fn is_configured() -> bool {
+        ["AZDO_ORG", "AZDO_PAT", "AZDO_PAT_FILE", "AZDO_USER_EMAIL"]
+            .iter()
+            .any(|name| config::var(name).is_some_and(|value| !value.trim().is_empty()))
+    }
+
+    /// `Ok(None)` if Azure DevOps isn't configured
+    pub fn get_or_init() -> Result<Option<Arc<Mutex<AzureDevops>>>, ConfigError> {
+        if !AzureDevops::is_configured() {
+            return Ok(None);
+        }
+        AZURE_DEVOPS
+            .get_or_try_init(|| Self::init_from_env_vars().map(|platform| Arc::new(Mutex::new(platform))))
+            .cloned()
+            .map(Some)
+    }
+
+    /// `POLLUX_AZDO_INCLUDE_VISIBILITIES`, only public repositories by default
+    fn include_visibilities_from_env() -> Vec<ProjectVisibility> {
+        let Some(input) = config::var("POLLUX_AZDO_INCLUDE_VISIBILITIES") else {
+            return vec![ProjectVisibility::Public];
+        };
+
+        match ProjectVisibility::parse_set(&input) {
+            Ok(visibilities) => visibilities,
+            Err(err) => {
+                warn!(
+                    "Unable to parse POLLUX_AZDO_INCLUDE_VISIBILITIES »{}«, using »public« as a fallback: {}",
+                    input, err
+                );
+                vec![ProjectVisibility::Public]
+            }
+        }
+    }
+
+    /// Url of `path` below the organization, with the api version and `params`
+    fn api_url(&self, base_url: &str, path: &str, params: &[(&str, &str)]) -> String {
+        let url = format!("{}/{}/{}", base_url, self.organization, path);
+        Url::parse_with_params(&url, [("api-version", API_VERSION)].iter().chain(params))
+            .map(String::from)
+            .unwrap_or(url)
+    }
+
+    /// PATs are sent as the password of basic auth, without a username
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        request.basic_auth("", Some(&self.pat))
+    }
+
+    /// Body and headers of a successful response
+    async fn get(&self, client: &reqwest::Client, url: &str) -> Result<(HeaderMap, String), PlatformError> {
+        let response = self
+            .retry_policy
+            .send_rate_limited(Self::GIT_PLATFORM_ID, self.rate_limit_retries, || {
+                self.authorize(client.get(url))
+            })
+            .await
+            .map_err(|err| PlatformError::Request(format!("Unable to get response from Azure DevOps: {}", err)))?;
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let payload = response
+            .text()
+            .await
+            .map_err(|err| PlatformError::Request(format!("Unable to decode response from Azure DevOps: {}", err)))?;
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(PlatformError::RateLimited {
+                status: status.as_u16(),
+                retries: self.rate_limit_retries,
+            });
+        }
+        // A rejected PAT is answered with the sign-in page instead of a 401
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::NON_AUTHORITATIVE_INFORMATION {
+            return Err(PlatformError::Status {
+                status: status.as_u16(),
+                message: format!("AZDO_PAT is invalid or expired (Azure DevOps answered {})", status),
+            });
+        }
+        if !status.is_success() {
+            error!("We got this data: {}", payload);
+            return Err(PlatformError::Status {
+                status: status.as_u16(),
+                message: credentials::rejection("Azure DevOps", status, &payload),
+            });
+        }
+        Ok((headers, payload))
+    }
+
+    fn decode<T: DeserializeOwned>(payload: &str) -> Result<T, PlatformError> {
+        serde_json::from_str(payload).map_err(|err| {
+            error!("Unable to decode json response from Azure DevOps, this is what we received:\n{}", payload);
+            PlatformError::InvalidResponse(format!("Unable to decode json response from Azure DevOps: {}", err))
+        })
+    }
+
+    /// All values of a listing. Pages are requested with the continuation token Azure DevOps answers with,
+    /// listings without one are paged with `$skip` until a partial page.
+    async fn get_list<T: DeserializeOwned + std::fmt::Debug>(
+        &self,
+        client: &reqwest::Client,
+        first_url: &str,
+    ) -> Result<FetchedEvents<T>, PlatformError> {
+        let mut values = Vec::new();
+        let mut pagination = PaginationGuard::from_env();
+        let mut url = pagination::with_query_param(first_url, "$top", &self.per_page.to_string())
+            .map_err(PlatformError::InvalidResponse)?;
+        let mut skip = 0;
+        loop {
+            if let Err(err) = pagination.visit(&url) {
+                return Ok(FetchedEvents::truncated(values, err));
+            }
+            debug!("Getting page from Azure DevOps... ({})", url);
+
+            let (headers, payload) = self.get(client, &url).await?;
+            let list: AzureList<T> = Self::decode(&payload)?;
+            if log_enabled!(Level::Debug) {
+                for element in &list.value {
+                    debug!("{:?}", element);
+                }
+            }
+            let page_size = list.value.len();
+            values.extend(list.value);
+
+            let next = match pagination::continuation_token(&headers) {
+                Some(token) => pagination::with_query_param(&url, "continuationToken", &token),
+                None if page_size == self.per_page as usize => {
+                    skip += self.per_page;
+                    pagination::with_query_param(&url, "$skip", &skip.to_string())
+                }
+                None => return Ok(FetchedEvents::complete(values)),
+            };
+            url = next.map_err(PlatformError::InvalidResponse)?;
+        }
+    }
+
+    /// Id of the identity with `AZDO_USER_EMAIL`, looked up once
+    async fn user_id(&mut self, client: &reqwest::Client) -> Result<String, PlatformError> {
+        if let Some(user_id) = &self.user_id {
+            return Ok(user_id.clone());
+        }
+
+        let url = self.api_url(
+            &self.identities_url,
+            "_apis/identities",
+            &[("searchFilter", "MailAddress"), ("filterValue", &self.user_email)],
+        );
+        let (_, payload) = self.get(client, &url).await?;
+        let identities: AzureList<AzureIdentity> = Self::decode(&payload)?;
+        let identity = identities.value.into_iter().next().ok_or_else(|| {
+            PlatformError::InvalidResponse(format!(
+                "Azure DevOps doesn't know anyone with AZDO_USER_EMAIL »{}«",
+                self.user_email
+            ))
+        })?;
+        self.user_id = Some(identity.id.clone());
+        Ok(identity.id)
+    }
+
+    /// Configured projects or all projects of the organization
+    async fn project_names(&self, client: &reqwest::Client) -> Result<FetchedEvents<String>, PlatformError> {
+        if !self.projects.is_empty() {
+            return Ok(FetchedEvents::complete(self.projects.clone()));
+        }
+        let url = self.api_url(&self.base_url, "_apis/projects", &[]);
+        info!("Getting projects from Azure DevOps... ({})", url);
+        let projects = self.get_list::<AzureProject>(client, &url).await?;
+        Ok(FetchedEvents {
+            events: projects.events.into_iter().map(|project| project.name).collect(),
+            truncated: projects.truncated,
+        })
+    }
+
+    /// Own pushes and pull requests in all repositories of the projects since `since`
+    pub async fn get_events_since(&mut self, since: DateTime<Utc>) -> Result<FetchedEvents<AzureDevopsEvent>, PlatformError> {
+        let client = http_client::platform_client();
+        let mut events = Vec::new();
+        let user_id = match self.user_id(&client).await {
+            Ok(user_id) => user_id,
+            Err(err) => return git_platform::stop_early(events, err),
+        };
+
+        let projects = match self.project_names(&client).await {
+            Ok(projects) => projects,
+            Err(err) => return git_platform::stop_early(events, err),
+        };
+        for project in &projects.events {
+            let url = self.api_url(&self.base_url, &format!("{}/_apis/git/repositories", project), &[]);
+            info!("Getting repositories from Azure DevOps... ({})", url);
+            let repos: AzureList<AzureRepo> = match self.get(&client, &url).await {
+                Ok((_, payload)) => Self::decode(&payload)?,
+                Err(err) => return git_platform::stop_early(events, err),
+            };
+
+            for repo in repos.value {
+                if !self.project_filter.includes(Self::GIT_PLATFORM_ID, &repo.full_name()) {
+                    continue;
+                }
+                if !self.include_visibilities.contains(&repo.visibility()) {
+                    debug!("Skipping repository {}: its visibility isn't included", repo.full_name());
+                    continue;
+                }
+
+                match self.get_repo_events(&client, &repo, &user_id, since).await {
+                    Ok(mut repo_events) => {
+                        events.append(&mut repo_events.events);
+                        if let Some(reason) = repo_events.truncated {
+                            return Ok(FetchedEvents::truncated(events, reason));
+                        }
+                    }
+                    // e.g. disabled repositories, the others are still synced
+                    Err(PlatformError::Status { status: 403 | 404, message }) => {
+                        warn!("Skipping repository {}: {}", repo.full_name(), message);
+                    }
+                    Err(err) => return git_platform::stop_early(events, err),
+                }
+            }
+        }
+        match projects.truncated {
+            Some(reason) => Ok(FetchedEvents::truncated(events, reason)),
+            None => Ok(FetchedEvents::complete(events)),
+        }
+    }
+
+    async fn get_repo_events(
+        &self,
+        client: &reqwest::Client,
+        repo: &AzureRepo,
+        user_id: &str,
+        since: DateTime<Utc>,
+    ) -> Result<FetchedEvents<AzureDevopsEvent>, PlatformError> {
+        let from = since.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let repo_path = format!("{}/_apis/git/repositories/{}", repo.project.name, repo.id);
+        let is_recent = |date: &str| date.parse::<DateTime<Utc>>().is_ok_and(|date| date >= since);
+        let event = |action_name: &str, created: String, git_ref: Option<String>, target_type: Option<&str>| {
+            AzureDevopsEvent {
+                action_name: action_name.to_string(),
+                created,
+                repo: repo.clone(),
+                git_ref,
+                target_type: target_type.map(str::to_string),
+            }
+        };
+
+        let url = self.api_url(
+            &self.base_url,
+            &format!("{}/pushes", repo_path),
+            &[
+                ("searchCriteria.pusherId", user_id),
+                ("searchCriteria.fromDate", &from),
+                ("searchCriteria.includeRefUpdates", "true"),
+            ],
+        );
+        info!("Getting pushes from Azure DevOps... ({})", url);
+        let pushes = self.get_list::<AzurePush>(client, &url).await?;
+        let mut events: Vec<AzureDevopsEvent> = pushes
+            .events
+            .into_iter()
+            .filter(|push| is_recent(&push.date))
+            .map(|push| {
+                let git_ref = push.ref_updates.first().map(|update| {
+                    let name = update.name.as_str();
+                    name.strip_prefix("refs/heads/")
+                        .or_else(|| name.strip_prefix("refs/tags/"))
+                        .unwrap_or(name)
+                        .to_string()
+                });
+                event("git.push", push.date, git_ref, None)
+            })
+            .collect();
+        if let Some(reason) = pushes.truncated {
+            return Ok(FetchedEvents::truncated(events, reason));
+        }
+
+        // Created by the user, and completed or abandoned by them, which can be someone else's
+        for range_type in ["created", "closed"] {
+            let mut params = vec![
+                ("searchCriteria.status", "all"),
+                ("searchCriteria.minTime", from.as_str()),
+                ("searchCriteria.queryTimeRangeType", range_type),
+            ];
+            if range_type == "created" {
+                params.push(("searchCriteria.creatorId", user_id));
+            }
+            let url = self.api_url(&self.base_url, &format!("{}/pullrequests", repo_path), &params);
+            info!("Getting pull requests from Azure DevOps... ({})", url);
+            let pull_requests = self.get_list::<AzurePullRequest>(client, &url).await?;
+
+            for pull_request in pull_requests.events {
+                if range_type == "created" {
+                    if pull_request.created_by.id == user_id && is_recent(&pull_request.creation_date) {
+                        events.push(event("git.pullrequest.created", pull_request.creation_date, None, Some("PullRequest")));
+                    }
+                    continue;
+                }
+
+                let closed_by_user = pull_request.closed_by.as_ref().is_some_and(|identity| identity.id == user_id);
+                let Some(closed_date) = pull_request.closed_date.filter(|date| closed_by_user && is_recent(date)) else {
+                    continue;
+                };
+                let action_name = match pull_request.status.as_str() {
+                    "completed" => "git.pullrequest.merged",
+                    _ => "git.pullrequest.updated",
+                };
+                events.push(event(action_name, closed_date, None, Some("PullRequest")));
+            }
+            if let Some(reason) = pull_requests.truncated {
+                return Ok(FetchedEvents::truncated(events, reason));
+            }
+        }
+        Ok(FetchedEvents::complete(events))
+    }
+
+    pub async fn validate_credentials(&self) -> CredentialCheck {
+        let client = http_client::platform_client();
+        let url = format!("{}/{}/_apis/connectionData?api-version={}-preview", self.base_url, self.organization, API_VERSION);
+        let outcome = match self.get(&client, &url).await {
+            Ok((_, payload)) => Self::decode::<AzureConnectionData>(&payload)
+                .map(|data| TokenInfo {
+                    username: data.authenticated_user.provider_display_name,
+                    scopes: None,
+                    expires_at: None,
+                })
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        CredentialCheck {
+            platform: Self::GIT_PLATFORM_ID,
+            env_var: "AZDO_PAT".to_string(),
+            outcome,
+        }
+    }
+
+    /// Id of the `GitProjects` row, stored on the first event of the repository
+    async fn project_of(
+        &self,
+        tx: &mut Transaction<'static, MySql>,
+        cache: &mut SyncCache,
+        repo: &AzureRepo,
+    ) -> u64 {
+        let platform_project_id = git_platform::platform_project_id_from(&repo.id);
+        if let Some(project) = cache.project(tx, platform_project_id, false).await {
+            return project.id;
+        }
+
+        let project = GitProject::reported(platform_project_id, repo.full_name(), &repo.web_url);
+        self.write_project_to_db(tx, &project, Some(repo.visibility())).await
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use wiremock::{
+        matchers::{header, method, path, query_param, query_param_is_missing},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn azure_devops_for_tests(base_url: String) -> AzureDevops {
+        AzureDevops {
+            organization: "contoso".to_string(),
+            pat: "pat".to_string(),
+            user_email: "2tefan@example.com".to_string(),
+            projects: Vec::new(),
+            identities_url: base_url.clone(),
+            base_url,
+            per_page: 2,
+            include_visibilities: vec![ProjectVisibility::Public, ProjectVisibility::Private],
+            project_filter: ProjectFilter::default(),
+            retry_policy: RetryPolicy {
+                attempts: 1,
+                base_delay: std::time::Duration::from_millis(1),
+                max_delay: std::time::Duration::from_millis(1),
+            },
+            rate_limit_retries: 0,
+            sync_overlap: chrono::Duration::hours(FALLBACK_SYNC_OVERLAP_HOURS),
+            user_id: Some("me-id".to_string()),
+        }
+    }
+
+    fn since() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap()
+    }
+
+    fn list(values: serde_json::Value) -> ResponseTemplate {
+        let count = values.as_array().map_or(0, Vec::len);
+        ResponseTemplate::new(200).set_body_json(serde_json::json!({"count": count, "value": values}))
+    }
+
+    fn repo(project: &str, name: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": format!("{}-{}-id", project, name),
+            "name": name,
+            "webUrl": format!("https://dev.azure.com/contoso/{}/_git/{}", project, name),
+            "project": {"id": format!("{}-id", project), "name": project, "visibility": "private"},
+        })
+    }
+
+    async fn mount_repo(server: &MockServer, project: &str, name: &str, pushes: serde_json::Value) {
+        let repo_path = format!("/contoso/{}/_apis/git/repositories/{}-{}-id", project, project, name);
+        Mock::given(method("GET"))
+            .and(path(format!("{}/pushes", repo_path)))
+            .and(query_param("searchCriteria.pusherId", "me-id"))
+            .and(query_param("searchCriteria.fromDate", "2024-05-01T00:00:00Z"))
+            .respond_with(list(pushes))
+            .expect(1)
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/pullrequests", repo_path)))
+            .respond_with(list(serde_json::json!([])))
+            .expect(2)
+            .mount(server)
+            .await;
+    }
+
+    #[test]
+    fn projects_are_comma_separated() {
+        assert_eq!(parse_projects(" pollux, ,castor ,"), vec!["pollux", "castor"]);
+    }
+
+    #[tokio::test]
+    async fn projects_are_paged_by_continuation_token() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/contoso/_apis/projects"))
+            .and(query_param("$top", "2"))
+            .and(query_param_is_missing("continuationToken"))
+            .and(header("authorization", "Basic OnBhdA=="))
+            .respond_with(list(serde_json::json!([{"name": "pollux"}, {"name": "castor"}])).insert_header("x-ms-continuationtoken", "a1b2;c3"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/contoso/_apis/projects"))
+            .and(query_param("continuationToken", "a1b2;c3"))
+            .respond_with(list(serde_json::json!([{"name": "archive"}])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        for project in ["pollux", "castor", "archive"] {
+            Mock::given(method("GET"))
+                .and(path(format!("/contoso/{}/_apis/git/repositories", project)))
+                .respond_with(list(serde_json::json!([repo(project, "app")])))
+                .expect(1)
+                .mount(&server)
+                .await;
+            mount_repo(&server, project, "app", serde_json::json!([{"pushId": 1, "date": "2024-05-03T18:40:12Z"}])).await;
+        }
+
+        let fetched = azure_devops_for_tests(server.uri()).get_events_since(since()).await.unwrap();
+        assert_eq!(fetched.truncated, None);
+        assert_eq!(
+            fetched.events.iter().map(|event| event.repo.full_name()).collect::<Vec<_>>(),
+            vec!["pollux/app", "castor/app", "archive/app"]
+        );
+    }
+
+    #[tokio::test]
+    async fn pushes_without_continuation_token_are_paged_by_skip() {
+        let server = MockServer::start().await;
+        let mut azure_devops = AzureDevops {
+            projects: vec!["pollux".to_string()],
+            ..azure_devops_for_tests(server.uri())
+        };
+        Mock::given(method("GET"))
+            .and(path("/contoso/pollux/_apis/git/repositories"))
+            .respond_with(list(serde_json::json!([repo("pollux", "app")])))
+            .mount(&server)
+            .await;
+        let pushes = "/contoso/pollux/_apis/git/repositories/pollux-app-id/pushes";
+        Mock::given(method("GET"))
+            .and(path(pushes))
+            .and(query_param_is_missing("$skip"))
+            .respond_with(list(serde_json::json!([
+                {"pushId": 3, "date": "2024-05-03T18:40:12.4170000Z", "refUpdates": [{"name": "refs/heads/main"}]},
+                {"pushId": 2, "date": "2024-05-02T10:00:00Z", "refUpdates": [{"name": "refs/tags/v1.0.0"}]},
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(pushes))
+            .and(query_param("$skip", "2"))
+            .respond_with(list(serde_json::json!([{"pushId": 1, "date": "2024-05-01T08:00:00Z"}])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/contoso/pollux/_apis/git/repositories/pollux-app-id/pullrequests"))
+            .respond_with(list(serde_json::json!([])))
+            .mount(&server)
+            .await;
+
+        let fetched = azure_devops.get_events_since(since()).await.unwrap();
+        assert_eq!(
+            fetched
+                .events
+                .iter()
+                .map(|event| (event.action_name.as_str(), event.git_ref.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![("git.push", Some("main")), ("git.push", Some("v1.0.0")), ("git.push", None)]
+        );
+    }
+
+    #[tokio::test]
+    async fn own_pull_requests_are_mapped() {
+        let server = MockServer::start().await;
+        let mut azure_devops = AzureDevops {
+            projects: vec!["pollux".to_string()],
+            ..azure_devops_for_tests(server.uri())
+        };
+        Mock::given(method("GET"))
+            .and(path("/contoso/pollux/_apis/git/repositories"))
+            .respond_with(list(serde_json::json!([repo("pollux", "app")])))
+            .mount(&server)
+            .await;
+        let repo_path = "/contoso/pollux/_apis/git/repositories/pollux-app-id";
+        Mock::given(method("GET"))
+            .and(path(format!("{}/pushes", repo_path)))
+            .respond_with(list(serde_json::json!([])))
+            .mount(&server)
+            .await;
+        let me = serde_json::json!({"id": "me-id", "uniqueName": "2tefan@example.com"});
+        let colleague = serde_json::json!({"id": "colleague-id", "uniqueName": "colleague@example.com"});
+        Mock::given(method("GET"))
+            .and(path(format!("{}/pullrequests", repo_path)))
+            .and(query_param("searchCriteria.queryTimeRangeType", "created"))
+            .and(query_param("searchCriteria.creatorId", "me-id"))
+            .respond_with(list(serde_json::json!([
+                {"pullRequestId": 7, "status": "active", "createdBy": me, "creationDate": "2024-05-03T08:00:00Z"},
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/pullrequests", repo_path)))
+            .and(query_param("searchCriteria.queryTimeRangeType", "closed"))
+            .respond_with(list(serde_json::json!([
+                {"pullRequestId": 6, "status": "completed", "createdBy": colleague, "creationDate": "2024-04-02T08:00:00Z",
+                    "closedBy": me, "closedDate": "2024-05-02T08:00:00Z"},
+                {"pullRequestId": 5, "status": "abandoned", "createdBy": me, "creationDate": "2024-04-01T08:00:00Z",
+                    "closedBy": me, "closedDate": "2024-05-01T08:00:00Z"},
+                {"pullRequestId": 4, "status": "completed", "createdBy": me, "creationDate": "2024-04-01T08:00:00Z",
+                    "closedBy": colleague, "closedDate": "2024-05-01T09:00:00Z"},
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let fetched = azure_devops.get_events_since(since()).await.unwrap();
+        let actions: Vec<_> = fetched
+            .events
+            .iter()
+            .map(|event| (event.action_name.as_str(), AzureDevops::map_action_name(&event.action_name)))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                ("git.pullrequest.created", Some("merge-request")),
+                ("git.pullrequest.merged", Some("merge-request")),
+                ("git.pullrequest.updated", Some("merge-request")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn user_is_looked_up_by_email() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/contoso/_apis/identities"))
+            .and(query_param("searchFilter", "MailAddress"))
+            .and(query_param("filterValue", "2tefan@example.com"))
+            .respond_with(list(serde_json::json!([{"id": "me-id", "providerDisplayName": "Stefan"}])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut azure_devops = AzureDevops {
+            user_id: None,
+            ..azure_devops_for_tests(server.uri())
+        };
+        let client = reqwest::Client::new();
+        assert_eq!(azure_devops.user_id(&client).await, Ok("me-id".to_string()));
+        // Only looked up once
+        assert_eq!(azure_devops.user_id(&client).await, Ok("me-id".to_string()));
+    }
+
+    #[tokio::test]
+    async fn sign_in_page_means_the_pat_is_rejected() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(203).set_body_string("<html>Sign in</html>"))
+            .mount(&server)
+            .await;
+
+        let check = azure_devops_for_tests(server.uri()).validate_credentials().await;
+        assert_eq!(
+            check.outcome.unwrap_err(),
+            "AZDO_PAT is invalid or expired (Azure DevOps answered 203 Non Authoritative Information)"
+        );
+    }
+}
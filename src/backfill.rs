@@ -0,0 +1,189 @@
+use chrono::{Datelike, NaiveDate};
+use schemars::JsonSchema;
+use serde::Serialize;
+use sqlx::{MySql, Pool};
+
+use crate::{git_platform::GitPlatform, stats};
+
+/// GitHub was founded in 2008 and GitLab in 2011, there are no contributions before
+static EARLIEST_BACKFILL_YEAR: i32 = 2008;
+
+/// Days to backfill from the contribution calendar, `since` and `until` included
+#[derive(Debug, PartialEq)]
+pub struct BackfillRange {
+    pub since: NaiveDate,
+    pub until: NaiveDate,
+}
+
+impl BackfillRange {
+    /// Defaults to the start of last year until `today`, `until` is capped at `today`
+    pub fn parse(since: Option<&str>, until: Option<&str>, today: NaiveDate) -> Result<BackfillRange, String> {
+        let parse = |name: &str, input: &str| {
+            NaiveDate::parse_from_str(input, "%Y-%m-%d")
+                .map_err(|err| format!("Couldn't parse {} »{}« as a date (YYYY-MM-DD): {}", name, input, err))
+        };
+        let since = match since {
+            Some(input) => parse("since", input)?,
+            None => NaiveDate::from_ymd_opt(today.year() - 1, 1, 1).unwrap(),
+        };
+        let until = match until {
+            Some(input) => parse("until", input)?.min(today),
+            None => today,
+        };
+
+        if since.year() < EARLIEST_BACKFILL_YEAR {
+            return Err(format!("since ({}) is before {}", since, EARLIEST_BACKFILL_YEAR));
+        }
+        if until < since {
+            return Err(format!("until ({}) is before since ({})", until, since));
+        }
+        Ok(BackfillRange { since, until })
+    }
+
+    /// The range split at year boundaries, GitHub only answers for at most one year per query
+    #[cfg(feature = "github")]
+    pub fn years(&self) -> Vec<(NaiveDate, NaiveDate)> {
+        (self.since.year()..=self.until.year())
+            .map(|year| {
+                let start = NaiveDate::from_ymd_opt(year, 1, 1).unwrap().max(self.since);
+                let end = NaiveDate::from_ymd_opt(year, 12, 31).unwrap().min(self.until);
+                (start, end)
+            })
+            .collect()
+    }
+
+    pub fn contains(&self, day: NaiveDate) -> bool {
+        (self.since..=self.until).contains(&day)
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, JsonSchema)]
+pub struct BackfillReport {
+    pub platform: String,
+    pub since: NaiveDate,
+    pub until: NaiveDate,
+    /// Days with at least one contribution
+    pub days: u32,
+    pub contributions: u64,
+}
+
+impl BackfillReport {
+    pub fn new<P: GitPlatform>(range: &BackfillRange, days: &[(NaiveDate, u32)]) -> BackfillReport {
+        BackfillReport {
+            platform: P::GIT_PLATFORM_ID.to_string(),
+            since: range.since,
+            until: range.until,
+            days: days.len() as u32,
+            contributions: days.iter().map(|(_, count)| u64::from(*count)).sum(),
+        }
+    }
+}
+
+/// Stores per-day counts in `ContributionCalendar`, replacing earlier imports of the same days.
+/// They are kept apart from the precise events, so they never show up in per-project stats.
+pub async fn upsert_calendar_days<P: GitPlatform>(pool: &Pool<MySql>, days: &[(NaiveDate, u32)]) {
+    let mut tx = pool.begin().await.expect("Couldn't start transaction!");
+    P::set_platform(&mut tx).await;
+
+    let imported_at = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    for (day, count) in days {
+        sqlx::query(
+            "INSERT INTO ContributionCalendar (platform, day, count, imported_at) VALUES ( ?, ?, ?, ? ) \
+                ON DUPLICATE KEY UPDATE count = VALUES(count), imported_at = VALUES(imported_at)",
+        )
+        .bind(P::GIT_PLATFORM_ID)
+        .bind(day)
+        .bind(count)
+        .bind(&imported_at)
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+    }
+
+    tx.commit().await.expect("Couldn't apply transaction ._.");
+    stats::invalidate_today_cache();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "github")]
+    use crate::database::tests::initialize;
+    #[cfg(feature = "github")]
+    use crate::github::Github;
+    #[cfg(all(feature = "github", feature = "gitlab"))]
+    use crate::gitlab::Gitlab;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn range_defaults_to_last_year() {
+        let today = date(2024, 5, 1);
+        assert_eq!(
+            BackfillRange::parse(None, None, today),
+            Ok(BackfillRange {
+                since: date(2023, 1, 1),
+                until: today
+            })
+        );
+        // Nothing to fetch from the future
+        assert_eq!(BackfillRange::parse(Some("2024-01-01"), Some("2030-01-01"), today).unwrap().until, today);
+    }
+
+    #[test]
+    fn invalid_ranges_are_rejected() {
+        let today = date(2024, 5, 1);
+        assert!(BackfillRange::parse(Some("last year"), None, today).is_err());
+        assert!(BackfillRange::parse(Some("2007-12-31"), None, today).is_err());
+        assert!(BackfillRange::parse(Some("2024-05-02"), None, today).is_err());
+    }
+
+    #[cfg(feature = "github")]
+    #[test]
+    fn range_is_split_into_years() {
+        let range = BackfillRange {
+            since: date(2022, 3, 15),
+            until: date(2024, 5, 1),
+        };
+        assert_eq!(
+            range.years(),
+            vec![
+                (date(2022, 3, 15), date(2022, 12, 31)),
+                (date(2023, 1, 1), date(2023, 12, 31)),
+                (date(2024, 1, 1), date(2024, 5, 1)),
+            ]
+        );
+    }
+
+    #[cfg(feature = "github")]
+    #[tokio::test]
+    async fn calendar_days_are_replaced_on_reimport() {
+        let (_container, pool) = initialize().await;
+
+        upsert_calendar_days::<Github>(&pool, &[(date(2024, 5, 1), 3), (date(2024, 5, 2), 1)]).await;
+        upsert_calendar_days::<Github>(&pool, &[(date(2024, 5, 2), 4)]).await;
+
+        let counts = stats::get_calendar_counts_per_day(&pool, date(2024, 1, 1)).await;
+        assert_eq!(counts.get(&("Github".to_string(), date(2024, 5, 1))), Some(&3));
+        assert_eq!(counts.get(&("Github".to_string(), date(2024, 5, 2))), Some(&4));
+    }
+
+    #[cfg(all(feature = "github", feature = "gitlab"))]
+    #[tokio::test]
+    async fn repeated_backfills_are_idempotent() {
+        let (_container, pool) = initialize().await;
+
+        let days = [(date(2024, 5, 1), 3), (date(2024, 5, 2), 1)];
+        upsert_calendar_days::<Gitlab>(&pool, &days).await;
+        upsert_calendar_days::<Gitlab>(&pool, &days).await;
+        upsert_calendar_days::<Github>(&pool, &[(date(2024, 5, 1), 2)]).await;
+
+        let counts = stats::get_calendar_counts_per_day(&pool, date(2024, 1, 1)).await;
+        assert_eq!(counts.get(&("Gitlab".to_string(), date(2024, 5, 1))), Some(&3));
+        assert_eq!(counts.get(&("Gitlab".to_string(), date(2024, 5, 2))), Some(&1));
+        // Platforms are kept apart
+        assert_eq!(counts.get(&("Github".to_string(), date(2024, 5, 1))), Some(&2));
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Duration, NaiveDate};
+use rocket::http::Header;
+use tiny_skia::{Color, Paint, Pixmap, Rect, Transform};
+
+use crate::stats::DailyCount;
+
+pub static DEFAULT_CALENDAR_WEEKS: u32 = 53;
+pub static MAX_CALENDAR_WEEKS: u32 = 53;
+pub static DEFAULT_CALENDAR_CELL: u32 = 10;
+/// Caps keep a single request from allocating huge images
+pub static MIN_CALENDAR_CELL: u32 = 2;
+pub static MAX_CALENDAR_CELL: u32 = 32;
+pub static MAX_BADGE_SCALE: u32 = 8;
+
+/// Same greens as the dashboard heatmap, from no activity to the busiest day
+static LEVEL_COLORS: [(u8, u8, u8); 5] = [
+    (0xeb, 0xed, 0xf0),
+    (0x9b, 0xe9, 0xa8),
+    (0x40, 0xc4, 0x63),
+    (0x30, 0xa1, 0x4e),
+    (0x21, 0x6e, 0x39),
+];
+static BADGE_LABEL_COLOR: (u8, u8, u8) = (0x55, 0x55, 0x55);
+static BADGE_VALUE_COLOR: (u8, u8, u8) = (0x44, 0xcc, 0x11);
+static BADGE_TEXT_COLOR: (u8, u8, u8) = (0xff, 0xff, 0xff);
+static BADGE_HEIGHT: u32 = 20;
+static BADGE_PADDING: u32 = 6;
+
+/// Counts only change with a sync, which happens every few hours
+static CACHE_CONTROL: &str = "public, max-age=3600";
+
+static GLYPH_WIDTH: u32 = 5;
+static GLYPH_HEIGHT: u32 = 7;
+
+/// 5x7 bitmap glyphs, one row per byte with the leftmost pixel in bit 4.
+/// Only what badges print: digits and the letters of their labels.
+fn glyph(character: char) -> Option<[u8; 7]> {
+    Some(match character {
+        '0' => [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+        '1' => [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        '2' => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+        '3' => [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+        '4' => [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+        '5' => [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+        '6' => [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+        '7' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+        '8' => [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+        '9' => [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+        'c' => [0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b10001, 0b01110],
+        'e' => [0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110],
+        'i' => [0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110],
+        'm' => [0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b10001],
+        'n' => [0b00000, 0b00000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001],
+        'o' => [0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b10001, 0b01110],
+        's' => [0b00000, 0b00000, 0b01110, 0b10000, 0b01110, 0b00001, 0b11110],
+        't' => [0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b01001, 0b00110],
+        'v' => [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100],
+        ' ' => [0; 7],
+        _ => return None,
+    })
+}
+
+fn paint((red, green, blue): (u8, u8, u8)) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color(Color::from_rgba8(red, green, blue, 0xff));
+    // Pixel art, every edge sits on whole pixels anyway
+    paint.anti_alias = false;
+    paint
+}
+
+fn fill(pixmap: &mut Pixmap, x: u32, y: u32, width: u32, height: u32, color: (u8, u8, u8)) {
+    if let Some(rect) = Rect::from_xywh(x as f32, y as f32, width as f32, height as f32) {
+        pixmap.fill_rect(rect, &paint(color), Transform::identity(), None);
+    }
+}
+
+/// Width of `text` in unscaled pixels, one pixel of spacing between glyphs
+fn text_width(text: &str) -> u32 {
+    let glyphs = text.chars().filter(|character| glyph(*character).is_some()).count() as u32;
+    (glyphs * (GLYPH_WIDTH + 1)).saturating_sub(1)
+}
+
+fn draw_text(pixmap: &mut Pixmap, text: &str, x: u32, y: u32, scale: u32) {
+    let mut x = x;
+    for rows in text.chars().filter_map(glyph) {
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                    let (pixel_x, pixel_y) = (x + column * scale, y + row as u32 * scale);
+                    fill(pixmap, pixel_x, pixel_y, scale, scale, BADGE_TEXT_COLOR);
+                }
+            }
+        }
+        x += (GLYPH_WIDTH + 1) * scale;
+    }
+}
+
+/// Two-part badge like `events | 1234`, `scale` multiplies the 20 pixel base height
+pub fn badge_pixmap(label: &str, value: &str, scale: u32) -> Pixmap {
+    let scale = scale.clamp(1, MAX_BADGE_SCALE);
+    let label_width = text_width(label) + 2 * BADGE_PADDING;
+    let value_width = text_width(value) + 2 * BADGE_PADDING;
+
+    let mut pixmap = Pixmap::new((label_width + value_width) * scale, BADGE_HEIGHT * scale)
+        .expect("badge dimensions are never zero");
+    fill(&mut pixmap, 0, 0, label_width * scale, BADGE_HEIGHT * scale, BADGE_LABEL_COLOR);
+    fill(&mut pixmap, label_width * scale, 0, value_width * scale, BADGE_HEIGHT * scale, BADGE_VALUE_COLOR);
+
+    let text_y = (BADGE_HEIGHT - GLYPH_HEIGHT) / 2 * scale;
+    draw_text(&mut pixmap, label, BADGE_PADDING * scale, text_y, scale);
+    draw_text(&mut pixmap, value, (label_width + BADGE_PADDING) * scale, text_y, scale);
+    pixmap
+}
+
+/// Monday of the first column of a calendar with `weeks` columns, the last one containing `today`
+pub fn calendar_start(today: NaiveDate, weeks: u32) -> NaiveDate {
+    let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    monday - Duration::weeks(weeks.clamp(1, MAX_CALENDAR_WEEKS) as i64 - 1)
+}
+
+fn level(count: u64, max: u64) -> usize {
+    if count == 0 {
+        return 0;
+    }
+    (count * 4).div_ceil(max.max(1)).min(4) as usize
+}
+
+/// Heatmap with one column per week (Monday on top) and one `cell` sized square per day up to `today`.
+/// The background stays transparent, so the image fits light and dark pages.
+pub fn calendar_pixmap(days: &[DailyCount], today: NaiveDate, weeks: u32, cell: u32) -> Pixmap {
+    let weeks = weeks.clamp(1, MAX_CALENDAR_WEEKS);
+    let cell = cell.clamp(MIN_CALENDAR_CELL, MAX_CALENDAR_CELL);
+    let gap = (cell / 5).max(1);
+
+    let mut pixmap = Pixmap::new(weeks * (cell + gap) + gap, 7 * (cell + gap) + gap)
+        .expect("calendar dimensions are never zero");
+
+    let counts: BTreeMap<NaiveDate, u64> = days.iter().map(|day| (day.date, day.count)).collect();
+    let start = calendar_start(today, weeks);
+    let max = counts.range(start..).map(|(_, count)| *count).max().unwrap_or(0);
+
+    for date in start.iter_days().take_while(|date| *date <= today) {
+        let offset = (date - start).num_days() as u32;
+        let (column, row) = (offset / 7, offset % 7);
+        let color = LEVEL_COLORS[level(counts.get(&date).copied().unwrap_or(0), max)];
+        fill(&mut pixmap, gap + column * (cell + gap), gap + row * (cell + gap), cell, cell, color);
+    }
+    pixmap
+}
+
+pub fn encode(pixmap: &Pixmap) -> Result<Vec<u8>, String> {
+    pixmap.encode_png().map_err(|err| format!("Unable to encode png: {}", err))
+}
+
+#[derive(Responder)]
+#[response(content_type = "image/png")]
+pub struct Png {
+    body: Vec<u8>,
+    cache_control: Header<'static>,
+}
+
+impl Png {
+    pub fn new(pixmap: &Pixmap) -> Result<Png, String> {
+        Ok(Png {
+            body: encode(pixmap)?,
+            cache_control: Header::new("Cache-Control", CACHE_CONTROL),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+
+    fn checksum(pixmap: &Pixmap) -> String {
+        Sha256::digest(pixmap.data())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Reads the dimensions from the IHDR chunk
+    fn png_dimensions(png: &[u8]) -> (u32, u32) {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        (
+            u32::from_be_bytes(png[16..20].try_into().unwrap()),
+            u32::from_be_bytes(png[20..24].try_into().unwrap()),
+        )
+    }
+
+    fn fixed_days() -> (Vec<DailyCount>, NaiveDate) {
+        let today = NaiveDate::from_ymd_opt(2024, 5, 15).unwrap();
+        let days = [(1, 1), (2, 4), (6, 2), (10, 8), (15, 3)]
+            .into_iter()
+            .map(|(day, count)| DailyCount {
+                date: NaiveDate::from_ymd_opt(2024, 5, day).unwrap(),
+                count,
+                sources: None,
+            })
+            .collect();
+        (days, today)
+    }
+
+    // The checksums pin every pixel, rerender and compare by eye before updating them
+    #[test]
+    fn calendar_matches_golden_image() {
+        let (days, today) = fixed_days();
+        let pixmap = calendar_pixmap(&days, today, 3, 10);
+
+        assert_eq!((pixmap.width(), pixmap.height()), (3 * 12 + 2, 7 * 12 + 2));
+        assert_eq!(png_dimensions(&encode(&pixmap).unwrap()), (38, 86));
+        assert_eq!(
+            checksum(&pixmap),
+            "7c7aa0bbacc067c132bc9739dd93d26dbce30373a6fb77515735c5cdd77263b4"
+        );
+    }
+
+    #[test]
+    fn badge_matches_golden_image() {
+        let pixmap = badge_pixmap("events", "1234", 2);
+
+        assert_eq!((pixmap.width(), pixmap.height()), ((47 + 35) * 2, 40));
+        assert_eq!(png_dimensions(&encode(&pixmap).unwrap()), (164, 40));
+        assert_eq!(
+            checksum(&pixmap),
+            "b7493ea065f3e3da921f90c684e496eb33ac67ed347e8c625bb501d749b35ac7"
+        );
+    }
+
+    #[get("/badge.png")]
+    fn badge() -> Result<Png, String> {
+        Png::new(&badge_pixmap("events", "42", 1))
+    }
+
+    #[tokio::test]
+    async fn png_is_served_with_content_type_and_cache_headers() {
+        let client = rocket::local::asynchronous::Client::untracked(rocket::build().mount("/", routes![badge]))
+            .await
+            .unwrap();
+
+        let response = client.get("/badge.png").dispatch().await;
+        assert_eq!(response.content_type(), Some(rocket::http::ContentType::PNG));
+        assert_eq!(response.headers().get_one("Cache-Control"), Some(CACHE_CONTROL));
+        let body = response.into_bytes().await.unwrap();
+        assert_eq!(png_dimensions(&body), (47 + 23, 20));
+    }
+
+    #[test]
+    fn dimensions_are_capped() {
+        let (days, today) = fixed_days();
+        let pixmap = calendar_pixmap(&days, today, 1000, 1000);
+        let gap = MAX_CALENDAR_CELL / 5;
+        assert_eq!(pixmap.width(), MAX_CALENDAR_WEEKS * (MAX_CALENDAR_CELL + gap) + gap);
+
+        assert_eq!(badge_pixmap("events", "1", 1000).height(), BADGE_HEIGHT * MAX_BADGE_SCALE);
+    }
+
+    #[test]
+    fn calendar_starts_on_a_monday() {
+        let today = NaiveDate::from_ymd_opt(2024, 5, 15).unwrap();
+        assert_eq!(calendar_start(today, 1), NaiveDate::from_ymd_opt(2024, 5, 13).unwrap());
+        assert_eq!(calendar_start(today, 3), NaiveDate::from_ymd_opt(2024, 4, 29).unwrap());
+    }
+
+    #[test]
+    fn busiest_day_gets_the_darkest_level() {
+        assert_eq!(level(0, 8), 0);
+        assert_eq!(level(1, 8), 1);
+        assert_eq!(level(4, 8), 2);
+        assert_eq!(level(8, 8), 4);
+    }
+
+    #[test]
+    fn badge_characters_have_glyphs() {
+        for character in "events commits 0123456789".chars() {
+            assert!(glyph(character).is_some(), "{}", character);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::{fmt, time::Duration};
+
+use chrono::NaiveDate;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    queries::GitEvents, stats::DailyCount, sync_runs::SyncRun, HealthResponse, VersionResponse,
+};
+
+static REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, PartialEq)]
+pub enum ClientError {
+    Request(String),
+    Status(u16, String),
+    Decode(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Request(err) => write!(f, "request failed: {}", err),
+            ClientError::Status(status, body) => write!(f, "got {}: {}", status, body),
+            ClientError::Decode(err) => write!(f, "unable to decode response: {}", err),
+        }
+    }
+}
+
+/// Typed client for the pollux API, deserializing into the same structs the routes serialize
+pub struct PolluxClient {
+    http: reqwest::Client,
+    base_url: String,
+    admin_token: Option<String>,
+}
+
+impl PolluxClient {
+    pub fn new(base_url: &str, admin_token: Option<String>) -> PolluxClient {
+        PolluxClient {
+            http: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("Unable to build http client!"),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            admin_token,
+        }
+    }
+
+    pub fn has_admin_token(&self) -> bool {
+        self.admin_token.is_some()
+    }
+
+    /// Fetches `path` and decodes the body, accepting the given non-2xx statuses as well
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        admin: bool,
+        accepted: &[StatusCode],
+    ) -> Result<T, ClientError> {
+        let mut request = self.http.get(format!("{}{}", self.base_url, path));
+        if let (true, Some(token)) = (admin, &self.admin_token) {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|err| ClientError::Request(err.to_string()))?;
+        let status = response.status();
+        let payload = response
+            .text()
+            .await
+            .map_err(|err| ClientError::Request(err.to_string()))?;
+
+        if !status.is_success() && !accepted.contains(&status) {
+            return Err(ClientError::Status(status.as_u16(), payload));
+        }
+        serde_json::from_str(&payload).map_err(|err| ClientError::Decode(err.to_string()))
+    }
+
+    /// A degraded instance answers with 503, but still reports its checks
+    pub async fn health(&self) -> Result<HealthResponse, ClientError> {
+        self.get("/health", false, &[StatusCode::SERVICE_UNAVAILABLE]).await
+    }
+
+    pub async fn version(&self) -> Result<VersionResponse, ClientError> {
+        self.get("/api/v1/version", false, &[]).await
+    }
+
+    pub async fn sync_runs(&self, limit: u32) -> Result<Vec<SyncRun>, ClientError> {
+        self.get(&format!("/api/v1/sync/runs?limit={}", limit), false, &[]).await
+    }
+
+    pub async fn git_events(&self, since: NaiveDate) -> Result<Vec<GitEvents>, ClientError> {
+        self.get(&format!("/api/v1/git-events?since={}", since), false, &[]).await
+    }
+
+    pub async fn daily_stats(&self, since: NaiveDate) -> Result<Vec<DailyCount>, ClientError> {
+        self.get(&format!("/api/v1/stats/daily?since={}", since), false, &[]).await
+    }
+
+    /// Returns the platforms a force-sync would sync, without syncing anything
+    pub async fn force_sync_dry_run(&self) -> Result<Vec<String>, ClientError> {
+        self.get("/api/v1/force-sync?dry_run=true", true, &[]).await
+    }
+}
@@ -0,0 +1,19 @@
+use chrono::{DateTime, Utc};
+
+/// Source of the current time, managed as Rocket state so handlers can be tested with a pinned clock
+pub struct Clock(Box<dyn Fn() -> DateTime<Utc> + Send + Sync>);
+
+impl Clock {
+    pub fn system() -> Clock {
+        Clock(Box::new(Utc::now))
+    }
+
+    #[cfg(test)]
+    pub fn fixed(now: DateTime<Utc>) -> Clock {
+        Clock(Box::new(move || now))
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        (self.0)()
+    }
+}
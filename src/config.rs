@@ -0,0 +1,376 @@
+use std::fmt;
+
+use once_cell::sync::Lazy;
+
+use crate::{
+    admin::AdminConfig,
+    anonymize::AnonymizeConfig,
+    config_file::{ConfigFile, FileVars},
+    database::DatabaseConfig,
+    event_archive,
+    http_client::ClientConfig,
+    platform_registry::PlatformRegistry,
+    project_refresh,
+    rate_limit::RateLimitConfig,
+    retention::RetentionConfig,
+    sync_schedule,
+};
+
+/// Read once, `check` reports if it's malformed
+static FILE_VARS: Lazy<Result<FileVars, ConfigError>> = Lazy::new(ConfigFile::load);
+
+/// The env var `name`, or the value given for it in `pollux.toml`
+pub fn var(name: &str) -> Option<String> {
+    layered(name, std::env::var(name).ok(), FILE_VARS.as_ref().ok())
+}
+
+/// Env vars override the file, blank ones don't though, as in a `.env` copied from `.env.template`
+pub fn layered(name: &str, env: Option<String>, file: Option<&FileVars>) -> Option<String> {
+    match env {
+        Some(value) if !value.trim().is_empty() => Some(value),
+        value => file.and_then(|file| file.get(name).cloned()).or(value),
+    }
+}
+
+/// Every missing and invalid env var, so all of them can be fixed before the next start
+/// instead of one per restart
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigError {
+    missing: Vec<String>,
+    invalid: Vec<(String, String)>,
+    /// Problems which don't come down to a single variable
+    other: Vec<String>,
+}
+
+impl ConfigError {
+    pub fn other(message: impl Into<String>) -> ConfigError {
+        ConfigError {
+            other: vec![message.into()],
+            ..ConfigError::default()
+        }
+    }
+
+    pub fn invalid(name: &str, reason: impl fmt::Display) -> ConfigError {
+        let mut error = ConfigError::default();
+        error.add_invalid(name, reason);
+        error
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.invalid.is_empty() && self.other.is_empty()
+    }
+
+    fn add_missing(&mut self, name: &str) {
+        if !self.missing.iter().any(|missing| missing == name) {
+            self.missing.push(name.to_string());
+        }
+    }
+
+    fn add_invalid(&mut self, name: &str, reason: impl fmt::Display) {
+        if !self.invalid.iter().any(|(invalid, _)| invalid == name) {
+            self.invalid.push((name.to_string(), reason.to_string()));
+        }
+    }
+
+    /// Several parts of the config read the same variables, e.g. `POLLUX_PLATFORMS`,
+    /// each of them is still only reported once
+    pub fn merge(&mut self, other: ConfigError) {
+        for name in &other.missing {
+            self.add_missing(name);
+        }
+        for (name, reason) in &other.invalid {
+            self.add_invalid(name, reason);
+        }
+        for message in other.other {
+            if !self.other.contains(&message) {
+                self.other.push(message);
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration, see .env.template:")?;
+        for name in &self.missing {
+            write!(f, "\n  - {} is missing", name)?;
+        }
+        for (name, reason) in &self.invalid {
+            write!(f, "\n  - {} is invalid: {}", name, reason)?;
+        }
+        for message in &self.other {
+            write!(f, "\n  - {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where `EnvConfig` reads variables from, the process environment outside of tests
+type EnvSource = Box<dyn Fn(&str) -> Option<String>>;
+
+/// Reads the env vars of one part of the config, collecting what's missing or invalid
+/// instead of panicking on the first one. `build` hands out the config only if nothing was.
+pub struct EnvConfig {
+    env: EnvSource,
+    errors: ConfigError,
+}
+
+impl EnvConfig {
+    /// Including `pollux.toml`, see `var`
+    pub fn from_env() -> EnvConfig {
+        EnvConfig::from(var)
+    }
+
+    pub fn from(env: impl Fn(&str) -> Option<String> + 'static) -> EnvConfig {
+        EnvConfig {
+            env: Box::new(env),
+            errors: ConfigError::default(),
+        }
+    }
+
+    /// Blank values count as unset
+    pub fn optional(&self, name: &str) -> Option<String> {
+        (self.env)(name).filter(|value| !value.trim().is_empty())
+    }
+
+    /// An empty placeholder if `name` isn't set, it's dropped by `build` anyway
+    pub fn required(&mut self, name: &str) -> String {
+        self.optional(name).unwrap_or_else(|| {
+            self.errors.add_missing(name);
+            String::new()
+        })
+    }
+
+    /// Secrets can be mounted as files instead, `<NAME>_FILE` points at one then and takes precedence.
+    /// Its contents are trimmed, an unreadable or empty file is reported.
+    pub fn optional_secret(&mut self, name: &str) -> Option<String> {
+        let file_var = format!("{}_FILE", name);
+        let Some(path) = self.optional(&file_var) else {
+            return self.optional(name);
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(secret) if !secret.trim().is_empty() => Some(secret.trim().to_string()),
+            Ok(_) => {
+                self.errors.add_invalid(&file_var, format!("»{}« is empty", path));
+                None
+            }
+            Err(err) => {
+                self.errors.add_invalid(&file_var, format!("Unable to read »{}«: {}", path, err));
+                None
+            }
+        }
+    }
+
+    /// `required`, but read from `<NAME>_FILE` if that's set, see `optional_secret`
+    pub fn secret(&mut self, name: &str) -> String {
+        let from_file = self.optional(&format!("{}_FILE", name)).is_some();
+        self.optional_secret(name).unwrap_or_else(|| {
+            // An unusable file is reported already
+            if !from_file {
+                self.errors.add_missing(name);
+            }
+            String::new()
+        })
+    }
+
+    /// `fallback` if `name` isn't set, the invalid value is reported otherwise
+    pub fn parsed<T: std::str::FromStr>(&mut self, name: &str, fallback: T) -> T
+    where
+        T::Err: fmt::Display,
+    {
+        let Some(input) = self.optional(name) else {
+            return fallback;
+        };
+        input.trim().parse().unwrap_or_else(|err| {
+            self.errors.add_invalid(name, format!("»{}« {}", input, err));
+            fallback
+        })
+    }
+
+    pub fn missing(&mut self, name: &str) {
+        self.errors.add_missing(name);
+    }
+
+    pub fn invalid(&mut self, name: &str, reason: impl fmt::Display) {
+        self.errors.add_invalid(name, reason);
+    }
+
+    pub fn build<T>(self, config: T) -> Result<T, ConfigError> {
+        if self.errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(self.errors)
+        }
+    }
+}
+
+/// Everything pollux needs from the environment before it starts, as checked by `pollux check-config`
+pub fn check() -> Result<PlatformRegistry, ConfigError> {
+    // Everything else would only report the variables missing because of it
+    if let Err(err) = FILE_VARS.as_ref() {
+        return Err(err.clone());
+    }
+
+    let database = DatabaseConfig::from_env();
+    let admin = AdminConfig::from_env();
+    let http = ClientConfig::from_env();
+    let retention = RetentionConfig::from_env();
+    let project_refresh = project_refresh::interval_from_env();
+    let event_archive = event_archive::retention_from_env();
+    let anonymize = AnonymizeConfig::from_env();
+    let rate_limit = RateLimitConfig::from_env();
+    let registry = PlatformRegistry::from_env();
+    // Only the registered platforms are scheduled
+    let schedule = match &registry {
+        Ok(registry) => sync_schedule::intervals_from_env(&registry.names()).map(|_| ()),
+        Err(_) => Ok(()),
+    };
+    match (database, admin, http, retention, project_refresh, event_archive, anonymize, rate_limit, registry, schedule) {
+        (Ok(_), Ok(_), Ok(_), Ok(_), Ok(_), Ok(_), Ok(_), Ok(_), Ok(registry), Ok(_)) => Ok(registry),
+        (database, admin, http, retention, project_refresh, event_archive, anonymize, rate_limit, registry, schedule) => {
+            let mut errors = ConfigError::default();
+            errors.merge(database.err().unwrap_or_default());
+            errors.merge(admin.err().unwrap_or_default());
+            errors.merge(http.err().unwrap_or_default());
+            errors.merge(retention.err().unwrap_or_default());
+            errors.merge(project_refresh.err().unwrap_or_default());
+            errors.merge(event_archive.err().unwrap_or_default());
+            errors.merge(anonymize.err().unwrap_or_default());
+            errors.merge(rate_limit.err().unwrap_or_default());
+            errors.merge(registry.err().unwrap_or_default());
+            errors.merge(schedule.err().unwrap_or_default());
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &'static [(&'static str, &'static str)]) -> EnvConfig {
+        EnvConfig::from(|name| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string()))
+    }
+
+    #[test]
+    fn every_missing_variable_is_named_once() {
+        let mut env = env(&[("MYSQL_USER", "pollux"), ("MYSQL_HOST", " ")]);
+        env.required("MYSQL_USER");
+        env.required("MYSQL_PASSWORD");
+        env.required("MYSQL_HOST");
+        env.required("MYSQL_PASSWORD");
+        env.required("MYSQL_DATABASE");
+
+        let message = env.build(()).unwrap_err().to_string();
+        for name in ["MYSQL_PASSWORD", "MYSQL_HOST", "MYSQL_DATABASE"] {
+            assert_eq!(message.matches(name).count(), 1, "{}", message);
+        }
+        assert!(!message.contains("MYSQL_USER"), "{}", message);
+    }
+
+    #[test]
+    fn invalid_values_are_reported_with_their_reason() {
+        let mut env = env(&[("MYSQL_PORT", "33o6")]);
+        assert_eq!(env.parsed("MYSQL_PORT", 3306u16), 3306);
+        assert_eq!(env.parsed("POLLUX_DB_RETRIES", 16), 16);
+
+        assert_eq!(
+            env.build(()).unwrap_err().to_string(),
+            "Invalid configuration, see .env.template:\n  - MYSQL_PORT is invalid: »33o6« invalid digit found in string"
+        );
+    }
+
+    #[test]
+    fn merged_errors_name_each_variable_once() {
+        let mut database = env(&[]);
+        database.required("MYSQL_HOST");
+        let mut platforms = env(&[]);
+        platforms.required("GITLAB_USER_ID");
+        platforms.required("MYSQL_HOST");
+
+        let mut errors = database.build(()).unwrap_err();
+        errors.merge(platforms.build(()).unwrap_err());
+        errors.merge(ConfigError::other("No platform is configured!"));
+        errors.merge(ConfigError::other("No platform is configured!"));
+
+        assert_eq!(
+            errors.to_string(),
+            "Invalid configuration, see .env.template:\n  - MYSQL_HOST is missing\n  - GITLAB_USER_ID is missing\n  - No platform is configured!"
+        );
+    }
+
+    #[test]
+    fn config_is_only_built_without_errors() {
+        let mut env = env(&[("GITLAB_API_TOKEN", "token")]);
+        let token = env.required("GITLAB_API_TOKEN");
+        assert_eq!(env.build(token), Ok("token".to_string()));
+    }
+
+    #[test]
+    fn env_vars_override_the_file_unless_blank() {
+        let file = FileVars::from([
+            ("MYSQL_HOST".to_string(), "db.internal".to_string()),
+            ("MYSQL_USER".to_string(), "pollux".to_string()),
+        ]);
+        let vars = [("MYSQL_HOST", "127.0.0.1"), ("MYSQL_USER", ""), ("MYSQL_DATABASE", "pollux")];
+        let var = |name: &str| {
+            let env = vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string());
+            layered(name, env, Some(&file))
+        };
+
+        assert_eq!(var("MYSQL_HOST").as_deref(), Some("127.0.0.1"));
+        assert_eq!(var("MYSQL_USER").as_deref(), Some("pollux"));
+        assert_eq!(var("MYSQL_DATABASE").as_deref(), Some("pollux"));
+        assert_eq!(var("MYSQL_PASSWORD"), None);
+        assert_eq!(layered("GITHUB_API_VERSION", Some(String::new()), None).as_deref(), Some(""));
+    }
+
+    fn secret_env(token: &str, file: &std::path::Path) -> EnvConfig {
+        let vars = [
+            ("GITLAB_API_TOKEN".to_string(), token.to_string()),
+            ("GITLAB_API_TOKEN_FILE".to_string(), file.display().to_string()),
+        ];
+        EnvConfig::from(move |name| vars.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone()))
+    }
+
+    #[test]
+    fn secret_file_takes_precedence_and_is_trimmed() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("gitlab_token");
+        std::fs::write(&file, "from-file\n").unwrap();
+
+        let mut env = secret_env("from-env", &file);
+        let token = env.secret("GITLAB_API_TOKEN");
+        assert_eq!(env.build(token), Ok("from-file".to_string()));
+
+        let mut env = self::env(&[("GITLAB_API_TOKEN", "from-env")]);
+        let token = env.secret("GITLAB_API_TOKEN");
+        assert_eq!(env.build(token), Ok("from-env".to_string()));
+    }
+
+    #[test]
+    fn unreadable_secret_file_is_reported_instead_of_the_variable() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("missing");
+
+        let mut env = secret_env("from-env", &file);
+        env.secret("GITLAB_API_TOKEN");
+        let message = env.build(()).unwrap_err().to_string();
+        assert!(message.contains("GITLAB_API_TOKEN_FILE is invalid: Unable to read »"), "{}", message);
+        assert!(!message.contains("is missing"), "{}", message);
+    }
+
+    #[test]
+    fn empty_secret_file_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("empty");
+        std::fs::write(&file, "\n").unwrap();
+
+        let mut env = secret_env("", &file);
+        assert_eq!(env.optional_secret("GITLAB_API_TOKEN"), None);
+        let message = env.build(()).unwrap_err().to_string();
+        assert!(message.contains("GITLAB_API_TOKEN_FILE is invalid: »"), "{}", message);
+        assert!(message.ends_with("« is empty"), "{}", message);
+    }
+}
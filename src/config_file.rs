@@ -0,0 +1,411 @@
+use std::{collections::HashMap, fmt::Display, path::Path};
+
+use serde::Deserialize;
+
+use crate::config::ConfigError;
+
+static DEFAULT_CONFIG_PATH: &str = "pollux.toml";
+
+/// Env var names with the values given for them in the config file
+pub type FileVars = HashMap<String, String>;
+
+/// `pollux.toml`, every setting in it has an env var of its own which overrides it.
+/// That way existing deployments configured by env vars only keep working unchanged.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub database: DatabaseSection,
+    pub github: GithubSection,
+    pub gitlab: GitlabSection,
+    pub gitea: GiteaSection,
+    pub codeberg: CodebergSection,
+    pub bitbucket: BitbucketSection,
+    pub sourcehut: SourcehutSection,
+    pub azure_devops: AzureDevopsSection,
+    pub local: LocalSection,
+    pub mock: MockSection,
+    pub sync: SyncSection,
+    pub api: ApiSection,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSection {
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub password_file: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub database: Option<String>,
+    /// Replica the API reads from
+    pub read_host: Option<String>,
+    pub read_port: Option<u16>,
+    pub retries: Option<u32>,
+    pub max_connections: Option<u32>,
+    pub min_connections: Option<u32>,
+    pub acquire_timeout_seconds: Option<u64>,
+    /// `0` keeps idle connections open
+    pub idle_timeout_seconds: Option<u64>,
+    /// `0` never recycles connections
+    pub max_lifetime_seconds: Option<u64>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GithubSection {
+    pub username: Option<String>,
+    pub api_token: Option<String>,
+    pub api_token_file: Option<String>,
+    pub orgs: Option<Vec<String>>,
+    pub api_base_url: Option<String>,
+    pub web_base_url: Option<String>,
+    pub api_version: Option<String>,
+    pub app_id: Option<String>,
+    pub app_installation_id: Option<String>,
+    pub app_private_key_file: Option<String>,
+    pub per_page: Option<u32>,
+    pub sync_overlap_minutes: Option<i64>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitlabSection {
+    pub api_token: Option<String>,
+    pub api_token_file: Option<String>,
+    pub user_id: Option<String>,
+    pub base_url: Option<String>,
+    pub groups: Option<Vec<String>>,
+    pub include_visibilities: Option<Vec<String>>,
+    pub per_page: Option<u32>,
+    pub sync_overlap_days: Option<i64>,
+    pub danger_accept_invalid_certs: Option<bool>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GiteaSection {
+    pub base_url: Option<String>,
+    pub api_token: Option<String>,
+    pub api_token_file: Option<String>,
+    pub username: Option<String>,
+    pub include_visibilities: Option<Vec<String>>,
+    pub per_page: Option<u32>,
+    pub sync_overlap_minutes: Option<i64>,
+    pub danger_accept_invalid_certs: Option<bool>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CodebergSection {
+    pub api_token: Option<String>,
+    pub api_token_file: Option<String>,
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BitbucketSection {
+    pub username: Option<String>,
+    pub app_password: Option<String>,
+    pub app_password_file: Option<String>,
+    pub access_token: Option<String>,
+    pub access_token_file: Option<String>,
+    pub workspaces: Option<Vec<String>>,
+    pub include_visibilities: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourcehutSection {
+    pub token: Option<String>,
+    pub token_file: Option<String>,
+    pub username: Option<String>,
+    pub git_url: Option<String>,
+    pub include_visibilities: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AzureDevopsSection {
+    pub org: Option<String>,
+    pub pat: Option<String>,
+    pub pat_file: Option<String>,
+    pub user_email: Option<String>,
+    pub projects: Option<Vec<String>>,
+    pub include_visibilities: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LocalSection {
+    pub repo_paths: Option<Vec<String>>,
+    pub author_emails: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MockSection {
+    pub enabled: Option<bool>,
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyncSection {
+    pub platforms: Option<Vec<String>>,
+    pub resync_timeout_hours: Option<u64>,
+    pub runs_retention: Option<u32>,
+    pub event_archive_retention_days: Option<u32>,
+    pub retention_days: Option<u32>,
+    pub retention_rollup: Option<bool>,
+    pub retention_prune_projects: Option<bool>,
+    pub project_refresh_days: Option<u32>,
+    pub max_pages: Option<u32>,
+    pub fail_fast: Option<bool>,
+    pub project_allowlist: Option<Vec<String>>,
+    pub project_denylist: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiSection {
+    pub admin_token: Option<String>,
+    pub admin_token_file: Option<String>,
+    pub enable_dev_mode: Option<bool>,
+    pub private_events: Option<String>,
+    pub pseudonym_secret: Option<String>,
+    pub anonymize_projects: Option<bool>,
+    pub rate_limit_per_minute: Option<u32>,
+    pub rate_limit_burst: Option<u32>,
+    pub trust_proxy: Option<bool>,
+    pub user_agent: Option<String>,
+    pub extra_ca_cert_file: Option<String>,
+}
+
+impl ConfigFile {
+    /// `POLLUX_CONFIG`, or `./pollux.toml` if that exists. Only a missing default file is fine.
+    pub fn load() -> Result<FileVars, ConfigError> {
+        match std::env::var("POLLUX_CONFIG") {
+            Ok(path) if !path.trim().is_empty() => {
+                let input = std::fs::read_to_string(&path).map_err(|err| {
+                    ConfigError::invalid("POLLUX_CONFIG", format!("Unable to read »{}«: {}", path, err))
+                })?;
+                ConfigFile::parse(&path, &input).map(ConfigFile::into_vars)
+            }
+            _ if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                let input = std::fs::read_to_string(DEFAULT_CONFIG_PATH).map_err(|err| {
+                    ConfigError::other(format!("Unable to read {}: {}", DEFAULT_CONFIG_PATH, err))
+                })?;
+                ConfigFile::parse(DEFAULT_CONFIG_PATH, &input).map(ConfigFile::into_vars)
+            }
+            _ => Ok(FileVars::new()),
+        }
+    }
+
+    /// The TOML error already points at the offending line and key
+    pub fn parse(path: &str, input: &str) -> Result<ConfigFile, ConfigError> {
+        toml::from_str(input).map_err(|err| ConfigError::other(format!("{} is malformed: {}", path, err)))
+    }
+
+    pub fn into_vars(self) -> FileVars {
+        let mut vars = Vars::default();
+
+        let database = self.database;
+        vars.set("MYSQL_USER", database.user);
+        vars.set("MYSQL_PASSWORD", database.password);
+        vars.set("MYSQL_PASSWORD_FILE", database.password_file);
+        vars.set("MYSQL_HOST", database.host);
+        vars.set("MYSQL_PORT", database.port);
+        vars.set("MYSQL_DATABASE", database.database);
+        vars.set("MYSQL_READ_HOST", database.read_host);
+        vars.set("MYSQL_READ_PORT", database.read_port);
+        vars.set("POLLUX_DB_RETRIES", database.retries);
+        vars.set("POLLUX_DB_MAX_CONNECTIONS", database.max_connections);
+        vars.set("POLLUX_DB_MIN_CONNECTIONS", database.min_connections);
+        vars.set("POLLUX_DB_ACQUIRE_TIMEOUT_SECONDS", database.acquire_timeout_seconds);
+        vars.set("POLLUX_DB_IDLE_TIMEOUT_SECONDS", database.idle_timeout_seconds);
+        vars.set("POLLUX_DB_MAX_LIFETIME_SECONDS", database.max_lifetime_seconds);
+
+        let github = self.github;
+        vars.set("GITHUB_USERNAME", github.username);
+        vars.set("GITHUB_API_TOKEN", github.api_token);
+        vars.set("GITHUB_API_TOKEN_FILE", github.api_token_file);
+        vars.set_list("GITHUB_ORGS", github.orgs);
+        vars.set("GITHUB_API_BASE_URL", github.api_base_url);
+        vars.set("GITHUB_WEB_BASE_URL", github.web_base_url);
+        vars.set("GITHUB_API_VERSION", github.api_version);
+        vars.set("GITHUB_APP_ID", github.app_id);
+        vars.set("GITHUB_APP_INSTALLATION_ID", github.app_installation_id);
+        vars.set("GITHUB_APP_PRIVATE_KEY_FILE", github.app_private_key_file);
+        vars.set("POLLUX_GITHUB_PER_PAGE", github.per_page);
+        vars.set("POLLUX_GITHUB_SYNC_OVERLAP_MINUTES", github.sync_overlap_minutes);
+
+        let gitlab = self.gitlab;
+        vars.set("GITLAB_API_TOKEN", gitlab.api_token);
+        vars.set("GITLAB_API_TOKEN_FILE", gitlab.api_token_file);
+        vars.set("GITLAB_USER_ID", gitlab.user_id);
+        vars.set("GITLAB_BASE_URL", gitlab.base_url);
+        vars.set_list("GITLAB_GROUPS", gitlab.groups);
+        vars.set_list("POLLUX_GITLAB_INCLUDE_VISIBILITIES", gitlab.include_visibilities);
+        vars.set("POLLUX_GITLAB_PER_PAGE", gitlab.per_page);
+        vars.set("POLLUX_GITLAB_SYNC_OVERLAP_DAYS", gitlab.sync_overlap_days);
+        vars.set("POLLUX_GITLAB_DANGER_ACCEPT_INVALID_CERTS", gitlab.danger_accept_invalid_certs);
+
+        let gitea = self.gitea;
+        vars.set("GITEA_BASE_URL", gitea.base_url);
+        vars.set("GITEA_API_TOKEN", gitea.api_token);
+        vars.set("GITEA_API_TOKEN_FILE", gitea.api_token_file);
+        vars.set("GITEA_USERNAME", gitea.username);
+        vars.set_list("POLLUX_GITEA_INCLUDE_VISIBILITIES", gitea.include_visibilities);
+        vars.set("POLLUX_GITEA_PER_PAGE", gitea.per_page);
+        vars.set("POLLUX_GITEA_SYNC_OVERLAP_MINUTES", gitea.sync_overlap_minutes);
+        vars.set("POLLUX_GITEA_DANGER_ACCEPT_INVALID_CERTS", gitea.danger_accept_invalid_certs);
+
+        let codeberg = self.codeberg;
+        vars.set("CODEBERG_API_TOKEN", codeberg.api_token);
+        vars.set("CODEBERG_API_TOKEN_FILE", codeberg.api_token_file);
+        vars.set("CODEBERG_USERNAME", codeberg.username);
+
+        let bitbucket = self.bitbucket;
+        vars.set("BITBUCKET_USERNAME", bitbucket.username);
+        vars.set("BITBUCKET_APP_PASSWORD", bitbucket.app_password);
+        vars.set("BITBUCKET_APP_PASSWORD_FILE", bitbucket.app_password_file);
+        vars.set("BITBUCKET_ACCESS_TOKEN", bitbucket.access_token);
+        vars.set("BITBUCKET_ACCESS_TOKEN_FILE", bitbucket.access_token_file);
+        vars.set_list("BITBUCKET_WORKSPACES", bitbucket.workspaces);
+        vars.set_list("POLLUX_BITBUCKET_INCLUDE_VISIBILITIES", bitbucket.include_visibilities);
+
+        let sourcehut = self.sourcehut;
+        vars.set("SOURCEHUT_TOKEN", sourcehut.token);
+        vars.set("SOURCEHUT_TOKEN_FILE", sourcehut.token_file);
+        vars.set("SOURCEHUT_USERNAME", sourcehut.username);
+        vars.set("SOURCEHUT_GIT_URL", sourcehut.git_url);
+        vars.set_list("POLLUX_SOURCEHUT_INCLUDE_VISIBILITIES", sourcehut.include_visibilities);
+
+        let azure_devops = self.azure_devops;
+        vars.set("AZDO_ORG", azure_devops.org);
+        vars.set("AZDO_PAT", azure_devops.pat);
+        vars.set("AZDO_PAT_FILE", azure_devops.pat_file);
+        vars.set("AZDO_USER_EMAIL", azure_devops.user_email);
+        vars.set_list("AZDO_PROJECTS", azure_devops.projects);
+        vars.set_list("POLLUX_AZDO_INCLUDE_VISIBILITIES", azure_devops.include_visibilities);
+
+        let local = self.local;
+        vars.set_list("POLLUX_LOCAL_REPO_PATHS", local.repo_paths);
+        vars.set_list("POLLUX_LOCAL_AUTHOR_EMAILS", local.author_emails);
+
+        let mock = self.mock;
+        vars.set("POLLUX_ENABLE_MOCK_PLATFORM", mock.enabled);
+        vars.set("POLLUX_MOCK_PLATFORM_SEED", mock.seed);
+
+        let sync = self.sync;
+        vars.set_list("POLLUX_PLATFORMS", sync.platforms);
+        vars.set("POLLUX_RESYNC_TIMEOUT_HOURS", sync.resync_timeout_hours);
+        vars.set("POLLUX_SYNC_RUNS_RETENTION", sync.runs_retention);
+        vars.set("POLLUX_EVENT_ARCHIVE_RETENTION_DAYS", sync.event_archive_retention_days);
+        vars.set("POLLUX_RETENTION_DAYS", sync.retention_days);
+        vars.set("POLLUX_RETENTION_ROLLUP", sync.retention_rollup);
+        vars.set("POLLUX_RETENTION_PRUNE_PROJECTS", sync.retention_prune_projects);
+        vars.set("POLLUX_PROJECT_REFRESH_DAYS", sync.project_refresh_days);
+        vars.set("POLLUX_MAX_PAGES", sync.max_pages);
+        vars.set("POLLUX_FAIL_FAST", sync.fail_fast);
+        vars.set_list("POLLUX_PROJECT_ALLOWLIST", sync.project_allowlist);
+        vars.set_list("POLLUX_PROJECT_DENYLIST", sync.project_denylist);
+
+        let api = self.api;
+        vars.set("POLLUX_ADMIN_TOKEN", api.admin_token);
+        vars.set("POLLUX_ADMIN_TOKEN_FILE", api.admin_token_file);
+        vars.set("POLLUX_ENABLE_DEV_MODE", api.enable_dev_mode);
+        vars.set("POLLUX_PRIVATE_EVENTS", api.private_events);
+        vars.set("POLLUX_PSEUDONYM_SECRET", api.pseudonym_secret);
+        vars.set("POLLUX_ANONYMIZE_PROJECTS", api.anonymize_projects);
+        vars.set("POLLUX_RATE_LIMIT_PER_MINUTE", api.rate_limit_per_minute);
+        vars.set("POLLUX_RATE_LIMIT_BURST", api.rate_limit_burst);
+        vars.set("POLLUX_TRUST_PROXY", api.trust_proxy);
+        vars.set("POLLUX_USER_AGENT", api.user_agent);
+        vars.set("POLLUX_EXTRA_CA_CERT_FILE", api.extra_ca_cert_file);
+
+        vars.0
+    }
+}
+
+/// Settings are validated where they are used, for env vars and the file alike
+#[derive(Default)]
+struct Vars(FileVars);
+
+impl Vars {
+    fn set(&mut self, name: &str, value: Option<impl Display>) {
+        if let Some(value) = value {
+            self.0.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    /// Lists are comma separated as env vars
+    fn set_list(&mut self, name: &str, values: Option<Vec<String>>) {
+        self.set(name, values.map(|values| values.join(",")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_are_mapped_to_their_env_vars() {
+        let file = ConfigFile::parse(
+            "pollux.toml",
+            r#"
+            [database]
+            user = "pollux"
+            port = 3307
+
+            [gitlab]
+            groups = ["pollux", "2tefan"]
+
+            [api]
+            enable_dev_mode = true
+            "#,
+        )
+        .unwrap();
+
+        let vars = file.into_vars();
+        assert_eq!(vars.len(), 4);
+        assert_eq!(vars["MYSQL_USER"], "pollux");
+        assert_eq!(vars["MYSQL_PORT"], "3307");
+        assert_eq!(vars["GITLAB_GROUPS"], "pollux,2tefan");
+        assert_eq!(vars["POLLUX_ENABLE_DEV_MODE"], "true");
+    }
+
+    #[test]
+    fn template_is_valid() {
+        let template = include_str!("../pollux.toml.template");
+        let vars = ConfigFile::parse("pollux.toml.template", template).unwrap().into_vars();
+        assert_eq!(vars["POLLUX_PLATFORMS"], "github,gitlab");
+    }
+
+    #[test]
+    fn empty_file_sets_nothing() {
+        assert_eq!(ConfigFile::parse("pollux.toml", "").unwrap(), ConfigFile::default());
+    }
+
+    #[test]
+    fn malformed_file_is_reported_with_its_location() {
+        let message = ConfigFile::parse("/etc/pollux.toml", "[database]\nport = \"3306\"\n")
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("/etc/pollux.toml is malformed"), "{}", message);
+        assert!(message.contains("line 2"), "{}", message);
+        assert!(message.contains("port"), "{}", message);
+
+        let message = ConfigFile::parse("pollux.toml", "[database\n").unwrap_err().to_string();
+        assert!(message.contains("pollux.toml is malformed"), "{}", message);
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        let message = ConfigFile::parse("pollux.toml", "[gitlab]\ntoken = \"secret\"\n")
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("unknown field `token`"), "{}", message);
+    }
+}
@@ -0,0 +1,261 @@
+use chrono::{NaiveDate, Utc};
+use log::{error, info, warn};
+use once_cell::sync::OnceCell;
+use reqwest::header::HeaderMap;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{config, platform_registry::PlatformRegistry, retry::env_parse};
+
+/// Results of the startup validation, reported by `/readyz`
+static CHECKS: OnceCell<Vec<CredentialCheck>> = OnceCell::new();
+static FALLBACK_EXPIRY_WARNING_DAYS: i64 = 14;
+
+/// Who a token belongs to, as far as the platform tells
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenInfo {
+    pub username: String,
+    /// `None` if the platform doesn't report them, e.g. for fine-grained tokens
+    pub scopes: Option<Vec<String>>,
+    pub expires_at: Option<String>,
+}
+
+impl TokenInfo {
+    /// Days left until `expires_at`, negative once it expired. Only the date is looked at,
+    /// GitLab reports a date while GitHub includes the time.
+    pub fn expires_in_days(&self, today: NaiveDate) -> Option<i64> {
+        let expires_on: NaiveDate = self.expires_at.as_deref()?.get(..10)?.parse().ok()?;
+        Some((expires_on - today).num_days())
+    }
+}
+
+/// A validated token as shown by `/sync/credentials`
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct CredentialStatus {
+    pub platform: &'static str,
+    pub env_var: String,
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// As reported by the platform, `null` if the token doesn't expire or it isn't known
+    pub expires_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_in_days: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Outcome of validating the credentials of one account
+#[derive(Debug, Clone, PartialEq)]
+pub struct CredentialCheck {
+    pub platform: &'static str,
+    /// Env var(s) to fix if the check failed
+    pub env_var: String,
+    pub outcome: Result<TokenInfo, String>,
+}
+
+impl CredentialCheck {
+    pub fn log(&self, today: NaiveDate, warning_days: i64) {
+        match &self.outcome {
+            Ok(info) => {
+                let scopes = match &info.scopes {
+                    Some(scopes) if scopes.is_empty() => "none".to_string(),
+                    Some(scopes) => scopes.join(", "),
+                    None => "not reported".to_string(),
+                };
+                info!(
+                    "Authenticated with {} as »{}« (scopes: {}, expires: {})",
+                    self.platform,
+                    info.username,
+                    scopes,
+                    info.expires_at.as_deref().unwrap_or("never")
+                );
+                if let Some(warning) = self.expiry_warning(today, warning_days) {
+                    warn!("{}", warning);
+                }
+            }
+            Err(err) => error!(
+                "Unable to authenticate with {}, please check {}! {}",
+                self.platform, self.env_var, err
+            ),
+        }
+    }
+
+    /// Set if the token expires within `warning_days`
+    pub fn expiry_warning(&self, today: NaiveDate, warning_days: i64) -> Option<String> {
+        let info = self.outcome.as_ref().ok()?;
+        let days = info.expires_in_days(today).filter(|days| *days <= warning_days)?;
+        Some(format!(
+            "The {} token expires in {} day(s) ({}), please renew {}!",
+            self.platform,
+            days,
+            info.expires_at.as_deref().unwrap_or_default(),
+            self.env_var
+        ))
+    }
+
+    pub fn status(&self, today: NaiveDate) -> CredentialStatus {
+        let info = self.outcome.as_ref().ok();
+        CredentialStatus {
+            platform: self.platform,
+            env_var: self.env_var.clone(),
+            valid: info.is_some(),
+            username: info.map(|info| info.username.clone()),
+            expires_at: info.and_then(|info| info.expires_at.clone()),
+            expires_in_days: info.and_then(|info| info.expires_in_days(today)),
+            error: self.outcome.as_ref().err().cloned(),
+        }
+    }
+
+    /// Shown by `/readyz`
+    pub fn failure(&self) -> Option<String> {
+        self.outcome
+            .as_ref()
+            .err()
+            .map(|err| format!("{} ({}): {}", self.platform, self.env_var, err))
+    }
+}
+
+/// `POLLUX_FAIL_FAST=true` exits if any credentials are invalid, instead of starting anyway
+pub fn fail_fast() -> bool {
+    let fail_fast = config::var("POLLUX_FAIL_FAST");
+    fail_fast.is_some_and(|fail_fast| fail_fast.eq_ignore_ascii_case("true"))
+}
+
+/// Validates the credentials of all platforms with one cheap request per account, logs and remembers the results
+pub async fn validate_all(registry: &PlatformRegistry) -> &'static [CredentialCheck] {
+    let checks = registry.check_credentials().await;
+
+    let warning_days = env_parse("POLLUX_TOKEN_EXPIRY_WARNING_DAYS", FALLBACK_EXPIRY_WARNING_DAYS);
+    let today = Utc::now().date_naive();
+    for check in &checks {
+        check.log(today, warning_days);
+    }
+    CHECKS.get_or_init(|| checks)
+}
+
+/// Empty until `validate_all` ran
+pub fn recorded() -> &'static [CredentialCheck] {
+    CHECKS.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+/// Comma-separated scopes, as in GitHub's `x-oauth-scopes`
+pub fn scopes_from_header(headers: &HeaderMap, name: &str) -> Option<Vec<String>> {
+    let value = headers.get(name)?.to_str().ok()?;
+    Some(
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|scope| !scope.is_empty())
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+/// Failure message for a response which isn't a success, with the platform's explanation if there is one
+pub fn rejection(platform: &str, status: reqwest::StatusCode, payload: &str) -> String {
+    let message = serde_json::from_str::<serde_json::Value>(payload)
+        .ok()
+        .and_then(|body| body.get("message").and_then(|message| message.as_str().map(str::to_string)));
+    match message {
+        Some(message) => format!("{} answered {}: {}", platform, status, message),
+        None => format!("{} answered {}", platform, status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn check(outcome: Result<TokenInfo, String>) -> CredentialCheck {
+        CredentialCheck {
+            platform: "Github",
+            env_var: "GITHUB_API_TOKEN".to_string(),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn scopes_are_split() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(scopes_from_header(&headers, "x-oauth-scopes"), None);
+
+        headers.insert("x-oauth-scopes", HeaderValue::from_static("repo, read:org"));
+        assert_eq!(
+            scopes_from_header(&headers, "x-oauth-scopes"),
+            Some(vec!["repo".to_string(), "read:org".to_string()])
+        );
+        headers.insert("x-oauth-scopes", HeaderValue::from_static(""));
+        assert_eq!(scopes_from_header(&headers, "x-oauth-scopes"), Some(Vec::new()));
+    }
+
+    #[test]
+    fn failures_name_the_env_var() {
+        let info = TokenInfo {
+            username: "2tefan".to_string(),
+            scopes: None,
+            expires_at: None,
+        };
+        assert_eq!(check(Ok(info)).failure(), None);
+
+        let payload = r#"{"message": "Bad credentials"}"#;
+        let err = rejection("Github", reqwest::StatusCode::UNAUTHORIZED, payload);
+        assert_eq!(
+            check(Err(err)).failure().as_deref(),
+            Some("Github (GITHUB_API_TOKEN): Github answered 401 Unauthorized: Bad credentials")
+        );
+    }
+
+    #[test]
+    fn expiry_is_counted_in_days() {
+        let today = NaiveDate::from_ymd_opt(2024, 5, 20).unwrap();
+        let info = |expires_at: Option<&str>| TokenInfo {
+            username: "2tefan".to_string(),
+            scopes: None,
+            expires_at: expires_at.map(str::to_string),
+        };
+        assert_eq!(info(Some("2024-06-01")).expires_in_days(today), Some(12));
+        assert_eq!(info(Some("2024-05-21 00:00:00 UTC")).expires_in_days(today), Some(1));
+        assert_eq!(info(Some("2024-05-18T10:00:00+00:00")).expires_in_days(today), Some(-2));
+        assert_eq!(info(Some("soon")).expires_in_days(today), None);
+        assert_eq!(info(None).expires_in_days(today), None);
+
+        let expiring = check(Ok(info(Some("2024-06-01"))));
+        assert_eq!(expiring.expiry_warning(today, 11), None);
+        assert_eq!(
+            expiring.expiry_warning(today, 14).as_deref(),
+            Some("The Github token expires in 12 day(s) (2024-06-01), please renew GITHUB_API_TOKEN!")
+        );
+        assert_eq!(check(Ok(info(None))).expiry_warning(today, 14), None);
+        assert_eq!(check(Err("Bad credentials".to_string())).expiry_warning(today, 14), None);
+    }
+
+    #[test]
+    fn status_shows_the_expiry() {
+        let today = NaiveDate::from_ymd_opt(2024, 5, 20).unwrap();
+        let status = check(Ok(TokenInfo {
+            username: "2tefan".to_string(),
+            scopes: None,
+            expires_at: Some("2024-06-01".to_string()),
+        }))
+        .status(today);
+        assert_eq!(
+            serde_json::to_value(status).unwrap(),
+            serde_json::json!({
+                "platform": "Github", "env_var": "GITHUB_API_TOKEN", "valid": true,
+                "username": "2tefan", "expires_at": "2024-06-01", "expires_in_days": 12,
+            })
+        );
+
+        let status = check(Err("Bad credentials".to_string())).status(today);
+        assert_eq!(
+            serde_json::to_value(status).unwrap(),
+            serde_json::json!({
+                "platform": "Github", "env_var": "GITHUB_API_TOKEN", "valid": false,
+                "expires_at": null, "error": "Bad credentials",
+            })
+        );
+    }
+}
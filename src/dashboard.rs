@@ -0,0 +1,75 @@
+use rocket::{
+    http::ContentType,
+    response::content::RawHtml,
+    Route,
+};
+
+// Embedded, so deployments stay a single binary without a static directory
+static INDEX: &str = include_str!("dashboard/index.html");
+static APP: &str = include_str!("dashboard/app.js");
+static STYLE: &str = include_str!("dashboard/style.css");
+
+#[get("/")]
+fn index() -> RawHtml<&'static str> {
+    RawHtml(INDEX)
+}
+
+/// Same page as `/`, all its urls are relative so both resolve to the same assets and API
+#[get("/dashboard")]
+fn dashboard() -> RawHtml<&'static str> {
+    RawHtml(INDEX)
+}
+
+#[get("/dashboard/app.js")]
+fn app() -> (ContentType, &'static str) {
+    (ContentType::JavaScript, APP)
+}
+
+#[get("/dashboard/style.css")]
+fn style() -> (ContentType, &'static str) {
+    (ContentType::CSS, STYLE)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![index, dashboard, app, style]
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{http::Status, local::asynchronous::Client};
+
+    use super::*;
+
+    async fn client() -> Client {
+        Client::untracked(rocket::build().mount("/", routes())).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn dashboard_is_served_as_html() {
+        let client = client().await;
+
+        for path in ["/", "/dashboard"] {
+            let response = client.get(path).dispatch().await;
+            assert_eq!(response.status(), Status::Ok, "{}", path);
+            assert_eq!(response.content_type(), Some(ContentType::HTML), "{}", path);
+            assert!(response.into_string().await.unwrap().contains("<title>pollux</title>"));
+        }
+    }
+
+    #[tokio::test]
+    async fn assets_are_served_with_their_content_type() {
+        let client = client().await;
+
+        let response = client.get("/dashboard/app.js").dispatch().await;
+        assert_eq!(response.content_type(), Some(ContentType::JavaScript));
+        let response = client.get("/dashboard/style.css").dispatch().await;
+        assert_eq!(response.content_type(), Some(ContentType::CSS));
+    }
+
+    #[test]
+    fn assets_are_referenced_relatively() {
+        // Absolute urls would break behind a reverse proxy serving pollux under a sub path
+        assert!(!INDEX.contains("=\"/"));
+        assert!(!APP.contains("fetchJson(`/"));
+    }
+}
@@ -0,0 +1,121 @@
+"use strict";
+
+const API_KEY_STORAGE = "pollux.apiKey";
+const HEATMAP_DAYS = 365;
+const RECENT_EVENTS_DAYS = 14;
+const RECENT_EVENTS_LIMIT = 20;
+
+function daysAgo(days) {
+    const date = new Date();
+    date.setUTCDate(date.getUTCDate() - days);
+    return date.toISOString().slice(0, 10);
+}
+
+// Asks for a key once a request is rejected and keeps it in localStorage for the next visits
+async function fetchJson(path) {
+    for (let attempt = 0; attempt < 2; attempt++) {
+        const key = localStorage.getItem(API_KEY_STORAGE);
+        const headers = key ? { Authorization: `Bearer ${key}` } : {};
+        const response = await fetch(path, { headers });
+
+        if (response.status === 401 || response.status === 403) {
+            const entered = window.prompt("This pollux instance needs an API key:");
+            if (!entered) {
+                throw new Error(`${path} needs an API key`);
+            }
+            localStorage.setItem(API_KEY_STORAGE, entered.trim());
+            continue;
+        }
+        if (!response.ok) {
+            throw new Error(`${path} answered ${response.status}`);
+        }
+        return response.json();
+    }
+    throw new Error(`${path} rejected the API key`);
+}
+
+function level(count, max) {
+    if (count === 0) {
+        return 0;
+    }
+    return Math.min(4, Math.ceil((count / max) * 4));
+}
+
+function renderHeatmap(days) {
+    const heatmap = document.getElementById("heatmap");
+    const counts = new Map(days.map((day) => [day.date, day.count]));
+    const max = Math.max(1, ...days.map((day) => day.count));
+
+    // Start on a Monday, so every column is one week
+    const start = new Date(daysAgo(HEATMAP_DAYS) + "T00:00:00Z");
+    start.setUTCDate(start.getUTCDate() - ((start.getUTCDay() + 6) % 7));
+
+    const today = daysAgo(0);
+    let total = 0;
+    for (const date = start; date.toISOString().slice(0, 10) <= today; date.setUTCDate(date.getUTCDate() + 1)) {
+        const day = date.toISOString().slice(0, 10);
+        const count = counts.get(day) || 0;
+        total += count;
+
+        const cell = document.createElement("span");
+        cell.className = `level-${level(count, max)}`;
+        cell.title = `${day}: ${count}`;
+        heatmap.appendChild(cell);
+    }
+    document.getElementById("heatmap-summary").textContent = `${total} events`;
+}
+
+function renderPlatforms(buckets) {
+    const totals = new Map();
+    for (const bucket of buckets) {
+        for (const [platform, count] of Object.entries(bucket.counts)) {
+            totals.set(platform, (totals.get(platform) || 0) + count);
+        }
+    }
+
+    const body = document.querySelector("#platforms tbody");
+    for (const [platform, count] of totals) {
+        const row = body.insertRow();
+        row.insertCell().textContent = platform;
+        row.insertCell().textContent = count;
+    }
+}
+
+function renderRecentEvents(events) {
+    const list = document.getElementById("recent-events");
+    for (const event of events.slice(-RECENT_EVENTS_LIMIT).reverse()) {
+        const item = document.createElement("li");
+        const time = document.createElement("time");
+        time.dateTime = event.timestamp;
+        time.textContent = new Date(event.timestamp).toLocaleString();
+        item.append(time, ` ${event.action} `, event.project_name || "unknown project", ` (${event.platform || "?"})`);
+        list.appendChild(item);
+    }
+}
+
+async function load() {
+    const forgetKey = document.getElementById("forget-key");
+    forgetKey.hidden = !localStorage.getItem(API_KEY_STORAGE);
+    forgetKey.addEventListener("click", () => {
+        localStorage.removeItem(API_KEY_STORAGE);
+        forgetKey.hidden = true;
+    });
+
+    try {
+        const [days, buckets, events] = await Promise.all([
+            fetchJson(`api/v1/stats/daily?since=${daysAgo(HEATMAP_DAYS)}`),
+            fetchJson(`api/v1/stats/platforms/timeseries?since=${daysAgo(HEATMAP_DAYS)}&granularity=month`),
+            fetchJson(`api/v1/git-events?since=${daysAgo(RECENT_EVENTS_DAYS)}`),
+        ]);
+        renderHeatmap(days);
+        renderPlatforms(buckets);
+        renderRecentEvents(events);
+    } catch (err) {
+        const error = document.getElementById("error");
+        error.textContent = `Couldn't load the dashboard: ${err.message}`;
+        error.hidden = false;
+    }
+    forgetKey.hidden = !localStorage.getItem(API_KEY_STORAGE);
+}
+
+document.addEventListener("DOMContentLoaded", load);
@@ -1,96 +1,250 @@
 
-use std::{thread::sleep, time::{Duration, Instant}};
+use std::{fmt, future::Future, sync::atomic::{AtomicBool, Ordering}, time::Duration};
 
 use log::{error, warn};
-use sqlx::{mysql::{MySqlConnectOptions, MySqlPoolOptions}, MySql, MySqlPool, Pool};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::{mysql::{MySqlConnectOptions, MySqlPoolOptions}, Executor, MySql, MySqlPool, Pool};
 use tokio::sync::OnceCell;
 
-static FALLBACK_DB_RETRIES: i32 = 16;
+use crate::config::{self, ConfigError, EnvConfig};
+
+static FALLBACK_DB_RETRIES: u32 = 16;
 static FALLBACK_MYSQL_PORT: u16 = 3306;
+static HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+// sqlx's own defaults
+static FALLBACK_DB_MAX_CONNECTIONS: u32 = 10;
+static FALLBACK_DB_MIN_CONNECTIONS: u32 = 0;
+static FALLBACK_DB_ACQUIRE_TIMEOUT_SECONDS: u64 = 30;
+static FALLBACK_DB_IDLE_TIMEOUT_SECONDS: u64 = 600;
+static FALLBACK_DB_MAX_LIFETIME_SECONDS: u64 = 1800;
+/// Waited after the first failed attempt to connect, doubled after every further one
+static RETRY_BASE_DELAY: Duration = Duration::from_millis(125);
+static RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
 
 pub static DATABASE: OnceCell<Database> = OnceCell::const_new();
+/// Set once the migrations ran through on the global pool
+static MIGRATIONS_APPLIED: AtomicBool = AtomicBool::new(false);
+
+/// Where the database is and how to log in
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseConfig {
+    pub user: String,
+    pub password: String,
+    pub host: String,
+    pub port: u16,
+    pub database: String,
+    pub pool: PoolConfig,
+    /// Replica the API reads from, `None` if everything goes to `host`
+    pub read_host: Option<String>,
+    pub read_port: u16,
+}
+
+/// Size and timeouts of the connection pool
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    /// Kept open even while idle
+    pub min_connections: u32,
+    /// How long a query waits for a free connection
+    pub acquire_timeout: Duration,
+    /// `None` if idle connections are never closed (`0`)
+    pub idle_timeout: Option<Duration>,
+    /// `None` if connections are never recycled (`0`)
+    pub max_lifetime: Option<Duration>,
+}
+
+impl PoolConfig {
+    fn from(env: &mut EnvConfig) -> PoolConfig {
+        let max_connections = env.parsed("POLLUX_DB_MAX_CONNECTIONS", FALLBACK_DB_MAX_CONNECTIONS);
+        if max_connections == 0 {
+            env.invalid("POLLUX_DB_MAX_CONNECTIONS", "has to be at least 1");
+        }
+        let min_connections = env.parsed("POLLUX_DB_MIN_CONNECTIONS", FALLBACK_DB_MIN_CONNECTIONS);
+        if min_connections > max_connections {
+            env.invalid(
+                "POLLUX_DB_MIN_CONNECTIONS",
+                format!("»{}« is more than POLLUX_DB_MAX_CONNECTIONS ({})", min_connections, max_connections),
+            );
+        }
+        let acquire_timeout = env.parsed("POLLUX_DB_ACQUIRE_TIMEOUT_SECONDS", FALLBACK_DB_ACQUIRE_TIMEOUT_SECONDS);
+        if acquire_timeout == 0 {
+            env.invalid("POLLUX_DB_ACQUIRE_TIMEOUT_SECONDS", "has to be at least 1");
+        }
+        let unless_zero = |seconds: u64| (seconds > 0).then(|| Duration::from_secs(seconds));
+
+        PoolConfig {
+            max_connections,
+            min_connections,
+            acquire_timeout: Duration::from_secs(acquire_timeout),
+            idle_timeout: unless_zero(env.parsed("POLLUX_DB_IDLE_TIMEOUT_SECONDS", FALLBACK_DB_IDLE_TIMEOUT_SECONDS)),
+            max_lifetime: unless_zero(env.parsed("POLLUX_DB_MAX_LIFETIME_SECONDS", FALLBACK_DB_MAX_LIFETIME_SECONDS)),
+        }
+    }
+
+    fn options(&self) -> MySqlPoolOptions {
+        MySqlPoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(self.acquire_timeout)
+            .idle_timeout(self.idle_timeout)
+            .max_lifetime(self.max_lifetime)
+    }
+}
+
+/// Reported by `/health`, there is no metrics endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PoolStats {
+    /// Open connections, idle or in use
+    pub size: u32,
+    pub idle: u32,
+    pub max_connections: u32,
+}
+
+pub fn pool_stats(pool: &Pool<MySql>) -> PoolStats {
+    PoolStats {
+        size: pool.size(),
+        idle: pool.num_idle().try_into().unwrap_or(u32::MAX),
+        max_connections: pool.options().get_max_connections(),
+    }
+}
+
+/// Refuses writes on every connection, so a write slipping onto the replica fails instead of diverging it
+fn read_only(options: MySqlPoolOptions) -> MySqlPoolOptions {
+    options.after_connect(|conn, _meta| {
+        Box::pin(async move {
+            conn.execute("SET SESSION TRANSACTION READ ONLY").await?;
+            Ok(())
+        })
+    })
+}
+
+/// Waited before retry number `retry` (starting at 1)
+fn retry_delay(retry: u32) -> Duration {
+    RETRY_BASE_DELAY.saturating_mul(2u32.saturating_pow(retry.saturating_sub(1))).min(RETRY_MAX_DELAY)
+}
+
+/// Runs `attempt` up to `max_attempts` times until it succeeds, sleeping `delay(retry)` in between
+async fn with_retries<T, E, F>(max_attempts: u32, delay: impl Fn(u32) -> Duration, mut attempt: impl FnMut() -> F) -> Result<T, E>
+where
+    E: fmt::Display,
+    F: Future<Output = Result<T, E>>,
+{
+    let mut tries = 1;
+    loop {
+        match attempt().await {
+            Err(err) if tries < max_attempts => {
+                let wait = delay(tries);
+                error!("Attempt {}/{}: Failed to connect to DB, retrying in {:?}: {}", tries, max_attempts, wait, err);
+                tokio::time::sleep(wait).await;
+                tries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+impl DatabaseConfig {
+    pub fn from_env() -> Result<DatabaseConfig, ConfigError> {
+        DatabaseConfig::from(EnvConfig::from_env())
+    }
+
+    fn from(mut env: EnvConfig) -> Result<DatabaseConfig, ConfigError> {
+        let port = env.parsed("MYSQL_PORT", FALLBACK_MYSQL_PORT);
+        let read_host = env.optional("MYSQL_READ_HOST");
+        if read_host.is_none() && env.optional("MYSQL_READ_PORT").is_some() {
+            env.invalid("MYSQL_READ_PORT", "is set without MYSQL_READ_HOST");
+        }
+        let config = DatabaseConfig {
+            user: env.required("MYSQL_USER"),
+            password: env.secret("MYSQL_PASSWORD"),
+            host: env.required("MYSQL_HOST"),
+            port,
+            database: env.required("MYSQL_DATABASE"),
+            pool: PoolConfig::from(&mut env),
+            read_host,
+            read_port: env.parsed("MYSQL_READ_PORT", port),
+        };
+        env.build(config)
+    }
+}
+
 pub(crate) struct Database {
-    pool: sqlx::MySqlPool,
+    pub(crate) pool: sqlx::MySqlPool,
+    /// Only set if `MYSQL_READ_HOST` is
+    pub(crate) read_pool: Option<sqlx::MySqlPool>,
 }
 
 impl Database {
     pub async fn init_from_env_vars() -> Database {
-        let pool = Database::connect_with_retries().await;
+        // Checked at startup already, see `config::check`
+        let config = DatabaseConfig::from_env().unwrap_or_else(|err| panic!("{}", err));
+        let pool = Database::connect_with_retries(&config, &config.host, config.port, config.pool.options()).await;
 
         debug!("Running DB migrations!");
         match sqlx::migrate!().run(&pool).await {
             Ok(result) => result,
             Err(err) => panic!("Couldn't run db migrations: {}", err),
         }
+        MIGRATIONS_APPLIED.store(true, Ordering::SeqCst);
 
-        Database { pool }
-    }
-
-
-    async fn connect_with_retries() -> MySqlPool {
-        let db_user = std::env::var("MYSQL_USER").expect("Please specify MYSQL_USER as env var!");
-        let db_password =
-            std::env::var("MYSQL_PASSWORD").expect("Please specify MYSQL_PASSWORD as env var!");
-        let db_host = std::env::var("MYSQL_HOST").expect("Please specify MYSQL_HOST as env var!");
-        let db_port = match std::env::var("MYSQL_PORT").expect("Please specify MYSQL_PORT as env var!").parse::<u16>() {
-            Ok(result) => result,
-            Err(err) => {
-                error!("MYSQL_PORT is not a valid u16, falling back to {}: {}", FALLBACK_MYSQL_PORT, err);
-                FALLBACK_MYSQL_PORT
-            }
+        // The replica receives the migrations through replication
+        let read_pool = match &config.read_host {
+            Some(host) => Some(Database::connect_with_retries(&config, host, config.read_port, read_only(config.pool.options())).await),
+            None => None,
         };
-        let db_target_database =
-            std::env::var("MYSQL_DATABASE").expect("Please specify MYSQL_DATABASE as env var!");
 
+        Database { pool, read_pool }
+    }
 
+    async fn connect_with_retries(config: &DatabaseConfig, host: &str, port: u16, options: MySqlPoolOptions) -> MySqlPool {
         let max_retries =
-            match std::env::var("POLLUX_DB_RETRIES").unwrap_or(FALLBACK_DB_RETRIES.to_string()).parse::<i32>() {
-                Ok(result) => result,
+            match config::var("POLLUX_DB_RETRIES").unwrap_or(FALLBACK_DB_RETRIES.to_string()).parse::<u32>() {
+                Ok(result) => result.max(1),
                 Err(err) => {
                     warn!("Unable to parse POLLUX_DB_RETRIES, using »{}« as a fallback: {}", FALLBACK_DB_RETRIES, err);
                     FALLBACK_DB_RETRIES
                 }};
-        let mut delay = 125;
-
-        info!("Connecting to {}@{}:{}/{}", db_user, db_host, db_port, db_target_database);
-
-        for attempt in 1..=max_retries {
-            let now = Instant::now();
-            let connect_options = MySqlConnectOptions::new().host(&db_host).port(db_port).username(&db_user).password(&db_password).database(&db_target_database);
-            match MySqlPoolOptions::new().acquire_timeout(Duration::from_millis(delay)).connect_with(connect_options).await {
-                Ok(pool) => return pool,
-                Err(err) if attempt < max_retries => {
-                    error!("Attempt {}/{}: Failed to connect to DB: {}", attempt, max_retries, err);
-
-                    let elapsed = now.elapsed();
-                    let target_delay = Duration::from_millis(delay);
-                    if elapsed < target_delay  {
-                        let delta = target_delay - elapsed;
-                        sleep(delta);
-                    }
-
-                    delay *= 2;
-                }
-                Err(err) => {
-                    panic!("Failed to connect to DB after {} attempts: {}", max_retries, err);
-                }
-            }
-        }
 
-        unreachable!("Retry logic should have either returned or panicked");
+        info!("Connecting to {}@{}:{}/{}", config.user, host, port, config.database);
+
+        let connect_options = MySqlConnectOptions::new().host(host).port(port).username(&config.user).password(&config.password).database(&config.database);
+        let connect = || options.clone().connect_with(connect_options.clone());
+        match with_retries(max_retries, retry_delay, connect).await {
+            Ok(pool) => pool,
+            Err(err) => panic!("Failed to connect to DB after {} attempts: {}", max_retries, err),
+        }
     }
 
     pub async fn get_or_init() -> &'static Database {
-        DATABASE.get_or_init(|| Self::init_from_env_vars()).await
+        DATABASE.get_or_init(Self::init_from_env_vars).await
     }
 
     pub async fn get_pool(&self) -> Pool<MySql> {
         self.pool.clone()
     }
+
+    /// For the API's reads, which may lag behind the primary. Ingestion and its transactions use `get_pool`.
+    pub async fn get_read_pool(&self) -> Pool<MySql> {
+        self.read_pool.as_ref().unwrap_or(&self.pool).clone()
+    }
+}
+
+pub fn migrations_applied() -> bool {
+    MIGRATIONS_APPLIED.load(Ordering::SeqCst)
+}
+
+/// Runs `SELECT 1` on the existing pool, giving up after `HEALTH_CHECK_TIMEOUT`
+pub async fn check_health(pool: &Pool<MySql>) -> Result<(), String> {
+    match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, sqlx::query("SELECT 1").execute(pool)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(_) => Err(format!("no answer within {:?}", HEALTH_CHECK_TIMEOUT)),
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     
 
     
@@ -103,10 +257,207 @@ mod tests {
         GenericImage, ImageExt,
     };
 
-    use std::sync::Once;
-    static INIT: Once = Once::new();
+    #[test]
+    fn database_config_names_every_missing_variable() {
+        let env = crate::config::EnvConfig::from(|name| match name {
+            "MYSQL_USER" => Some("pollux".to_string()),
+            "MYSQL_PORT" => Some("three".to_string()),
+            _ => None,
+        });
+
+        let message = super::DatabaseConfig::from(env).unwrap_err().to_string();
+        for name in ["MYSQL_PASSWORD", "MYSQL_HOST", "MYSQL_DATABASE", "MYSQL_PORT"] {
+            assert_eq!(message.matches(name).count(), 1, "{}", message);
+        }
+        assert!(message.contains("MYSQL_PORT is invalid"), "{}", message);
+        assert!(!message.contains("MYSQL_USER"), "{}", message);
+    }
+
+    fn database_config(
+        env: &'static [(&'static str, &'static str)],
+        file: &str,
+    ) -> Result<super::DatabaseConfig, crate::config::ConfigError> {
+        let file = crate::config_file::ConfigFile::parse("pollux.toml", file).unwrap().into_vars();
+        super::DatabaseConfig::from(crate::config::EnvConfig::from(move |name| {
+            let value = env.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string());
+            crate::config::layered(name, value, Some(&file))
+        }))
+    }
+
+    static DATABASE_SECTION: &str = r#"
+        [database]
+        user = "pollux"
+        password = "from-file"
+        host = "db.internal"
+        port = 3307
+        database = "pollux"
+    "#;
+
+    #[test]
+    fn database_config_from_file_only() {
+        let config = database_config(&[], DATABASE_SECTION).unwrap();
+        assert_eq!(config.host, "db.internal");
+        assert_eq!(config.port, 3307);
+        assert_eq!(config.password, "from-file");
+    }
+
+    #[test]
+    fn database_config_from_env_only() {
+        let env = &[
+            ("MYSQL_USER", "pollux"),
+            ("MYSQL_PASSWORD", "from-env"),
+            ("MYSQL_HOST", "127.0.0.1"),
+            ("MYSQL_DATABASE", "pollux"),
+        ];
+        let config = database_config(env, "").unwrap();
+        assert_eq!(config.host, "127.0.0.1");
+        assert_eq!(config.port, 3306);
+        assert_eq!(config.password, "from-env");
+    }
+
+    #[test]
+    fn env_vars_override_the_database_section() {
+        let env = &[("MYSQL_HOST", "127.0.0.1"), ("MYSQL_PORT", "3306"), ("MYSQL_PASSWORD", "")];
+        let config = database_config(env, DATABASE_SECTION).unwrap();
+        assert_eq!(config.host, "127.0.0.1");
+        assert_eq!(config.port, 3306);
+        assert_eq!(config.user, "pollux");
+        assert_eq!(config.password, "from-file");
+    }
+
+    static MYSQL_VARS: &[(&str, &str)] = &[
+        ("MYSQL_USER", "pollux"),
+        ("MYSQL_PASSWORD", "pollux"),
+        ("MYSQL_HOST", "127.0.0.1"),
+        ("MYSQL_DATABASE", "pollux"),
+    ];
+
+    fn mysql_config(vars: &'static [(&'static str, &'static str)]) -> Result<super::DatabaseConfig, crate::config::ConfigError> {
+        super::DatabaseConfig::from(crate::config::EnvConfig::from(|name| {
+            vars.iter().chain(MYSQL_VARS).find(|(key, _)| *key == name).map(|(_, value)| value.to_string())
+        }))
+    }
+
+    #[test]
+    fn pool_defaults_to_sqlx_defaults() {
+        let pool = mysql_config(&[]).unwrap().pool;
+        assert_eq!(pool.max_connections, 10);
+        assert_eq!(pool.min_connections, 0);
+        assert_eq!(pool.acquire_timeout, std::time::Duration::from_secs(30));
+        assert_eq!(pool.idle_timeout, Some(std::time::Duration::from_secs(600)));
+        assert_eq!(pool.max_lifetime, Some(std::time::Duration::from_secs(1800)));
+    }
+
+    #[test]
+    fn pool_options_are_parsed() {
+        let vars = &[
+            ("POLLUX_DB_MAX_CONNECTIONS", "32"),
+            ("POLLUX_DB_MIN_CONNECTIONS", "4"),
+            ("POLLUX_DB_ACQUIRE_TIMEOUT_SECONDS", "5"),
+            ("POLLUX_DB_IDLE_TIMEOUT_SECONDS", "0"),
+            ("POLLUX_DB_MAX_LIFETIME_SECONDS", "3600"),
+        ];
+        let pool = mysql_config(vars).unwrap().pool;
+        assert_eq!(pool.max_connections, 32);
+        assert_eq!(pool.min_connections, 4);
+        assert_eq!(pool.acquire_timeout, std::time::Duration::from_secs(5));
+        assert_eq!(pool.idle_timeout, None);
+        assert_eq!(pool.max_lifetime, Some(std::time::Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn invalid_pool_options_are_reported() {
+        let message = mysql_config(&[("POLLUX_DB_MAX_CONNECTIONS", "2"), ("POLLUX_DB_MIN_CONNECTIONS", "3")])
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("POLLUX_DB_MIN_CONNECTIONS is invalid: »3« is more than"), "{}", message);
+
+        let vars = &[("POLLUX_DB_MAX_CONNECTIONS", "0"), ("POLLUX_DB_ACQUIRE_TIMEOUT_SECONDS", "-1")];
+        let message = mysql_config(vars).unwrap_err().to_string();
+        for name in ["POLLUX_DB_MAX_CONNECTIONS", "POLLUX_DB_ACQUIRE_TIMEOUT_SECONDS"] {
+            assert!(message.contains(&format!("{} is invalid", name)), "{}", message);
+        }
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_a_cap() {
+        use std::time::Duration;
+
+        assert_eq!(super::retry_delay(1), Duration::from_millis(125));
+        assert_eq!(super::retry_delay(2), Duration::from_millis(250));
+        assert_eq!(super::retry_delay(5), Duration::from_secs(2));
+        assert_eq!(super::retry_delay(16), Duration::from_secs(30));
+        assert_eq!(super::retry_delay(u32::MAX), Duration::from_secs(30));
+    }
+
+    #[tokio::test]
+    async fn retries_wait_between_attempts() {
+        use std::time::{Duration, Instant};
+
+        let start = Instant::now();
+        let mut attempts = Vec::new();
+        let result = super::with_retries(3, |retry| Duration::from_millis(40 * u64::from(retry)), || {
+            attempts.push(start.elapsed());
+            let attempt = attempts.len();
+            async move { if attempt < 3 { Err("refused") } else { Ok(attempt) } }
+        })
+        .await;
+
+        assert_eq!(result, Ok(3));
+        assert!(attempts[1] - attempts[0] >= Duration::from_millis(40), "{:?}", attempts);
+        assert!(attempts[2] - attempts[1] >= Duration::from_millis(80), "{:?}", attempts);
+    }
+
+    #[tokio::test]
+    async fn retries_give_up_with_the_last_error() {
+        let mut attempts = 0;
+        let result: Result<(), String> = super::with_retries(4, |_| std::time::Duration::ZERO, || {
+            attempts += 1;
+            let attempt = attempts;
+            async move { Err(format!("attempt {}", attempt)) }
+        })
+        .await;
+
+        assert_eq!(result, Err("attempt 4".to_string()));
+        assert_eq!(attempts, 4);
+    }
+
+    #[test]
+    fn read_replica_is_optional() {
+        let config = mysql_config(&[]).unwrap();
+        assert_eq!(config.read_host, None);
+
+        let config = mysql_config(&[("MYSQL_READ_HOST", "replica.internal")]).unwrap();
+        assert_eq!(config.read_host.as_deref(), Some("replica.internal"));
+        // Same port as the primary unless set
+        assert_eq!(config.read_port, 3306);
+
+        let config = mysql_config(&[("MYSQL_READ_HOST", "replica.internal"), ("MYSQL_READ_PORT", "3307")]).unwrap();
+        assert_eq!(config.read_port, 3307);
+
+        let message = mysql_config(&[("MYSQL_READ_PORT", "3307")]).unwrap_err().to_string();
+        assert!(message.contains("MYSQL_READ_PORT is invalid: is set without MYSQL_READ_HOST"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn reads_go_to_the_replica_pool() {
+        let (_container, pool) = initialize().await;
+        let read_pool = super::read_only(sqlx::mysql::MySqlPoolOptions::new())
+            .connect_with((*pool.connect_options()).clone())
+            .await
+            .unwrap();
+        let db = super::Database { pool: pool.clone(), read_pool: Some(read_pool) };
+
+        let update = "UPDATE GitProjects SET name = name";
+        assert!(sqlx::query(update).execute(&db.get_pool().await).await.is_ok());
+        assert!(sqlx::query(update).execute(&db.get_read_pool().await).await.is_err());
+
+        // Without a replica, reads use the primary
+        let db = super::Database { pool, read_pool: None };
+        assert!(sqlx::query(update).execute(&db.get_read_pool().await).await.is_ok());
+    }
 
-    async fn initialize() -> (
+    pub(crate) async fn initialize() -> (
         testcontainers::ContainerAsync<GenericImage>,
         sqlx::Pool<MySql>,
     ) {
@@ -167,7 +518,7 @@ mod tests {
 
         // We have to return both pool and container
         // Otherwise container will be stopped, if it goes out-of-scope
-        return (container, pool);
+        (container, pool)
     }
 
     #[tokio::test]
@@ -188,7 +539,7 @@ mod tests {
         //     println!("{:?}", table);
         // }
 
-        assert!(tables.len() > 0);
+        assert!(!tables.is_empty());
     }
 
     #[tokio::test]
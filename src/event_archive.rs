@@ -0,0 +1,237 @@
+use chrono::{Duration, Utc};
+use log::{debug, error, info, warn};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use sqlx::{MySql, Pool, QueryBuilder, Transaction};
+
+use crate::{
+    config::{ConfigError, EnvConfig},
+    database,
+    git_platform::{self, GitEventAPI, GitPlatform},
+    stats,
+    sync_runs::SyncReport,
+};
+
+static EVENT_ARCHIVE_RETENTION_DAYS: &str = "POLLUX_EVENT_ARCHIVE_RETENTION_DAYS";
+static FALLBACK_EVENT_ARCHIVE_RETENTION_DAYS: u32 = 365;
+/// zstd's default, compresses JSON well enough without slowing down syncs
+static COMPRESSION_LEVEL: i32 = 3;
+/// Rows per `INSERT`, like the events themselves
+const ARCHIVE_BATCH_SIZE: usize = 100;
+
+/// Where the events handed to a platform's insert come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventSource {
+    /// Just fetched, they're archived and the last sync moves on
+    Fetched,
+    /// Loaded by `reprocess`, neither happens
+    Archive,
+}
+
+/// Archived events older than this are pruned. `0` disables the archive.
+pub fn retention_from_env() -> Result<Duration, ConfigError> {
+    retention(EnvConfig::from_env())
+}
+
+fn retention(mut env: EnvConfig) -> Result<Duration, ConfigError> {
+    let days: u32 = env.parsed(EVENT_ARCHIVE_RETENTION_DAYS, FALLBACK_EVENT_ARCHIVE_RETENTION_DAYS);
+    env.build(Duration::days(days.into()))
+}
+
+/// See `retention_from_env`
+pub fn get_retention() -> Duration {
+    // Checked at startup already, see `config::check`
+    retention_from_env().unwrap_or(Duration::days(FALLBACK_EVENT_ARCHIVE_RETENTION_DAYS.into()))
+}
+
+pub fn compress(payload: &[u8]) -> Vec<u8> {
+    zstd::encode_all(payload, COMPRESSION_LEVEL).expect("Couldn't compress in memory")
+}
+
+pub fn decompress(compressed: &[u8]) -> Result<Vec<u8>, String> {
+    zstd::decode_all(compressed).map_err(|err| format!("Unable to decompress archived event: {}", err))
+}
+
+/// A row of `EventArchive`, besides its platform and when it was received
+#[derive(Debug, PartialEq)]
+struct ArchivedEvent {
+    external_id: Option<String>,
+    payload_hash: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+fn encode<E: GitEventAPI>(event: &E) -> ArchivedEvent {
+    let json = serde_json::to_vec(event).expect("Events are always serializable");
+    ArchivedEvent {
+        external_id: event.external_id(),
+        payload_hash: Sha256::digest(&json).to_vec(),
+        payload: compress(&json),
+    }
+}
+
+fn decode<E: DeserializeOwned>(payload: &[u8]) -> Result<E, String> {
+    let json = decompress(payload)?;
+    serde_json::from_slice(&json).map_err(|err| format!("Unable to decode archived event: {}", err))
+}
+
+/// Archives the fetched `events` of `platform` before they're mapped, as part of the sync's
+/// transaction. Events archived by an earlier sync are left alone. Prunes expired ones, see `get_retention`.
+pub async fn archive<E: GitEventAPI>(tx: &mut Transaction<'static, MySql>, platform: &str, events: &[E]) {
+    let retention = get_retention();
+    if retention.is_zero() {
+        return;
+    }
+
+    let received_at = Utc::now();
+    let received_at_value = received_at.format("%Y-%m-%d %H:%M:%S").to_string();
+    let archived: Vec<ArchivedEvent> = events.iter().map(encode).collect();
+    let mut stored = 0;
+    for batch in archived.chunks(ARCHIVE_BATCH_SIZE) {
+        let mut query = QueryBuilder::<MySql>::new(
+            "INSERT INTO EventArchive (platform, external_id, received_at, payload_hash, payload) ",
+        );
+        query.push_values(batch, |mut row, event| {
+            row.push_bind(platform)
+                .push_bind(&event.external_id)
+                .push_bind(&received_at_value)
+                .push_bind(&event.payload_hash)
+                .push_bind(&event.payload);
+        });
+        query.push(" ON DUPLICATE KEY UPDATE id = id");
+        stored += query.build().execute(&mut **tx).await.unwrap().rows_affected();
+    }
+
+    let pruned = sqlx::query("DELETE FROM EventArchive WHERE platform = ? AND received_at < ?")
+        .bind(platform)
+        .bind((received_at - retention).format("%Y-%m-%d %H:%M:%S").to_string())
+        .execute(&mut **tx)
+        .await
+        .unwrap()
+        .rows_affected();
+    debug!(
+        "Archived {} new of {} events from {}, pruned {} expired ones",
+        stored,
+        events.len(),
+        platform,
+        pruned
+    );
+}
+
+/// Archived events of `platform`, oldest first. Ones which can't be decoded anymore are skipped.
+pub async fn load<E: GitEventAPI>(pool: &Pool<MySql>, platform: &str) -> Vec<E> {
+    sqlx::query_as::<_, (u32, Vec<u8>)>("SELECT id, payload FROM EventArchive WHERE platform = ? ORDER BY received_at, id")
+        .bind(platform)
+        .fetch_all(pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter_map(|(id, payload)| match decode(&payload) {
+            Ok(event) => Some(event),
+            Err(err) => {
+                warn!("Skipping archived event {} of {}: {}", id, platform, err);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Runs the archived events of `P` through its mapping again, e.g. once an unknown action is mapped.
+/// Only what isn't stored yet is inserted, the last sync stays as it is. Isn't recorded as a sync run.
+pub async fn reprocess<P: GitPlatform>(platform: &P) -> SyncReport {
+    let started_at = Utc::now();
+    let db = database::Database::get_or_init().await;
+    let pool = db.get_pool().await;
+
+    let events = load::<P::GitEventAPI>(&pool, P::GIT_PLATFORM_ID).await;
+    info!("Reprocessing {} archived events of {}", events.len(), P::GIT_PLATFORM_ID);
+    let result = platform.insert_archived_events(events).await.map_err(|err| {
+        error!("Reprocessing {} failed: {}", P::GIT_PLATFORM_ID, err);
+        err.to_string()
+    });
+
+    let run = git_platform::sync_run(P::GIT_PLATFORM_ID, started_at, result);
+    if run.events_inserted > 0 {
+        stats::invalidate_today_cache();
+    }
+    SyncReport::from(&run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{database::tests::initialize, gitea::GiteaActivity};
+
+    static GITEA_ACTIVITY: &str = r#"{"id": 5120, "op_type": "commit_repo", "repo_id": 7,
+        "repo": {"id": 7, "full_name": "2tefan/dotfiles", "html_url": "https://git.example.com/2tefan/dotfiles",
+            "private": false, "internal": false},
+        "ref_name": "refs/heads/main", "content": "{\"Len\":3}", "created": "2024-05-03T18:40:12+02:00"}"#;
+
+    #[test]
+    fn invalid_retention_is_reported() {
+        let env = |value: &'static str| {
+            EnvConfig::from(move |name| (name == EVENT_ARCHIVE_RETENTION_DAYS).then(|| value.to_string()))
+        };
+        assert_eq!(retention(env(" ")), Ok(Duration::days(365)));
+        assert_eq!(retention(env("0")), Ok(Duration::zero()));
+
+        let message = retention(env("a year")).unwrap_err().to_string();
+        assert!(message.contains("POLLUX_EVENT_ARCHIVE_RETENTION_DAYS is invalid"), "{}", message);
+    }
+
+    #[test]
+    fn payloads_survive_compression() {
+        let payload = GITEA_ACTIVITY.repeat(20).into_bytes();
+        let compressed = compress(&payload);
+        assert!(compressed.len() < payload.len() / 4, "{} of {}", compressed.len(), payload.len());
+        assert_eq!(decompress(&compressed).unwrap(), payload);
+
+        let error = decompress(b"not zstd").unwrap_err();
+        assert!(error.contains("Unable to decompress"), "{}", error);
+    }
+
+    #[test]
+    fn archived_events_decode_to_the_fetched_ones() {
+        let activity: GiteaActivity = serde_json::from_str(GITEA_ACTIVITY).unwrap();
+        let archived = encode(&activity);
+        assert_eq!(archived.external_id.as_deref(), Some("5120"));
+        assert_eq!(decode::<GiteaActivity>(&archived.payload).unwrap(), activity);
+        assert_eq!(encode(&activity).payload_hash, archived.payload_hash);
+    }
+
+    #[cfg(feature = "github")]
+    #[test]
+    fn github_events_keep_their_account() {
+        use crate::github::GithubEvent;
+
+        // Not part of GitHub's payload, it's added while fetching
+        let event = GithubEvent {
+            account: "2tefan".to_string(),
+            ..GithubEvent::default()
+        };
+        assert_eq!(decode::<GithubEvent>(&encode(&event).payload).unwrap(), event);
+    }
+
+    #[tokio::test]
+    async fn events_are_archived_once_and_pruned() {
+        let (_container, pool) = initialize().await;
+        let activities: Vec<GiteaActivity> = vec![serde_json::from_str(GITEA_ACTIVITY).unwrap()];
+
+        for _ in 0..2 {
+            let mut tx = pool.begin().await.unwrap();
+            archive(&mut tx, "Gitea", &activities).await;
+            tx.commit().await.unwrap();
+        }
+        assert_eq!(load::<GiteaActivity>(&pool, "Gitea").await, activities);
+        assert!(load::<GiteaActivity>(&pool, "Codeberg").await.is_empty());
+
+        sqlx::query("UPDATE EventArchive SET received_at = ?")
+            .bind((Utc::now() - Duration::days(i64::from(FALLBACK_EVENT_ARCHIVE_RETENTION_DAYS) + 1)).naive_utc())
+            .execute(&pool)
+            .await
+            .unwrap();
+        let mut tx = pool.begin().await.unwrap();
+        archive::<GiteaActivity>(&mut tx, "Gitea", &[]).await;
+        tx.commit().await.unwrap();
+        assert!(load::<GiteaActivity>(&pool, "Gitea").await.is_empty());
+    }
+}
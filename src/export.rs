@@ -0,0 +1,274 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use rocket::{futures::StreamExt, response::stream::TextStream};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{prelude::FromRow, MySql, Pool};
+
+/// Bumped whenever the layout of the export changes, so an import can tell which one it got.
+/// Version 2 added the columns which came after the first exports and `daily_aggregates`.
+pub static EXPORT_FORMAT_VERSION: u32 = 2;
+pub static EXPORT_FORMAT: &str = "pollux-export";
+
+#[derive(Debug, FromRow, Serialize, Deserialize)]
+pub struct ExportPlatform {
+    pub name: String,
+    pub first_sync: DateTime<Utc>,
+    pub last_sync: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow, Serialize, Deserialize)]
+pub struct ExportAction {
+    pub name: String,
+}
+
+#[derive(Debug, FromRow, Serialize, Deserialize)]
+pub struct ExportProject {
+    pub platform: String,
+    pub platform_project_id: u32,
+    pub pseudonymous: bool,
+    pub name: String,
+    pub url: String,
+    /// Missing in version 1, like all other optional columns
+    #[serde(default)]
+    pub visibility: Option<String>,
+    #[serde(default)]
+    pub unavailable_since: Option<DateTime<Utc>>,
+    /// Only for ingested projects, see `ingest`
+    #[serde(default)]
+    pub external_id: Option<String>,
+}
+
+#[derive(Debug, FromRow, Serialize, Deserialize)]
+pub struct ExportEvent {
+    pub platform: String,
+    pub platform_project_id: u32,
+    pub pseudonymous: bool,
+    pub timestamp: DateTime<Utc>,
+    pub action: String,
+    /// Added after the first exports were written, those import without it
+    #[serde(default)]
+    pub commit_count: Option<u32>,
+    /// Part of what makes an event unique, two accounts pushing in the same second are two events
+    #[serde(default)]
+    pub account: Option<String>,
+    #[serde(default)]
+    pub git_ref: Option<String>,
+    #[serde(default)]
+    pub target_type: Option<String>,
+}
+
+/// Events rolled up by the retention, see `DailyAggregates`
+#[derive(Debug, FromRow, Serialize, Deserialize)]
+pub struct ExportAggregate {
+    pub platform: String,
+    pub day: NaiveDate,
+    pub action: String,
+    pub event_count: u32,
+    pub commit_count: u32,
+}
+
+/// Everything before the first section: format, version and a description of the sections
+fn header(exported_at: DateTime<Utc>) -> String {
+    let header = json!({
+        "format": EXPORT_FORMAT,
+        "version": EXPORT_FORMAT_VERSION,
+        "exported_at": exported_at,
+        "sections": {
+            "platforms": "Git platforms, identified by name",
+            "actions": "Event actions (e.g. PushEvent), identified by name",
+            "projects": "Projects, identified by (platform, platform_project_id, pseudonymous)",
+            "events": "Events, referencing their project by (platform, platform_project_id, pseudonymous) and their action by name",
+            "daily_aggregates": "Events deleted by the retention, counted per platform, day and action",
+        },
+    })
+    .to_string();
+
+    // Leave the object open, the sections are appended while streaming
+    format!("{},", header.strip_suffix('}').unwrap())
+}
+
+/// Serializes a row as an array element, separated from the previous one
+fn entry<T: Serialize>(row: &T, first: &mut bool) -> String {
+    let separator = if *first { "" } else { "," };
+    *first = false;
+    format!("{}\n{}", separator, serde_json::to_string(row).unwrap())
+}
+
+/// Streams all data as a single JSON document, read from one transaction so the sections are consistent.
+/// On a database error the stream stops early, leaving the document incomplete (and invalid JSON).
+pub fn export(pool: Pool<MySql>, exported_at: DateTime<Utc>) -> TextStream![String] {
+    TextStream! {
+        let mut tx = match pool.begin().await {
+            Ok(tx) => tx,
+            Err(err) => {
+                error!("Couldn't start export transaction: {}", err);
+                return;
+            }
+        };
+        yield header(exported_at);
+
+        yield "\"platforms\":[".to_string();
+        let mut first = true;
+        let mut rows = sqlx::query_as::<_, ExportPlatform>(
+            "SELECT name, firstSync AS first_sync, lastSync AS last_sync FROM GitPlatforms ORDER BY name",
+        )
+        .fetch(&mut *tx);
+        while let Some(row) = rows.next().await {
+            match row {
+                Ok(platform) => yield entry(&platform, &mut first),
+                Err(err) => {
+                    error!("Export of platforms failed: {}", err);
+                    return;
+                }
+            }
+        }
+        drop(rows);
+
+        yield "],\"actions\":[".to_string();
+        let mut first = true;
+        let mut rows = sqlx::query_as::<_, ExportAction>("SELECT name FROM GitActions ORDER BY name").fetch(&mut *tx);
+        while let Some(row) = rows.next().await {
+            match row {
+                Ok(action) => yield entry(&action, &mut first),
+                Err(err) => {
+                    error!("Export of actions failed: {}", err);
+                    return;
+                }
+            }
+        }
+        drop(rows);
+
+        yield "],\"projects\":[".to_string();
+        let mut first = true;
+        let mut rows = sqlx::query_as::<_, ExportProject>(
+            "SELECT platform, platform_project_id, pseudonymous, name, url, visibility, unavailable_since, external_id FROM GitProjects \
+            ORDER BY platform, platform_project_id, pseudonymous",
+        )
+        .fetch(&mut *tx);
+        while let Some(row) = rows.next().await {
+            match row {
+                Ok(project) => yield entry(&project, &mut first),
+                Err(err) => {
+                    error!("Export of projects failed: {}", err);
+                    return;
+                }
+            }
+        }
+        drop(rows);
+
+        yield "],\"events\":[".to_string();
+        let mut first = true;
+        let mut rows = sqlx::query_as::<_, ExportEvent>(
+            "SELECT proj.platform, proj.platform_project_id, proj.pseudonymous, evt.timestamp, act.name AS action, gevt.commit_count, gevt.account, gevt.git_ref, gevt.target_type \
+            FROM Events AS evt \
+            INNER JOIN GitEvents AS gevt ON evt.id = gevt.id \
+            INNER JOIN GitProjects AS proj ON gevt.project_fk = proj.id \
+            INNER JOIN GitActions AS act ON gevt.action_fk = act.id \
+            ORDER BY evt.timestamp, evt.id",
+        )
+        .fetch(&mut *tx);
+        while let Some(row) = rows.next().await {
+            match row {
+                Ok(event) => yield entry(&event, &mut first),
+                Err(err) => {
+                    error!("Export of events failed: {}", err);
+                    return;
+                }
+            }
+        }
+        drop(rows);
+
+        yield "\n],\"daily_aggregates\":[".to_string();
+        let mut first = true;
+        let mut rows = sqlx::query_as::<_, ExportAggregate>(
+            "SELECT agg.platform, agg.day, act.name AS action, agg.event_count, agg.commit_count \
+            FROM DailyAggregates AS agg \
+            INNER JOIN GitActions AS act ON agg.action_fk = act.id \
+            ORDER BY agg.day, agg.platform, act.name",
+        )
+        .fetch(&mut *tx);
+        while let Some(row) = rows.next().await {
+            match row {
+                Ok(aggregate) => yield entry(&aggregate, &mut first),
+                Err(err) => {
+                    error!("Export of daily aggregates failed: {}", err);
+                    return;
+                }
+            }
+        }
+        drop(rows);
+
+        yield "\n]}\n".to_string();
+        // Nothing was written, so rolling back just ends the snapshot
+        tx.rollback().await.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn collect(pool: Pool<MySql>) -> serde_json::Value {
+        let exported_at = DateTime::from_timestamp(1_714_557_600, 0).unwrap();
+        let document: Vec<String> = export(pool, exported_at).0.collect().await;
+        serde_json::from_str(&document.concat()).unwrap()
+    }
+
+    #[test]
+    fn header_describes_the_format() {
+        let header = header(DateTime::from_timestamp(0, 0).unwrap());
+        let document: serde_json::Value = serde_json::from_str(&format!("{}\"events\":[]}}", header)).unwrap();
+
+        assert_eq!(document["format"], EXPORT_FORMAT);
+        assert_eq!(document["version"], EXPORT_FORMAT_VERSION);
+        assert_eq!(document["exported_at"], "1970-01-01T00:00:00Z");
+        assert!(document["sections"]["events"].is_string());
+    }
+
+    #[test]
+    fn entries_are_separated() {
+        let mut first = true;
+        let entries = [entry(&1, &mut first), entry(&2, &mut first)].concat();
+        assert_eq!(entries, "\n1,\n2");
+    }
+
+    #[tokio::test]
+    async fn export_uses_natural_keys() {
+        let (_container, pool) = crate::database::tests::initialize().await;
+        for statement in [
+            "INSERT INTO GitPlatforms (name, firstSync) VALUES ('Github', '2024-01-01 00:00:00')",
+            "INSERT INTO GitActions (id, name) VALUES (7, 'PushEvent')",
+            "INSERT INTO GitProjects (id, name, url, platform, platform_project_id) VALUES \
+                (3, '2tefan/pollux', 'https://github.com/2tefan/pollux', 'Github', 42)",
+            "INSERT INTO Events (id, timestamp) VALUES (1, '2024-05-01 10:00:00')",
+            "INSERT INTO GitEvents (id, action_fk, project_fk) VALUES (1, 7, 3)",
+            "INSERT INTO DailyAggregates (platform, day, action_fk, event_count, commit_count) VALUES ('Github', '2023-01-01', 7, 4, 9)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+
+        let document = collect(pool).await;
+        assert_eq!(document["version"], EXPORT_FORMAT_VERSION);
+        assert_eq!(document["platforms"][0]["name"], "Github");
+        assert_eq!(document["actions"], json!([{"name": "PushEvent"}]));
+        assert_eq!(document["projects"][0]["platform_project_id"], 42);
+        assert_eq!(
+            document["events"],
+            json!([{
+                "platform": "Github",
+                "platform_project_id": 42,
+                "pseudonymous": false,
+                "timestamp": "2024-05-01T10:00:00Z",
+                "action": "PushEvent",
+                "commit_count": null,
+                "account": null,
+                "git_ref": null,
+                "target_type": null
+            }])
+        );
+        assert_eq!(
+            document["daily_aggregates"],
+            json!([{"platform": "Github", "day": "2023-01-01", "action": "PushEvent", "event_count": 4, "commit_count": 9}])
+        );
+    }
+}
@@ -1,10 +1,24 @@
-use crate::database;
-use chrono::{DateTime, NaiveDate, Utc};
+use crate::{
+    config::ConfigError,
+    database,
+    event_archive::{self, EventSource},
+    pagination::{FetchedEvents, PaginationError},
+    queries::push_ids,
+    records, retention, stats,
+    sync_runs::{self, NewSyncRun, RateLimitStatus, SyncReport, SyncRunStatus},
+    visibility::ProjectVisibility,
+};
+use chrono::{DateTime, NaiveDateTime, Timelike, Utc};
 use log::trace;
 use rocket::futures::TryStreamExt;
-use serde::{Deserialize, Serialize};
-use sqlx::{prelude::FromRow, MySql, Row, Transaction};
-use std::{borrow::BorrowMut, sync::Arc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Connection, MySql, MySqlConnection, Pool, QueryBuilder, Row, Transaction};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+    time::{Duration, Instant},
+};
 use time::{format_description, OffsetDateTime};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -13,207 +27,955 @@ pub struct GitProject {
     pub platform_project_id: u64,
     pub name: String,
     pub url: String,
+    pub pseudonymous: bool,
+    /// Set for projects which were already gone when their events were synced
+    pub unavailable_since: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, FromRow, Serialize)]
-pub struct GitEvents {
-    timestamp: DateTime<Utc>,
-    project_name: String,
-    action: String,
-    platform: String,
-    url: String,
+impl GitProject {
+    /// A project as a platform reports it, its `id` is only known once it's stored (see `write_project_to_db`)
+    pub fn reported(platform_project_id: u64, name: impl Into<String>, url: impl Into<String>) -> GitProject {
+        GitProject {
+            id: 0,
+            platform_project_id,
+            name: name.into(),
+            url: url.into(),
+            pseudonymous: false,
+            unavailable_since: None,
+        }
+    }
 }
 
-pub trait GitEventAPI {}
+/// `GitProjects.platform_project_id` is an unsigned 32 bit column, platforms which only have
+/// uuids (or other strings) as ids get one derived from them
+pub fn platform_project_id_from(id: &str) -> u64 {
+    let digest = Sha256::digest(id.as_bytes());
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) as u64
+}
 
-pub trait GitPlatform {
-    const GIT_PLATFORM_ID: &'static str;
-    type GitEventAPI: GitEventAPI;
+/// Adds the platform `name` to `GitPlatforms`, if it doesn't exist yet
+pub async fn set_platform_named(tx: &mut Transaction<'static, MySql>, name: &str) {
+    let rows = sqlx::query("SELECT name FROM GitPlatforms WHERE name = ?")
+        .bind(name)
+        .fetch_all(&mut **tx) // Use fetch_all to collect all rows immediately
+        .await
+        .unwrap();
 
-    fn init_from_env_vars() -> Self;
+    if rows.len() > 1 {
+        panic!(
+            "There are more than 1x platforms with the same name! (name={}) - This can't be!",
+            name
+        );
+    }
 
-    async fn update_provider(&mut self) -> Option<i32>;
+    // Add platform, if it not yet exists
+    if rows.is_empty() {
+        let format =
+            format_description::parse("[year]-[month]-[day] [hour]:[minute]:[second]").unwrap();
+        sqlx::query("INSERT INTO GitPlatforms (name, firstSync) VALUES ( ?, ? )")
+            .bind(name)
+            .bind(OffsetDateTime::now_utc().format(&format).unwrap())
+            .execute(&mut **tx)
+            .await
+            .unwrap();
+    }
+}
 
-    // pub fn get_or_init() {
-    //     GITHUB.get_or_init(|| Self::init_from_env_vars());
-    // }
+/// Project of `platform` with the given id, `None` if there is none (or more than one)
+pub async fn fetch_git_project(
+    tx: &mut Transaction<'static, MySql>,
+    platform: &str,
+    platform_project_id: u64,
+    pseudonymous: bool,
+) -> Option<GitProject> {
+    let mut rows =
+        sqlx::query("SELECT id, platform_project_id, name, url, unavailable_since FROM GitProjects WHERE platform_project_id = ? AND platform = ? AND pseudonymous = ?")
+        .bind(platform_project_id)
+        .bind(platform)
+        .bind(pseudonymous)
+        .fetch(&mut **tx);
 
-    async fn get_events(&mut self) -> Vec<Self::GitEventAPI>;
+    let mut number_of_projects = 0;
+    let mut project = Option::None;
+    while let Some(row) = rows.try_next().await.unwrap() {
+        if number_of_projects > 0 {
+            error!(
+                "There are more than 1x Git projects in DB (id={}, platform={}) - skipping this event!",
+                platform_project_id, platform
+            );
+            return Option::None;
+        }
 
-    async fn set_platform(tx: &mut Transaction<'static, MySql>) {
-        let rows = sqlx::query("SELECT name FROM GitPlatforms WHERE name = ?")
-            .bind(Self::GIT_PLATFORM_ID)
-            .fetch_all(&mut **tx) // Use fetch_all to collect all rows immediately
-            .await
-            .unwrap();
+        number_of_projects += 1;
+        let id: u64 = row.try_get("id").unwrap();
+        let platform_project_id: u64 = row.try_get("platform_project_id").unwrap();
+        let name: &str = row.try_get("name").unwrap();
+        let url: &str = row.try_get("url").unwrap();
+        project = Some(GitProject {
+            id,
+            platform_project_id,
+            name: name.to_string(),
+            url: url.to_string(),
+            pseudonymous,
+            unavailable_since: row.try_get("unavailable_since").unwrap(),
+        });
+    }
+
+    project
+}
+
+/// Actions shared by all platforms, what `map_action` maps onto
+pub static ACTIONS: [&str; 10] = [
+    "commit",
+    "created_branch",
+    "merge-request",
+    "issue",
+    "reviewed",
+    "commented",
+    "released",
+    "starred",
+    "forked",
+    "project-management",
+];
 
-        if rows.len() > 1 {
-            panic!(
-                "There are more than 1x platforms with the same name! (name={}) - This can't be!",
-                Self::GIT_PLATFORM_ID
+/// Id of the action `action_name`, actions aren't bound to a platform
+pub async fn git_action_id(
+    tx: &mut Transaction<'static, MySql>,
+    action_name: &str,
+) -> Option<u64> {
+    let mut rows = sqlx::query("SELECT id FROM GitActions WHERE name = ?")
+        .bind(action_name)
+        .fetch(&mut **tx);
+
+    let mut number_of_actions = 0;
+    let mut git_action_id = Option::None;
+    while let Some(row) = rows.try_next().await.unwrap() {
+        if number_of_actions > 0 {
+            error!(
+                "There are more than 1x Git Actions with the same name! (name={}) - skipping this event!",
+                action_name
             );
+            return Option::None;
         }
 
-        // Add platform, if it not yet exists
-        if rows.is_empty() {
-            let format =
-                format_description::parse("[year]-[month]-[day] [hour]:[minute]:[second]").unwrap();
-            sqlx::query("INSERT INTO GitPlatforms (name, firstSync) VALUES ( ?, ? )")
-                .bind(Self::GIT_PLATFORM_ID)
-                .bind(OffsetDateTime::now_utc().format(&format).unwrap())
+        number_of_actions += 1;
+        git_action_id = Some(row.try_get("id").unwrap());
+    }
+
+    git_action_id
+}
+
+pub async fn add_git_action(tx: &mut Transaction<'static, MySql>, action_name: &str) -> u64 {
+    let action_id = sqlx::query("INSERT INTO GitActions (name) VALUES ( ? )")
+        .bind(action_name)
+        .execute(&mut **tx)
+        .await
+        .unwrap()
+        .last_insert_id();
+    trace!(
+        "Inserted Git action - id: {} ({})",
+        action_id,
+        action_name
+    );
+    action_id
+}
+
+/// Projects and actions of one platform, loaded once per sync instead of looked up for every event.
+/// Misses fall back to the database, e.g. for projects the sync just created.
+#[derive(Debug)]
+pub struct SyncCache {
+    platform: &'static str,
+    /// By `platform_project_id` and whether it's pseudonymous
+    projects: HashMap<(u64, bool), GitProject>,
+    actions: HashMap<String, u64>,
+}
+
+impl SyncCache {
+    pub async fn load(tx: &mut Transaction<'static, MySql>, platform: &'static str) -> Self {
+        let rows = sqlx::query(
+            "SELECT id, platform_project_id, name, url, pseudonymous, unavailable_since FROM GitProjects WHERE platform = ?",
+        )
+        .bind(platform)
+        .fetch_all(&mut **tx)
+        .await
+        .unwrap();
+
+        let projects = rows
+            .into_iter()
+            .map(|row| GitProject {
+                id: row.try_get("id").unwrap(),
+                platform_project_id: row.try_get("platform_project_id").unwrap(),
+                name: row.try_get("name").unwrap(),
+                url: row.try_get("url").unwrap(),
+                pseudonymous: row.try_get("pseudonymous").unwrap(),
+                unavailable_since: row.try_get("unavailable_since").unwrap(),
+            })
+            .map(|project| ((project.platform_project_id, project.pseudonymous), project))
+            .collect();
+
+        let actions = sqlx::query_as("SELECT name, id FROM GitActions")
+            .fetch_all(&mut **tx)
+            .await
+            .unwrap()
+            .into_iter()
+            .collect();
+
+        SyncCache {
+            platform,
+            projects,
+            actions,
+        }
+    }
+
+    /// Like `fetch_git_project`
+    pub async fn project(
+        &mut self,
+        tx: &mut Transaction<'static, MySql>,
+        platform_project_id: u64,
+        pseudonymous: bool,
+    ) -> Option<GitProject> {
+        let key = (platform_project_id, pseudonymous);
+        if let Some(project) = self.projects.get(&key) {
+            return Some(project.clone());
+        }
+
+        let project = fetch_git_project(tx, self.platform, platform_project_id, pseudonymous).await?;
+        self.projects.insert(key, project.clone());
+        Some(project)
+    }
+
+    /// Id of the action `action_name`, added if it's new
+    pub async fn action(&mut self, tx: &mut Transaction<'static, MySql>, action_name: &str) -> u64 {
+        if let Some(id) = self.actions.get(action_name) {
+            return *id;
+        }
+
+        let id = match git_action_id(tx, action_name).await {
+            Some(id) => id,
+            None => add_git_action(tx, action_name).await,
+        };
+        self.actions.insert(action_name.to_string(), id);
+        id
+    }
+}
+
+/// Inserts the event with its `Events` row, unless it's stored already. `None` for such a duplicate.
+/// Pushes within the same second only differ by their commit count, the same push of two accounts is
+/// counted for both. The database tells duplicates apart, see `GitEvents_UNIQUE`.
+pub async fn add_unique_event(
+    tx: &mut Transaction<'static, MySql>,
+    datetime: DateTime<Utc>,
+    action_id: u64,
+    project_id: u64,
+    commit_count: Option<u64>,
+    account: Option<&str>,
+    details: EventDetails<'_>,
+) -> Option<u64> {
+    let timestamp = datetime.format("%Y-%m-%d %H:%M:%S").to_string();
+    let event_id = sqlx::query("INSERT INTO Events (timestamp) VALUES ( ? )")
+        .bind(&timestamp)
+        .execute(&mut **tx)
+        .await
+        .unwrap()
+        .last_insert_id();
+
+    let inserted = sqlx::query(
+        "INSERT INTO GitEvents (id, action_fk, project_fk, timestamp, commit_count, account, git_ref, target_type) \
+            VALUES ( ?, ?, ?, ?, ?, ?, ?, ? )",
+    )
+        .bind(event_id)
+        .bind(action_id)
+        .bind(project_id)
+        .bind(&timestamp)
+        .bind(commit_count)
+        .bind(account)
+        .bind(details.git_ref)
+        .bind(details.target_type)
+        .execute(&mut **tx)
+        .await;
+    match inserted {
+        Ok(_) => {
+            trace!("Inserted Git event - id: {} @ {}", event_id, datetime);
+            Some(event_id)
+        }
+        Err(err) if err.as_database_error().is_some_and(|err| err.is_unique_violation()) => {
+            sqlx::query("DELETE FROM Events WHERE id = ?")
+                .bind(event_id)
                 .execute(&mut **tx)
                 .await
                 .unwrap();
+            None
         }
+        Err(err) => panic!("Couldn't insert Git event @ {}: {}", datetime, err),
     }
+}
 
-    async fn update_last_sync_timestamp(tx: &mut Transaction<'static, MySql>) {
-        let format =
-            format_description::parse("[year]-[month]-[day] [hour]:[minute]:[second]").unwrap();
+/// An event for `add_unique_events`, see `add_unique_event` for its fields
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NewEvent<'a> {
+    pub datetime: DateTime<Utc>,
+    pub action_id: u64,
+    pub project_id: u64,
+    pub commit_count: Option<u64>,
+    pub account: Option<&'a str>,
+    pub details: EventDetails<'a>,
+}
 
-        sqlx::query("UPDATE GitPlatforms SET lastSync = ? WHERE name = ?")
-            .bind(OffsetDateTime::now_utc().format(&format).unwrap())
-            .bind(Self::GIT_PLATFORM_ID)
-            .execute(&mut **tx)
-            .await
-            .unwrap();
+/// Events inserted with the same two statements by `add_unique_events`
+const EVENT_BATCH_SIZE: usize = 100;
+
+/// What `GitEvents_UNIQUE` tells events apart by: project, action, second, commit count and account
+type EventKey = (u64, u64, NaiveDateTime, u64, String);
+
+impl NewEvent<'_> {
+    fn key(&self) -> EventKey {
+        (
+            self.project_id,
+            self.action_id,
+            self.datetime.naive_utc().with_nanosecond(0).unwrap(),
+            self.commit_count.unwrap_or(0),
+            self.account.unwrap_or_default().to_string(),
+        )
     }
+}
 
-    async fn get_last_sync_timestamp() -> Option<DateTime<Utc>> {
-        let db = database::Database::get_or_init().await;
-        let pool = db.get_pool().await;
+/// Project, action and second of an event
+type EventSecond = (u64, u64, NaiveDateTime);
 
-        // Starting transaction 💪
-        let mut tx = pool.begin().await.expect("Couldn't start transaction!");
-        let tx_ref = tx.borrow_mut();
-        Self::set_platform(tx_ref).await; // TODO: Only do this at initial setup
+/// What a stored event has in the columns of `GitEvents_UNIQUE` beyond its `EventSecond`, NULL as `None`
+#[derive(Debug)]
+struct StoredKey {
+    commit_count: Option<u64>,
+    account: Option<String>,
+}
+
+/// The stored events by `EventSecond`
+#[derive(Debug, Default)]
+struct StoredKeys(HashMap<EventSecond, Vec<StoredKey>>);
+
+impl StoredKeys {
+    /// Like `GitEvents_UNIQUE`, except that events stored without a commit count or account (as they were
+    /// before those were stored) match any, also under the actions in `LEGACY_ACTIONS` they're mapped onto now.
+    /// Syncing them again doesn't store them a second time.
+    fn contains(&self, event: &NewEvent) -> bool {
+        let (project_id, action_id, second, commit_count, account) = event.key();
+        self.0.get(&(project_id, action_id, second)).is_some_and(|stored| {
+            stored.iter().any(|stored| {
+                stored.commit_count.is_none_or(|stored_count| stored_count == commit_count)
+                    && stored.account.as_ref().is_none_or(|stored_account| *stored_account == account)
+            })
+        })
+    }
+}
+
+/// Actions events were stored as before these were told apart, and an action they're mapped onto now:
+/// created branches were commits, issues were comments (GitHub) or merge requests (GitLab), like deleted
+/// branches and closed milestones. Those events were stored without commit count, account and target.
+static LEGACY_ACTIONS: [(&str, &str); 4] = [
+    ("commit", "created_branch"),
+    ("commented", "issue"),
+    ("merge-request", "issue"),
+    ("merge-request", "project-management"),
+];
+
+/// The stored events of the projects of `events`, within their time range
+async fn stored_keys(tx: &mut Transaction<'static, MySql>, events: &[NewEvent<'_>]) -> StoredKeys {
+    let datetimes = events.iter().map(|event| event.datetime);
+    let (Some(first), Some(last)) = (datetimes.clone().min(), datetimes.max()) else {
+        return StoredKeys::default();
+    };
+    let projects: Vec<u64> = events.iter().map(|event| event.project_id).collect::<BTreeSet<_>>().into_iter().collect();
+
+    let actions: HashMap<String, u64> = sqlx::query_as::<_, (u64, String)>("SELECT id, name FROM GitActions")
+        .fetch_all(&mut **tx)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(id, name)| (name, id))
+        .collect();
 
-        match sqlx::query_scalar("SELECT lastSync FROM GitPlatforms WHERE name = ?")
-            .bind(Self::GIT_PLATFORM_ID)
-            .fetch_optional(&mut **tx_ref)
-            .await {
-                Ok(result) => result,
-                Err(_) => None
+    let mut query = QueryBuilder::<MySql>::new(
+        "SELECT gevt.project_fk, gevt.action_fk, gact.name AS action, gevt.timestamp, gevt.commit_count, \
+        gevt.account, gevt.target_type FROM GitEvents AS gevt JOIN GitActions AS gact ON gact.id = gevt.action_fk \
+        WHERE gevt.timestamp BETWEEN ",
+    );
+    query
+        .push_bind(first.format("%Y-%m-%d %H:%M:%S").to_string())
+        .push(" AND ")
+        .push_bind(last.format("%Y-%m-%d %H:%M:%S").to_string())
+        .push(" AND gevt.project_fk IN ");
+    push_ids(&mut query, &projects);
 
+    let mut stored = StoredKeys::default();
+    for row in query.build().fetch_all(&mut **tx).await.unwrap() {
+        let (project_id, action_id, timestamp): EventSecond = (
+            row.try_get("project_fk").unwrap(),
+            row.try_get("action_fk").unwrap(),
+            row.try_get("timestamp").unwrap(),
+        );
+        let key = StoredKey {
+            commit_count: row.try_get("commit_count").unwrap(),
+            account: row.try_get("account").unwrap(),
+        };
+        let target_type: Option<String> = row.try_get("target_type").unwrap();
+        if key.commit_count.is_none() && key.account.is_none() && target_type.is_none() {
+            let action: String = row.try_get("action").unwrap();
+            let successors = LEGACY_ACTIONS
+                .iter()
+                .filter(|(legacy, _)| *legacy == action)
+                .filter_map(|(_, successor)| actions.get(*successor));
+            for successor_id in successors {
+                let legacy_key = StoredKey { commit_count: None, account: None };
+                stored.0.entry((project_id, *successor_id, timestamp)).or_default().push(legacy_key);
             }
+        }
+        stored.0.entry((project_id, action_id, timestamp)).or_default().push(key);
     }
+    stored
+}
 
-    async fn get_git_action_by_name(
-        tx: &mut Transaction<'static, MySql>,
-        action_name: &str,
-    ) -> Option<u64> {
-        let mut rows = sqlx::query("SELECT id FROM GitActions WHERE name = ?")
-            .bind(action_name)
-            .fetch(&mut **tx);
-
-        let mut number_of_actions = 0;
-        let mut git_action_id = Option::None;
-        while let Some(row) = rows.try_next().await.unwrap() {
-            if number_of_actions > 0 {
-                error!(
-                    "There are more than 1x Git Actions with the same name! (name={}) - skipping this event!",
-                    action_name
+/// Like `add_unique_event` for all `events`, with two statements per batch instead of two per event.
+/// Events stored before are looked up once for all of them, only the rest is inserted.
+/// The ids are in the order of `events`, `None` for the duplicates.
+pub async fn add_unique_events(tx: &mut Transaction<'static, MySql>, events: &[NewEvent<'_>]) -> Vec<Option<u64>> {
+    let stored = stored_keys(tx, events).await;
+    let unknown: Vec<(usize, NewEvent)> = events
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(_, event)| !stored.contains(event))
+        .collect();
+
+    let mut ids = vec![None; events.len()];
+    for batch in unknown.chunks(EVENT_BATCH_SIZE) {
+        let (indices, batch): (Vec<usize>, Vec<NewEvent>) = batch.iter().copied().unzip();
+        for (index, id) in indices.into_iter().zip(add_batch(tx, &batch).await) {
+            ids[index] = id;
+        }
+    }
+    ids
+}
+
+/// Step between the ids of the rows of one insert, `None` if they can't be told from `LAST_INSERT_ID()`
+static BATCH_ID_STEP: tokio::sync::OnceCell<Option<u64>> = tokio::sync::OnceCell::const_new();
+
+/// Ids of a multi-row insert are only consecutive (apart from `auto_increment_increment`) if InnoDB
+/// doesn't interleave them with concurrent inserts, which MySQL 8 does by default
+async fn batch_id_step(conn: &mut MySqlConnection) -> Option<u64> {
+    *BATCH_ID_STEP
+        .get_or_init(|| async {
+            let (lock_mode, increment): (u64, u64) = sqlx::query_as(
+                "SELECT CAST(@@innodb_autoinc_lock_mode AS UNSIGNED), CAST(@@auto_increment_increment AS UNSIGNED)",
+            )
+            .fetch_one(conn)
+            .await
+            .expect("Couldn't read the auto increment settings!");
+            if lock_mode == 2 {
+                warn!(
+                    "innodb_autoinc_lock_mode is 2 (interleaved), so events are inserted one by one. \
+                    Set it to 1 (consecutive) to insert them in batches."
                 );
-                return Option::None;
+                return None;
             }
+            Some(increment)
+        })
+        .await
+}
 
-            number_of_actions += 1;
-            git_action_id = Some(row.try_get("id").unwrap());
+/// The database still rejects duplicates within the batch or stored in the meantime
+async fn add_batch(tx: &mut Transaction<'static, MySql>, batch: &[NewEvent<'_>]) -> Vec<Option<u64>> {
+    let Some(id_step) = batch_id_step(tx).await else {
+        return add_one_by_one(tx, batch).await;
+    };
+
+    // Savepoint, so a failed batch leaves nothing behind for the fallback
+    let mut savepoint = tx.begin().await.expect("Couldn't start savepoint!");
+    match insert_batch(&mut savepoint, batch, id_step).await {
+        Ok(ids) => {
+            savepoint.commit().await.expect("Couldn't release savepoint!");
+            ids
+        }
+        Err(err) => {
+            savepoint.rollback().await.expect("Couldn't roll back savepoint!");
+            warn!("Couldn't insert {} events at once, inserting them one by one: {}", batch.len(), err);
+            add_one_by_one(tx, batch).await
         }
+    }
+}
 
-        git_action_id
+async fn add_one_by_one(tx: &mut Transaction<'static, MySql>, batch: &[NewEvent<'_>]) -> Vec<Option<u64>> {
+    let mut ids = Vec::with_capacity(batch.len());
+    for event in batch {
+        ids.push(
+            add_unique_event(
+                tx,
+                event.datetime,
+                event.action_id,
+                event.project_id,
+                event.commit_count,
+                event.account,
+                event.details,
+            )
+            .await,
+        );
     }
+    ids
+}
 
-    async fn count_all_matching_events(
-        tx: &mut Transaction<'static, MySql>,
-        datetime: &DateTime<Utc>,
-        action_id: &u64,
-        project_id: &u64,
-    ) -> i64 {
-        // i64 needed by sqlx return type
-        let result = sqlx::query(
-            "SELECT COUNT(1) AS CNT FROM GitEvents AS ge, Events AS e \
-                WHERE ge.id = e.id \
-                AND e.timestamp = ? \
-                AND ge.project_fk = ? \
-                AND ge.action_fk = ?",
-        )
-        .bind(datetime.format("%Y-%m-%d %H:%M:%S").to_string())
-        .bind(project_id)
-        .bind(action_id)
-        .fetch_one(&mut **tx);
+/// `id_step` is the `auto_increment_increment`, see `batch_id_step`
+async fn insert_batch(
+    conn: &mut MySqlConnection,
+    batch: &[NewEvent<'_>],
+    id_step: u64,
+) -> Result<Vec<Option<u64>>, sqlx::Error> {
+    let timestamps: Vec<String> =
+        batch.iter().map(|event| event.datetime.format("%Y-%m-%d %H:%M:%S").to_string()).collect();
+
+    let mut events = QueryBuilder::<MySql>::new("INSERT INTO Events (timestamp) ");
+    events.push_values(&timestamps, |mut row, timestamp| {
+        row.push_bind(timestamp);
+    });
+    // `LAST_INSERT_ID()` is the id of the first row, instead of MariaDB's `RETURNING` which MySQL lacks
+    let inserted = events.build().execute(&mut *conn).await?;
+    if inserted.rows_affected() != batch.len() as u64 {
+        return Err(sqlx::Error::Protocol(format!(
+            "inserted {} of {} events",
+            inserted.rows_affected(),
+            batch.len()
+        )));
+    }
+    let event_ids: Vec<u64> = (0..batch.len() as u64).map(|row| inserted.last_insert_id() + row * id_step).collect();
+
+    // Duplicates, of stored events or within the batch, are left alone
+    let mut git_events = QueryBuilder::<MySql>::new(
+        "INSERT INTO GitEvents (id, action_fk, project_fk, timestamp, commit_count, account, git_ref, target_type) ",
+    );
+    git_events.push_values(batch.iter().zip(&event_ids).zip(&timestamps), |mut row, ((event, id), timestamp)| {
+        row.push_bind(*id)
+            .push_bind(event.action_id)
+            .push_bind(event.project_id)
+            .push_bind(timestamp)
+            .push_bind(event.commit_count)
+            .push_bind(event.account)
+            .push_bind(event.details.git_ref)
+            .push_bind(event.details.target_type);
+    });
+    git_events.push(" ON DUPLICATE KEY UPDATE id = id");
+    git_events.build().execute(&mut *conn).await?;
 
-        let query_option = Some(result.await.unwrap().try_get("CNT").unwrap());
+    let mut stored = QueryBuilder::<MySql>::new("SELECT id FROM GitEvents WHERE id IN ");
+    push_ids(&mut stored, &event_ids);
+    let stored: HashSet<u64> = stored.build_query_scalar().fetch_all(&mut *conn).await?.into_iter().collect();
 
-        if query_option.is_none() {
-            return 0;
+    let duplicates: Vec<u64> = event_ids.iter().copied().filter(|id| !stored.contains(id)).collect();
+    if !duplicates.is_empty() {
+        let mut orphans = QueryBuilder::<MySql>::new("DELETE FROM Events WHERE id IN ");
+        push_ids(&mut orphans, &duplicates);
+        orphans.build().execute(&mut *conn).await?;
+    }
+    trace!("Inserted {} of {} Git events", stored.len(), batch.len());
+
+    Ok(event_ids.into_iter().map(|id| stored.contains(&id).then_some(id)).collect())
+}
+
+/// Maps GitLab's `action_name`, GitHub's event `type`, Gitea's `op_type` and the names given to the
+/// Bitbucket, Sourcehut and Azure DevOps events onto the actions shared by all platforms.
+/// Unknown names are `None`, callers collect them in `UnknownActions`.
+///
+/// GitLab names opening and closing issues like it does for merge requests, those are told apart
+/// by their target in `GitlabEvent::action`.
+pub fn map_action(input: &str) -> Option<&'static str> {
+    match input {
+        // GitLab, see `Event#action_name` of GitLab. Merged merge requests are "accepted".
+        "pushed to" | "pushed new" => Some("commit"),
+        "closed" | "accepted" | "opened" | "reopened" | "merged" => Some("merge-request"),
+        "approved" => Some("reviewed"),
+        "commented on" => Some("commented"),
+        // "deleted" is a deleted branch or tag like GitHub's DeleteEvent, "added" and "removed" are designs
+        "deleted" | "created" | "imported" | "updated" | "destroyed" | "added" | "removed" | "joined" | "left"
+        | "removed due to membership expiration from" => Some("project-management"),
+        // GitHub, a CreateEvent is a new branch or tag (or the repository itself)
+        "PushEvent" => Some("commit"),
+        "CreateEvent" => Some("created_branch"),
+        "PullRequestEvent" => Some("merge-request"),
+        "IssuesEvent" => Some("issue"),
+        "PullRequestReviewEvent" | "PullRequestReviewThreadEvent" => Some("reviewed"),
+        "IssueCommentEvent" | "CommitCommentEvent" | "PullRequestReviewCommentEvent" | "DiscussionEvent" => {
+            Some("commented")
+        }
+        "ReleaseEvent" => Some("released"),
+        // Starring is a WatchEvent for historical reasons
+        "WatchEvent" => Some("starred"),
+        "ForkEvent" => Some("forked"),
+        "DeleteEvent" | "GollumEvent" | "MemberEvent" | "PublicEvent" | "SponsorshipEvent" => {
+            Some("project-management")
+        }
+        // Gitea, see `ActionType` of Gitea. Mirror syncs are skipped before, they aren't done by the user.
+        // New branches are only pushed commits there, new tags are like GitHub's CreateEvent.
+        "commit_repo" => Some("commit"),
+        "push_tag" => Some("created_branch"),
+        "create_pull_request" | "merge_pull_request" | "close_pull_request" | "reopen_pull_request"
+        | "auto_merge_pull_request" | "pull_request_ready_for_review" => Some("merge-request"),
+        "create_issue" | "close_issue" | "reopen_issue" => Some("issue"),
+        "approve_pull_request" | "reject_pull_request" | "pull_review_dismissed" => Some("reviewed"),
+        "comment_issue" | "comment_pull" => Some("commented"),
+        "publish_release" => Some("released"),
+        "star_repo" => Some("starred"),
+        "create_repo" | "rename_repo" | "transfer_repo" | "delete_tag" | "delete_branch" | "watch_repo" => {
+            Some("project-management")
         }
+        // Bitbucket, named like its webhook events as it has no events API
+        "repo:push" => Some("commit"),
+        "pullrequest:updated" | "pullrequest:fulfilled" | "pullrequest:rejected" | "pullrequest:superseded" => {
+            Some("merge-request")
+        }
+        "pullrequest:approved" | "pullrequest:changes_request_created" => Some("reviewed"),
+        "pullrequest:comment_created" => Some("commented"),
+        // Sourcehut, only commits are known
+        "pushed" => Some("commit"),
+        // Azure DevOps, named like its service hook events
+        "git.push" => Some("commit"),
+        "git.pullrequest.created" | "git.pullrequest.merged" | "git.pullrequest.updated" => Some("merge-request"),
+        _ => None,
+    }
+}
 
-        let number_of_rows = query_option.unwrap();
+/// Optional columns of `GitEvents` only some platforms know, all `None` by default
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EventDetails<'a> {
+    pub git_ref: Option<&'a str>,
+    pub target_type: Option<&'a str>,
+}
 
-        if number_of_rows > 1 {
-            error!(
-                "There are {}x events with the same action (id={}) on the same project (id={}) at the same time ({}). \
-                This means there are already duplicate events in your DB!",
-                number_of_rows, action_id, project_id, datetime
+/// Why fetching from a platform failed, reported by the sync run instead of panicking its task
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlatformError {
+    /// No answer at all, e.g. the connection was refused
+    Request(String),
+    /// Answered with an unexpected status
+    Status { status: u16, message: String },
+    /// Body or headers aren't what the API documents
+    InvalidResponse(String),
+    /// Still rate limited after `retries` retries
+    RateLimited { status: u16, retries: u32 },
+}
+
+impl fmt::Display for PlatformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlatformError::Request(message) | PlatformError::InvalidResponse(message) => write!(f, "{}", message),
+            PlatformError::Status { message, .. } => write!(f, "{}", message),
+            PlatformError::RateLimited { status, retries } => {
+                write!(f, "{}", PaginationError::RateLimited { status: *status, retries: *retries })
+            }
+        }
+    }
+}
+
+impl PlatformError {
+    /// Rate limits end a sync early, with what was fetched until then, instead of failing it
+    pub fn truncation(&self) -> Option<PaginationError> {
+        match self {
+            PlatformError::RateLimited { status, retries } => Some(PaginationError::RateLimited {
+                status: *status,
+                retries: *retries,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Ends fetching with the `events` so far if `err` is a rate limit (see `PlatformError::truncation`),
+/// anything else fails the sync
+pub fn stop_early<E>(events: Vec<E>, err: PlatformError) -> Result<FetchedEvents<E>, PlatformError> {
+    match err.truncation() {
+        Some(reason) => Ok(FetchedEvents::truncated(events, reason)),
+        None => Err(err),
+    }
+}
+
+/// Moves `lastSync` of `platform` to `synced_at`, which only takes effect once `tx` is committed,
+/// so an interrupted sync is fetched again
+pub async fn set_last_sync(tx: &mut Transaction<'static, MySql>, platform: &str, synced_at: DateTime<Utc>) {
+    sqlx::query("UPDATE GitPlatforms SET lastSync = ? WHERE name = ?")
+        .bind(synced_at.format("%Y-%m-%d %H:%M:%S").to_string())
+        .bind(platform)
+        .execute(&mut **tx)
+        .await
+        .unwrap();
+}
+
+pub async fn last_sync_of(pool: &Pool<MySql>, platform: &str) -> Option<DateTime<Utc>> {
+    sqlx::query_scalar::<_, Option<DateTime<Utc>>>("SELECT lastSync FROM GitPlatforms WHERE name = ?")
+        .bind(platform)
+        .fetch_optional(pool)
+        .await
+        .unwrap()
+        .flatten()
+}
+
+/// Action names `map_action_name` doesn't know, collected during a sync so each is only logged once
+#[derive(Debug, Default)]
+pub struct UnknownActions(BTreeMap<String, u32>);
+
+impl UnknownActions {
+    pub fn record(&mut self, action_name: &str) {
+        *self.0.entry(action_name.to_string()).or_default() += 1;
+    }
+
+    pub fn log(&self, platform: &str) {
+        for (action_name, count) in &self.0 {
+            warn!(
+                "Skipped {} {} event(s) with unknown action name »{}« - pls open a issue, so this action name can be added!",
+                count, platform, action_name
+            );
+        }
+    }
+}
+
+/// Why `InsertEvents::insert_events` leaves out an event, counted in `SyncResult`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Skipped<'a> {
+    /// Its action, see `UnknownActions`
+    UnknownAction(&'a str),
+    /// Its project couldn't be resolved
+    #[cfg_attr(not(feature = "github"), allow(dead_code))]
+    ProjectError,
+    /// See `SyncResult::skipped_other`
+    Other,
+}
+
+/// What a sync did with the events it fetched, every fetched event is either inserted or skipped
+/// (or stays behind because the sync stopped early)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncResult {
+    pub fetched: usize,
+    pub inserted: usize,
+    /// `map_action_name` doesn't know their action, see `UnknownActions`
+    pub skipped_unknown_action: usize,
+    /// Already stored by an earlier sync
+    pub skipped_duplicates: usize,
+    /// Their project couldn't be resolved, so they can't be stored
+    pub skipped_project_errors: usize,
+    /// Older than the retention, they were pruned (and rolled up) already
+    pub skipped_expired: usize,
+    /// On purpose or unusable, e.g. filtered out or with a date which couldn't be parsed
+    pub skipped_other: usize,
+    /// Set by `sync_and_record`
+    pub duration: Duration,
+    /// Why fetching (or inserting) stopped early, what made it until then is kept
+    pub truncated: Option<PaginationError>,
+    /// Only for platforms reporting it
+    pub rate_limit: Option<RateLimitStatus>,
+}
+
+/// Syncs `platform` and records the outcome as a sync run
+pub async fn sync_and_record<P: GitPlatform>(platform: &mut P) -> SyncReport {
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let result = match platform.update_provider().await {
+        Ok(result) => {
+            let result = SyncResult {
+                duration: started.elapsed(),
+                ..result
+            };
+            info!(
+                "Synced {} in {:?}: {} fetched, {} inserted, skipped {} duplicate(s), {} with unknown actions, {} without project, {} expired, {} other",
+                P::GIT_PLATFORM_ID,
+                result.duration,
+                result.fetched,
+                result.inserted,
+                result.skipped_duplicates,
+                result.skipped_unknown_action,
+                result.skipped_project_errors,
+                result.skipped_expired,
+                result.skipped_other
             );
+            Ok(result)
         }
+        Err(err) => {
+            error!("Syncing {} failed: {}", P::GIT_PLATFORM_ID, err);
+            Err(err.to_string())
+        }
+    };
+    record_sync_run(P::GIT_PLATFORM_ID, started_at, result).await
+}
 
-        number_of_rows
+/// Records the outcome of a sync of `platform` and returns it as a report
+pub async fn record_sync_run(platform: &str, started_at: DateTime<Utc>, result: Result<SyncResult, String>) -> SyncReport {
+    let run = sync_run(platform, started_at, result);
+    sync_runs::record_sync_run(&run).await;
+    if run.events_inserted > 0 {
+        stats::invalidate_today_cache();
     }
 
-    async fn fetch_single_git_project_from_db(
+    SyncReport::from(&run)
+}
+
+/// The outcome of a sync of `platform` which started at `started_at` and is done now
+pub fn sync_run(platform: &str, started_at: DateTime<Utc>, result: Result<SyncResult, String>) -> NewSyncRun {
+    let (result, status, error_message) = match result {
+        Ok(result) => {
+            let (status, error_message) = match &result.truncated {
+                None => (SyncRunStatus::Success, None),
+                Some(reason @ PaginationError::RateLimited { .. }) => {
+                    (SyncRunStatus::RateLimited, Some(reason.to_string()))
+                }
+                Some(reason) => (SyncRunStatus::Truncated, Some(reason.to_string())),
+            };
+            (result, status, error_message)
+        }
+        Err(err) => (SyncResult::default(), SyncRunStatus::Failed, Some(err)),
+    };
+
+    NewSyncRun {
+        platform: platform.to_string(),
+        started_at,
+        finished_at: Utc::now(),
+        events_fetched: result.fetched as u32,
+        events_inserted: result.inserted as u32,
+        skipped_unknown_action: result.skipped_unknown_action as u32,
+        skipped_duplicates: result.skipped_duplicates as u32,
+        skipped_project_errors: result.skipped_project_errors as u32,
+        skipped_expired: result.skipped_expired as u32,
+        skipped_other: result.skipped_other as u32,
+        status,
+        error_message,
+        rate_limit: result.rate_limit,
+    }
+}
+
+/// Platforms whose events are stored one by one with `insert_events`
+pub trait InsertEvents: GitPlatform {
+    /// Resolves the action and project of an event, or tells why it's skipped
+    async fn to_new_event<'e>(
+        &self,
         tx: &mut Transaction<'static, MySql>,
-        platform_project_id: u64,
-    ) -> Option<GitProject> {
-        let mut rows =
-            sqlx::query("SELECT id, platform_project_id, name, url FROM GitProjects WHERE platform_project_id = ? AND platform = ?")
-            .bind(platform_project_id)
-            .bind(Self::GIT_PLATFORM_ID)
-            .fetch(&mut **tx);
-
-        let mut number_of_projects = 0;
-        let mut github_project = Option::None;
-        while let Some(row) = rows.try_next().await.unwrap() {
-            if number_of_projects > 0 {
-                error!(
-                    "There are more than 1x Git projects in DB (id={}, platform={}) - skipping this event!",
-                    platform_project_id, Self::GIT_PLATFORM_ID
-                );
-                return Option::None;
+        cache: &mut SyncCache,
+        event: &'e Self::GitEventAPI,
+    ) -> Result<NewEvent<'e>, Skipped<'e>>;
+
+    /// Inserts `events` in one transaction. Fetched ones are archived first, and if they're `complete`
+    /// the last sync moves on. Every event is either inserted or counted as skipped.
+    async fn insert_events(&self, events: &[Self::GitEventAPI], source: EventSource, complete: bool) -> SyncResult {
+        let db = database::Database::get_or_init().await;
+        let pool = db.get_pool().await;
+
+        info!("Starting to insert events from {}", Self::GIT_PLATFORM_ID);
+        let mut result = SyncResult {
+            fetched: events.len(),
+            ..SyncResult::default()
+        };
+        let mut new_events = Vec::new();
+        let mut unknown_actions = UnknownActions::default();
+
+        let started = Instant::now();
+        let mut tx = pool.begin().await.expect("Couldn't start transaction!");
+        Self::set_platform(&mut tx).await;
+        if source == EventSource::Fetched {
+            event_archive::archive(&mut tx, Self::GIT_PLATFORM_ID, events).await;
+        }
+        let mut cache = SyncCache::load(&mut tx, Self::GIT_PLATFORM_ID).await;
+
+        for event in events {
+            match self.to_new_event(&mut tx, &mut cache, event).await {
+                Ok(new_event) => new_events.push(new_event),
+                Err(Skipped::UnknownAction(action_name)) => {
+                    unknown_actions.record(action_name);
+                    result.skipped_unknown_action += 1;
+                }
+                Err(Skipped::ProjectError) => result.skipped_project_errors += 1,
+                Err(Skipped::Other) => result.skipped_other += 1,
             }
+        }
 
-            number_of_projects += 1;
-            let id: u64 = row.try_get("id").unwrap();
-            let platform_project_id: u64 = row.try_get("platform_project_id").unwrap();
-            let name: &str = row.try_get("name").unwrap();
-            let url: &str = row.try_get("url").unwrap();
-            github_project = Some(GitProject {
-                id,
-                platform_project_id,
-                name: name.to_string(),
-                url: url.to_string(),
-            });
+        let inserted_at = Self::insert_unique_events(&mut tx, &new_events, &mut result).await;
+        unknown_actions.log(Self::GIT_PLATFORM_ID);
+        if complete && source == EventSource::Fetched {
+            Self::update_last_sync_timestamp(&mut tx).await;
         }
+        tx.commit().await.expect("Couldn't apply transaction ._.");
+        records::update_stat_records(&pool, &inserted_at).await;
+        info!(
+            "Inserted {} new {} events from {} total events into DB in {:?}",
+            result.inserted,
+            Self::GIT_PLATFORM_ID,
+            result.fetched,
+            started.elapsed()
+        );
+        result
+    }
+}
 
-        github_project
+/// An event as fetched from a platform, serialized into `EventArchive` (see `event_archive`)
+pub trait GitEventAPI: Serialize + DeserializeOwned {
+    /// The platform's id of the event, for platforms which have one
+    fn external_id(&self) -> Option<String> {
+        None
+    }
+}
+
+pub trait GitPlatform {
+    const GIT_PLATFORM_ID: &'static str;
+    type GitEventAPI: GitEventAPI;
+
+    /// Reports every missing or invalid env var at once, see `config::EnvConfig`
+    fn init_from_env_vars() -> Result<Self, ConfigError>
+    where
+        Self: Sized;
+
+    /// Fetches and inserts the new events, see `sync_and_record` for recording the outcome
+    async fn update_provider(&mut self) -> Result<SyncResult, PlatformError>;
+
+    // pub fn get_or_init() {
+    //     GITHUB.get_or_init(|| Self::init_from_env_vars());
+    // }
+
+    async fn get_events(&mut self) -> Result<FetchedEvents<Self::GitEventAPI>, PlatformError>;
+
+    /// Inserts events loaded from the archive like fetched ones, without archiving them again
+    /// or moving the last sync, see `event_archive::reprocess`
+    async fn insert_archived_events(&self, events: Vec<Self::GitEventAPI>) -> Result<SyncResult, PlatformError>;
+
+    async fn set_platform(tx: &mut Transaction<'static, MySql>) {
+        set_platform_named(tx, Self::GIT_PLATFORM_ID).await
+    }
+
+    /// Marks now as the last sync, the next sync only fetches what's newer (see `get_last_sync_timestamp`)
+    async fn update_last_sync_timestamp(tx: &mut Transaction<'static, MySql>) {
+        set_last_sync(tx, Self::GIT_PLATFORM_ID, Utc::now()).await
     }
 
+    /// `None` until the first sync of this platform went through
+    async fn get_last_sync_timestamp() -> Option<DateTime<Utc>> {
+        let db = database::Database::get_or_init().await;
+        let pool = db.get_pool().await;
+        last_sync_of(&pool, Self::GIT_PLATFORM_ID).await
+    }
+
+    /// Adds `project` to `GitProjects` and returns the id it got there, `project.id` is ignored.
+    /// `visibility` is left NULL for platforms which don't report it.
     async fn write_project_to_db(
         &self,
         tx: &mut Transaction<'static, MySql>,
         project: &GitProject,
+        visibility: Option<ProjectVisibility>,
     ) -> u64 {
-        let project_id =
-            sqlx::query("INSERT INTO GitProjects (platform, platform_project_id, name, url) VALUES ( ?, ?, ?, ? )")
-            .bind(Self::GIT_PLATFORM_ID)
-            .bind(project.id.clone())
-            .bind(project.name.clone())
-            .bind(project.url.clone())
-            .execute(&mut **tx)
-            .await
-            .unwrap()
-            .last_insert_id();
+        let project_id = sqlx::query(
+            "INSERT INTO GitProjects (platform, platform_project_id, name, url, pseudonymous, unavailable_since, visibility) \
+            VALUES ( ?, ?, ?, ?, ?, ?, ? )",
+        )
+        .bind(Self::GIT_PLATFORM_ID)
+        .bind(project.platform_project_id)
+        .bind(&project.name)
+        .bind(&project.url)
+        .bind(project.pseudonymous)
+        .bind(project.unavailable_since.map(|since| since.format("%Y-%m-%d %H:%M:%S").to_string()))
+        .bind(visibility.map(|visibility| visibility.as_str()))
+        .execute(&mut **tx)
+        .await
+        .unwrap()
+        .last_insert_id();
         trace!(
             "Inserted GitProject ({}) id: {}",
             Self::GIT_PLATFORM_ID,
@@ -222,145 +984,415 @@ pub trait GitPlatform {
         project_id
     }
 
-    async fn insert_git_action(tx: &mut Transaction<'static, MySql>, action_name: &str) -> u64 {
-        let action_id = sqlx::query("INSERT INTO GitActions (name) VALUES ( ? )")
-            .bind(action_name)
-            .execute(&mut **tx)
-            .await
-            .unwrap()
-            .last_insert_id();
-        trace!(
-            "Inserted Git action ({}) - id: {} ({})",
-            Self::GIT_PLATFORM_ID,
-            action_id,
-            action_name
-        );
-        return action_id;
+    /// Dates as platforms send them, a relaxed form of RFC3339. Events with another one are skipped.
+    fn parse_datetime(value: &str) -> Result<DateTime<Utc>, Skipped<'static>> {
+        value.parse().map_err(|err| {
+            error!(
+                "Couldn't parse date from {} using a relaxed form of RFC3339. Event will be skipped! \
+                Received value: {} - error msg: {}",
+                Self::GIT_PLATFORM_ID,
+                value,
+                err
+            );
+            Skipped::Other
+        })
     }
 
-    async fn insert_event(tx: &mut Transaction<'static, MySql>, datetime: DateTime<Utc>) -> u64 {
-        let event_id = sqlx::query("INSERT INTO Events (timestamp) VALUES ( ? )")
-            .bind(datetime.format("%Y-%m-%d %H:%M:%S").to_string())
-            .execute(&mut **tx)
-            .await
-            .unwrap()
-            .last_insert_id();
-        trace!(
-            "Inserted Git event ({}) - id: {} @ {}",
-            Self::GIT_PLATFORM_ID,
-            event_id,
-            datetime
+    /// Inserts the events which aren't stored yet and counts them in `result`, see `add_unique_events`.
+    /// Events older than the retention were pruned (and rolled up) already, they're skipped as expired.
+    /// Returns when the inserted ones happened.
+    async fn insert_unique_events(
+        tx: &mut Transaction<'static, MySql>,
+        events: &[NewEvent<'_>],
+        result: &mut SyncResult,
+    ) -> Vec<DateTime<Utc>> {
+        let cutoff = retention::cutoff(Utc::now());
+        let (expired, events): (Vec<NewEvent>, Vec<NewEvent>) =
+            events.iter().copied().partition(|event| cutoff.is_some_and(|cutoff| event.datetime < cutoff));
+        if !expired.is_empty() {
+            debug!("Skipping {} events older than the retention", expired.len());
+            result.skipped_expired += expired.len();
+        }
+
+        let ids = add_unique_events(tx, &events).await;
+        let mut inserted_at = Vec::new();
+        for (event, id) in events.iter().zip(ids) {
+            if id.is_some() {
+                inserted_at.push(event.datetime);
+                result.inserted += 1;
+            } else {
+                debug!("Skipping insert! Event already exists @ {}", event.datetime);
+                result.skipped_duplicates += 1;
+            }
+        }
+        inserted_at
+    }
+
+    /// Maps the platform's action names onto the actions shared by all platforms, see `map_action`
+    fn map_action_name(input: &str) -> Option<&str> {
+        map_action(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn action_names_are_mapped_onto_shared_actions() {
+        let expected = [
+            // GitLab
+            ("pushed to", "commit"),
+            ("pushed new", "commit"),
+            ("closed", "merge-request"),
+            ("accepted", "merge-request"),
+            ("opened", "merge-request"),
+            ("reopened", "merge-request"),
+            ("merged", "merge-request"),
+            ("approved", "reviewed"),
+            ("commented on", "commented"),
+            ("deleted", "project-management"),
+            ("created", "project-management"),
+            ("imported", "project-management"),
+            ("updated", "project-management"),
+            ("destroyed", "project-management"),
+            ("added", "project-management"),
+            ("removed", "project-management"),
+            ("joined", "project-management"),
+            ("left", "project-management"),
+            ("removed due to membership expiration from", "project-management"),
+            // GitHub
+            ("PushEvent", "commit"),
+            ("CreateEvent", "created_branch"),
+            ("PullRequestEvent", "merge-request"),
+            ("PullRequestReviewEvent", "reviewed"),
+            ("PullRequestReviewThreadEvent", "reviewed"),
+            ("IssueCommentEvent", "commented"),
+            ("IssuesEvent", "issue"),
+            ("CommitCommentEvent", "commented"),
+            ("PullRequestReviewCommentEvent", "commented"),
+            ("DiscussionEvent", "commented"),
+            ("ReleaseEvent", "released"),
+            ("WatchEvent", "starred"),
+            ("DeleteEvent", "project-management"),
+            ("ForkEvent", "forked"),
+            ("GollumEvent", "project-management"),
+            ("MemberEvent", "project-management"),
+            ("PublicEvent", "project-management"),
+            ("SponsorshipEvent", "project-management"),
+            // Gitea
+            ("commit_repo", "commit"),
+            ("create_pull_request", "merge-request"),
+            ("merge_pull_request", "merge-request"),
+            ("close_pull_request", "merge-request"),
+            ("reopen_pull_request", "merge-request"),
+            ("auto_merge_pull_request", "merge-request"),
+            ("pull_request_ready_for_review", "merge-request"),
+            ("approve_pull_request", "reviewed"),
+            ("reject_pull_request", "reviewed"),
+            ("pull_review_dismissed", "reviewed"),
+            ("comment_issue", "commented"),
+            ("comment_pull", "commented"),
+            ("create_issue", "issue"),
+            ("close_issue", "issue"),
+            ("reopen_issue", "issue"),
+            ("publish_release", "released"),
+            ("star_repo", "starred"),
+            ("create_repo", "project-management"),
+            ("rename_repo", "project-management"),
+            ("transfer_repo", "project-management"),
+            ("push_tag", "created_branch"),
+            ("delete_tag", "project-management"),
+            ("delete_branch", "project-management"),
+            ("watch_repo", "project-management"),
+            // Bitbucket
+            ("repo:push", "commit"),
+            ("pullrequest:updated", "merge-request"),
+            ("pullrequest:fulfilled", "merge-request"),
+            ("pullrequest:rejected", "merge-request"),
+            ("pullrequest:superseded", "merge-request"),
+            ("pullrequest:approved", "reviewed"),
+            ("pullrequest:changes_request_created", "reviewed"),
+            ("pullrequest:comment_created", "commented"),
+            // Sourcehut
+            ("pushed", "commit"),
+            // Azure DevOps
+            ("git.push", "commit"),
+            ("git.pullrequest.created", "merge-request"),
+            ("git.pullrequest.merged", "merge-request"),
+            ("git.pullrequest.updated", "merge-request"),
+        ];
+
+        for (input, action) in expected {
+            assert_eq!(map_action(input), Some(action), "{}", input);
+            assert!(ACTIONS.contains(&action), "{}", action);
+        }
+        for unknown in ["", "pushEvent", "expired", "Pushed to", "SomethingNewEvent", "mirror_sync_push"] {
+            assert_eq!(map_action(unknown), None, "{}", unknown);
+        }
+    }
+
+    #[test]
+    fn unknown_actions_are_counted_per_name() {
+        let mut unknown = UnknownActions::default();
+        for action_name in ["expired", "SomethingNewEvent", "expired"] {
+            unknown.record(action_name);
+        }
+        assert_eq!(
+            unknown.0.into_iter().collect::<Vec<_>>(),
+            vec![("SomethingNewEvent".to_string(), 1), ("expired".to_string(), 2)]
         );
-        return event_id;
     }
 
-    async fn insert_git_event(
-        tx: &mut Transaction<'static, MySql>,
-        event_id: u64,
-        action_id: u64,
-        project_id: u64,
-    ) -> u64 {
-        sqlx::query("INSERT INTO GitEvents (id, action_fk, project_fk) VALUES ( ?, ?, ? )")
-            .bind(event_id)
+    async fn seeded_pool() -> (testcontainers::ContainerAsync<testcontainers::GenericImage>, Pool<MySql>) {
+        let (container, pool) = crate::database::tests::initialize().await;
+        for statement in [
+            "INSERT INTO GitPlatforms (name, firstSync) VALUES ('Github', NOW())",
+            "INSERT INTO GitActions (id, name) VALUES (1, 'commit')",
+            "INSERT INTO GitProjects (id, name, url, platform, platform_project_id) VALUES (1, '2tefan/pollux', '', 'Github', 1)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        (container, pool)
+    }
+
+    async fn event_count(tx: &mut Transaction<'static, MySql>) -> (i64, i64) {
+        let events: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM Events").fetch_one(&mut **tx).await.unwrap();
+        let git_events: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM GitEvents").fetch_one(&mut **tx).await.unwrap();
+        (events, git_events)
+    }
+
+    #[tokio::test]
+    async fn pushes_in_the_same_second_are_told_apart_by_their_commit_count() {
+        let (_container, pool) = seeded_pool().await;
+        let mut tx = pool.begin().await.unwrap();
+        let pushed = Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        let details = EventDetails::default();
+
+        assert!(add_unique_event(&mut tx, pushed, 1, 1, Some(30), None, details).await.is_some());
+        assert!(add_unique_event(&mut tx, pushed, 1, 1, Some(30), None, details).await.is_none());
+        assert!(add_unique_event(&mut tx, pushed, 1, 1, Some(2), None, details).await.is_some());
+        assert!(add_unique_event(&mut tx, pushed, 1, 1, None, None, details).await.is_some());
+        assert!(add_unique_event(&mut tx, pushed, 1, 1, None, None, details).await.is_none());
+
+        // Rejected duplicates don't leave an `Events` row behind
+        assert_eq!(event_count(&mut tx).await, (3, 3));
+    }
+
+    #[tokio::test]
+    async fn events_are_only_deduplicated_within_one_account() {
+        let (_container, pool) = seeded_pool().await;
+        let mut tx = pool.begin().await.unwrap();
+        let pushed = Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        let details = EventDetails::default();
+
+        assert!(add_unique_event(&mut tx, pushed, 1, 1, None, Some("2tefan"), details).await.is_some());
+        assert!(add_unique_event(&mut tx, pushed, 1, 1, None, Some("2tefan"), details).await.is_none());
+        assert!(add_unique_event(&mut tx, pushed, 1, 1, None, Some("work-account"), details).await.is_some());
+        assert_eq!(event_count(&mut tx).await, (2, 2));
+    }
+
+    /// Stored before commit counts and accounts were, as the migrations leave them
+    async fn seed_legacy_event(tx: &mut Transaction<'static, MySql>, id: u64, action_id: u64, datetime: DateTime<Utc>) {
+        let timestamp = datetime.format("%Y-%m-%d %H:%M:%S").to_string();
+        sqlx::query("INSERT INTO Events (id, timestamp) VALUES (?, ?)")
+            .bind(id)
+            .bind(&timestamp)
+            .execute(&mut **tx)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO GitEvents (id, project_fk, action_fk, timestamp) VALUES (?, 1, ?, ?)")
+            .bind(id)
             .bind(action_id)
-            .bind(project_id)
+            .bind(&timestamp)
             .execute(&mut **tx)
             .await
-            .unwrap()
-            .last_insert_id()
+            .unwrap();
     }
 
-    fn map_action_name(input: &str) -> Option<&str> {
-        match input {
-            "pushed to" | "pushed new" | "PushEvent" | "CreateEvent" => Some("commit"),
-            "deleted" | "closed" | "accepted" | "opened" => Some("merge-request"),
-            "commented on" | "IssueCommentEvent" | "IssuesEvent" => Some("comments"),
-            "created" | "WatchEvent" => Some("project-management"),
-            _ => {
-                warn!("Action name not known! {} - pls open a issue, so this action name can be added! Will just use string as is for now...", input);
-                None
-            }
-        }
+    #[tokio::test]
+    async fn events_stored_without_commit_count_or_account_are_synced_once() {
+        let (_container, pool) = seeded_pool().await;
+        let mut tx = pool.begin().await.unwrap();
+        let pushed = Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        seed_legacy_event(&mut tx, 1, 1, pushed).await;
+
+        let resynced = NewEvent {
+            datetime: pushed,
+            action_id: 1,
+            project_id: 1,
+            commit_count: Some(3),
+            account: Some("2tefan"),
+            details: EventDetails::default(),
+        };
+        assert_eq!(add_unique_events(&mut tx, &[resynced]).await, vec![None]);
+        assert_eq!(add_unique_events(&mut tx, &[NewEvent { account: None, ..resynced }]).await, vec![None]);
+        assert_eq!(event_count(&mut tx).await, (1, 1));
+
+        // Only the same second matches
+        let later = NewEvent {
+            datetime: pushed + chrono::Duration::seconds(1),
+            ..resynced
+        };
+        assert!(add_unique_events(&mut tx, &[later]).await[0].is_some());
+        assert_eq!(event_count(&mut tx).await, (2, 2));
     }
 
-    async fn get_all_git_events(since: NaiveDate) -> Vec<GitEvents> {
-        let db = database::Database::get_or_init().await;
-        let pool = db.get_pool().await;
+    #[tokio::test]
+    async fn events_stored_under_their_former_action_are_synced_once() {
+        let (_container, pool) = seeded_pool().await;
+        let mut tx = pool.begin().await.unwrap();
+        let mut cache = SyncCache::load(&mut tx, "Github").await;
+        let merge_request = cache.action(&mut tx, "merge-request").await;
+        let created = Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        // A created branch stored as commit and an opened issue stored as merge request
+        seed_legacy_event(&mut tx, 1, 1, created).await;
+        seed_legacy_event(&mut tx, 2, merge_request, created).await;
+
+        let created_branch = NewEvent {
+            datetime: created,
+            action_id: cache.action(&mut tx, "created_branch").await,
+            project_id: 1,
+            commit_count: None,
+            account: Some("2tefan"),
+            details: EventDetails::default(),
+        };
+        let opened_issue = NewEvent {
+            action_id: cache.action(&mut tx, "issue").await,
+            account: None,
+            details: EventDetails { target_type: Some("Issue"), ..EventDetails::default() },
+            ..created_branch
+        };
+        assert_eq!(add_unique_events(&mut tx, &[created_branch, opened_issue]).await, vec![None, None]);
+        assert_eq!(event_count(&mut tx).await, (2, 2));
+
+        // Only former actions match, a review wasn't stored as anything else
+        let reviewed = NewEvent {
+            action_id: cache.action(&mut tx, "reviewed").await,
+            ..created_branch
+        };
+        assert!(add_unique_events(&mut tx, &[reviewed]).await[0].is_some());
+        assert_eq!(event_count(&mut tx).await, (3, 3));
+    }
+
+    #[tokio::test]
+    async fn the_sync_cache_finds_projects_and_actions_like_the_database() {
+        let (_container, pool) = seeded_pool().await;
+        let mut tx = pool.begin().await.unwrap();
+        let mut cache = SyncCache::load(&mut tx, "Github").await;
+
+        assert_eq!(cache.project(&mut tx, 1, false).await, fetch_git_project(&mut tx, "Github", 1, false).await);
+        assert_eq!(cache.project(&mut tx, 1, true).await, None);
+        assert_eq!(cache.project(&mut tx, 2, false).await, None);
+
+        // Created during the sync
+        sqlx::query("INSERT INTO GitProjects (id, name, url, platform, platform_project_id) VALUES (2, '2tefan/castor', '', 'Github', 2)")
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        assert_eq!(cache.project(&mut tx, 2, false).await.map(|project| project.id), Some(2));
+
+        assert_eq!(cache.action(&mut tx, "commit").await, 1);
+        let review = cache.action(&mut tx, "review").await;
+        assert_eq!(git_action_id(&mut tx, "review").await, Some(review));
+        assert_eq!(cache.action(&mut tx, "review").await, review);
+    }
+
+    /// Every push three times, some apart by more than a batch
+    fn repeated_pushes() -> Vec<NewEvent<'static>> {
+        let morning = Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        (0..250u32)
+            .map(|i| NewEvent {
+                datetime: morning + chrono::Duration::seconds(i64::from(i % 120)),
+                action_id: 1,
+                project_id: 1,
+                commit_count: Some(u64::from(i % 3)),
+                account: (i % 5 == 0).then_some("work-account"),
+                details: EventDetails {
+                    git_ref: Some("refs/heads/main"),
+                    target_type: None,
+                },
+            })
+            .collect()
+    }
 
-        sqlx::query_as::<_, GitEvents>(
-            r#"
-                SELECT 
-                    evt.timestamp as timestamp, 
-                    gpro.name as project_name, 
-                    gact.name as action,
-                    gpro.platform as platform,
-                    gpro.url as url
-                FROM 
-                    Events AS evt, 
-                    GitEvents AS gevt,
-                    GitActions AS gact,
-                    GitProjects AS gpro
-                WHERE evt.timestamp > ?
-                AND   evt.id = gevt.id
-                AND   gevt.action_fk = gact.id
-                AND   gevt.project_fk = gpro.id
-                ORDER BY evt.timestamp
-                "#,
+    type StoredEvent = (chrono::NaiveDateTime, u64, u64, Option<u64>, Option<String>, Option<String>, Option<String>);
+
+    /// Without the ids, rolled back inserts still use up theirs
+    async fn stored_events(tx: &mut Transaction<'static, MySql>) -> Vec<StoredEvent> {
+        let orphans: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM Events LEFT JOIN GitEvents USING (id) WHERE GitEvents.id IS NULL")
+            .fetch_one(&mut **tx)
+            .await
+            .unwrap();
+        assert_eq!(orphans, 0);
+        sqlx::query_as(
+            "SELECT evt.timestamp, action_fk, project_fk, commit_count, account, git_ref, target_type \
+                FROM Events AS evt JOIN GitEvents AS gevt ON evt.id = gevt.id \
+                ORDER BY evt.timestamp, commit_count, account",
         )
-        .bind(since.to_owned())
-        .fetch_all(&pool)
+        .fetch_all(&mut **tx)
         .await
         .unwrap()
     }
 
-    // // // TODO
-    // pub async fn insert_github_events_into_db(&self, events: Vec<GithubEvent>) {
-    //     let db = database::Database::get_or_init().await;
-    //     let pool = db.get_pool().await;
-
-    //     for event in events.iter() {
-    //         // Starting transaction 💪
-    //         let mut tx = pool.begin().await.expect("Couldn't start transaction!");
-    //         let tx_ref = tx.borrow_mut();
-
-    //         // TODO: Maybe check if name is still up-to-date etc.
-    //         let github_project_option_future =
-    //             Github::fetch_single_github_project_from_db(tx_ref, event.repo.id);
-
-    //         let datetime: DateTime<Utc> = match event.created_at.parse() {
-    //             Ok(datetime) => datetime,
-    //             Err(err) => {
-    //                 // Parsing failed - https://docs.rs/chrono/latest/chrono/struct.DateTime.html#impl-FromStr-for-DateTime%3CUtc%3E
-    //                 error!("Couldn't parse date from Github using a relaxed form of RFC3339. Event will be skipped! Received 'created_at' value: {} - error msg: {}", event.created_at, err);
-    //                 continue;
-    //             }
-    //         };
-
-    //         // Inserting GithubProject
-    //         // TODO fetching name + url from github and insert it, if missing
-    //         let project_id = if let Some(project) = github_project_option_future.await {
-    //             project.id
-    //         } else {
-    //             self.write_project_to_db(tx_ref, &event.repo)
-    //                 .await
-    //         };
-
-    //         // TODO: Handle push_data (multiple commits!)
-    //         let action_id =
-    //             match Github::get_github_action_by_name(tx_ref, &event.type_of_action).await {
-    //                 Some(value) => value,
-    //                 None => Github::insert_github_action(tx_ref, &event.type_of_action).await,
-    //             };
-
-    //         // Add event itself
-    //         let event_id = Github::insert_event(tx_ref, datetime).await;
-
-    //         let github_event_id =
-    //             Github::insert_github_event(&mut tx, event_id, action_id, project_id).await;
-
-    //         tx.commit().await.expect("Couldn't apply transaction ._.");
-    //     }
-    // }
+    #[tokio::test]
+    async fn batches_store_the_same_events_as_single_inserts() {
+        let (_container, pool) = seeded_pool().await;
+        let events = repeated_pushes();
+
+        let mut tx = pool.begin().await.unwrap();
+        let mut single = Vec::new();
+        for event in &events {
+            let id = add_unique_event(
+                &mut tx,
+                event.datetime,
+                event.action_id,
+                event.project_id,
+                event.commit_count,
+                event.account,
+                event.details,
+            )
+            .await;
+            single.push(id.is_some());
+        }
+        let stored = stored_events(&mut tx).await;
+        tx.rollback().await.unwrap();
+        assert_eq!(stored.len(), 120);
+
+        let mut tx = pool.begin().await.unwrap();
+        let batched: Vec<bool> = add_unique_events(&mut tx, &events).await.iter().map(Option::is_some).collect();
+        assert_eq!(batched, single);
+        assert_eq!(stored_events(&mut tx).await, stored);
+
+        // Synced again
+        assert!(add_unique_events(&mut tx, &events).await.iter().all(Option::is_none));
+        assert_eq!(stored_events(&mut tx).await, stored);
+    }
+
+    #[tokio::test]
+    async fn last_sync_only_advances_with_its_transaction() {
+        let (_container, pool) = crate::database::tests::initialize().await;
+        sqlx::query("INSERT INTO GitPlatforms (name, firstSync) VALUES ('Gitea', NOW())")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(last_sync_of(&pool, "Gitea").await, None);
+        assert_eq!(last_sync_of(&pool, "Codeberg").await, None);
+
+        let synced_at = Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        let mut tx = pool.begin().await.unwrap();
+        set_last_sync(&mut tx, "Gitea", synced_at).await;
+        tx.commit().await.unwrap();
+        assert_eq!(last_sync_of(&pool, "Gitea").await, Some(synced_at));
+
+        // e.g. a sync which panicked while inserting
+        let mut tx = pool.begin().await.unwrap();
+        set_last_sync(&mut tx, "Gitea", synced_at + chrono::Duration::hours(1)).await;
+        tx.rollback().await.unwrap();
+        assert_eq!(last_sync_of(&pool, "Gitea").await, Some(synced_at));
+    }
+
 }
 
 // #[cfg(test)]
@@ -0,0 +1,898 @@
+use crate::{
+    config::{self, ConfigError, EnvConfig},
+    credentials::{self, CredentialCheck, TokenInfo},
+    event_archive::{self, EventSource},
+    git_platform::{self, EventDetails, GitEventAPI, GitPlatform, GitProject, InsertEvents, NewEvent, PlatformError, Skipped, SyncCache, SyncResult},
+    http_client,
+    pagination::{FetchedEvents, PaginationGuard},
+    platform_registry::{BoxFuture, PlatformRunner},
+    project_filter::ProjectFilter,
+    project_refresh::{self, ProjectLookup, ProjectMetadata, RefreshReport},
+    retry::{env_parse, RetryPolicy},
+    sync_runs::SyncReport,
+    visibility::ProjectVisibility,
+};
+
+use std::{marker::PhantomData, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use log::{error, log_enabled, warn, Level};
+use once_cell::sync::OnceCell;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::{MySql, Transaction};
+use tokio::sync::Mutex;
+
+static GITEA: OnceCell<Arc<Mutex<Gitea>>> = OnceCell::new();
+static CODEBERG: OnceCell<Arc<Mutex<Codeberg>>> = OnceCell::new();
+/// Gitea's default `MAX_RESPONSE_ITEMS`, larger pages are cut down to it anyway
+static FALLBACK_GITEA_PER_PAGE: u32 = 50;
+/// Events can show up in the feed a while after they happened, so pages are fetched a bit past the last sync
+static FALLBACK_SYNC_OVERLAP_MINUTES: i64 = 60;
+
+/// A Gitea instance synced as its own platform, so several of them can be used at the same time
+pub trait GiteaInstance: Send + Sync + Sized + 'static {
+    /// Name in `GitPlatforms`
+    const PLATFORM_ID: &'static str;
+    /// Of the env vars, e.g. `GITEA` for `GITEA_API_TOKEN` and `POLLUX_GITEA_PER_PAGE`
+    const ENV_PREFIX: &'static str;
+    /// Used if `{ENV_PREFIX}_BASE_URL` isn't set, `None` if it is required
+    const DEFAULT_BASE_URL: Option<&'static str>;
+
+    fn cell() -> &'static OnceCell<Arc<Mutex<GiteaPlatform<Self>>>>;
+}
+
+/// Self-hosted instance configured with `GITEA_BASE_URL`
+#[derive(Debug)]
+pub struct SelfHosted;
+
+impl GiteaInstance for SelfHosted {
+    const PLATFORM_ID: &'static str = "Gitea";
+    const ENV_PREFIX: &'static str = "GITEA";
+    const DEFAULT_BASE_URL: Option<&'static str> = None;
+
+    fn cell() -> &'static OnceCell<Arc<Mutex<Gitea>>> {
+        &GITEA
+    }
+}
+
+/// Preset for codeberg.org, only `CODEBERG_API_TOKEN` and `CODEBERG_USERNAME` are needed
+#[derive(Debug)]
+pub struct CodebergOrg;
+
+impl GiteaInstance for CodebergOrg {
+    const PLATFORM_ID: &'static str = "Codeberg";
+    const ENV_PREFIX: &'static str = "CODEBERG";
+    const DEFAULT_BASE_URL: Option<&'static str> = Some("https://codeberg.org");
+
+    fn cell() -> &'static OnceCell<Arc<Mutex<Codeberg>>> {
+        &CODEBERG
+    }
+}
+
+pub type Gitea = GiteaPlatform<SelfHosted>;
+pub type Codeberg = GiteaPlatform<CodebergOrg>;
+
+/// One entry of `GET /users/{username}/activities/feeds`
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GiteaActivity {
+    pub id: u64,
+    /// e.g. `commit_repo`, `create_issue` or `merge_pull_request`
+    pub op_type: String,
+    pub repo_id: u64,
+    /// `null` once the repository is deleted
+    #[serde(default)]
+    pub repo: Option<GiteaRepo>,
+    /// Full ref like `refs/heads/main` for pushes, empty otherwise
+    #[serde(default)]
+    pub ref_name: String,
+    /// Depends on `op_type`, the pushed commits as JSON for `commit_repo`
+    #[serde(default)]
+    pub content: String,
+    pub created: String,
+}
+
+impl GitEventAPI for GiteaActivity {
+    fn external_id(&self) -> Option<String> {
+        Some(self.id.to_string())
+    }
+}
+
+impl GiteaActivity {
+    /// Branch or tag name without `refs/heads/` or `refs/tags/`
+    pub fn git_ref(&self) -> Option<&str> {
+        let name = self.ref_name.as_str();
+        let name = name
+            .strip_prefix("refs/heads/")
+            .or_else(|| name.strip_prefix("refs/tags/"))
+            .unwrap_or(name);
+        (!name.is_empty()).then_some(name)
+    }
+
+    /// Number of pushed commits, only known for pushes
+    pub fn commit_count(&self) -> Option<u64> {
+        if self.op_type != "commit_repo" {
+            return None;
+        }
+        serde_json::from_str::<GiteaPushCommits>(&self.content)
+            .ok()
+            .map(|commits| commits.len)
+    }
+}
+
+/// Only the count of the pushed commits
+#[derive(Debug, Deserialize)]
+struct GiteaPushCommits {
+    #[serde(rename = "Len")]
+    len: u64,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GiteaRepo {
+    pub id: u64,
+    /// e.g. `2tefan/pollux`, matched by `ProjectFilter`
+    pub full_name: String,
+    pub html_url: String,
+    #[serde(default)]
+    pub private: bool,
+    /// Visible to every signed-in user of the instance
+    #[serde(default)]
+    pub internal: bool,
+}
+
+impl GiteaRepo {
+    pub fn visibility(&self) -> ProjectVisibility {
+        match (self.private, self.internal) {
+            (true, _) => ProjectVisibility::Private,
+            (false, true) => ProjectVisibility::Internal,
+            (false, false) => ProjectVisibility::Public,
+        }
+    }
+}
+
+/// Only the parts of `GET /user` used to validate the token
+#[derive(Debug, Deserialize)]
+struct GiteaUser {
+    login: String,
+}
+
+/// Any Gitea instance, use `Gitea` or `Codeberg`
+#[derive(Debug)]
+pub struct GiteaPlatform<I: GiteaInstance> {
+    token: String,
+    username: String,
+    /// Web url of the instance without a trailing slash, the API lives under `/api/v1`
+    base_url: String,
+    per_page: u32,
+    /// Checked when a repository is stored, activities in other ones are skipped
+    include_visibilities: Vec<ProjectVisibility>,
+    project_filter: ProjectFilter,
+    retry_policy: RetryPolicy,
+    /// Feed pages are read until activities older than the last sync minus this
+    sync_overlap: chrono::Duration,
+    /// Built once, see `http_client::instance_client`
+    client: reqwest::Client,
+    instance: PhantomData<I>,
+}
+
+impl<I: GiteaInstance> GitPlatform for GiteaPlatform<I> {
+    const GIT_PLATFORM_ID: &'static str = I::PLATFORM_ID;
+    type GitEventAPI = GiteaActivity;
+
+    fn init_from_env_vars() -> Result<Self, ConfigError> {
+        let mut env = EnvConfig::from_env();
+        let base_url = match I::DEFAULT_BASE_URL {
+            Some(default) => env.optional(&Self::env_var("BASE_URL")).unwrap_or(default.to_string()),
+            None => env.required(&Self::env_var("BASE_URL")),
+        };
+        let accept_invalid_certs = Self::setting("DANGER_ACCEPT_INVALID_CERTS");
+        let client = http_client::instance_client(
+            Self::GIT_PLATFORM_ID,
+            &accept_invalid_certs,
+            env.parsed(&accept_invalid_certs, false),
+        );
+        let gitea = GiteaPlatform {
+            token: env.secret(&Self::env_var("API_TOKEN")),
+            username: env.required(&Self::env_var("USERNAME")),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            per_page: env_parse(&Self::setting("PER_PAGE"), FALLBACK_GITEA_PER_PAGE).max(1),
+            include_visibilities: Self::include_visibilities_from_env(),
+            project_filter: ProjectFilter::from_env(),
+            retry_policy: RetryPolicy::from_env(),
+            sync_overlap: chrono::Duration::minutes(env_parse(
+                &Self::setting("SYNC_OVERLAP_MINUTES"),
+                FALLBACK_SYNC_OVERLAP_MINUTES,
+            )),
+            client,
+            instance: PhantomData,
+        };
+        env.build(gitea)
+    }
+
+    async fn get_events(&mut self) -> Result<FetchedEvents<Self::GitEventAPI>, PlatformError> {
+        let since = match Self::get_last_sync_timestamp().await {
+            Some(last_sync) => Some(last_sync - self.sync_overlap),
+            None => {
+                info!("Initial run! Fetching all events {} still has...", Self::GIT_PLATFORM_ID);
+                None
+            }
+        };
+        self.get_events_since(since).await
+    }
+
+    async fn update_provider(&mut self) -> Result<SyncResult, PlatformError> {
+        info!("Updating events from {} ({})...", Self::GIT_PLATFORM_ID, self.base_url);
+        let fetched = self.get_events().await?;
+        let result = self.insert_events(&fetched.events, EventSource::Fetched, true).await;
+        Ok(SyncResult {
+            truncated: fetched.truncated,
+            ..result
+        })
+    }
+
+    async fn insert_archived_events(&self, events: Vec<GiteaActivity>) -> Result<SyncResult, PlatformError> {
+        Ok(self.insert_events(&events, EventSource::Archive, true).await)
+    }
+}
+
+impl<I: GiteaInstance> PlatformRunner for GiteaPlatform<I> {
+    fn sync(&mut self) -> BoxFuture<'_, SyncReport> {
+        Box::pin(git_platform::sync_and_record(self))
+    }
+
+    fn check_credentials(&mut self) -> BoxFuture<'_, Vec<CredentialCheck>> {
+        Box::pin(async { vec![GiteaPlatform::<I>::validate_credentials(self).await] })
+    }
+
+    fn reprocess(&mut self) -> BoxFuture<'_, SyncReport> {
+        Box::pin(event_archive::reprocess(self))
+    }
+
+    fn refresh_projects(&mut self) -> BoxFuture<'_, Option<RefreshReport>> {
+        Box::pin(async { Some(project_refresh::refresh(self).await) })
+    }
+}
+
+impl<I: GiteaInstance> InsertEvents for GiteaPlatform<I> {
+    async fn to_new_event<'e>(
+        &self,
+        tx: &mut Transaction<'static, MySql>,
+        cache: &mut SyncCache,
+        event: &'e GiteaActivity,
+    ) -> Result<NewEvent<'e>, Skipped<'e>> {
+        // Pulled from another instance, not done by us
+        if event.op_type.starts_with("mirror_sync_") {
+            debug!("Skipping mirror sync of repository {}", event.repo_id);
+            return Err(Skipped::Other);
+        }
+
+        let datetime = Self::parse_datetime(&event.created)?;
+        let action_name = Self::map_action_name(&event.op_type).ok_or(Skipped::UnknownAction(&event.op_type))?;
+        let project_id = self.project_of(tx, cache, event).await.ok_or(Skipped::Other)?;
+        let action_id = cache.action(tx, action_name).await;
+
+        Ok(NewEvent {
+            datetime,
+            action_id,
+            project_id,
+            commit_count: event.commit_count(),
+            account: None,
+            details: EventDetails {
+                git_ref: event.git_ref(),
+                target_type: None,
+            },
+        })
+    }
+}
+
+impl<I: GiteaInstance> ProjectLookup for GiteaPlatform<I> {
+    async fn lookup_project(&mut self, platform_project_id: u64) -> Result<Option<ProjectMetadata>, PlatformError> {
+        let client = self.client.clone();
+        let url = format!("{}/repositories/{}", self.api_base_url(), platform_project_id);
+        let payload = match self.get(&client, &url).await {
+            Ok(payload) => payload,
+            Err(PlatformError::Status { status: 404, .. }) => return Ok(None),
+            // Codeberg limits requests per IP, without telling when to retry
+            Err(PlatformError::Status { status: 429, .. }) => {
+                return Err(PlatformError::RateLimited { status: 429, retries: 0 })
+            }
+            Err(err) => return Err(err),
+        };
+
+        let repo: GiteaRepo = serde_json::from_str(&payload).map_err(|err| {
+            PlatformError::InvalidResponse(format!("Unable to decode json response from {}: {}", Self::GIT_PLATFORM_ID, err))
+        })?;
+        Ok(Some(ProjectMetadata {
+            visibility: Some(repo.visibility().as_str().to_string()),
+            name: repo.full_name,
+            url: repo.html_url,
+        }))
+    }
+}
+
+impl<I: GiteaInstance> GiteaPlatform<I> {
+    /// Credentials and location of the instance, e.g. `GITEA_API_TOKEN`
+    fn env_var(name: &str) -> String {
+        format!("{}_{}", I::ENV_PREFIX, name)
+    }
+
+    /// Optional tuning, e.g. `POLLUX_GITEA_PER_PAGE`
+    fn setting(name: &str) -> String {
+        format!("POLLUX_{}_{}", I::ENV_PREFIX, name)
+    }
+
+    /// Set up if the token, the username and (without a default) the base url are given
+    /// Any of its credentials is set, `init_from_env_vars` reports the missing ones
+    pub fn is_configured() -> bool {
+        let is_set = |name: &str| config::var(&Self::env_var(name)).is_some_and(|value| !value.trim().is_empty());
+        is_set("API_TOKEN") || is_set("API_TOKEN_FILE") || is_set("USERNAME") || is_set("BASE_URL")
+    }
+
+    /// `Ok(None)` if the instance isn't configured
+    pub fn get_or_init() -> Result<Option<Arc<Mutex<Self>>>, ConfigError> {
+        if !Self::is_configured() {
+            return Ok(None);
+        }
+        I::cell()
+            .get_or_try_init(|| Self::init_from_env_vars().map(|platform| Arc::new(Mutex::new(platform))))
+            .cloned()
+            .map(Some)
+    }
+
+    /// `POLLUX_GITEA_INCLUDE_VISIBILITIES` (or the instance's prefix), only public repositories by default
+    fn include_visibilities_from_env() -> Vec<ProjectVisibility> {
+        let name = Self::setting("INCLUDE_VISIBILITIES");
+        let Some(input) = config::var(&name) else {
+            return vec![ProjectVisibility::Public];
+        };
+
+        match ProjectVisibility::parse_set(&input) {
+            Ok(visibilities) => visibilities,
+            Err(err) => {
+                warn!(
+                    "Unable to parse {} »{}«, using »public« as a fallback: {}",
+                    name, input, err
+                );
+                vec![ProjectVisibility::Public]
+            }
+        }
+    }
+
+    fn api_base_url(&self) -> String {
+        format!("{}/api/v1", self.base_url)
+    }
+
+    pub async fn validate_credentials(&self) -> CredentialCheck {
+        let client = self.client.clone();
+        let outcome = match self.get(&client, &format!("{}/user", self.api_base_url())).await {
+            Ok(payload) => serde_json::from_str::<GiteaUser>(&payload)
+                .map(|user| TokenInfo {
+                    username: user.login,
+                    scopes: None,
+                    expires_at: None,
+                })
+                .map_err(|err| format!("Unable to decode json response from {}: {}", Self::GIT_PLATFORM_ID, err)),
+            Err(err) => Err(err.to_string()),
+        };
+        CredentialCheck {
+            platform: Self::GIT_PLATFORM_ID,
+            env_var: Self::env_var("API_TOKEN"),
+            outcome,
+        }
+    }
+
+    /// Body of a successful response
+    async fn get(&self, client: &reqwest::Client, url: &str) -> Result<String, PlatformError> {
+        let response = self
+            .retry_policy
+            .send(Self::GIT_PLATFORM_ID, || {
+                client.get(url).header("Authorization", format!("token {}", self.token))
+            })
+            .await
+            .map_err(|err| {
+                PlatformError::Request(format!("Unable to get response from {}: {}", Self::GIT_PLATFORM_ID, err))
+            })?;
+
+        let status = response.status();
+        let payload = response
+            .text()
+            .await
+            .map_err(|err| {
+                PlatformError::Request(format!("Unable to decode response from {}: {}", Self::GIT_PLATFORM_ID, err))
+            })?;
+        if !status.is_success() {
+            error!("We got this data: {}", payload);
+            let rejection = credentials::rejection(Self::GIT_PLATFORM_ID, status, &payload);
+            return Err(PlatformError::Status {
+                status: status.as_u16(),
+                message: if status == StatusCode::UNAUTHORIZED {
+                    format!("{} is invalid or expired ({})", Self::env_var("API_TOKEN"), rejection)
+                } else {
+                    rejection
+                },
+            });
+        }
+        Ok(payload)
+    }
+
+    /// Own activities, newest first. Pages are fetched until a partial page or one reaching past `since`.
+    pub async fn get_events_since(
+        &self,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<FetchedEvents<GiteaActivity>, PlatformError> {
+        let client = self.client.clone();
+        let mut gitea_events: Vec<GiteaActivity> = Vec::new();
+
+        let mut pagination = PaginationGuard::from_env();
+        let mut page = 1;
+        loop {
+            let url = format!(
+                "{}/users/{}/activities/feeds?only-performed-by=true&limit={}&page={}",
+                self.api_base_url(),
+                self.username,
+                self.per_page,
+                page
+            );
+            if let Err(err) = pagination.visit(&url) {
+                return Ok(FetchedEvents::truncated(gitea_events, err));
+            }
+            info!("Getting events from {}... ({})", Self::GIT_PLATFORM_ID, url);
+
+            let payload = self.get(&client, &url).await?;
+            let data: Vec<GiteaActivity> = serde_json::from_str(&payload).map_err(|err| {
+                error!(
+                    "Unable to decode json response from {}, this is what we received:\n{}",
+                    Self::GIT_PLATFORM_ID, payload
+                );
+                PlatformError::InvalidResponse(format!(
+                    "Unable to decode json response from {}: {}",
+                    Self::GIT_PLATFORM_ID, err
+                ))
+            })?;
+            if log_enabled!(Level::Debug) {
+                for element in &data {
+                    debug!("{:?}", element);
+                }
+            }
+
+            let partial_page = data.len() < self.per_page as usize;
+            let mut reached_since = false;
+            for activity in data {
+                let created = activity.created.parse::<DateTime<Utc>>().ok();
+                if let (Some(since), Some(created)) = (since, created) {
+                    if created < since {
+                        reached_since = true;
+                        continue;
+                    }
+                }
+                gitea_events.push(activity);
+            }
+
+            if partial_page || reached_since {
+                debug!("This was the last page");
+                return Ok(FetchedEvents::complete(gitea_events));
+            }
+            page += 1;
+        }
+    }
+
+    /// The visibility of a repository whose events are synced, `None` if they are skipped
+    fn included_visibility(&self, repo: &GiteaRepo) -> Option<ProjectVisibility> {
+        Some(repo.visibility()).filter(|visibility| self.include_visibilities.contains(visibility))
+    }
+
+    /// Id of the `GitProjects` row, `None` if the repository is filtered
+    async fn project_of(
+        &self,
+        tx: &mut Transaction<'static, MySql>,
+        cache: &mut SyncCache,
+        event: &GiteaActivity,
+    ) -> Option<u64> {
+        if let Some(project) = cache.project(tx, event.repo_id, false).await {
+            return self
+                .project_filter
+                .includes(Self::GIT_PLATFORM_ID, &project.name)
+                .then_some(project.id);
+        }
+
+        let Some(repo) = &event.repo else {
+            warn!("Repository {} is unavailable, storing a placeholder", event.repo_id);
+            let placeholder = GitProject {
+                unavailable_since: Some(Utc::now()),
+                ..GitProject::reported(event.repo_id, format!("deleted-repo-{}", event.repo_id), &self.base_url)
+            };
+            return Some(self.write_project_to_db(tx, &placeholder, None).await);
+        };
+
+        if !self.project_filter.includes(Self::GIT_PLATFORM_ID, &repo.full_name) {
+            return None;
+        }
+        let Some(visibility) = self.included_visibility(repo) else {
+            debug!("Skipping event: visibility of repository {} isn't included", repo.full_name);
+            return None;
+        };
+
+        let project = GitProject::reported(repo.id, &repo.full_name, &repo.html_url);
+        Some(self.write_project_to_db(tx, &project, Some(visibility)).await)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use wiremock::{
+        matchers::{header, method, path, query_param},
+        Mock, MockBuilder, MockServer, ResponseTemplate,
+    };
+
+    fn gitea_for_tests<I: GiteaInstance>(base_url: String) -> GiteaPlatform<I> {
+        GiteaPlatform {
+            token: "token".to_string(),
+            username: "2tefan".to_string(),
+            base_url,
+            per_page: 2,
+            include_visibilities: vec![ProjectVisibility::Public],
+            project_filter: ProjectFilter::default(),
+            retry_policy: RetryPolicy {
+                attempts: 1,
+                base_delay: std::time::Duration::from_millis(1),
+                max_delay: std::time::Duration::from_millis(1),
+            },
+            sync_overlap: chrono::Duration::minutes(FALLBACK_SYNC_OVERLAP_MINUTES),
+            client: http_client::platform_client(),
+            instance: PhantomData,
+        }
+    }
+
+    /// Captured from `GET /api/v1/users/2tefan/activities/feeds`, trimmed to the interesting parts
+    static COMMIT_REPO: &str = r#"{"id": 5120, "user_id": 1, "op_type": "commit_repo", "act_user_id": 1,
+        "act_user": {"id": 1, "login": "2tefan"}, "repo_id": 7,
+        "repo": {"id": 7, "full_name": "2tefan/dotfiles", "html_url": "https://git.example.com/2tefan/dotfiles",
+            "private": false, "internal": false},
+        "comment_id": 0, "ref_name": "refs/heads/main", "is_private": false,
+        "content": "{\"Commits\":[{\"Sha1\":\"2b9f0c4e\",\"Message\":\"Add zshrc\\n\"}],\"HeadCommit\":null,\"CompareURL\":\"2tefan/dotfiles/compare/8d1c5f1b...2b9f0c4e\",\"Len\":3}",
+        "created": "2024-05-03T18:40:12+02:00"}"#;
+    static MERGE_PULL_REQUEST: &str = r#"{"id": 5121, "user_id": 1, "op_type": "merge_pull_request", "act_user_id": 1,
+        "repo_id": 7, "repo": {"id": 7, "full_name": "2tefan/dotfiles", "html_url": "https://git.example.com/2tefan/dotfiles",
+            "private": false, "internal": false},
+        "comment_id": 0, "ref_name": "", "is_private": false, "content": "4|Add zshrc",
+        "created": "2024-05-03T19:02:40+02:00"}"#;
+    static DELETED_REPO: &str = r#"{"id": 5122, "user_id": 1, "op_type": "comment_issue", "act_user_id": 1,
+        "repo_id": 9, "repo": null, "comment_id": 31, "ref_name": "", "is_private": true, "content": "2|Looks good",
+        "created": "2024-05-03T19:10:00+02:00"}"#;
+
+    #[test]
+    fn activities_are_deserialized() {
+        let push: GiteaActivity = serde_json::from_str(COMMIT_REPO).unwrap();
+        assert_eq!(push.git_ref(), Some("main"));
+        assert_eq!(push.commit_count(), Some(3));
+        assert_eq!(push.repo.as_ref().unwrap().visibility(), ProjectVisibility::Public);
+        assert_eq!(Gitea::map_action_name(&push.op_type), Some("commit"));
+
+        let merge: GiteaActivity = serde_json::from_str(MERGE_PULL_REQUEST).unwrap();
+        assert_eq!(merge.git_ref(), None);
+        assert_eq!(merge.commit_count(), None);
+        assert_eq!(Gitea::map_action_name(&merge.op_type), Some("merge-request"));
+
+        let comment: GiteaActivity = serde_json::from_str(DELETED_REPO).unwrap();
+        assert_eq!(comment.repo, None);
+        assert_eq!(Gitea::map_action_name(&comment.op_type), Some("commented"));
+    }
+
+    #[test]
+    fn tags_are_named_without_prefix() {
+        let activity = GiteaActivity {
+            op_type: "push_tag".to_string(),
+            ref_name: "refs/tags/v1.2.0".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(activity.git_ref(), Some("v1.2.0"));
+        // Pushes without the commits in their content
+        assert_eq!(activity.commit_count(), None);
+    }
+
+    #[test]
+    fn visibility_follows_the_repository_flags() {
+        let repo = |private, internal| GiteaRepo {
+            private,
+            internal,
+            ..Default::default()
+        };
+        assert_eq!(repo(false, false).visibility(), ProjectVisibility::Public);
+        assert_eq!(repo(false, true).visibility(), ProjectVisibility::Internal);
+        assert_eq!(repo(true, false).visibility(), ProjectVisibility::Private);
+
+        let gitea = gitea_for_tests::<SelfHosted>("https://git.example.com".to_string());
+        assert_eq!(gitea.included_visibility(&repo(false, false)), Some(ProjectVisibility::Public));
+        assert_eq!(gitea.included_visibility(&repo(true, false)), None);
+    }
+
+    fn feed_page(page: u32, activities: &[&str]) -> Mock {
+        Mock::given(method("GET"))
+            .and(path("/api/v1/users/2tefan/activities/feeds"))
+            .and(query_param("only-performed-by", "true"))
+            .and(query_param("limit", "2"))
+            .and(query_param("page", page.to_string()))
+            .and(header("authorization", "token token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!("[{}]", activities.join(","))))
+    }
+
+    #[tokio::test]
+    async fn pages_are_fetched_until_a_partial_one() {
+        let server = MockServer::start().await;
+        feed_page(1, &[COMMIT_REPO, MERGE_PULL_REQUEST]).expect(1).mount(&server).await;
+        feed_page(2, &[DELETED_REPO]).expect(1).mount(&server).await;
+
+        let fetched = gitea_for_tests::<SelfHosted>(server.uri()).get_events_since(None).await.unwrap();
+        assert_eq!(fetched.truncated, None);
+        assert_eq!(
+            fetched.events.iter().map(|event| event.id).collect::<Vec<_>>(),
+            vec![5120, 5121, 5122]
+        );
+    }
+
+    #[tokio::test]
+    async fn pages_older_than_the_last_sync_are_not_requested() {
+        let server = MockServer::start().await;
+        feed_page(1, &[DELETED_REPO, MERGE_PULL_REQUEST]).expect(1).mount(&server).await;
+        feed_page(2, &[COMMIT_REPO]).expect(0).mount(&server).await;
+
+        let since = Utc.with_ymd_and_hms(2024, 5, 3, 17, 5, 0).unwrap();
+        let fetched = gitea_for_tests::<SelfHosted>(server.uri()).get_events_since(Some(since)).await.unwrap();
+        assert_eq!(fetched.events.iter().map(|event| event.id).collect::<Vec<_>>(), vec![5122]);
+    }
+
+    #[tokio::test]
+    async fn rejected_token_is_named() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(401).set_body_string(r#"{"message": "user does not exist"}"#))
+            .mount(&server)
+            .await;
+
+        let gitea = gitea_for_tests::<SelfHosted>(server.uri());
+        let err = gitea.get_events_since(None).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "GITEA_API_TOKEN is invalid or expired (Gitea answered 401 Unauthorized: user does not exist)"
+        );
+        assert!(gitea.validate_credentials().await.outcome.is_err());
+    }
+
+    #[tokio::test]
+    async fn valid_token_is_checked() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/user"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"id": 1, "login": "2tefan"}"#))
+            .mount(&server)
+            .await;
+
+        let check = gitea_for_tests::<SelfHosted>(server.uri()).validate_credentials().await;
+        assert_eq!(check.outcome.unwrap().username, "2tefan");
+    }
+
+    #[tokio::test]
+    async fn rejected_codeberg_token_is_named() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(401).set_body_string(r#"{"message": "user does not exist"}"#))
+            .mount(&server)
+            .await;
+
+        let codeberg = gitea_for_tests::<CodebergOrg>(server.uri());
+        let err = codeberg.get_events_since(None).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "CODEBERG_API_TOKEN is invalid or expired (Codeberg answered 401 Unauthorized: user does not exist)"
+        );
+        assert_eq!(codeberg.validate_credentials().await.platform, "Codeberg");
+    }
+
+    #[test]
+    fn instances_have_their_own_env_vars() {
+        assert_eq!(Gitea::env_var("BASE_URL"), "GITEA_BASE_URL");
+        assert_eq!(Gitea::setting("PER_PAGE"), "POLLUX_GITEA_PER_PAGE");
+        assert_eq!(Codeberg::env_var("API_TOKEN"), "CODEBERG_API_TOKEN");
+        assert_eq!(Codeberg::setting("SYNC_OVERLAP_MINUTES"), "POLLUX_CODEBERG_SYNC_OVERLAP_MINUTES");
+    }
+
+    #[tokio::test]
+    async fn instances_keep_repositories_with_the_same_id_apart() {
+        let (_container, pool) = crate::database::tests::initialize().await;
+        let gitea = gitea_for_tests::<SelfHosted>("https://git.example.com".to_string());
+        let codeberg = gitea_for_tests::<CodebergOrg>(CodebergOrg::DEFAULT_BASE_URL.unwrap().to_string());
+        let activity = |html_url: &str| GiteaActivity {
+            repo_id: 7,
+            repo: Some(GiteaRepo {
+                id: 7,
+                full_name: "2tefan/dotfiles".to_string(),
+                html_url: html_url.to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let mut tx = pool.begin().await.unwrap();
+        Gitea::set_platform(&mut tx).await;
+        Codeberg::set_platform(&mut tx).await;
+        let mut gitea_cache = SyncCache::load(&mut tx, Gitea::GIT_PLATFORM_ID).await;
+        let mut codeberg_cache = SyncCache::load(&mut tx, Codeberg::GIT_PLATFORM_ID).await;
+        let gitea_project = gitea
+            .project_of(&mut tx, &mut gitea_cache, &activity("https://git.example.com/2tefan/dotfiles"))
+            .await;
+        let codeberg_project = codeberg
+            .project_of(&mut tx, &mut codeberg_cache, &activity("https://codeberg.org/2tefan/dotfiles"))
+            .await;
+        assert!(gitea_project.is_some());
+        assert_ne!(gitea_project, codeberg_project);
+
+        // Found again per instance instead of being inserted twice, by this sync and the next one
+        assert_eq!(gitea.project_of(&mut tx, &mut gitea_cache, &activity("")).await, gitea_project);
+        assert_eq!(codeberg.project_of(&mut tx, &mut codeberg_cache, &activity("")).await, codeberg_project);
+        let mut next_sync = SyncCache::load(&mut tx, Gitea::GIT_PLATFORM_ID).await;
+        assert_eq!(gitea.project_of(&mut tx, &mut next_sync, &activity("")).await, gitea_project);
+        tx.commit().await.unwrap();
+
+        let platforms: Vec<(String,)> = sqlx::query_as("SELECT name FROM GitPlatforms ORDER BY name")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(platforms, vec![("Codeberg".to_string(),), ("Gitea".to_string(),)]);
+        let projects: Vec<(String, String)> =
+            sqlx::query_as("SELECT platform, url FROM GitProjects WHERE platform_project_id = 7 ORDER BY platform")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            projects,
+            vec![
+                ("Codeberg".to_string(), "https://codeberg.org/2tefan/dotfiles".to_string()),
+                ("Gitea".to_string(), "https://git.example.com/2tefan/dotfiles".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn every_fetched_event_is_counted() {
+        let (_container, _pool) = crate::database::tests::initialize().await;
+        let gitea = gitea_for_tests::<SelfHosted>("https://git.example.com".to_string());
+        let activity = |id: u64, op_type: &str, created: &str| GiteaActivity {
+            id,
+            op_type: op_type.to_string(),
+            repo_id: 7,
+            repo: Some(GiteaRepo {
+                id: 7,
+                full_name: "2tefan/dotfiles".to_string(),
+                html_url: "https://git.example.com/2tefan/dotfiles".to_string(),
+                ..Default::default()
+            }),
+            created: created.to_string(),
+            ..Default::default()
+        };
+        let events = vec![
+            activity(1, "commit_repo", "2024-05-01T10:00:00Z"),
+            activity(2, "commit_repo", "yesterday"),
+            activity(3, "mirror_sync_push", "2024-05-01T11:00:00Z"),
+            activity(4, "rename_repo_twice", "2024-05-01T12:00:00Z"),
+        ];
+
+        let result = gitea.insert_events(&events, EventSource::Fetched, true).await;
+        assert_eq!(result.inserted, 1);
+        assert_eq!(result.skipped_unknown_action, 1);
+        assert_eq!(result.skipped_other, 2);
+        assert_eq!(
+            result.fetched,
+            result.inserted
+                + result.skipped_unknown_action
+                + result.skipped_duplicates
+                + result.skipped_project_errors
+                + result.skipped_expired
+                + result.skipped_other
+        );
+    }
+
+    fn repository(id: u64) -> MockBuilder {
+        Mock::given(method("GET")).and(path(format!("/api/v1/repositories/{}", id)))
+    }
+
+    #[tokio::test]
+    async fn repositories_are_looked_up_by_id() {
+        let server = MockServer::start().await;
+        repository(7)
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"id": 7, "full_name": "2tefan/configs", "html_url": "https://git.example.com/2tefan/configs",
+                    "private": true, "internal": false}"#,
+            ))
+            .mount(&server)
+            .await;
+        repository(9)
+            .respond_with(ResponseTemplate::new(404).set_body_string(r#"{"message": "repository does not exist"}"#))
+            .mount(&server)
+            .await;
+        repository(11).respond_with(ResponseTemplate::new(429)).mount(&server).await;
+
+        let mut gitea = gitea_for_tests::<SelfHosted>(server.uri());
+        assert_eq!(
+            gitea.lookup_project(7).await,
+            Ok(Some(ProjectMetadata {
+                name: "2tefan/configs".to_string(),
+                url: "https://git.example.com/2tefan/configs".to_string(),
+                visibility: Some("private".to_string()),
+            }))
+        );
+        assert_eq!(gitea.lookup_project(9).await, Ok(None));
+        assert_eq!(gitea.lookup_project(11).await, Err(PlatformError::RateLimited { status: 429, retries: 0 }));
+    }
+
+    #[tokio::test]
+    async fn projects_are_refreshed_from_the_api() {
+        let (_container, pool) = crate::database::tests::initialize().await;
+        let server = MockServer::start().await;
+        let body = |name: &str| {
+            ResponseTemplate::new(200).set_body_string(format!(
+                r#"{{"id": 1, "full_name": "{}", "html_url": "https://git.example.com/{}", "private": false, "internal": false}}"#,
+                name, name
+            ))
+        };
+        repository(7).respond_with(body("2tefan/configs")).expect(1).mount(&server).await;
+        repository(8).respond_with(body("2tefan/pollux")).expect(1).mount(&server).await;
+        repository(9).respond_with(ResponseTemplate::new(404)).expect(1).mount(&server).await;
+        // Pseudonymous projects aren't looked up
+        repository(10).respond_with(body("2tefan/secret")).expect(0).mount(&server).await;
+
+        let mut tx = pool.begin().await.unwrap();
+        Gitea::set_platform(&mut tx).await;
+        tx.commit().await.unwrap();
+        for (id, name, pseudonymous) in
+            [(7, "2tefan/dotfiles", false), (8, "2tefan/pollux", false), (9, "2tefan/gone", false), (10, "private-1", true)]
+        {
+            sqlx::query(
+                "INSERT INTO GitProjects (platform, platform_project_id, name, url, visibility, pseudonymous, refreshed_at) \
+                    VALUES ('Gitea', ?, ?, ?, 'public', ?, '2024-05-01 00:00:00')",
+            )
+            .bind(id)
+            .bind(name)
+            .bind(format!("https://git.example.com/{}", name))
+            .bind(pseudonymous)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let mut gitea = gitea_for_tests::<SelfHosted>(server.uri());
+        let report = project_refresh::refresh_due(&pool, &mut gitea, Utc::now()).await;
+        assert_eq!(
+            report,
+            RefreshReport {
+                checked: 3,
+                updated: 1,
+                unavailable: 1,
+                failed: 0,
+                rate_limited: false,
+            }
+        );
+
+        let projects: Vec<(u64, String, String, bool, bool)> = sqlx::query_as(
+            "SELECT platform_project_id, name, url, unavailable_since IS NOT NULL, updated_at IS NOT NULL \
+                FROM GitProjects WHERE pseudonymous = 0 ORDER BY platform_project_id",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            projects,
+            vec![
+                (7, "2tefan/configs".to_string(), "https://git.example.com/2tefan/configs".to_string(), false, true),
+                (8, "2tefan/pollux".to_string(), "https://git.example.com/2tefan/pollux".to_string(), false, false),
+                (9, "2tefan/gone".to_string(), "https://git.example.com/2tefan/gone".to_string(), true, true),
+            ]
+        );
+
+        // Refreshed just now, so they aren't due again
+        let since = Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        assert_eq!(project_refresh::refresh_due(&pool, &mut gitea, since).await, RefreshReport::default());
+    }
+}
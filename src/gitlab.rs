@@ -1,106 +1,475 @@
 use crate::{
+    backfill::BackfillRange,
+    config::{self, ConfigError, EnvConfig},
+    credentials::{self, CredentialCheck, TokenInfo},
     database,
-    git_platform::{GitEventAPI, GitPlatform},
+    event_archive::{self, EventSource},
+    http_client::{self, HttpClient, HttpRequest},
+    project_filter::ProjectFilter,
+    git_platform::{self, EventDetails, GitEventAPI, GitPlatform, GitProject, NewEvent, PlatformError, SyncCache, SyncResult, UnknownActions},
+    pagination::{self, FetchedEvents, PaginationGuard},
+    platform_registry::{BoxFuture, PlatformRunner},
+    project_refresh::{self, ProjectLookup, ProjectMetadata, RefreshReport},
+    records,
+    retry::{env_parse, RetryPolicy},
+    sync_runs::SyncReport,
+    visibility::ProjectVisibility,
 };
 
-use std::{borrow::BorrowMut, sync::Arc};
+use std::{borrow::BorrowMut, collections::BTreeMap, sync::Arc, time::Duration};
 
-use chrono::{DateTime, Utc};
-use log::{error, log_enabled, trace, warn, Level};
+use chrono::{DateTime, NaiveDate, Utc};
+use log::{error, log_enabled, warn, Level};
 use once_cell::sync::OnceCell;
+use governor::Jitter;
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    StatusCode,
+};
 use serde::{Deserialize, Serialize};
 use sqlx::{MySql, Transaction};
 use tokio::sync::Mutex;
 
 static GITLAB: OnceCell<Arc<Mutex<Gitlab>>> = OnceCell::new();
+static FALLBACK_GITLAB_BASE_URL: &str = "https://gitlab.com";
+static FALLBACK_GITLAB_PER_PAGE: u32 = 100;
+/// GitLab answers larger page sizes with 100 items anyway
+static MAX_GITLAB_PER_PAGE: u32 = 100;
+/// Syncs with more events than this are logged, they hint at a long gap since the last one
+static LARGE_SYNC_EVENTS: u32 = 400;
+static FALLBACK_RATE_LIMIT_MAX_WAIT_SECS: u64 = 300;
+static FALLBACK_RATE_LIMIT_RETRIES: u32 = 3;
+/// `after` only takes a date and is exclusive, so the window reaches back this far past the last sync
+static FALLBACK_SYNC_OVERLAP_DAYS: i64 = 2;
+/// Only for instances whose certificate can't be verified, even with `POLLUX_EXTRA_CA_CERT_FILE`
+static ACCEPT_INVALID_CERTS: &str = "POLLUX_GITLAB_DANGER_ACCEPT_INVALID_CERTS";
+/// Waited after a 429 without `Retry-After`
+static DEFAULT_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// How to wait out 429 responses, GitLab tells how long with `Retry-After`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GitlabRateLimitPolicy {
+    /// Longest single pause, so a sync never stalls for long
+    pub max_wait: Duration,
+    /// Retries per request, the sync run ends as rate limited afterwards
+    pub retries: u32,
+}
+
+impl GitlabRateLimitPolicy {
+    pub fn from_env() -> GitlabRateLimitPolicy {
+        GitlabRateLimitPolicy {
+            max_wait: Duration::from_secs(env_parse(
+                "POLLUX_GITLAB_RATE_LIMIT_MAX_WAIT_SECS",
+                FALLBACK_RATE_LIMIT_MAX_WAIT_SECS,
+            )),
+            retries: env_parse("POLLUX_GITLAB_RATE_LIMIT_RETRIES", FALLBACK_RATE_LIMIT_RETRIES),
+        }
+    }
+
+    fn retry_after(&self, headers: &HeaderMap) -> Duration {
+        headers
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map_or(DEFAULT_RATE_LIMIT_WAIT, Duration::from_secs)
+            .min(self.max_wait)
+    }
+}
+
+/// Only the parts of `GET /user` used to validate the token
+#[derive(Debug, Deserialize)]
+struct GitlabUser {
+    username: String,
+}
+
+/// `GET /personal_access_tokens/self`, not available for other kinds of tokens
+#[derive(Debug, Deserialize)]
+struct GitlabTokenDetails {
+    scopes: Vec<String>,
+    expires_at: Option<String>,
+}
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GitlabEvent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub project_id: u64,
+    /// Only needed to tell own events apart in project feeds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author_id: Option<u64>,
     pub action_name: String,
     pub created_at: String,
     pub push_data: Option<PushData>,
+    /// e.g. `Issue`, `MergeRequest`, or `Note`/`DiffNote`/`DiscussionNote` for comments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_iid: Option<u64>,
+    /// Only set for comments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<GitlabNote>,
+}
+
+impl GitEventAPI for GitlabEvent {
+    fn external_id(&self) -> Option<String> {
+        self.id.map(|id| id.to_string())
+    }
+}
+
+impl GitlabEvent {
+    /// What the event acted on, the commented issue or merge request for comments
+    pub fn target(&self) -> Option<&str> {
+        self.note
+            .as_ref()
+            .and_then(|note| note.noteable_type.as_deref())
+            .or(self.target_type.as_deref())
+    }
+
+    /// The shared action, GitLab names opening and closing issues and milestones like merge requests
+    pub fn action(&self) -> Option<&'static str> {
+        match (self.action_name.as_str(), self.target()) {
+            ("opened" | "closed" | "reopened", Some("Issue")) => Some("issue"),
+            ("opened" | "closed" | "reopened", Some("Milestone")) => Some("project-management"),
+            (action_name, _) => git_platform::map_action(action_name),
+        }
+    }
 }
 
-impl GitEventAPI for GitlabEvent {}
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GitlabNote {
+    /// `Issue`, `MergeRequest`, `Commit`, `Snippet`, ...
+    pub noteable_type: Option<String>,
+    pub noteable_iid: Option<u64>,
+}
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PushData {
     pub commit_count: u64,
+    /// Branch or tag name, `null` e.g. for some tag deletions and bulk pushes
+    pub r#ref: Option<String>,
+    /// `branch` or `tag`
+    pub ref_type: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GitlabProjectAPI {
     pub id: u64,
     pub name_with_namespace: String,
+    /// e.g. `2tefan-projects/stats/pollux`, matched by `ProjectFilter`
+    #[serde(default)]
+    pub path_with_namespace: String,
     pub web_url: String,
     pub visibility: Option<String>,
+    /// Set in project lists, projects without activity since the last sync aren't asked for events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_activity_at: Option<String>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct GitlabProject {
-    pub id: u64,
-    pub platform_project_id: u64,
-    pub name: String,
-    pub url: String,
-}
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Gitlab {
     token: String,
     user_id: String,
+    /// Web url of the instance without a trailing slash, the API lives under `/api/v4`
+    base_url: String,
+    per_page: u32,
+    /// Ids or full paths of groups whose project feeds are synced as well, for events missing in the user feed
+    groups: Vec<String>,
+    /// Projects with any other visibility are skipped, with their events
+    include_visibilities: Vec<ProjectVisibility>,
+    project_filter: ProjectFilter,
+    rate_limit_policy: GitlabRateLimitPolicy,
+    retry_policy: RetryPolicy,
+    /// Subtracted from the last sync, events fetched again are dropped when inserting
+    sync_overlap: chrono::Duration,
+    /// Built once, see `http_client::instance_client`
+    client: Arc<dyn HttpClient>,
 }
 
 impl GitPlatform for Gitlab {
     const GIT_PLATFORM_ID: &'static str = "Gitlab";
     type GitEventAPI = GitlabEvent;
 
-    fn init_from_env_vars() -> Self {
-        Gitlab {
-            token: std::env::var("GITLAB_API_TOKEN")
-                .expect("Please specify GITLAB_API_TOKEN as env var!"),
-                user_id: std::env::var("GITLAB_USER_ID")
-                    .expect("Please specify GITLAB_USER_ID as env var!"),
+    fn init_from_env_vars() -> Result<Self, ConfigError> {
+        let mut env = EnvConfig::from_env();
+        let accept_invalid_certs = env.parsed(ACCEPT_INVALID_CERTS, false);
+        let gitlab = Gitlab {
+            token: env.secret("GITLAB_API_TOKEN"),
+            user_id: env.required("GITLAB_USER_ID"),
+            base_url: Gitlab::base_url_from_env(),
+            per_page: Gitlab::per_page_from_env(),
+            groups: Gitlab::groups_from_env(),
+            include_visibilities: Gitlab::include_visibilities_from_env(),
+            project_filter: ProjectFilter::from_env(),
+            rate_limit_policy: GitlabRateLimitPolicy::from_env(),
+            retry_policy: RetryPolicy::from_env(),
+            sync_overlap: chrono::Duration::days(env_parse(
+                "POLLUX_GITLAB_SYNC_OVERLAP_DAYS",
+                FALLBACK_SYNC_OVERLAP_DAYS,
+            )),
+            client: Arc::new(http_client::instance_client(
+                Self::GIT_PLATFORM_ID,
+                ACCEPT_INVALID_CERTS,
+                accept_invalid_certs,
+            )),
+        };
+        env.build(gitlab)
+    }
+
+    async fn get_events(&mut self) -> Result<FetchedEvents<Self::GitEventAPI>, PlatformError> {
+        let last_sync = Gitlab::get_last_sync_timestamp().await;
+        if last_sync.is_none() {
+            info!("Initial run! Fetching last 90 days from Gitlab ({})...", self.host());
+        }
+        let (after, before) = self.sync_window(last_sync, Utc::now());
+
+        let mut fetched = Gitlab::get_events(self, after, before).await?;
+        // Overlap with the user feed is dropped when inserting, like any other known event
+        for group in &self.groups {
+            if fetched.truncated.is_some() {
+                break;
+            }
+            let mut group_events = self.get_group_events(group, after, before).await?;
+            fetched.events.append(&mut group_events.events);
+            fetched.truncated = group_events.truncated;
         }
+        Ok(fetched)
     }
 
-    async fn get_events(&mut self) -> Vec<Self::GitEventAPI> {
-        let before = match Gitlab::get_last_sync_timestamp().await {
-            Some(value) => value,
-            None => {
-                info!("Initial run! Fetching last 90 days from Gitlab...");
-                Utc::now() - chrono::Duration::days(90)
-            }};
-        Gitlab::get_events(
-            &self, 
-            before - chrono::Duration::days(1),
-            Utc::now() + chrono::Duration::days(1)
-        ).await
+    async fn update_provider(&mut self) -> Result<SyncResult, PlatformError> {
+        info!("Updating events from Gitlab ({})...", self.host());
+        let fetched = self.get_events().await?;
+        let (result, stopped_by) = self.insert_gitlab_events_into_db(fetched.events, EventSource::Fetched).await;
+
+        let truncated = match stopped_by {
+            None => fetched.truncated,
+            Some(err) => Some(err.truncation().ok_or(err)?),
+        };
+        Ok(SyncResult { truncated, ..result })
+    }
+
+    async fn insert_archived_events(&self, events: Vec<GitlabEvent>) -> Result<SyncResult, PlatformError> {
+        let (result, stopped_by) = self.insert_gitlab_events_into_db(events, EventSource::Archive).await;
+        let truncated = stopped_by.map(|err| err.truncation().ok_or(err)).transpose()?;
+        Ok(SyncResult { truncated, ..result })
+    }
+}
+
+impl PlatformRunner for Gitlab {
+    fn sync(&mut self) -> BoxFuture<'_, SyncReport> {
+        Box::pin(git_platform::sync_and_record(self))
+    }
+
+    fn check_credentials(&mut self) -> BoxFuture<'_, Vec<CredentialCheck>> {
+        Box::pin(async { vec![Gitlab::validate_credentials(self).await] })
+    }
+
+    fn reprocess(&mut self) -> BoxFuture<'_, SyncReport> {
+        Box::pin(event_archive::reprocess(self))
+    }
+
+    fn refresh_projects(&mut self) -> BoxFuture<'_, Option<RefreshReport>> {
+        Box::pin(async { Some(project_refresh::refresh(self).await) })
+    }
+
+    /// Syncs a copy, so the shared `Gitlab` stays available meanwhile. It keeps no state between syncs,
+    /// there's nothing to write back.
+    fn sync_shared(runner: Arc<Mutex<Gitlab>>) -> BoxFuture<'static, SyncReport> {
+        Box::pin(async move {
+            let mut gitlab = runner.lock().await.clone();
+            git_platform::sync_and_record(&mut gitlab).await
+        })
     }
 
-    async fn update_provider(&mut self) -> Option<i32> {
-        info!("Updating events from Gitlab...");
-        let events = self.get_events().await;
-        let new_events = self.insert_gitlab_events_into_db(events).await;
+    /// Like `sync_shared`
+    fn refresh_shared(runner: Arc<Mutex<Gitlab>>) -> BoxFuture<'static, Option<RefreshReport>> {
+        Box::pin(async move {
+            let mut gitlab = runner.lock().await.clone();
+            Some(project_refresh::refresh(&mut gitlab).await)
+        })
+    }
+}
 
-        Some(new_events)
+impl ProjectLookup for Gitlab {
+    async fn lookup_project(&mut self, platform_project_id: u64) -> Result<Option<ProjectMetadata>, PlatformError> {
+        match self.get_project_details_by_id(platform_project_id).await {
+            Ok(project) => Ok(Some(ProjectMetadata {
+                name: project.name_with_namespace,
+                url: project.web_url,
+                visibility: project.visibility,
+            })),
+            // Like when its events were inserted, see `fetch_project_from_gitlab_and_write_to_db`
+            Err(PlatformError::Status { status: 403 | 404, .. }) => Ok(None),
+            Err(err) => Err(err),
+        }
     }
 }
 
 impl Gitlab {
-    pub fn get_or_init() -> Arc<Mutex<Gitlab>>{
-        GITLAB.get_or_init(|| Arc::new(Mutex::new(Self::init_from_env_vars()))).clone()
+    /// Any of its credentials is set, `init_from_env_vars` reports the missing ones
+    pub fn is_configured() -> bool {
+        Gitlab::is_configured_in(config::var)
+    }
+
+    fn is_configured_in(env: impl Fn(&str) -> Option<String>) -> bool {
+        ["GITLAB_API_TOKEN", "GITLAB_API_TOKEN_FILE", "GITLAB_USER_ID"]
+            .iter()
+            .any(|name| env(name).is_some_and(|value| !value.trim().is_empty()))
+    }
+
+    /// `Ok(None)` if Gitlab isn't configured
+    pub fn get_or_init() -> Result<Option<Arc<Mutex<Gitlab>>>, ConfigError> {
+        if !Gitlab::is_configured() {
+            return Ok(None);
+        }
+        GITLAB
+            .get_or_try_init(|| Self::init_from_env_vars().map(|platform| Arc::new(Mutex::new(platform))))
+            .cloned()
+            .map(Some)
+    }
+
+    /// Accepts absolute https urls only, so the token is never sent in plain text
+    fn parse_base_url(input: &str) -> Result<String, String> {
+        let url = reqwest::Url::parse(input.trim()).map_err(|err| err.to_string())?;
+        if url.scheme() != "https" {
+            return Err(format!("{} isn't https", url.scheme()));
+        }
+        if url.host_str().is_none() {
+            return Err("no host given".to_string());
+        }
+        if url.query().is_some() || url.fragment().is_some() {
+            return Err("query and fragment aren't allowed".to_string());
+        }
+        Ok(url.as_str().trim_end_matches('/').to_string())
     }
 
-    pub async fn get_events(&self, after: DateTime<Utc>, before: DateTime<Utc>) -> Vec<GitlabEvent> {
-        let client = reqwest::Client::new();
-        let token = &self.token;
-        let user_id = &self.user_id;
+    /// `GITLAB_BASE_URL` of a self-hosted instance, `https://gitlab.com` otherwise
+    fn base_url_from_env() -> String {
+        let Some(input) = config::var("GITLAB_BASE_URL") else {
+            return FALLBACK_GITLAB_BASE_URL.to_string();
+        };
+
+        match Gitlab::parse_base_url(&input) {
+            Ok(base_url) => base_url,
+            Err(err) => {
+                warn!(
+                    "Unable to parse GITLAB_BASE_URL »{}«, using »{}« as a fallback: {}",
+                    input, FALLBACK_GITLAB_BASE_URL, err
+                );
+                FALLBACK_GITLAB_BASE_URL.to_string()
+            }
+        }
+    }
+
+    /// Larger values are capped at 100 instead of being rejected
+    fn parse_per_page(input: &str) -> Result<u32, String> {
+        match input.trim().parse::<u32>() {
+            Ok(0) => Err("0 is not a page size".to_string()),
+            Ok(per_page) => Ok(per_page.min(MAX_GITLAB_PER_PAGE)),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    fn per_page_from_env() -> u32 {
+        let Some(input) = config::var("POLLUX_GITLAB_PER_PAGE") else {
+            return FALLBACK_GITLAB_PER_PAGE;
+        };
+
+        match Gitlab::parse_per_page(&input) {
+            Ok(per_page) => per_page,
+            Err(err) => {
+                warn!(
+                    "Unable to parse POLLUX_GITLAB_PER_PAGE »{}«, using »{}« as a fallback: {}",
+                    input, FALLBACK_GITLAB_PER_PAGE, err
+                );
+                FALLBACK_GITLAB_PER_PAGE
+            }
+        }
+    }
+
+    /// The `after` and `before` bounds of a sync, the last 90 days for the initial one
+    fn sync_window(&self, last_sync: Option<DateTime<Utc>>, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let after = match last_sync {
+            Some(last_sync) => last_sync - self.sync_overlap,
+            None => now - chrono::Duration::days(90),
+        };
+        (after, now + chrono::Duration::days(1))
+    }
+
+    /// Comma-separated, empty entries are ignored
+    fn parse_groups(input: &str) -> Vec<String> {
+        input
+            .split(',')
+            .map(str::trim)
+            .filter(|group| !group.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// `GITLAB_GROUPS`, no groups by default
+    fn groups_from_env() -> Vec<String> {
+        config::var("GITLAB_GROUPS")
+            .map(|input| Gitlab::parse_groups(&input))
+            .unwrap_or_default()
+    }
+
+    /// `POLLUX_GITLAB_INCLUDE_VISIBILITIES`, only public projects by default
+    fn include_visibilities_from_env() -> Vec<ProjectVisibility> {
+        let Some(input) = config::var("POLLUX_GITLAB_INCLUDE_VISIBILITIES") else {
+            return vec![ProjectVisibility::Public];
+        };
+
+        match ProjectVisibility::parse_set(&input) {
+            Ok(visibilities) => visibilities,
+            Err(err) => {
+                warn!(
+                    "Unable to parse POLLUX_GITLAB_INCLUDE_VISIBILITIES »{}«, using »public« as a fallback: {}",
+                    input, err
+                );
+                vec![ProjectVisibility::Public]
+            }
+        }
+    }
+
+    /// The visibility of a project whose events are synced, `None` if they are skipped
+    fn included_visibility(&self, visibility: Option<&str>) -> Option<ProjectVisibility> {
+        visibility
+            .and_then(ProjectVisibility::parse)
+            .filter(|visibility| self.include_visibilities.contains(visibility))
+    }
+
+    fn api_base_url(&self) -> String {
+        format!("{}/api/v4", self.base_url)
+    }
+
+    /// Host (and path) of the instance, to tell instances apart in the logs
+    fn host(&self) -> &str {
+        self.base_url
+            .split_once("://")
+            .map_or(self.base_url.as_str(), |(_, host)| host)
+    }
+
+    pub async fn validate_credentials(&self) -> CredentialCheck {
+        CredentialCheck {
+            platform: Self::GIT_PLATFORM_ID,
+            env_var: "GITLAB_API_TOKEN".to_string(),
+            outcome: validate_token(self.client.as_ref(), &self.api_base_url(), &self.token).await,
+        }
+    }
+
+    pub async fn get_events(
+        &self,
+        after: DateTime<Utc>,
+        before: DateTime<Utc>,
+    ) -> Result<FetchedEvents<GitlabEvent>, PlatformError> {
         let url = format!(
-            "https://gitlab.com/api/v4/users/{}/events?after={}&before={}",
-            user_id,
-            after.format("%Y-%m-%d").to_string(),
-            before.format("%Y-%m-%d").to_string()
+            "{}/users/{}/events?after={}&before={}&per_page={}",
+            self.api_base_url(),
+            self.user_id,
+            after.format("%Y-%m-%d"),
+            before.format("%Y-%m-%d"),
+            self.per_page
         );
 
         if after >= before {
@@ -110,217 +479,468 @@ impl Gitlab {
             );
         }
         info!("Getting events from Gitlab... ({})", url);
+        self.fetch_events(&url).await
+    }
 
-        let mut gitlab_events: Vec<GitlabEvent> = Vec::new();
+    /// Keyset pagination if the server supports it, counting pages otherwise
+    async fn fetch_events(&self, url: &str) -> Result<FetchedEvents<GitlabEvent>, PlatformError> {
+        match self.get_events_by_keyset(url).await? {
+            Some(fetched) => Ok(fetched),
+            None => {
+                info!(
+                    "Gitlab ({}) doesn't support keyset pagination for events, counting pages instead",
+                    self.host()
+                );
+                self.get_events_by_page_number(url).await
+            }
+        }
+    }
 
-        let mut current_page = 1;
-        loop {
-            let res = client
-                .get(format!("{}&page={}", url, current_page))
-                .bearer_auth(token)
-                .send()
-                .await;
+    /// Own events from the feeds of all projects in `group` and its subgroups.
+    /// Project lists are fetched page by page, most recently active first, and only until the first
+    /// project without activity since `after`. Filtered projects aren't asked for their events at all.
+    pub async fn get_group_events(
+        &self,
+        group: &str,
+        after: DateTime<Utc>,
+        before: DateTime<Utc>,
+    ) -> Result<FetchedEvents<GitlabEvent>, PlatformError> {
+        let Ok(author_id) = self.user_id.parse::<u64>() else {
+            warn!(
+                "GITLAB_USER_ID »{}« isn't a numeric id, skipping the events of group {}",
+                self.user_id, group
+            );
+            return Ok(FetchedEvents::complete(Vec::new()));
+        };
 
-            let initial_res = match res {
-                Ok(initial_response) => initial_response,
-                Err(err) => panic!("Unable to get response from Gitlab!: {}", err),
-            };
+        let mut gitlab_events: Vec<GitlabEvent> = Vec::new();
+        let mut pagination = PaginationGuard::from_env();
+        let mut next_page = Some(1);
+        info!("Getting events of the projects in group {} from Gitlab ({})...", group, self.host());
+
+        while let Some(page) = next_page {
+            let url = format!(
+                "{}/groups/{}/projects?include_subgroups=true&order_by=last_activity_at&sort=desc&per_page={}&page={}",
+                self.api_base_url(),
+                group.replace('/', "%2F"),
+                self.per_page,
+                page
+            );
+            if let Err(err) = pagination.visit(&url) {
+                return Ok(FetchedEvents::truncated(gitlab_events, err));
+            }
 
-            let status = initial_res.status();
-            let header = initial_res.headers().clone();
-            let payload = match initial_res.text().await {
-                Ok(text) => text,
-                Err(err) => panic!("Unable to decode response from Gitlab: {}", err),
+            let (status, header, payload) = match self.get_page(&url).await {
+                Ok(page) => page,
+                Err(err) => return git_platform::stop_early(gitlab_events, err),
             };
+            let projects: Vec<GitlabProjectAPI> = self.decode(status, &payload)?;
+            // Empty on the last page
+            next_page = header
+                .get("x-next-page")
+                .and_then(|next_page| next_page.to_str().ok()?.trim().parse::<u32>().ok());
+
+            for project in projects {
+                let last_activity = project
+                    .last_activity_at
+                    .as_deref()
+                    .and_then(|last_activity| last_activity.parse::<DateTime<Utc>>().ok());
+                if last_activity.is_some_and(|last_activity| last_activity < after) {
+                    debug!("No activity in {} since {}, skipping the remaining projects", project.path_with_namespace, after);
+                    next_page = None;
+                    break;
+                }
+                if self.included_visibility(project.visibility.as_deref()).is_none()
+                    || !self.project_filter.includes(Self::GIT_PLATFORM_ID, &project.path_with_namespace)
+                {
+                    continue;
+                }
+
+                let url = format!(
+                    "{}/projects/{}/events?after={}&before={}&per_page={}",
+                    self.api_base_url(),
+                    project.id,
+                    after.format("%Y-%m-%d"),
+                    before.format("%Y-%m-%d"),
+                    self.per_page
+                );
+                debug!("Getting events of {} from Gitlab... ({})", project.path_with_namespace, url);
+                let fetched = self.fetch_events(&url).await?;
+                gitlab_events.extend(
+                    fetched
+                        .events
+                        .into_iter()
+                        .filter(|event| event.author_id == Some(author_id)),
+                );
+                if let Some(reason) = fetched.truncated {
+                    return Ok(FetchedEvents::truncated(gitlab_events, reason));
+                }
+            }
+        }
+
+        Ok(FetchedEvents::complete(gitlab_events))
+    }
+
+    /// Sends a GET request, any answer but a 429 is returned as is.
+    /// 429 responses are retried after `Retry-After`, an error once the retries are used up.
+    async fn get_page(&self, url: &str) -> Result<(StatusCode, HeaderMap, String), PlatformError> {
+        let request = HttpRequest::get(url).bearer_auth(&self.token);
+        let mut attempt = 0;
+        loop {
+            let response = self
+                .retry_policy
+                .send_http(Self::GIT_PLATFORM_ID, self.client.as_ref(), &request)
+                .await
+                .map_err(|err| {
+                    PlatformError::Request(format!("Unable to get response from Gitlab ({}): {}", self.host(), err))
+                })?;
+
+            let (status, header, payload) = (response.status, response.headers, response.body);
             debug!("{:?}", payload);
+            if status != StatusCode::TOO_MANY_REQUESTS {
+                return Ok((status, header, payload));
+            }
+            if attempt == self.rate_limit_policy.retries {
+                return Err(PlatformError::RateLimited {
+                    status: status.as_u16(),
+                    retries: attempt,
+                });
+            }
+
+            attempt += 1;
+            let wait = self.rate_limit_policy.retry_after(&header);
+            let wait = Jitter::up_to(wait / 10) + wait;
+            warn!(
+                "Gitlab ({}) answered {}, retrying in {:?} ({}/{})",
+                self.host(),
+                status,
+                wait,
+                attempt,
+                self.rate_limit_policy.retries
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Decodes the body of a successful response, what else Gitlab answered otherwise
+    fn decode<T: serde::de::DeserializeOwned>(&self, status: StatusCode, payload: &str) -> Result<T, PlatformError> {
+        if !status.is_success() {
+            error!("We got this data: {}", payload);
+            return Err(PlatformError::Status {
+                status: status.as_u16(),
+                message: format!("{} ({})", rejection(status, payload), self.host()),
+            });
+        }
+
+        serde_json::from_str(payload).map_err(|err| {
+            error!("Unable to decode json response from Gitlab, this is what we received:\n{}", payload);
+            PlatformError::InvalidResponse(format!("Unable to decode json response from Gitlab: {}", err))
+        })
+    }
 
-            if !status.is_success() {
-                error!("We got this data: {}", payload.as_str());
-                panic!("Couldn't fetch events from Gitlab! {}", status.as_str());
+    fn decode_events(&self, status: StatusCode, payload: &str) -> Result<Vec<GitlabEvent>, PlatformError> {
+        let data: Vec<GitlabEvent> = self.decode(status, payload)?;
+
+        if log_enabled!(Level::Debug) {
+            for element in &data {
+                debug!("{:?}", element);
+            }
+        }
+        Ok(data)
+    }
+
+    /// Follows the `Link: rel="next"` header of keyset paginated pages, which neither skips nor duplicates
+    /// events arriving in between. `None` if the server doesn't support keyset pagination for events.
+    async fn get_events_by_keyset(&self, url: &str) -> Result<Option<FetchedEvents<GitlabEvent>>, PlatformError> {
+        let mut gitlab_events: Vec<GitlabEvent> = Vec::new();
+
+        let mut next_page_url = format!("{}&pagination=keyset&order_by=id&sort=desc", url);
+        let mut pagination = PaginationGuard::from_env();
+        let mut first_page = true;
+        loop {
+            if let Err(err) = pagination.visit(&next_page_url) {
+                return Ok(Some(FetchedEvents::truncated(gitlab_events, err)));
             }
 
-            let total_pages = match header.get("x-total-pages") {
-                Some(x_total_pages) => x_total_pages
-                    .to_str()
-                    .expect("Unable to get string from header")
-                    .parse::<u32>()
-                    .expect("x-total is not a valid number!"),
-                None => panic!("Didn't got x-total header back from Gitlab!"),
+            let (status, header, payload) = match self.get_page(&next_page_url).await {
+                Ok(page) => page,
+                Err(err) => return git_platform::stop_early(gitlab_events, err).map(Some),
             };
-            if current_page == 1 && total_pages > 20 {
-                warn!(
-                    "Getting more than 20 pages/400 events! [{} pages]",
-                    total_pages
-                )
+            if first_page && status == StatusCode::METHOD_NOT_ALLOWED {
+                debug!("Keyset pagination was rejected: {}", payload);
+                return Ok(None);
             }
 
-            match header.get("x-page") {
-                Some(x_page) => {
-                    let gitlab_current_page = x_page
-                        .to_str()
-                        .expect("Unable to get string from header")
-                        .parse::<u32>()
-                        .expect("x-page is not a valid number!");
-                    assert_eq!(gitlab_current_page, current_page);
-                }
-                None => panic!("Didn't got x-page header back from Gitlab!"),
+            let link = header
+                .get("link")
+                .map(|link| link.to_str())
+                .transpose()
+                .map_err(|err| PlatformError::InvalidResponse(format!("Unable to read link header: {}", err)))?;
+            // Without a link header, more pages can only be reached by counting them
+            let more_pages = header
+                .get("x-total-pages")
+                .and_then(|total_pages| total_pages.to_str().ok()?.parse::<u32>().ok())
+                .is_some_and(|total_pages| total_pages > 1);
+            if first_page && link.is_none() && more_pages {
+                return Ok(None);
             }
 
-            let mut data: Vec<GitlabEvent> = match serde_json::from_str(&payload) {
-                Ok(data) => data,
-                Err(err) => panic!(
-                    "Unable to decode json response from Gitlab: {}\nThis is what we received:\n{}",
-                    err, payload
-                ),
+            gitlab_events.append(&mut self.decode_events(status, &payload)?);
+            first_page = false;
+
+            next_page_url = match link.map(pagination::parse_header_for_next_page) {
+                Some(Ok(Some(next_page_url))) => next_page_url,
+                Some(Ok(None)) | None => {
+                    debug!("This was the last page");
+                    return Ok(Some(FetchedEvents::complete(gitlab_events)));
+                }
+                Some(Err(err)) => return Ok(Some(FetchedEvents::truncated(gitlab_events, err))),
             };
+        }
+    }
 
-            gitlab_events.append(data.borrow_mut());
+    /// Offset pagination, up to `x-total-pages`. Gitlab leaves that header out for expensive queries,
+    /// pages are fetched until a partial one then.
+    async fn get_events_by_page_number(&self, url: &str) -> Result<FetchedEvents<GitlabEvent>, PlatformError> {
+        let mut gitlab_events: Vec<GitlabEvent> = Vec::new();
+
+        let mut current_page = 1;
+        let mut pagination = PaginationGuard::from_env();
+        loop {
+            let page_url = format!("{}&page={}", url, current_page);
+            if let Err(err) = pagination.visit(&page_url) {
+                return Ok(FetchedEvents::truncated(gitlab_events, err));
+            }
 
-            if log_enabled!(Level::Debug) {
-                for element in data {
-                    debug!("{:?}", element);
+            let (status, header, payload) = match self.get_page(&page_url).await {
+                Ok(page) => page,
+                Err(err) => return git_platform::stop_early(gitlab_events, err),
+            };
+            let mut data = self.decode_events(status, &payload)?;
+            let partial_page = data.len() < self.per_page as usize;
+
+            let total_pages = Gitlab::page_header(&header, "x-total-pages")?;
+            if current_page == 1 {
+                match total_pages {
+                    Some(total_pages) if total_pages.saturating_mul(self.per_page) > LARGE_SYNC_EVENTS => warn!(
+                        "Getting more than {} events! [{} pages of {}]",
+                        LARGE_SYNC_EVENTS, total_pages, self.per_page
+                    ),
+                    Some(_) => {}
+                    None => info!(
+                        "Gitlab ({}) didn't send x-total-pages, fetching pages until a partial one",
+                        self.host()
+                    ),
                 }
-                debug!("This was page {} of {}", current_page, total_pages);
             }
 
-            if current_page >= total_pages {
+            match Gitlab::page_header(&header, "x-page")? {
+                Some(gitlab_current_page) if gitlab_current_page != current_page => warn!(
+                    "Gitlab answered with page {} instead of page {}",
+                    gitlab_current_page, current_page
+                ),
+                Some(_) => {}
+                None => debug!("Gitlab didn't send x-page for page {}", current_page),
+            }
+
+            gitlab_events.append(&mut data);
+            debug!("This was page {} of {:?}", current_page, total_pages);
+
+            let last_page = match total_pages {
+                Some(total_pages) => current_page >= total_pages,
+                None => partial_page,
+            };
+            if last_page {
                 break;
             }
             current_page += 1;
         }
 
-        gitlab_events
+        Ok(FetchedEvents::complete(gitlab_events))
     }
 
-    pub async fn get_project_details_by_id(&self, gitlab_project_id: u64) -> GitlabProjectAPI {
-        let client = reqwest::Client::new();
-        let token = &self.token;
-        let url = format!("https://gitlab.com/api/v4/projects/{}", gitlab_project_id);
+    /// Reads a page number header like `x-page` or `x-total-pages`, `None` if Gitlab left it out
+    fn page_header(header: &HeaderMap, name: &str) -> Result<Option<u32>, PlatformError> {
+        let Some(value) = header.get(name) else {
+            return Ok(None);
+        };
+        value
+            .to_str()
+            .ok()
+            .and_then(|value| value.trim().parse::<u32>().ok())
+            .map(Some)
+            .ok_or_else(|| PlatformError::InvalidResponse(format!("{} is not a valid number: {:?}", name, value)))
+    }
 
-        info!("Getting project info from Gitlab... ({})", url);
+    /// Per-day contribution counts from the calendar of the profile page. It isn't part of the API and only
+    /// covers the last year, earlier days and days without contributions are left out.
+    pub async fn fetch_contribution_days(&self, range: &BackfillRange) -> Result<Vec<(NaiveDate, u32)>, String> {
+        // The calendar is only known by username, GITLAB_USER_ID may be the numeric id
+        let username = match self.user_id.parse::<u64>() {
+            Ok(_) => {
+                let url = format!("{}/users/{}", self.api_base_url(), self.user_id);
+                let user: GitlabUser = get_json(self.client.as_ref(), &url, &self.token).await?;
+                user.username
+            }
+            Err(_) => self.user_id.clone(),
+        };
 
-        let res = client.get(url).bearer_auth(token).send().await;
+        let url = format!("{}/users/{}/calendar.json", self.base_url, username);
+        info!(
+            "Getting contributions of {} from Gitlab between {} and {}... ({})",
+            username, range.since, range.until, url
+        );
+        let calendar: BTreeMap<String, u32> = get_json(self.client.as_ref(), &url, &self.token).await?;
+        contribution_days(calendar, range)
+    }
 
-        let initial_res = match res {
-            Ok(initial_response) => initial_response,
-            Err(err) => panic!("Unable to get response from Gitlab! {}", err),
-        };
+    pub async fn get_project_details_by_id(&self, gitlab_project_id: u64) -> Result<GitlabProjectAPI, PlatformError> {
+        let url = format!("{}/projects/{}", self.api_base_url(), gitlab_project_id);
 
-        let payload = match initial_res.text().await {
-            Ok(text) => text,
-            Err(err) => panic!("Unable to decode response from Gitlab: {}", err),
-        };
+        info!("Getting project info from Gitlab... ({})", url);
 
-        match serde_json::from_str(&payload) {
-            Ok(data) => data,
-            Err(err) => panic!(
-                "Unable to decode json response from Gitlab: {}\nThis is what we received:\n{}",
-                err, payload
-            ),
-        }
+        let (status, _, payload) = self.get_page(&url).await?;
+        self.decode(status, &payload)
     }
 
     async fn fetch_project_from_gitlab_and_write_to_db(
         &self,
         tx: &mut Transaction<'static, MySql>,
         project_id: u64,
-    ) -> Result<u64, String> {
+    ) -> Result<Option<u64>, PlatformError> {
         let gitlab_project_future = self.get_project_details_by_id(project_id);
 
         Gitlab::set_platform(tx).await; // TODO: Only do this at initial setup
 
-        let gitlab_project = gitlab_project_future.await;
-
-        if gitlab_project.visibility.unwrap() != "public" {
-            return Err("Skipping not public project".to_string());
+        let gitlab_project = match gitlab_project_future.await {
+            Ok(gitlab_project) => gitlab_project,
+            // Deleted, or we lost access to it, its events are kept anyway
+            Err(PlatformError::Status { status: 403 | 404, message }) => {
+                warn!("Project {} is unavailable, storing a placeholder: {}", project_id, message);
+                return Ok(Some(self.insert_unavailable_project(tx, project_id).await));
+            }
+            Err(err) => return Err(err),
+        };
+        if !self.project_filter.includes(Self::GIT_PLATFORM_ID, &gitlab_project.path_with_namespace) {
+            return Ok(None);
         }
 
-        let project_id =
-            sqlx::query("INSERT INTO GitProjects (platform, platform_project_id, name, url) VALUES ( ?, ?, ?, ? )")
-            .bind(Self::GIT_PLATFORM_ID)
-            .bind(gitlab_project.id)
-            .bind(gitlab_project.name_with_namespace)
-            .bind(gitlab_project.web_url)
-            .execute(&mut **tx)
-            .await
-            .unwrap()
-            .last_insert_id();
-        trace!("Inserted GitProject (Gitlab) id: {}", project_id);
-        Ok(project_id)
+        let Some(visibility) = self.included_visibility(gitlab_project.visibility.as_deref()) else {
+            debug!(
+                "Skipping event: visibility {:?} of project {} isn't included",
+                gitlab_project.visibility, gitlab_project.id
+            );
+            return Ok(None);
+        };
+
+        let project = GitProject::reported(
+            gitlab_project.id,
+            gitlab_project.name_with_namespace,
+            gitlab_project.web_url,
+        );
+        Ok(Some(self.write_project_to_db(tx, &project, Some(visibility)).await))
     }
 
-    pub async fn insert_gitlab_events_into_db(&self, events: Vec<GitlabEvent>) -> i32 {
+    /// Placeholder for a project we can't look up anymore, its url is hidden while `unavailable_since` is set
+    async fn insert_unavailable_project(&self, tx: &mut Transaction<'static, MySql>, gitlab_project_id: u64) -> u64 {
+        let placeholder = GitProject {
+            unavailable_since: Some(Utc::now()),
+            ..GitProject::reported(
+                gitlab_project_id,
+                format!("deleted-project-{}", gitlab_project_id),
+                format!("{}/projects/{}", self.base_url, gitlab_project_id),
+            )
+        };
+        self.write_project_to_db(tx, &placeholder, None).await
+    }
+
+    /// Returns what happened to the events, and why inserting stopped early if it did
+    pub async fn insert_gitlab_events_into_db(
+        &self,
+        events: Vec<GitlabEvent>,
+        source: EventSource,
+    ) -> (SyncResult, Option<PlatformError>) {
         let db = database::Database::get_or_init().await;
         let pool = db.get_pool().await;
 
-        info!("Starting to insert events from Gitlab");
-        let mut total_events = 0;
-        let mut added_events = 0;
+        info!("Starting to insert events from Gitlab ({})", self.host());
+        let mut result = SyncResult {
+            fetched: events.len(),
+            ..SyncResult::default()
+        };
+        let mut new_events = Vec::new();
+        let mut stopped_by = None;
+        let mut unknown_actions = UnknownActions::default();
 
         // Starting transaction 💪
+        let started = std::time::Instant::now();
         let mut tx = pool.begin().await.expect("Couldn't start transaction!");
         let tx_ref = tx.borrow_mut();
         Self::set_platform(tx_ref).await; // TODO: Only do this at initial setup
+        if source == EventSource::Fetched {
+            event_archive::archive(tx_ref, Self::GIT_PLATFORM_ID, &events).await;
+        }
+        let mut cache = SyncCache::load(tx_ref, Self::GIT_PLATFORM_ID).await;
 
-        for event in events.iter() {
-            total_events += 1;
-
+        for (index, event) in events.iter().enumerate() {
             // TODO: Maybe check if name is still up-to-date etc.
             let gitlab_project_option_future =
-                Gitlab::fetch_single_git_project_from_db(tx_ref, event.project_id);
-
-            let datetime: DateTime<Utc> = match event.created_at.parse() {
-                Ok(datetime) => datetime,
-                Err(err) => {
-                    // Parsing failed - https://docs.rs/chrono/latest/chrono/struct.DateTime.html#impl-FromStr-for-DateTime%3CUtc%3E
-                    error!(
-                        "Couldn't parse date from Gitlab using a relaxed form of RFC3339. \
-                    Event will be skipped! Received 'created_at' value: {} - error msg: {}",
-                    event.created_at, err
-                    );
-                    continue;
-                }
+                cache.project(tx_ref, event.project_id, false);
+
+            let Ok(datetime) = Self::parse_datetime(&event.created_at) else {
+                result.skipped_other += 1;
+                continue;
             };
 
             // Inserting GitlabProject
             let project_id = if let Some(project) = gitlab_project_option_future.await {
+                // Stored before the project was filtered, its path is only known from the url
+                let path = project.url.strip_prefix(&format!("{}/", self.base_url));
+                if path.is_some_and(|path| !self.project_filter.includes(Self::GIT_PLATFORM_ID, path)) {
+                    result.skipped_other += 1;
+                    continue;
+                }
                 project.id
             } else {
                 match self.fetch_project_from_gitlab_and_write_to_db(tx_ref, event.project_id)
                     .await {
-                        Ok(result) => result,
-                        Err(err) => {
-                            debug!("Skipping event: {}", err);
+                        Ok(Some(result)) => result,
+                        Ok(None) => {
+                            result.skipped_other += 1;
                             continue;
                         }
+                        Err(err) => {
+                            warn!("Unable to get project info, skipping the remaining events: {}", err);
+                            result.skipped_project_errors = events.len() - index;
+                            stopped_by = Some(err);
+                            break;
+                        }
                     }
             };
 
-            let action_name = match Gitlab::map_action_name(event.action_name.as_str()) {
+            let action_name = match event.action() {
                 Some(value) => value,
                 None => {
-                    warn!("Skipping event - because action name unknown! {:#?}", event);
+                    debug!("Skipping event - because action name unknown! {:#?}", event);
+                    unknown_actions.record(&event.action_name);
+                    result.skipped_unknown_action += 1;
                     continue;
                 }
             };
-            // TODO: Handle push_data (multiple commits!)
-            let action_id = match Gitlab::get_git_action_by_name(tx_ref, &action_name).await {
-                Some(value) => value,
-                None => Gitlab::insert_git_action(tx_ref, &action_name).await,
-            };
-
-            if Gitlab::count_all_matching_events(tx_ref, &datetime, &action_id, &project_id).await
-                > 0
-            {
-                debug!("Skipping insert! Event already exists");
-                continue;
-            }
-
-            // Add event itself
-            let event_id = Gitlab::insert_event(tx_ref, datetime).await;
-
-            let _gitlab_event_id =
-                Gitlab::insert_git_event(tx_ref, event_id, action_id, project_id).await;
+            let action_id = cache.action(tx_ref, action_name).await;
+
+            let commit_count = event.push_data.as_ref().map(|push_data| push_data.commit_count);
+            new_events.push(NewEvent {
+                datetime,
+                action_id,
+                project_id,
+                commit_count,
+                account: None,
+                details: EventDetails {
+                    git_ref: event.push_data.as_ref().and_then(|push_data| push_data.r#ref.as_deref()),
+                    target_type: event.target(),
+                },
+            });
 
             // let event_id = sqlx::query("INSERT INTO GitlabProjects (id, name, url) VALUES ( ? )")
             //     .bind(event.)
@@ -329,88 +949,1298 @@ impl Gitlab {
             //     .unwrap()
             //     .last_insert_id();
             // trace!("Inserted Gitlab event id: {} @ {}", event_id, datetime);
-
-            added_events += 1;
         }
 
-        Gitlab::update_last_sync_timestamp(tx_ref).await;
+        let inserted_at = Gitlab::insert_unique_events(tx_ref, &new_events, &mut result).await;
+        unknown_actions.log(Self::GIT_PLATFORM_ID);
+        // The skipped events are fetched again by the next sync
+        if stopped_by.is_none() && source == EventSource::Fetched {
+            Gitlab::update_last_sync_timestamp(tx_ref).await;
+        }
         tx.commit().await.expect("Couldn't apply transaction ._.");
+        records::update_stat_records(&pool, &inserted_at).await;
         info!(
-            "Inserted {} new Gitlab events from {} total events into DB",
-            added_events, total_events
+            "Inserted {} new Gitlab events from {} total events into DB ({}) in {:?}",
+            result.inserted, result.fetched, self.host(), started.elapsed()
         );
 
-        added_events
+        (result, stopped_by)
+    }
+}
+
+/// Days of `calendar.json` within `range`, skipping empty ones
+fn contribution_days(calendar: BTreeMap<String, u32>, range: &BackfillRange) -> Result<Vec<(NaiveDate, u32)>, String> {
+    let mut days = Vec::new();
+    for (day, count) in calendar {
+        let day = NaiveDate::parse_from_str(&day, "%Y-%m-%d")
+            .map_err(|err| format!("Unable to decode contributions from Gitlab, »{}« isn't a day: {}", day, err))?;
+        if count > 0 && range.contains(day) {
+            days.push((day, count));
+        }
+    }
+    Ok(days)
+}
+
+/// Like `credentials::rejection`, pointing at the token if Gitlab didn't accept it
+fn rejection(status: StatusCode, payload: &str) -> String {
+    let rejection = credentials::rejection(Gitlab::GIT_PLATFORM_ID, status, payload);
+    if status == StatusCode::UNAUTHORIZED {
+        format!("GITLAB_API_TOKEN is invalid or expired ({})", rejection)
+    } else {
+        rejection
+    }
+}
+
+async fn get_json<T: serde::de::DeserializeOwned>(client: &dyn HttpClient, url: &str, token: &str) -> Result<T, String> {
+    let response = client
+        .send(HttpRequest::get(url).bearer_auth(token))
+        .await
+        .map_err(|err| format!("Unable to get response from Gitlab: {}", err))?;
+    if !response.status.is_success() {
+        return Err(rejection(response.status, &response.body));
     }
+    serde_json::from_str(&response.body).map_err(|err| format!("Unable to decode json response from Gitlab: {}", err))
+}
+
+/// `GET /user`, the scopes and expiry are only known for personal access tokens
+async fn validate_token(client: &dyn HttpClient, api_base_url: &str, token: &str) -> Result<TokenInfo, String> {
+    let user: GitlabUser = get_json(client, &format!("{}/user", api_base_url), token).await?;
+    let details: Option<GitlabTokenDetails> =
+        get_json(client, &format!("{}/personal_access_tokens/self", api_base_url), token).await.ok();
+
+    Ok(TokenInfo {
+        username: user.username,
+        scopes: details.as_ref().map(|details| details.scopes.clone()),
+        expires_at: details.and_then(|details| details.expires_at),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::http_client::tests::{response, MockHttpClient};
+    use crate::http_client::HttpError;
+    use crate::pagination::PaginationError;
     use chrono::TimeZone;
     use dotenv::dotenv;
+    use wiremock::{
+        matchers::{header, method, path, query_param, query_param_is_missing},
+        Mock, MockServer, ResponseTemplate,
+    };
 
-    #[tokio::test]
-    async fn gitlab_api_is_still_sane() {
-        dotenv().ok();
-        let gitlab = Gitlab::init_from_env_vars();
+    fn gitlab_for_tests(base_url: String) -> Gitlab {
+        Gitlab {
+            token: "token".to_string(),
+            user_id: "42".to_string(),
+            base_url,
+            per_page: FALLBACK_GITLAB_PER_PAGE,
+            groups: Vec::new(),
+            include_visibilities: vec![ProjectVisibility::Public],
+            project_filter: ProjectFilter::default(),
+            rate_limit_policy: GitlabRateLimitPolicy {
+                max_wait: Duration::from_millis(1),
+                retries: 2,
+            },
+            retry_policy: RetryPolicy {
+                attempts: 1,
+                base_delay: std::time::Duration::from_millis(1),
+                max_delay: std::time::Duration::from_millis(1),
+            },
+            sync_overlap: chrono::Duration::days(FALLBACK_SYNC_OVERLAP_DAYS),
+            client: Arc::new(http_client::platform_client()),
+        }
+    }
 
-        let result = gitlab
-            .get_events(
-                Utc.with_ymd_and_hms(2024, 05, 01, 0, 0, 0).unwrap(),
-                Utc.with_ymd_and_hms(2024, 05, 05, 0, 0, 0).unwrap(),
-            )
-            .await;
-        //assert_eq!(result, OffsetDateTime::now_utc().date().to_string())
-        assert_eq!(result.len(), 31);
+    fn events_page(page: u32, total_pages: u32, project_id: u64) -> ResponseTemplate {
+        ResponseTemplate::new(200)
+            .insert_header("x-page", page.to_string())
+            .insert_header("x-total-pages", total_pages.to_string())
+            .set_body_string(format!(
+                r#"[{{"project_id": {}, "action_name": "pushed to", "created_at": "2024-05-01T12:00:00.000Z", "push_data": {{"commit_count": 2}}}}]"#,
+                project_id
+            ))
     }
 
-    #[tokio::test]
-    async fn gitlab_api_is_still_sane_without_pagination() {
-        dotenv().ok();
-        Gitlab::get_or_init();
-        let gitlab = Gitlab::init_from_env_vars();
+    /// Captured from `GET /users/:id/events`, trimmed to the interesting parts
+    static PUSH_TO_BRANCH: &str = r#"{"id": 3401855418, "project_id": 61345567, "action_name": "pushed to",
+        "target_id": null, "target_type": null, "author_id": 10930117, "target_title": null,
+        "created_at": "2024-05-03T18:40:12.155Z", "author_username": "2tefan",
+        "push_data": {"commit_count": 2, "action": "pushed", "ref_type": "branch",
+            "commit_from": "8d1c5f1b3e0d5e2f6a4b9c7d0e1f2a3b4c5d6e7f", "commit_to": "2b9f0c4e7a1d3f5b8c6e9a0d2f4b6c8e0a1c3e5f",
+            "ref": "main", "commit_title": "Add sync runs to the dashboard", "ref_count": null}}"#;
+    static DELETED_TAG: &str = r#"{"id": 3401861020, "project_id": 61345567, "action_name": "deleted",
+        "created_at": "2024-05-03T18:52:40.012Z", "author_username": "2tefan",
+        "push_data": {"commit_count": 0, "action": "removed", "ref_type": "tag",
+            "commit_from": "2b9f0c4e7a1d3f5b8c6e9a0d2f4b6c8e0a1c3e5f", "commit_to": null,
+            "ref": null, "commit_title": null, "ref_count": null}}"#;
+    static BULK_PUSH: &str = r#"{"id": 3401870077, "project_id": 61345567, "action_name": "pushed new",
+        "created_at": "2024-05-03T19:01:05.731Z", "author_username": "2tefan",
+        "push_data": {"commit_count": 0, "action": "created", "ref_type": "branch",
+            "commit_from": null, "commit_to": null, "ref_count": 5, "commit_title": null}}"#;
+
+    #[test]
+    fn gitlab_is_configured_by_any_of_its_credentials() {
+        let configured = |vars: &[(&str, &str)]| {
+            Gitlab::is_configured_in(|name| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string()))
+        };
 
-        let result = gitlab
-            .get_events(
-                Utc.with_ymd_and_hms(2024, 05, 03, 0, 0, 0).unwrap(),
-                Utc.with_ymd_and_hms(2024, 05, 05, 0, 0, 0).unwrap(),
-            )
-            .await;
-        assert_eq!(result.len(), 4);
+        // Neither platform, or only Github
+        assert!(!configured(&[]));
+        assert!(!configured(&[("GITHUB_USERNAME", "2tefan"), ("GITHUB_API_TOKEN", "token")]));
+        assert!(!configured(&[("GITLAB_API_TOKEN", " "), ("GITLAB_USER_ID", "")]));
+
+        assert!(configured(&[("GITLAB_API_TOKEN", "token"), ("GITLAB_USER_ID", "10930117")]));
+        // Only partially, `init_from_env_vars` reports the missing user id
+        assert!(configured(&[("GITLAB_API_TOKEN", "token")]));
     }
 
-    #[tokio::test]
-    async fn gitlab_get_pollux_project() {
-        dotenv().ok();
-        Gitlab::get_or_init();
-        let gitlab = Gitlab::init_from_env_vars();
+    #[test]
+    fn pushed_ref_is_deserialized() {
+        let event: GitlabEvent = serde_json::from_str(PUSH_TO_BRANCH).unwrap();
+        let push_data = event.push_data.unwrap();
+        assert_eq!(push_data.commit_count, 2);
+        assert_eq!(push_data.r#ref.as_deref(), Some("main"));
+        assert_eq!(push_data.ref_type.as_deref(), Some("branch"));
+    }
 
-        let result = gitlab.get_project_details_by_id(61345567).await;
+    static COMMENT_ON_MERGE_REQUEST: &str = r#"{"id": 3402011873, "project_id": 61345567, "action_name": "commented on",
+        "target_id": 1893450211, "target_iid": 1893450211, "target_type": "DiffNote",
+        "author_id": 10930117, "target_title": "Add sync runs to the dashboard",
+        "created_at": "2024-05-04T09:12:30.411Z", "author_username": "2tefan",
+        "note": {"id": 1893450211, "type": "DiffNote", "body": "Needs a migration", "system": false,
+            "noteable_id": 293848211, "noteable_type": "MergeRequest", "noteable_iid": 42}}"#;
+    static COMMENT_ON_ISSUE: &str = r#"{"id": 3402015530, "project_id": 61345567, "action_name": "commented on",
+        "target_id": 1893461987, "target_iid": 1893461987, "target_type": "Note",
+        "author_id": 10930117, "target_title": "Stats are off by one day",
+        "created_at": "2024-05-04T09:20:02.108Z", "author_username": "2tefan",
+        "note": {"id": 1893461987, "type": null, "body": "Timezones again", "system": false,
+            "noteable_id": 146603422, "noteable_type": "Issue", "noteable_iid": 17}}"#;
+    static OPENED_ISSUE: &str = r#"{"id": 3402020014, "project_id": 61345567, "action_name": "opened",
+        "target_id": 146603422, "target_iid": 17, "target_type": "Issue",
+        "author_id": 10930117, "target_title": "Stats are off by one day",
+        "created_at": "2024-05-04T08:58:44.920Z", "author_username": "2tefan"}"#;
+
+    #[test]
+    fn comment_targets_are_deserialized() {
+        let event: GitlabEvent = serde_json::from_str(COMMENT_ON_MERGE_REQUEST).unwrap();
+        assert_eq!(event.action(), Some("commented"));
+        assert_eq!(event.target_type.as_deref(), Some("DiffNote"));
+        assert_eq!(event.target_title.as_deref(), Some("Add sync runs to the dashboard"));
+        assert_eq!(event.note.as_ref().and_then(|note| note.noteable_iid), Some(42));
+        assert_eq!(event.target(), Some("MergeRequest"));
+
+        let event: GitlabEvent = serde_json::from_str(COMMENT_ON_ISSUE).unwrap();
+        assert_eq!(event.target_type.as_deref(), Some("Note"));
+        assert_eq!(event.target(), Some("Issue"));
+
+        let event: GitlabEvent = serde_json::from_str(OPENED_ISSUE).unwrap();
+        assert_eq!(event.target_iid, Some(17));
+        assert_eq!(event.note, None);
+        assert_eq!(event.target(), Some("Issue"));
+        assert_eq!(event.action(), Some("issue"));
+    }
+
+    #[test]
+    fn issues_are_told_apart_from_merge_requests() {
+        let expected = [
+            ("opened", Some("Issue"), Some("issue")),
+            ("closed", Some("Issue"), Some("issue")),
+            ("reopened", Some("Issue"), Some("issue")),
+            ("opened", Some("MergeRequest"), Some("merge-request")),
+            ("closed", Some("MergeRequest"), Some("merge-request")),
+            ("accepted", Some("MergeRequest"), Some("merge-request")),
+            ("closed", Some("Milestone"), Some("project-management")),
+            ("closed", None, Some("merge-request")),
+            ("commented on", Some("Note"), Some("commented")),
+            ("pushed to", None, Some("commit")),
+            ("expired", None, None),
+        ];
+
+        for (action_name, target_type, action) in expected {
+            let event = GitlabEvent {
+                action_name: action_name.to_string(),
+                target_type: target_type.map(str::to_string),
+                ..Default::default()
+            };
+            assert_eq!(event.action(), action, "{} {:?}", action_name, target_type);
+        }
+    }
+
+    #[test]
+    fn events_without_target_are_tolerated() {
+        for payload in [PUSH_TO_BRANCH, DELETED_TAG, BULK_PUSH] {
+            let event: GitlabEvent = serde_json::from_str(payload).unwrap();
+            assert_eq!(event.target(), None, "{}", payload);
+            assert_eq!(event.target_title, None);
+        }
+    }
+
+    #[test]
+    fn missing_refs_are_tolerated() {
+        let event: GitlabEvent = serde_json::from_str(DELETED_TAG).unwrap();
+        let push_data = event.push_data.unwrap();
+        assert_eq!(push_data.r#ref, None);
+        assert_eq!(push_data.ref_type.as_deref(), Some("tag"));
+
+        // Bulk pushes only tell how many refs there were
+        let event: GitlabEvent = serde_json::from_str(BULK_PUSH).unwrap();
+        assert_eq!(event.push_data.unwrap().r#ref, None);
+    }
+
+    #[test]
+    fn base_url_is_validated() {
+        assert_eq!(Gitlab::parse_base_url("https://gitlab.com"), Ok("https://gitlab.com".to_string()));
+        assert_eq!(
+            Gitlab::parse_base_url(" https://git.example.com/ "),
+            Ok("https://git.example.com".to_string())
+        );
+        assert_eq!(
+            Gitlab::parse_base_url("https://example.com/gitlab/"),
+            Ok("https://example.com/gitlab".to_string())
+        );
+        for input in ["http://git.example.com", "git.example.com", "/gitlab", "https://example.com/?page=1", ""] {
+            assert!(Gitlab::parse_base_url(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn host_is_named_in_the_logs() {
+        assert_eq!(gitlab_for_tests(FALLBACK_GITLAB_BASE_URL.to_string()).host(), "gitlab.com");
+        assert_eq!(
+            gitlab_for_tests("https://example.com/gitlab".to_string()).host(),
+            "example.com/gitlab"
+        );
+    }
+
+    #[tokio::test]
+    async fn events_are_fetched_by_keyset() {
+        let server = MockServer::start().await;
+        let keyset_page = |cursor: Option<&str>, next: Option<&str>, project_id: u64| {
+            let mut mock = Mock::given(method("GET"))
+                .and(path("/api/v4/users/42/events"))
+                .and(query_param("pagination", "keyset"))
+                .and(query_param("order_by", "id"))
+                .and(query_param("sort", "desc"));
+            mock = match cursor {
+                Some(cursor) => mock.and(query_param("id_before", cursor)),
+                None => mock.and(query_param_is_missing("id_before")),
+            };
+            let mut response = events_page(1, 1, project_id);
+            if let Some(next) = next {
+                response = response.insert_header(
+                    "link",
+                    format!(
+                        r#"<{}/api/v4/users/42/events?pagination=keyset&order_by=id&sort=desc&id_before={}>; rel="next""#,
+                        server.uri(),
+                        next
+                    ),
+                );
+            }
+            mock.respond_with(response).expect(1)
+        };
+        keyset_page(None, Some("300"), 1).mount(&server).await;
+        keyset_page(Some("300"), Some("200"), 2).mount(&server).await;
+        keyset_page(Some("200"), None, 3).mount(&server).await;
+
+        let fetched = gitlab_for_tests(server.uri())
+            .get_events(
+                Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(fetched.truncated, None);
+        assert_eq!(
+            fetched.events.iter().map(|event| event.project_id).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[tokio::test]
+    async fn page_numbers_are_counted_without_keyset_support() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/users/42/events"))
+            .and(query_param("pagination", "keyset"))
+            .respond_with(ResponseTemplate::new(405).set_body_string(
+                r#"{"message": "Keyset pagination is not yet available for this type of request"}"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        for page in 1..=2 {
+            Mock::given(method("GET"))
+                .and(path("/api/v4/users/42/events"))
+                .and(query_param("page", page.to_string()))
+                .and(header("authorization", "Bearer token"))
+                .respond_with(events_page(page, 2, page as u64))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let fetched = gitlab_for_tests(server.uri())
+            .get_events(
+                Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(fetched.truncated, None);
+        assert_eq!(
+            fetched.events.iter().map(|event| event.project_id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+    }
+
+    #[tokio::test]
+    async fn page_numbers_are_counted_without_link_header() {
+        let server = MockServer::start().await;
+        // Keyset parameters are ignored, the first page is answered with offset headers only
+        Mock::given(method("GET"))
+            .and(path("/api/v4/users/42/events"))
+            .and(query_param("pagination", "keyset"))
+            .respond_with(events_page(1, 2, 1))
+            .expect(1)
+            .mount(&server)
+            .await;
+        for page in 1..=2 {
+            Mock::given(method("GET"))
+                .and(path("/api/v4/users/42/events"))
+                .and(query_param("page", page.to_string()))
+                .respond_with(events_page(page, 2, page as u64))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let fetched = gitlab_for_tests(server.uri())
+            .get_events(
+                Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            fetched.events.iter().map(|event| event.project_id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn per_page_is_validated_and_capped() {
+        assert_eq!(Gitlab::parse_per_page("100"), Ok(100));
+        assert_eq!(Gitlab::parse_per_page(" 20 "), Ok(20));
+        assert_eq!(Gitlab::parse_per_page("500"), Ok(100));
+        for input in ["0", "-5", "many", ""] {
+            assert!(Gitlab::parse_per_page(input).is_err(), "{}", input);
+        }
+    }
+
+    #[tokio::test]
+    async fn configured_page_size_is_requested_until_a_partial_page() {
+        let server = MockServer::start().await;
+        let event = r#"{"project_id": 1, "action_name": "pushed to", "created_at": "2024-05-01T12:00:00.000Z"}"#;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/users/42/events"))
+            .and(query_param("per_page", "2"))
+            .and(query_param_is_missing("id_before"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header(
+                        "link",
+                        format!(
+                            r#"<{}/api/v4/users/42/events?pagination=keyset&per_page=2&order_by=id&sort=desc&id_before=2>; rel="next""#,
+                            server.uri()
+                        ),
+                    )
+                    .set_body_string(format!("[{}, {}]", event, event)),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/users/42/events"))
+            .and(query_param("per_page", "2"))
+            .and(query_param("id_before", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!("[{}]", event)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut gitlab = gitlab_for_tests(server.uri());
+        gitlab.per_page = 2;
+        let fetched = gitlab
+            .get_events(
+                Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(fetched.truncated, None);
+        assert_eq!(fetched.events.len(), 3);
+    }
+
+    #[test]
+    fn retry_after_is_capped() {
+        let policy = GitlabRateLimitPolicy {
+            max_wait: Duration::from_secs(120),
+            retries: 3,
+        };
+        let mut headers = HeaderMap::new();
+        assert_eq!(policy.retry_after(&headers), DEFAULT_RATE_LIMIT_WAIT);
+
+        headers.insert(RETRY_AFTER, "30".parse().unwrap());
+        assert_eq!(policy.retry_after(&headers), Duration::from_secs(30));
+        headers.insert(RETRY_AFTER, "3600".parse().unwrap());
+        assert_eq!(policy.retry_after(&headers), Duration::from_secs(120));
+    }
+
+    #[tokio::test]
+    async fn rate_limited_page_is_retried_after_waiting() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/users/42/events"))
+            .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "1"))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/users/42/events"))
+            .respond_with(events_page(1, 1, 1))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let fetched = gitlab_for_tests(server.uri())
+            .get_events(
+                Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(fetched.truncated, None);
+        assert_eq!(fetched.events.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn persistent_rate_limit_ends_the_sync_early() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429))
+            // The first try and both retries, for the events and the project each
+            .expect(6)
+            .mount(&server)
+            .await;
+
+        let gitlab = gitlab_for_tests(server.uri());
+        let fetched = gitlab
+            .get_events(
+                Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(fetched.events.is_empty());
+        assert_eq!(fetched.truncated, Some(PaginationError::RateLimited { status: 429, retries: 2 }));
+
+        assert_eq!(
+            gitlab.get_project_details_by_id(1).await,
+            Err(PlatformError::RateLimited { status: 429, retries: 2 })
+        );
+    }
+
+    async fn get_events_of(server: &MockServer) -> Result<FetchedEvents<GitlabEvent>, PlatformError> {
+        gitlab_for_tests(server.uri())
+            .get_events(
+                Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap(),
+            )
+            .await
+    }
+
+    const MOCK_BASE_URL: &str = "https://gitlab.example.com";
+    const MOCK_EVENTS_URL: &str =
+        "https://gitlab.example.com/api/v4/users/42/events?after=2024-05-01&before=2024-05-02&per_page=100";
+
+    /// Answered by `client` instead of a server
+    async fn get_events_with(client: &Arc<MockHttpClient>) -> Result<FetchedEvents<GitlabEvent>, PlatformError> {
+        Gitlab {
+            client: client.clone(),
+            ..gitlab_for_tests(MOCK_BASE_URL.to_string())
+        }
+        .get_events(
+            Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap(),
+        )
+        .await
+    }
+
+    fn keyset_url(id_before: Option<u64>) -> String {
+        let url = format!("{}&pagination=keyset&order_by=id&sort=desc", MOCK_EVENTS_URL);
+        match id_before {
+            Some(id_before) => format!("{}&id_before={}", url, id_before),
+            None => url,
+        }
+    }
+
+    #[tokio::test]
+    async fn keyset_pages_are_followed_with_canned_responses() {
+        let client = Arc::new(MockHttpClient::default());
+        let next = format!(r#"<{}>; rel="next""#, keyset_url(Some(3401861020)));
+        client
+            .respond(
+                keyset_url(None),
+                response(200, &[("link", &next)], &format!("[{}, {}]", PUSH_TO_BRANCH, DELETED_TAG)),
+            )
+            .respond(keyset_url(Some(3401861020)), response(200, &[], &format!("[{}]", BULK_PUSH)));
+
+        let fetched = get_events_with(&client).await.unwrap();
+        assert_eq!(fetched.truncated, None);
+        assert_eq!(
+            fetched.events.iter().map(|event| event.action_name.as_str()).collect::<Vec<_>>(),
+            vec!["pushed to", "deleted", "pushed new"]
+        );
+        let requests = client.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|request| request.bearer.as_deref() == Some("token")));
+    }
+
+    #[tokio::test]
+    async fn rejected_keyset_falls_back_to_canned_page_numbers() {
+        let client = Arc::new(MockHttpClient::default());
+        let page = |page: u32| {
+            response(
+                200,
+                &[("x-page", &page.to_string()), ("x-total-pages", "2")],
+                &format!("[{}]", PUSH_TO_BRANCH),
+            )
+        };
+        client
+            .respond(keyset_url(None), response(405, &[], ""))
+            .respond(format!("{}&page=1", MOCK_EVENTS_URL), page(1))
+            .respond(format!("{}&page=2", MOCK_EVENTS_URL), page(2));
+
+        let fetched = get_events_with(&client).await.unwrap();
+        assert_eq!(fetched.truncated, None);
+        assert_eq!(fetched.events.len(), 2);
+        assert_eq!(client.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_keyset_page_keeps_the_pages_before() {
+        let client = Arc::new(MockHttpClient::default());
+        let next = format!(r#"<{}>; rel="next""#, keyset_url(Some(3401855418)));
+        client.respond(keyset_url(None), response(200, &[("link", &next)], &format!("[{}]", PUSH_TO_BRANCH)));
+        // The first try and both retries
+        for _ in 0..3 {
+            client.respond(keyset_url(Some(3401855418)), response(429, &[("retry-after", "0")], ""));
+        }
+
+        let fetched = get_events_with(&client).await.unwrap();
+        assert_eq!(fetched.events.len(), 1);
+        assert_eq!(fetched.truncated, Some(PaginationError::RateLimited { status: 429, retries: 2 }));
+    }
+
+    #[tokio::test]
+    async fn unreachable_gitlab_fails_the_sync() {
+        let client = Arc::new(MockHttpClient::default());
+        client.fail(
+            keyset_url(None),
+            HttpError {
+                message: "connection refused".to_string(),
+                transient: true,
+            },
+        );
+
+        let err = get_events_with(&client).await.unwrap_err();
+        assert!(matches!(err, PlatformError::Request(ref message) if message.contains("connection refused")), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn server_error_is_returned_instead_of_panicking() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500).set_body_string(r#"{"message": "500 Internal Server Error"}"#))
+            .mount(&server)
+            .await;
+
+        let err = get_events_of(&server).await.unwrap_err();
+        assert!(matches!(err, PlatformError::Status { status: 500, .. }), "{:?}", err);
+        assert!(err.to_string().contains("500 Internal Server Error"), "{}", err);
+        assert!(matches!(
+            gitlab_for_tests(server.uri()).get_project_details_by_id(1).await,
+            Err(PlatformError::Status { status: 500, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn malformed_json_is_returned_instead_of_panicking() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(events_page(1, 1, 1).set_body_string("<html>Maintenance</html>"))
+            .mount(&server)
+            .await;
+
+        assert!(matches!(
+            get_events_of(&server).await,
+            Err(PlatformError::InvalidResponse(_))
+        ));
+        assert!(matches!(
+            gitlab_for_tests(server.uri()).get_project_details_by_id(1).await,
+            Err(PlatformError::InvalidResponse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn unexpected_page_is_only_warned_about() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(query_param("pagination", "keyset"))
+            .respond_with(ResponseTemplate::new(405))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(query_param("page", "1"))
+            .respond_with(events_page(2, 1, 1))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let fetched = get_events_of(&server).await.unwrap();
+        assert_eq!(fetched.events.len(), 1);
+        assert_eq!(fetched.truncated, None);
+    }
+
+    #[tokio::test]
+    async fn pages_are_fetched_until_a_partial_one_without_total_pages() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(query_param("pagination", "keyset"))
+            .respond_with(ResponseTemplate::new(405))
+            .mount(&server)
+            .await;
+        let event = r#"{"project_id": 1, "action_name": "pushed to", "created_at": "2024-05-01T12:00:00.000Z"}"#;
+        for (page, events) in [(1, 2), (2, 2), (3, 0)] {
+            Mock::given(method("GET"))
+                .and(query_param("page", page.to_string()))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_string(format!("[{}]", vec![event; events].join(","))),
+                )
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(query_param("page", "4"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let mut gitlab = gitlab_for_tests(server.uri());
+        gitlab.per_page = 2;
+        let fetched = gitlab
+            .get_events(
+                Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(fetched.events.len(), 4);
+        assert_eq!(fetched.truncated, None);
+    }
+
+    #[tokio::test]
+    async fn partial_page_without_total_pages_is_the_last() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(query_param("pagination", "keyset"))
+            .respond_with(ResponseTemplate::new(405))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(query_param("page", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"[{"project_id": 1, "action_name": "pushed to", "created_at": "2024-05-01T12:00:00.000Z"}]"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let fetched = get_events_of(&server).await.unwrap();
+        assert_eq!(fetched.events.len(), 1);
+        assert_eq!(fetched.truncated, None);
+    }
+
+    #[tokio::test]
+    async fn failed_sync_is_recorded() {
+        dotenv().ok();
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        let mut gitlab = gitlab_for_tests(server.uri());
+        let report = git_platform::sync_and_record(&mut gitlab).await;
+        assert_eq!(report.events_inserted, 0);
+        assert!(report.error.unwrap().starts_with("Gitlab answered 500 Internal Server Error"));
+
+        let pool = database::Database::get_or_init().await.get_pool().await;
+        let runs = crate::sync_runs::get_sync_runs(&pool, 1).await;
+        assert_eq!(runs[0].status, "failed");
+    }
+
+    async fn insert_event_of_unavailable_project(status: u16, gitlab_project_id: u64) {
+        dotenv().ok();
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/api/v4/projects/{}", gitlab_project_id)))
+            .respond_with(ResponseTemplate::new(status).set_body_string(format!(r#"{{"message": "{} Project Unavailable"}}"#, status)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let event = GitlabEvent {
+            project_id: gitlab_project_id,
+            action_name: "pushed to".to_string(),
+            created_at: Utc::now().to_rfc3339(),
+            push_data: Some(PushData {
+                commit_count: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let (result, stopped_by) =
+            gitlab_for_tests(server.uri()).insert_gitlab_events_into_db(vec![event], EventSource::Fetched).await;
+        assert_eq!((result.inserted, stopped_by), (1, None));
+
+        let pool = database::Database::get_or_init().await.get_pool().await;
+        let (name, unavailable): (String, bool) = sqlx::query_as(
+            "SELECT name, unavailable_since IS NOT NULL FROM GitProjects WHERE platform = 'Gitlab' AND platform_project_id = ?",
+        )
+        .bind(gitlab_project_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(name, format!("deleted-project-{}", gitlab_project_id));
+        assert!(unavailable);
+    }
+
+    #[tokio::test]
+    async fn events_of_deleted_projects_are_kept() {
+        insert_event_of_unavailable_project(404, 990000404).await;
+    }
+
+    #[tokio::test]
+    async fn events_of_inaccessible_projects_are_kept() {
+        insert_event_of_unavailable_project(403, 990000403).await;
+    }
+
+    #[tokio::test]
+    async fn skipped_events_are_counted_by_reason() {
+        dotenv().ok();
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/projects/990000405"))
+            .respond_with(ResponseTemplate::new(404).set_body_string(r#"{"message": "404 Project Not Found"}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/projects/990000500"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        let created_at = Utc::now().to_rfc3339();
+        let event = |project_id: u64, action_name: &str| GitlabEvent {
+            project_id,
+            action_name: action_name.to_string(),
+            created_at: created_at.clone(),
+            push_data: Some(PushData {
+                commit_count: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let events = vec![
+            event(990000405, "pushed to"),
+            event(990000405, "pushed to"),
+            event(990000405, "teleported"),
+            event(990000500, "pushed to"),
+            event(990000405, "pushed to"),
+        ];
+
+        let (result, stopped_by) =
+            gitlab_for_tests(server.uri()).insert_gitlab_events_into_db(events, EventSource::Fetched).await;
+        assert_eq!(result.fetched, 5);
+        assert_eq!(result.inserted, 1);
+        assert_eq!(result.skipped_duplicates, 1);
+        assert_eq!(result.skipped_unknown_action, 1);
+        // The project error stops inserting, so the last event is skipped as well
+        assert_eq!(result.skipped_project_errors, 2);
+        assert!(matches!(stopped_by, Some(PlatformError::Status { status: 500, .. })));
+    }
+
+    #[test]
+    fn projects_are_included_by_their_visibility() {
+        let configurations = [
+            ("public", ["public"].as_slice()),
+            ("internal", ["internal"].as_slice()),
+            ("private", ["private"].as_slice()),
+            ("public,internal", ["public", "internal"].as_slice()),
+            ("public,private", ["public", "private"].as_slice()),
+            ("public,internal,private", ["public", "internal", "private"].as_slice()),
+        ];
+
+        let mut gitlab = gitlab_for_tests(FALLBACK_GITLAB_BASE_URL.to_string());
+        for (configuration, included) in configurations {
+            gitlab.include_visibilities = ProjectVisibility::parse_set(configuration).unwrap();
+            for visibility in ["public", "internal", "private"] {
+                assert_eq!(
+                    gitlab.included_visibility(Some(visibility)).map(|visibility| visibility.as_str()),
+                    included.contains(&visibility).then_some(visibility),
+                    "{} with {}",
+                    visibility,
+                    configuration
+                );
+            }
+            // GitLab always reports it, but don't count projects of unknown visibility
+            assert_eq!(gitlab.included_visibility(None), None);
+            assert_eq!(gitlab.included_visibility(Some("secret")), None);
+        }
+    }
+
+    #[test]
+    fn sync_window_overlaps_the_last_sync() {
+        let gitlab = gitlab_for_tests("https://gitlab.com".to_string());
+        let now = Utc.with_ymd_and_hms(2024, 5, 4, 8, 0, 0).unwrap();
+
+        let (after, before) = gitlab.sync_window(Some(Utc.with_ymd_and_hms(2024, 5, 3, 23, 30, 0).unwrap()), now);
+        assert_eq!(after, Utc.with_ymd_and_hms(2024, 5, 1, 23, 30, 0).unwrap());
+        assert_eq!(before, Utc.with_ymd_and_hms(2024, 5, 5, 8, 0, 0).unwrap());
+
+        let (after, _) = gitlab.sync_window(None, now);
+        assert_eq!(after, Utc.with_ymd_and_hms(2024, 2, 4, 8, 0, 0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn event_on_the_day_of_the_last_sync_is_fetched_again() {
+        // Synced in the morning, the push in the evening of the same day wasn't in the feed yet
+        let last_sync = Utc.with_ymd_and_hms(2024, 5, 3, 9, 0, 0).unwrap();
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/users/42/events"))
+            .and(query_param("after", "2024-05-01"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("x-page", "1")
+                    .insert_header("x-total-pages", "1")
+                    .set_body_string(
+                        r#"[{"project_id": 1, "action_name": "pushed to", "created_at": "2024-05-03T18:00:00.000Z"}]"#,
+                    ),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let gitlab = gitlab_for_tests(server.uri());
+        let (after, before) = gitlab.sync_window(Some(last_sync), Utc.with_ymd_and_hms(2024, 5, 4, 8, 0, 0).unwrap());
+        let fetched = gitlab.get_events(after, before).await.unwrap();
+        assert_eq!(
+            fetched.events.iter().map(|event| event.created_at.as_str()).collect::<Vec<_>>(),
+            vec!["2024-05-03T18:00:00.000Z"]
+        );
+    }
+
+    #[tokio::test]
+    async fn contribution_days_are_read_from_the_calendar() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/users/42"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"id": 42, "username": "2tefan"}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/users/2tefan/calendar.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"2023-12-31": 4, "2024-01-02": 3, "2024-01-03": 0, "2024-05-01": 1, "2024-05-03": 2}"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let range = BackfillRange {
+            since: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            until: NaiveDate::from_ymd_opt(2024, 5, 2).unwrap(),
+        };
+        assert_eq!(
+            gitlab_for_tests(server.uri()).fetch_contribution_days(&range).await,
+            Ok(vec![
+                (NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(), 3),
+                (NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(), 1)
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn calendar_of_usernames_is_fetched_directly() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/users/2tefan/calendar.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"2024-05-01": 1, "yesterday": 2}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut gitlab = gitlab_for_tests(server.uri());
+        gitlab.user_id = "2tefan".to_string();
+        let range = BackfillRange {
+            since: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            until: NaiveDate::from_ymd_opt(2024, 5, 2).unwrap(),
+        };
+        let err = gitlab.fetch_contribution_days(&range).await.unwrap_err();
+        assert!(err.contains("»yesterday« isn't a day"), "{}", err);
+    }
+
+    #[test]
+    fn groups_are_comma_separated() {
+        assert_eq!(
+            Gitlab::parse_groups(" 2tefan-projects, ,2tefan-projects/stats ,1234,"),
+            vec!["2tefan-projects", "2tefan-projects/stats", "1234"]
+        );
+        assert_eq!(Gitlab::parse_groups(""), Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    async fn group_events_are_filtered_by_author() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/groups/2tefan-projects%2Fstats/projects"))
+            .and(query_param("include_subgroups", "true"))
+            .and(query_param("order_by", "last_activity_at"))
+            .and(query_param("page", "1"))
+            .respond_with(ResponseTemplate::new(200).insert_header("x-next-page", "2").set_body_string(
+                r#"[{"id": 1, "name_with_namespace": "Stats / Pollux", "path_with_namespace": "2tefan-projects/stats/pollux",
+                    "web_url": "https://gitlab.com/2tefan-projects/stats/pollux", "visibility": "public",
+                    "last_activity_at": "2024-05-01T18:00:00.000Z"},
+                   {"id": 2, "name_with_namespace": "Stats / Secret", "path_with_namespace": "2tefan-projects/stats/secret",
+                    "web_url": "https://gitlab.com/2tefan-projects/stats/secret", "visibility": "private",
+                    "last_activity_at": "2024-05-01T17:00:00.000Z"}]"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/groups/2tefan-projects%2Fstats/projects"))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).insert_header("x-next-page", "").set_body_string(
+                r#"[{"id": 3, "name_with_namespace": "Stats / Old", "path_with_namespace": "2tefan-projects/stats/old",
+                    "web_url": "https://gitlab.com/2tefan-projects/stats/old", "visibility": "public",
+                    "last_activity_at": "2023-01-01T00:00:00.000Z"}]"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/projects/1/events"))
+            .and(query_param("after", "2024-05-01"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("x-page", "1")
+                    .insert_header("x-total-pages", "1")
+                    .set_body_string(
+                        r#"[{"project_id": 1, "author_id": 42, "action_name": "pushed to", "created_at": "2024-05-01T12:00:00.000Z"},
+                           {"project_id": 1, "author_id": 7, "action_name": "pushed to", "created_at": "2024-05-01T13:00:00.000Z"},
+                           {"project_id": 1, "action_name": "opened", "created_at": "2024-05-01T14:00:00.000Z"},
+                           {"project_id": 1, "author_id": 42, "action_name": "opened", "created_at": "2024-05-01T15:00:00.000Z"}]"#,
+                    ),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        // Private and inactive projects aren't asked for their events
+        for project_id in [2, 3] {
+            Mock::given(method("GET"))
+                .and(path(format!("/api/v4/projects/{}/events", project_id)))
+                .respond_with(ResponseTemplate::new(500))
+                .expect(0)
+                .mount(&server)
+                .await;
+        }
+
+        let fetched = gitlab_for_tests(server.uri())
+            .get_group_events(
+                "2tefan-projects/stats",
+                Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(fetched.truncated, None);
+        assert_eq!(
+            fetched.events.iter().map(|event| event.created_at.as_str()).collect::<Vec<_>>(),
+            vec!["2024-05-01T12:00:00.000Z", "2024-05-01T15:00:00.000Z"]
+        );
+    }
+
+    #[tokio::test]
+    async fn group_events_need_a_numeric_user_id() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let mut gitlab = gitlab_for_tests(server.uri());
+        gitlab.user_id = "2tefan".to_string();
+        let fetched = gitlab
+            .get_group_events(
+                "2tefan-projects",
+                Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(fetched.events.is_empty());
+    }
+
+    async fn token_check(user: ResponseTemplate, token_details: ResponseTemplate) -> CredentialCheck {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/user"))
+            .and(header("authorization", "Bearer token"))
+            .respond_with(user)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/personal_access_tokens/self"))
+            .respond_with(token_details)
+            .mount(&server)
+            .await;
+        gitlab_for_tests(server.uri()).validate_credentials().await
+    }
+
+    #[tokio::test]
+    async fn valid_token_is_checked() {
+        let check = token_check(
+            ResponseTemplate::new(200).set_body_string(r#"{"id": 42, "username": "2tefan"}"#),
+            ResponseTemplate::new(200).set_body_string(r#"{"scopes": ["read_api"], "expires_at": "2025-01-01"}"#),
+        )
+        .await;
+        assert_eq!(
+            check.outcome,
+            Ok(TokenInfo {
+                username: "2tefan".to_string(),
+                scopes: Some(vec!["read_api".to_string()]),
+                expires_at: Some("2025-01-01".to_string()),
+            })
+        );
+        let today = chrono::NaiveDate::from_ymd_opt(2024, 5, 20).unwrap();
+        assert_eq!(check.expiry_warning(today, 14), None);
+    }
+
+    #[tokio::test]
+    async fn token_expiring_soon_is_warned_about() {
+        let check = token_check(
+            ResponseTemplate::new(200).set_body_string(r#"{"id": 42, "username": "2tefan"}"#),
+            ResponseTemplate::new(200).set_body_string(r#"{"scopes": ["read_api"], "expires_at": "2024-05-25"}"#),
+        )
+        .await;
+        let today = chrono::NaiveDate::from_ymd_opt(2024, 5, 20).unwrap();
+        assert_eq!(
+            check.expiry_warning(today, 14).as_deref(),
+            Some("The Gitlab token expires in 5 day(s) (2024-05-25), please renew GITLAB_API_TOKEN!")
+        );
+        assert_eq!(check.status(today).expires_in_days, Some(5));
+    }
+
+    #[tokio::test]
+    async fn invalid_token_is_named() {
+        let unauthorized = || ResponseTemplate::new(401).set_body_string(r#"{"message": "401 Unauthorized"}"#);
+        let check = token_check(unauthorized(), unauthorized()).await;
+        assert_eq!(
+            check.outcome,
+            Err("GITLAB_API_TOKEN is invalid or expired (Gitlab answered 401 Unauthorized: 401 Unauthorized)".to_string())
+        );
+
+        // Also once the token expires while running
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(unauthorized())
+            .mount(&server)
+            .await;
+        let err = get_events_of(&server).await.unwrap_err();
+        assert!(err.to_string().contains("GITLAB_API_TOKEN is invalid or expired"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn project_details_are_fetched_from_the_configured_host() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/projects/1"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"id": 1, "name_with_namespace": "2tefan / Pollux", "web_url": "https://git.example.com/2tefan/pollux", "visibility": "public"}"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let project = gitlab_for_tests(server.uri()).get_project_details_by_id(1).await;
+        assert_eq!(project.unwrap().web_url, "https://git.example.com/2tefan/pollux");
+    }
+
+    #[tokio::test]
+    async fn project_details_are_deserialized() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/projects/61345567"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{
+                    "id": 61345567,
+                    "name": "Pollux",
+                    "name_with_namespace": "2tefan Projects / Stats / Pollux",
+                    "path_with_namespace": "2tefan-projects/stats/pollux",
+                    "web_url": "https://gitlab.com/2tefan-projects/stats/pollux",
+                    "visibility": "public",
+                    "last_activity_at": "2024-05-04T18:12:43.000Z",
+                    "star_count": 0
+                }"#,
+            ))
+            .mount(&server)
+            .await;
+
+        let project = gitlab_for_tests(server.uri())
+            .get_project_details_by_id(61345567)
+            .await
+            .map(|project| GitlabProjectAPI { last_activity_at: None, ..project });
+        assert_eq!(
+            project,
+            Ok(GitlabProjectAPI {
+                id: 61345567,
+                name_with_namespace: "2tefan Projects / Stats / Pollux".to_string(),
+                path_with_namespace: "2tefan-projects/stats/pollux".to_string(),
+                web_url: "https://gitlab.com/2tefan-projects/stats/pollux".to_string(),
+                visibility: Some("public".to_string()),
+                last_activity_at: None,
+            })
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "Needs real API tokens, run with --ignored"]
+    async fn gitlab_api_is_still_sane() {
+        dotenv().ok();
+        let gitlab = Gitlab::init_from_env_vars().unwrap();
+
+        let result = gitlab
+            .get_events(
+                Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 5, 5, 0, 0, 0).unwrap(),
+            )
+            .await
+            .unwrap()
+            .events;
+        //assert_eq!(result, OffsetDateTime::now_utc().date().to_string())
+        assert_eq!(result.len(), 31);
+    }
+
+    #[tokio::test]
+    #[ignore = "Needs real API tokens, run with --ignored"]
+    async fn gitlab_api_is_still_sane_without_pagination() {
+        dotenv().ok();
+        Gitlab::get_or_init().unwrap();
+        let gitlab = Gitlab::init_from_env_vars().unwrap();
+
+        let result = gitlab
+            .get_events(
+                Utc.with_ymd_and_hms(2024, 5, 3, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 5, 5, 0, 0, 0).unwrap(),
+            )
+            .await
+            .unwrap()
+            .events;
+        assert_eq!(result.len(), 4);
+    }
+
+    #[tokio::test]
+    #[ignore = "Needs real API tokens, run with --ignored"]
+    async fn gitlab_get_pollux_project() {
+        dotenv().ok();
+        Gitlab::get_or_init().unwrap();
+        let gitlab = Gitlab::init_from_env_vars().unwrap();
+
+        // The last activity changes with every push
+        let result = gitlab
+            .get_project_details_by_id(61345567)
+            .await
+            .map(|project| GitlabProjectAPI { last_activity_at: None, ..project });
         println!("{:?}", result);
         assert_eq!(
             result,
-            GitlabProjectAPI {
+            Ok(GitlabProjectAPI {
                 id: 61345567,
                 name_with_namespace: "2tefan Projects / Stats / Pollux".to_string(),
+                path_with_namespace: "2tefan-projects/stats/pollux".to_string(),
                 web_url: "https://gitlab.com/2tefan-projects/stats/pollux".to_string(),
-                visibility: Some("public".to_string())
-            }
+                visibility: Some("public".to_string()),
+                last_activity_at: None,
+            })
         );
     }
 
     #[tokio::test]
+    #[ignore = "Needs real API tokens and a database, run with --ignored"]
     async fn import_data_from_gitlab_into_database() {
         dotenv().ok();
-        Gitlab::get_or_init();
-        let gitlab = Gitlab::init_from_env_vars();
+        Gitlab::get_or_init().unwrap();
+        let gitlab = Gitlab::init_from_env_vars().unwrap();
 
         let events = gitlab
             .get_events(
-                Utc.with_ymd_and_hms(2024, 05, 03, 0, 0, 0).unwrap(),
-                Utc.with_ymd_and_hms(2024, 05, 05, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 5, 3, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 5, 5, 0, 0, 0).unwrap(),
             )
+            .await
+            .unwrap()
+            .events;
+        gitlab.insert_gitlab_events_into_db(events, EventSource::Fetched).await; // TODO: Fix test
+    }
+
+    #[tokio::test]
+    async fn renamed_and_deleted_projects_are_looked_up() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/projects/1"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"id": 1, "name_with_namespace": "2tefan / Stats", "web_url": "https://git.example.com/2tefan/stats", "visibility": "internal"}"#,
+            ))
+            .mount(&server)
             .await;
-        gitlab.insert_gitlab_events_into_db(events).await; // TODO: Fix test
+        for (project, status) in [(2, 404), (3, 403)] {
+            Mock::given(method("GET"))
+                .and(path(format!("/api/v4/projects/{}", project)))
+                .respond_with(ResponseTemplate::new(status).set_body_string(r#"{"message": "404 Project Not Found"}"#))
+                .mount(&server)
+                .await;
+        }
+
+        let mut gitlab = gitlab_for_tests(server.uri());
+        assert_eq!(
+            gitlab.lookup_project(1).await,
+            Ok(Some(ProjectMetadata {
+                name: "2tefan / Stats".to_string(),
+                url: "https://git.example.com/2tefan/stats".to_string(),
+                visibility: Some("internal".to_string()),
+            }))
+        );
+        assert_eq!(gitlab.lookup_project(2).await, Ok(None));
+        assert_eq!(gitlab.lookup_project(3).await, Ok(None));
     }
 }
@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+
+use async_graphql::{
+    dataloader::{DataLoader, Loader},
+    ComplexObject, Context, EmptyMutation, EmptySubscription, Object, Request, Result, Schema,
+    SimpleObject,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::{prelude::FromRow, MySql, Pool, QueryBuilder};
+
+use crate::{
+    queries::push_ids,
+    stats::{self, MergeStrategy, Weight},
+    visibility::Pseudonymizer,
+};
+
+/// At most this many events are loaded per project for `recentEvents`
+static MAX_RECENT_EVENTS: u32 = 50;
+static DEFAULT_EVENTS_DAYS: i64 = 30;
+static DEFAULT_EVENTS_LIMIT: u32 = 100;
+/// Like `limit` of `/api/v2/git-events`
+static MAX_EVENTS_LIMIT: u32 = 1000;
+/// Enough for `projects { recentEvents { project { name } } }` and a bit, not for endless nesting
+static MAX_QUERY_DEPTH: usize = 8;
+/// Every field costs 1, lists of events as much as their fields times their `limit`
+static MAX_QUERY_COMPLEXITY: usize = 20_000;
+
+pub type PolluxSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub fn build_schema() -> PolluxSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_QUERY_DEPTH)
+        .limit_complexity(MAX_QUERY_COMPLEXITY)
+        .finish()
+}
+
+/// Attaches the pool and fresh data loaders, so every request batches its own lookups.
+/// With a pseudonymizer, project names and urls are anonymized like in the REST responses.
+pub fn prepare(request: Request, pool: Pool<MySql>, pseudonymizer: Option<Pseudonymizer>) -> Request {
+    let request = request
+        .data(DataLoader::new(ProjectLoader { pool: pool.clone() }, tokio::spawn))
+        .data(DataLoader::new(EventCountLoader { pool: pool.clone() }, tokio::spawn))
+        .data(DataLoader::new(RecentEventsLoader { pool: pool.clone() }, tokio::spawn))
+        .data(pool);
+    match pseudonymizer {
+        Some(pseudonymizer) => request.data(pseudonymizer),
+        None => request,
+    }
+}
+
+#[derive(Debug, Clone, SimpleObject, FromRow)]
+#[graphql(complex)]
+pub struct Event {
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    pub action: String,
+    #[graphql(skip)]
+    pub project_id: u64,
+}
+
+#[ComplexObject]
+impl Event {
+    async fn project(&self, ctx: &Context<'_>) -> Result<Option<Project>> {
+        Ok(ctx
+            .data_unchecked::<DataLoader<ProjectLoader>>()
+            .load_one(self.project_id)
+            .await?)
+    }
+}
+
+#[derive(Debug, Clone, SimpleObject, FromRow)]
+#[graphql(complex)]
+pub struct Project {
+    pub id: u64,
+    #[graphql(skip)]
+    pub name: String,
+    #[graphql(skip)]
+    pub url: Option<String>,
+    pub platform: String,
+}
+
+#[ComplexObject]
+impl Project {
+    async fn name(&self, ctx: &Context<'_>) -> String {
+        match ctx.data_opt::<Pseudonymizer>() {
+            Some(pseudonymizer) => pseudonymizer.project_alias(self.id),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Empty for anonymized requests, null if the project isn't available on its platform anymore
+    async fn url(&self, ctx: &Context<'_>) -> Option<String> {
+        match ctx.data_opt::<Pseudonymizer>() {
+            Some(_) => Some(String::new()),
+            None => self.url.clone(),
+        }
+    }
+
+    /// Number of events of this project, ever
+    async fn event_count(&self, ctx: &Context<'_>) -> Result<u64> {
+        Ok(ctx
+            .data_unchecked::<DataLoader<EventCountLoader>>()
+            .load_one(self.id)
+            .await?
+            .unwrap_or(0))
+    }
+
+    /// Newest events first, at most 50
+    #[graphql(complexity = "limit.min(MAX_RECENT_EVENTS) as usize * child_complexity")]
+    async fn recent_events(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 10)] limit: u32,
+    ) -> Result<Vec<Event>> {
+        let events = ctx
+            .data_unchecked::<DataLoader<RecentEventsLoader>>()
+            .load_one(self.id)
+            .await?
+            .unwrap_or_default();
+        Ok(events.into_iter().take(limit.min(MAX_RECENT_EVENTS) as usize).collect())
+    }
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+pub struct DailyStat {
+    pub date: NaiveDate,
+    pub count: u64,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Events ordered by timestamp. `since` defaults to 30 days ago, `until` is inclusive.
+    /// At most `limit` of them, which is capped at 1000.
+    #[graphql(complexity = "limit.clamp(1, MAX_EVENTS_LIMIT) as usize * child_complexity")]
+    async fn events(
+        &self,
+        ctx: &Context<'_>,
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+        platform: Option<String>,
+        action: Option<String>,
+        #[graphql(default_with = "DEFAULT_EVENTS_LIMIT")] limit: u32,
+    ) -> Result<Vec<Event>> {
+        let pool = ctx.data_unchecked::<Pool<MySql>>();
+        let since = since.unwrap_or_else(|| (Utc::now() - Duration::days(DEFAULT_EVENTS_DAYS)).date_naive());
+
+        let mut query = QueryBuilder::<MySql>::new(
+            r#"
+                SELECT
+                    evt.id AS id,
+                    evt.timestamp AS timestamp,
+                    gact.name AS action,
+                    gevt.project_fk AS project_id
+                FROM
+                    Events AS evt,
+                    GitEvents AS gevt,
+                    GitActions AS gact,
+                    GitProjects AS gpro
+                WHERE evt.id = gevt.id
+                AND   gevt.action_fk = gact.id
+                AND   gevt.project_fk = gpro.id
+                AND   evt.timestamp >= "#,
+        );
+        query.push_bind(since);
+        if let Some(until) = until {
+            query.push(" AND evt.timestamp < ").push_bind(until + Duration::days(1));
+        }
+        if let Some(platform) = platform {
+            query.push(" AND gpro.platform = ").push_bind(platform);
+        }
+        if let Some(action) = action {
+            query.push(" AND gact.name = ").push_bind(action);
+        }
+        query
+            .push(" ORDER BY evt.timestamp LIMIT ")
+            .push_bind(limit.clamp(1, MAX_EVENTS_LIMIT));
+
+        Ok(query.build_query_as::<Event>().fetch_all(pool).await?)
+    }
+
+    async fn projects(&self, ctx: &Context<'_>, platform: Option<String>) -> Result<Vec<Project>> {
+        let pool = ctx.data_unchecked::<Pool<MySql>>();
+
+        let mut query = QueryBuilder::<MySql>::new("SELECT id, name, IF(unavailable_since IS NULL, url, NULL) AS url, platform FROM GitProjects");
+        if let Some(platform) = platform {
+            query.push(" WHERE platform = ").push_bind(platform);
+        }
+        query.push(" ORDER BY id");
+
+        Ok(query.build_query_as::<Project>().fetch_all(pool).await?)
+    }
+
+    /// Events per day, merged like `/api/v1/stats/daily`
+    async fn stats(&self, ctx: &Context<'_>, since: Option<NaiveDate>) -> Result<Vec<DailyStat>> {
+        let pool = ctx.data_unchecked::<Pool<MySql>>();
+        let since = since.unwrap_or_else(|| (Utc::now() - Duration::days(DEFAULT_EVENTS_DAYS)).date_naive());
+
+        Ok(stats::get_daily_counts(pool, since, MergeStrategy::from_env(), false, Weight::Events, &[])
+            .await
+            .into_iter()
+            .map(|day| DailyStat {
+                date: day.date,
+                count: day.count,
+            })
+            .collect())
+    }
+}
+
+pub struct ProjectLoader {
+    pool: Pool<MySql>,
+}
+
+impl Loader<u64> for ProjectLoader {
+    type Value = Project;
+    type Error = String;
+
+    async fn load(&self, ids: &[u64]) -> Result<HashMap<u64, Project>, String> {
+        let mut query = QueryBuilder::<MySql>::new("SELECT id, name, IF(unavailable_since IS NULL, url, NULL) AS url, platform FROM GitProjects WHERE id IN ");
+        push_ids(&mut query, ids);
+
+        let projects: Vec<Project> = query
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| err.to_string())?;
+        Ok(projects.into_iter().map(|project| (project.id, project)).collect())
+    }
+}
+
+pub struct EventCountLoader {
+    pool: Pool<MySql>,
+}
+
+impl Loader<u64> for EventCountLoader {
+    type Value = u64;
+    type Error = String;
+
+    async fn load(&self, ids: &[u64]) -> Result<HashMap<u64, u64>, String> {
+        let mut query = QueryBuilder::<MySql>::new("SELECT project_fk, COUNT(1) FROM GitEvents WHERE project_fk IN ");
+        push_ids(&mut query, ids);
+        query.push(" GROUP BY project_fk");
+
+        let counts: Vec<(u64, i64)> = query
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| err.to_string())?;
+        Ok(counts
+            .into_iter()
+            .map(|(id, count)| (id, count.max(0) as u64))
+            .collect())
+    }
+}
+
+pub struct RecentEventsLoader {
+    pool: Pool<MySql>,
+}
+
+impl Loader<u64> for RecentEventsLoader {
+    type Value = Vec<Event>;
+    type Error = String;
+
+    async fn load(&self, ids: &[u64]) -> Result<HashMap<u64, Vec<Event>>, String> {
+        let mut query = QueryBuilder::<MySql>::new(
+            r#"
+                SELECT id, timestamp, action, project_id FROM (
+                    SELECT
+                        evt.id AS id,
+                        evt.timestamp AS timestamp,
+                        gact.name AS action,
+                        gevt.project_fk AS project_id,
+                        ROW_NUMBER() OVER (PARTITION BY gevt.project_fk ORDER BY evt.timestamp DESC) AS position
+                    FROM
+                        Events AS evt,
+                        GitEvents AS gevt,
+                        GitActions AS gact
+                    WHERE evt.id = gevt.id
+                    AND   gevt.action_fk = gact.id
+                    AND   gevt.project_fk IN "#,
+        );
+        push_ids(&mut query, ids);
+        query
+            .push(") AS ranked WHERE position <= ")
+            .push_bind(MAX_RECENT_EVENTS)
+            .push(" ORDER BY project_id, timestamp DESC");
+
+        let events: Vec<Event> = query
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        let mut per_project: HashMap<u64, Vec<Event>> = HashMap::new();
+        for event in events {
+            per_project.entry(event.project_id).or_default().push(event);
+        }
+        Ok(per_project)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_matches_snapshot() {
+        let sdl = build_schema().sdl();
+        if std::env::var("UPDATE_SNAPSHOTS").is_ok() {
+            std::fs::write(concat!(env!("CARGO_MANIFEST_DIR"), "/src/schema.graphql"), &sdl).unwrap();
+        }
+
+        assert_eq!(
+            sdl,
+            include_str!("schema.graphql"),
+            "GraphQL schema changed, rerun with UPDATE_SNAPSHOTS=1 if that's intended"
+        );
+    }
+
+    #[tokio::test]
+    async fn deep_and_expensive_queries_are_rejected() {
+        let schema = build_schema();
+        let nested = format!(
+            "{{ projects {}name{} }}",
+            "{ recentEvents(limit: 1) { project ".repeat(4),
+            " } }".repeat(4)
+        );
+        let too_deep = schema.execute(Request::new(nested)).await;
+        assert!(too_deep.errors[0].message.contains("Query is nested too deep"), "{:?}", too_deep.errors);
+
+        let expensive = schema
+            .execute(Request::new("{ events(limit: 5000) { id project { recentEvents(limit: 50) { id } } } }"))
+            .await;
+        assert!(expensive.errors[0].message.contains("Query is too complex"), "{:?}", expensive.errors);
+    }
+
+    async fn seed() -> (testcontainers::ContainerAsync<testcontainers::GenericImage>, Pool<MySql>) {
+        let (container, pool) = crate::database::tests::initialize().await;
+        for statement in [
+            "INSERT INTO GitPlatforms (name, firstSync) VALUES ('Github', NOW()), ('Gitlab', NOW())",
+            "INSERT INTO GitActions (id, name) VALUES (1, 'commit'), (2, 'merge')",
+            "INSERT INTO GitProjects (id, name, url, platform, platform_project_id) VALUES \
+                (1, '2tefan/pollux', 'https://github.com/2tefan/pollux', 'Github', 1), \
+                (2, '2tefan/castor', 'https://gitlab.com/2tefan/castor', 'Gitlab', 2)",
+            "INSERT INTO Events (id, timestamp) VALUES \
+                (1, '2024-05-01 10:00:00'), (2, '2024-05-02 10:00:00'), (3, '2024-05-03 10:00:00')",
+            "INSERT INTO GitEvents (id, action_fk, project_fk) VALUES (1, 1, 1), (2, 2, 1), (3, 1, 2)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        (container, pool)
+    }
+
+    #[tokio::test]
+    async fn projects_with_events_are_queried_in_one_round_trip() {
+        let (_container, pool) = seed().await;
+
+        let query = r#"{
+            projects(platform: "Github") { name eventCount recentEvents(limit: 1) { action timestamp } }
+            events(since: "2024-05-01", until: "2024-05-02") { id action project { name platform } }
+            first: events(since: "2024-05-01", limit: 1) { id }
+        }"#;
+        let response = build_schema()
+            .execute(prepare(Request::new(query), pool, None))
+            .await
+            .into_result()
+            .unwrap();
+
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            serde_json::json!({
+                "projects": [{
+                    "name": "2tefan/pollux",
+                    "eventCount": 2,
+                    "recentEvents": [{"action": "merge", "timestamp": "2024-05-02T10:00:00Z"}]
+                }],
+                "events": [
+                    {"id": 1, "action": "commit", "project": {"name": "2tefan/pollux", "platform": "Github"}},
+                    {"id": 2, "action": "merge", "project": {"name": "2tefan/pollux", "platform": "Github"}}
+                ],
+                "first": [{"id": 1}]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn anonymized_queries_leak_no_project_names() {
+        let (_container, pool) = seed().await;
+        let pseudonymizer = Pseudonymizer::new("secret");
+
+        let query = r#"{
+            projects { id name url eventCount }
+            events(since: "2024-05-01") { id project { name url } }
+        }"#;
+        let response = build_schema()
+            .execute(prepare(Request::new(query), pool, Some(pseudonymizer.clone())))
+            .await
+            .into_result()
+            .unwrap();
+
+        let data = response.data.into_json().unwrap();
+        let payload = data.to_string();
+        assert!(!payload.contains("pollux") && !payload.contains("castor"), "{}", payload);
+        assert_eq!(data["projects"][0]["name"], pseudonymizer.project_alias(1));
+        assert_eq!(data["projects"][0]["eventCount"], 2);
+        assert_eq!(data["events"][0]["project"]["name"], pseudonymizer.project_alias(1));
+    }
+}
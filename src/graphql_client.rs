@@ -0,0 +1,133 @@
+use log::error;
+use reqwest::{header::CONTENT_TYPE, RequestBuilder};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{credentials, git_platform::PlatformError, retry::RetryPolicy};
+
+/// Answer of a GraphQL API, `data` is missing or partial if there are `errors`
+#[derive(Debug, Deserialize)]
+pub struct GraphqlResponse<T> {
+    pub data: Option<T>,
+    #[serde(default)]
+    pub errors: Vec<GraphqlError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GraphqlError {
+    pub message: String,
+}
+
+/// Body of a POST request running `query`
+pub fn request_body(query: &str, variables: serde_json::Value) -> String {
+    serde_json::json!({
+        "query": query,
+        "variables": variables,
+    })
+    .to_string()
+}
+
+/// `data` of a response, the first error instead if the query failed
+pub fn decode<T: DeserializeOwned>(platform: &str, payload: &str) -> Result<T, String> {
+    let response: GraphqlResponse<T> = serde_json::from_str(payload)
+        .map_err(|err| format!("Unable to decode json response from {}: {}", platform, err))?;
+    if let Some(error) = response.errors.first() {
+        return Err(format!("{} couldn't answer the query: {}", platform, error.message));
+    }
+    response.data.ok_or_else(|| format!("{} answered the query without any data", platform))
+}
+
+/// Runs `query` at `url`, requests are authorized by `authorize` and retried like any other.
+/// A response which isn't a success is a `PlatformError::Status`, with the platform's explanation.
+pub async fn post<T: DeserializeOwned>(
+    client: &reqwest::Client,
+    retry_policy: &RetryPolicy,
+    platform: &str,
+    url: &str,
+    authorize: impl Fn(RequestBuilder) -> RequestBuilder,
+    query: &str,
+    variables: serde_json::Value,
+) -> Result<T, PlatformError> {
+    let body = request_body(query, variables);
+    let response = retry_policy
+        .send(platform, || {
+            authorize(client.post(url))
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone())
+        })
+        .await
+        .map_err(|err| PlatformError::Request(format!("Unable to get response from {}: {}", platform, err)))?;
+
+    let status = response.status();
+    let payload = response
+        .text()
+        .await
+        .map_err(|err| PlatformError::Request(format!("Unable to decode response from {}: {}", platform, err)))?;
+    if !status.is_success() {
+        error!("We got this data: {}", payload);
+        return Err(PlatformError::Status {
+            status: status.as_u16(),
+            message: credentials::rejection(platform, status, &payload),
+        });
+    }
+    decode(platform, &payload).map_err(PlatformError::InvalidResponse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{body_partial_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Me {
+        name: String,
+    }
+
+    #[test]
+    fn first_error_is_reported() {
+        let payload = r#"{"data": null, "errors": [{"message": "Access denied"}, {"message": "Unknown field"}]}"#;
+        assert_eq!(
+            decode::<Me>("Sourcehut", payload),
+            Err("Sourcehut couldn't answer the query: Access denied".to_string())
+        );
+        assert_eq!(
+            decode::<Me>("Sourcehut", r#"{"data": null}"#),
+            Err("Sourcehut answered the query without any data".to_string())
+        );
+        assert_eq!(decode::<Me>("Sourcehut", r#"{"data": {"name": "2tefan"}}"#), Ok(Me { name: "2tefan".to_string() }));
+    }
+
+    #[tokio::test]
+    async fn query_is_posted_with_its_variables() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(header("authorization", "Bearer token"))
+            .and(header("content-type", "application/json"))
+            .and(body_partial_json(serde_json::json!({"variables": {"cursor": "abc"}})))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"data": {"name": "2tefan"}}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let retry_policy = RetryPolicy {
+            attempts: 1,
+            base_delay: std::time::Duration::from_millis(1),
+            max_delay: std::time::Duration::from_millis(1),
+        };
+        let me: Me = post(
+            &reqwest::Client::new(),
+            &retry_policy,
+            "Sourcehut",
+            &format!("{}/query", server.uri()),
+            |request| request.bearer_auth("token"),
+            "query($cursor: Cursor) { me { name } }",
+            serde_json::json!({"cursor": "abc"}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(me.name, "2tefan");
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use log::warn;
+use reqwest::header::HeaderValue;
+use sqlx::{MySql, Pool};
+
+use crate::{config, database};
+
+static FALLBACK_HTTP_CACHE_TTL_DAYS: i64 = 30;
+/// Length of `HttpCache.url`, longer urls are only cached in memory
+static MAX_URL_LENGTH: usize = 512;
+static MAX_ETAG_LENGTH: usize = 255;
+
+/// ETags older than this are pruned, so urls which aren't fetched anymore don't pile up.
+/// `0` disables persisting ETags at all.
+pub fn get_ttl() -> Duration {
+    match config::var("POLLUX_HTTP_CACHE_TTL_DAYS")
+        .unwrap_or(FALLBACK_HTTP_CACHE_TTL_DAYS.to_string())
+        .parse::<u32>()
+    {
+        Ok(days) => Duration::days(days.into()),
+        Err(err) => {
+            warn!(
+                "Unable to parse POLLUX_HTTP_CACHE_TTL_DAYS, using »{}« as a fallback: {}",
+                FALLBACK_HTTP_CACHE_TTL_DAYS, err
+            );
+            Duration::days(FALLBACK_HTTP_CACHE_TTL_DAYS)
+        }
+    }
+}
+
+pub async fn load_etags(pool: &Pool<MySql>, platform: &str) -> HashMap<String, String> {
+    sqlx::query_as::<_, (String, String)>("SELECT url, etag FROM HttpCache WHERE platform = ?")
+        .bind(platform)
+        .fetch_all(pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .collect()
+}
+
+pub async fn upsert_etag(pool: &Pool<MySql>, platform: &str, url: &str, etag: &str, fetched_at: DateTime<Utc>) {
+    sqlx::query(
+        "INSERT INTO HttpCache (platform, url, etag, fetched_at) VALUES ( ?, ?, ?, ? ) \
+            ON DUPLICATE KEY UPDATE etag = VALUES(etag), fetched_at = VALUES(fetched_at)",
+    )
+    .bind(platform)
+    .bind(url)
+    .bind(etag)
+    .bind(fetched_at.format("%Y-%m-%d %H:%M:%S").to_string())
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// Deletes all ETags fetched before `older_than`
+pub async fn prune_etags(pool: &Pool<MySql>, older_than: DateTime<Utc>) -> u64 {
+    sqlx::query("DELETE FROM HttpCache WHERE fetched_at < ?")
+        .bind(older_than.format("%Y-%m-%d %H:%M:%S").to_string())
+        .execute(pool)
+        .await
+        .unwrap()
+        .rows_affected()
+}
+
+#[derive(Debug, Clone)]
+enum Store {
+    Memory,
+    /// `pool` is resolved on first use, if not given up front
+    Database { pool: Option<Pool<MySql>>, restored: bool },
+}
+
+/// ETags per fetched url. Keying by the full url means pages of another size or
+/// another user never match, their ETags are simply not used.
+#[derive(Debug, Clone)]
+pub struct ETagCache {
+    platform: &'static str,
+    etags: HashMap<String, HeaderValue>,
+    store: Store,
+}
+
+impl ETagCache {
+    /// Forgets everything on restart
+    pub fn in_memory(platform: &'static str) -> ETagCache {
+        ETagCache {
+            platform,
+            etags: HashMap::new(),
+            store: Store::Memory,
+        }
+    }
+
+    /// Persisted in `HttpCache` of the global database, unless `POLLUX_HTTP_CACHE_TTL_DAYS` is 0
+    pub fn from_env(platform: &'static str) -> ETagCache {
+        if get_ttl().is_zero() {
+            return ETagCache::in_memory(platform);
+        }
+
+        ETagCache {
+            platform,
+            etags: HashMap::new(),
+            store: Store::Database {
+                pool: None,
+                restored: false,
+            },
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_pool(platform: &'static str, pool: Pool<MySql>) -> ETagCache {
+        ETagCache {
+            platform,
+            etags: HashMap::new(),
+            store: Store::Database {
+                pool: Some(pool),
+                restored: false,
+            },
+        }
+    }
+
+    /// Call before every sync: prunes expired ETags and restores the remaining ones after a restart
+    pub async fn prepare(&mut self) {
+        let Store::Database { pool, restored } = &mut self.store else {
+            return;
+        };
+        let pool = match pool {
+            Some(pool) => pool,
+            None => pool.insert(database::Database::get_or_init().await.get_pool().await),
+        };
+
+        let pruned = prune_etags(pool, Utc::now() - get_ttl()).await;
+        if pruned > 0 {
+            debug!("Pruned {} expired ETags", pruned);
+        }
+
+        if !*restored {
+            for (url, etag) in load_etags(pool, self.platform).await {
+                match HeaderValue::from_str(&etag) {
+                    Ok(etag) => {
+                        self.etags.entry(url).or_insert(etag);
+                    }
+                    Err(err) => warn!("Ignoring stored ETag »{}« of {}: {}", etag, url, err),
+                }
+            }
+            debug!("Restored {} ETags of {}", self.etags.len(), self.platform);
+            *restored = true;
+        }
+    }
+
+    pub fn get(&self, url: &str) -> Option<&HeaderValue> {
+        self.etags.get(url)
+    }
+
+    pub async fn insert(&mut self, url: &str, etag: HeaderValue) {
+        if let Store::Database { pool: Some(pool), .. } = &self.store {
+            match etag.to_str() {
+                Ok(value) if url.len() <= MAX_URL_LENGTH && value.len() <= MAX_ETAG_LENGTH => {
+                    upsert_etag(pool, self.platform, url, value, Utc::now()).await;
+                }
+                _ => debug!("Keeping the ETag of {} in memory only", url),
+            }
+        }
+        self.etags.insert(url.to_string(), etag);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::initialize;
+
+    #[tokio::test]
+    async fn etags_are_restored_after_a_restart() {
+        let (_container, pool) = initialize().await;
+
+        let mut cache = ETagCache::with_pool("Github", pool.clone());
+        cache.prepare().await;
+        cache.insert("https://api.github.com/a", HeaderValue::from_static("\"1\"")).await;
+        cache.insert("https://api.github.com/a", HeaderValue::from_static("\"2\"")).await;
+
+        let mut restarted = ETagCache::with_pool("Github", pool.clone());
+        assert_eq!(restarted.get("https://api.github.com/a"), None);
+        restarted.prepare().await;
+        assert_eq!(restarted.get("https://api.github.com/a"), Some(&HeaderValue::from_static("\"2\"")));
+
+        // Other platforms have their own
+        let mut other = ETagCache::with_pool("Gitlab", pool);
+        other.prepare().await;
+        assert_eq!(other.get("https://api.github.com/a"), None);
+    }
+
+    #[tokio::test]
+    async fn expired_etags_are_pruned() {
+        let (_container, pool) = initialize().await;
+        let now = Utc::now();
+
+        upsert_etag(&pool, "Github", "https://api.github.com/old", "\"old\"", now - Duration::days(60)).await;
+        upsert_etag(&pool, "Github", "https://api.github.com/new", "\"new\"", now).await;
+
+        assert_eq!(prune_etags(&pool, now - Duration::days(30)).await, 1);
+        let etags = load_etags(&pool, "Github").await;
+        assert_eq!(etags.keys().collect::<Vec<_>>(), vec!["https://api.github.com/new"]);
+    }
+
+    #[tokio::test]
+    async fn in_memory_cache_needs_no_database() {
+        let mut cache = ETagCache::in_memory("Github");
+        cache.prepare().await;
+        cache.insert("https://api.github.com/a", HeaderValue::from_static("\"1\"")).await;
+        assert_eq!(cache.get("https://api.github.com/a"), Some(&HeaderValue::from_static("\"1\"")));
+    }
+}
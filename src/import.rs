@@ -0,0 +1,539 @@
+use std::{collections::{HashMap, HashSet}, fmt, fs::File, io::BufReader, io::Read, path::PathBuf};
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{
+    de::{DeserializeSeed, Error, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use sqlx::{MySql, Pool, Transaction};
+use tokio::sync::mpsc;
+
+use crate::{
+    export::{ExportAction, ExportAggregate, ExportEvent, ExportPlatform, ExportProject, EXPORT_FORMAT, EXPORT_FORMAT_VERSION},
+    git_platform::{self, EventDetails, GitProject},
+    records, retention, stats, KNOWN_PLATFORMS,
+};
+
+/// Parsed elements waiting to be written, bounds the memory used while importing
+static IMPORT_QUEUE_SIZE: usize = 1024;
+
+#[derive(Debug)]
+pub enum ImportItem {
+    Platform(ExportPlatform),
+    Action(ExportAction),
+    Project(ExportProject),
+    Event(ExportEvent),
+    Aggregate(ExportAggregate),
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, JsonSchema)]
+pub struct ImportCounts {
+    pub created: u64,
+    pub skipped: u64,
+}
+
+impl ImportCounts {
+    fn count(&mut self, created: bool) {
+        if created {
+            self.created += 1;
+        } else {
+            self.skipped += 1;
+        }
+    }
+}
+
+/// Rows created vs. already existing (skipped) per table
+#[derive(Debug, Default, PartialEq, Serialize, JsonSchema)]
+pub struct ImportSummary {
+    pub platforms: ImportCounts,
+    pub actions: ImportCounts,
+    pub projects: ImportCounts,
+    pub events: ImportCounts,
+    /// Days which already have an aggregate are skipped, not added up
+    pub daily_aggregates: ImportCounts,
+    /// Events older than the retention, left out since they were pruned (and rolled up) already
+    pub expired_events: u64,
+}
+
+/// Walks through an export, handing every element to `sink` as soon as it is parsed
+struct Dump<'a, F> {
+    sink: &'a mut F,
+}
+
+impl<'de, F: FnMut(ImportItem) -> Result<(), String>> DeserializeSeed<'de> for Dump<'_, F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, F: FnMut(ImportItem) -> Result<(), String>> Visitor<'de> for Dump<'_, F> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a pollux export")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut version = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "format" => {
+                    let format: String = map.next_value()?;
+                    if format != EXPORT_FORMAT {
+                        return Err(A::Error::custom(format!("»{}« is not a pollux export", format)));
+                    }
+                }
+                "version" => {
+                    let found: u32 = map.next_value()?;
+                    // Older versions lack columns, which are imported as NULL
+                    if !(1..=EXPORT_FORMAT_VERSION).contains(&found) {
+                        return Err(A::Error::custom(format!(
+                            "Unsupported export version {}, this version of pollux imports versions 1 to {}",
+                            found, EXPORT_FORMAT_VERSION
+                        )));
+                    }
+                    version = Some(found);
+                }
+                "platforms" | "actions" | "projects" | "events" | "daily_aggregates" => {
+                    if version.is_none() {
+                        return Err(A::Error::custom("The export has to state its version before any data"));
+                    }
+                    map.next_value_seed(Section {
+                        name: key,
+                        sink: &mut *self.sink,
+                    })?;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        match version {
+            Some(_) => Ok(()),
+            None => Err(A::Error::custom("The export doesn't state its version")),
+        }
+    }
+}
+
+struct Section<'a, F> {
+    name: String,
+    sink: &'a mut F,
+}
+
+impl<'de, F: FnMut(ImportItem) -> Result<(), String>> DeserializeSeed<'de> for Section<'_, F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F: FnMut(ImportItem) -> Result<(), String>> Visitor<'de> for Section<'_, F> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a list of {}", self.name)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<(), A::Error> {
+        match self.name.as_str() {
+            "platforms" => drain(seq, self.sink, ImportItem::Platform),
+            "actions" => drain(seq, self.sink, ImportItem::Action),
+            "projects" => drain(seq, self.sink, ImportItem::Project),
+            "daily_aggregates" => drain(seq, self.sink, ImportItem::Aggregate),
+            _ => drain(seq, self.sink, ImportItem::Event),
+        }
+    }
+}
+
+fn drain<'de, A: SeqAccess<'de>, T: Deserialize<'de>>(
+    mut seq: A,
+    sink: &mut impl FnMut(ImportItem) -> Result<(), String>,
+    wrap: fn(T) -> ImportItem,
+) -> Result<(), A::Error> {
+    while let Some(element) = seq.next_element::<T>()? {
+        sink(wrap(element)).map_err(A::Error::custom)?;
+    }
+    Ok(())
+}
+
+/// Parses an export without holding more than one element in memory
+pub fn parse_dump<R: Read>(reader: R, mut sink: impl FnMut(ImportItem) -> Result<(), String>) -> Result<(), String> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    Dump { sink: &mut sink }
+        .deserialize(&mut deserializer)
+        .map_err(|err| err.to_string())?;
+    deserializer.end().map_err(|err| err.to_string())
+}
+
+struct Importer {
+    tx: Transaction<'static, MySql>,
+    summary: ImportSummary,
+    /// See `retention::cutoff`
+    cutoff: Option<DateTime<Utc>>,
+    /// Named in the export's `platforms`, ingested platforms aren't known otherwise
+    platforms: HashSet<String>,
+    projects: HashMap<(String, u32, bool), u64>,
+    actions: HashMap<String, u64>,
+}
+
+impl Importer {
+    async fn import(&mut self, item: ImportItem) -> Result<(), String> {
+        match item {
+            ImportItem::Platform(platform) => self.platform(platform).await,
+            ImportItem::Action(action) => {
+                self.action(&action.name).await;
+            }
+            ImportItem::Project(project) => {
+                self.project(&project).await?;
+            }
+            ImportItem::Event(event) => self.event(event).await?,
+            ImportItem::Aggregate(aggregate) => self.aggregate(aggregate).await,
+        }
+        Ok(())
+    }
+
+    async fn platform(&mut self, platform: ExportPlatform) {
+        let existing: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM GitPlatforms WHERE name = ?")
+            .bind(&platform.name)
+            .fetch_one(&mut *self.tx)
+            .await
+            .unwrap();
+        self.platforms.insert(platform.name.clone());
+
+        if existing == 0 {
+            sqlx::query("INSERT INTO GitPlatforms (name, firstSync, lastSync) VALUES ( ?, ?, ? )")
+                .bind(&platform.name)
+                .bind(platform.first_sync.format("%Y-%m-%d %H:%M:%S").to_string())
+                .bind(platform.last_sync.map(|last_sync| last_sync.format("%Y-%m-%d %H:%M:%S").to_string()))
+                .execute(&mut *self.tx)
+                .await
+                .unwrap();
+        }
+        self.summary.platforms.count(existing == 0);
+    }
+
+    async fn action(&mut self, name: &str) -> u64 {
+        if let Some(id) = self.actions.get(name) {
+            return *id;
+        }
+
+        let existing = git_platform::git_action_id(&mut self.tx, name).await;
+        self.summary.actions.count(existing.is_none());
+        let id = match existing {
+            Some(id) => id,
+            None => git_platform::add_git_action(&mut self.tx, name).await,
+        };
+        self.actions.insert(name.to_string(), id);
+        id
+    }
+
+    async fn find_project(&mut self, platform: &str, platform_project_id: u64, pseudonymous: bool) -> Result<Option<GitProject>, String> {
+        if !KNOWN_PLATFORMS.contains(&platform) && !self.platforms.contains(platform) {
+            return Err(format!("Unknown platform »{}«, it's missing in the export's platforms", platform));
+        }
+        Ok(git_platform::fetch_git_project(&mut self.tx, platform, platform_project_id, pseudonymous).await)
+    }
+
+    async fn project(&mut self, project: &ExportProject) -> Result<u64, String> {
+        let key = (project.platform.clone(), project.platform_project_id, project.pseudonymous);
+        if let Some(id) = self.projects.get(&key) {
+            return Ok(*id);
+        }
+
+        let existing = self
+            .find_project(&project.platform, project.platform_project_id.into(), project.pseudonymous)
+            .await?;
+        self.summary.projects.count(existing.is_none());
+        let id = match existing {
+            Some(existing) => existing.id,
+            None => {
+                git_platform::set_platform_named(&mut self.tx, &project.platform).await;
+                sqlx::query(
+                    "INSERT INTO GitProjects (platform, platform_project_id, name, url, pseudonymous, visibility, unavailable_since, external_id) \
+                        VALUES ( ?, ?, ?, ?, ?, ?, ?, ? )",
+                )
+                    .bind(&project.platform)
+                    .bind(project.platform_project_id)
+                    .bind(&project.name)
+                    .bind(&project.url)
+                    .bind(project.pseudonymous)
+                    .bind(&project.visibility)
+                    .bind(project.unavailable_since.map(|since| since.format("%Y-%m-%d %H:%M:%S").to_string()))
+                    .bind(&project.external_id)
+                    .execute(&mut *self.tx)
+                    .await
+                    .unwrap()
+                    .last_insert_id()
+            }
+        };
+        self.projects.insert(key, id);
+        Ok(id)
+    }
+
+    async fn event(&mut self, event: ExportEvent) -> Result<(), String> {
+        if self.cutoff.is_some_and(|cutoff| event.timestamp < cutoff) {
+            self.summary.expired_events += 1;
+            return Ok(());
+        }
+        let key = (event.platform.clone(), event.platform_project_id, event.pseudonymous);
+        let project_id = match self.projects.get(&key) {
+            Some(id) => *id,
+            None => {
+                let project = self
+                    .find_project(&event.platform, event.platform_project_id.into(), event.pseudonymous)
+                    .await?
+                    .ok_or(format!(
+                        "Event at {} references the unknown project {} on {}",
+                        event.timestamp, event.platform_project_id, event.platform
+                    ))?;
+                self.projects.insert(key, project.id);
+                project.id
+            }
+        };
+        let action_id = self.action(&event.action).await;
+
+        let commit_count = event.commit_count.map(u64::from);
+        let inserted = git_platform::add_unique_event(
+            &mut self.tx,
+            event.timestamp,
+            action_id,
+            project_id,
+            commit_count,
+            event.account.as_deref(),
+            EventDetails {
+                git_ref: event.git_ref.as_deref(),
+                target_type: event.target_type.as_deref(),
+            },
+        )
+        .await;
+        self.summary.events.count(inserted.is_some());
+        Ok(())
+    }
+
+    async fn aggregate(&mut self, aggregate: ExportAggregate) {
+        git_platform::set_platform_named(&mut self.tx, &aggregate.platform).await;
+        let action_id = self.action(&aggregate.action).await;
+
+        let inserted = sqlx::query(
+            "INSERT INTO DailyAggregates (platform, day, action_fk, event_count, commit_count) VALUES ( ?, ?, ?, ?, ? ) \
+                ON DUPLICATE KEY UPDATE platform = platform",
+        )
+        .bind(&aggregate.platform)
+        .bind(aggregate.day)
+        .bind(action_id)
+        .bind(aggregate.event_count)
+        .bind(aggregate.commit_count)
+        .execute(&mut *self.tx)
+        .await
+        .unwrap()
+        .rows_affected();
+        self.summary.daily_aggregates.count(inserted > 0);
+    }
+}
+
+/// Imports an export from `path` in one transaction, so a broken or mismatching file leaves the database untouched.
+/// Rows which already exist are skipped, importing the same file twice doesn't create duplicates.
+pub async fn import_file(pool: &Pool<MySql>, path: PathBuf) -> Result<ImportSummary, String> {
+    let (sender, mut receiver) = mpsc::channel(IMPORT_QUEUE_SIZE);
+    let parser = tokio::task::spawn_blocking(move || {
+        let file = File::open(&path).map_err(|err| format!("Couldn't open {}: {}", path.display(), err))?;
+        parse_dump(BufReader::new(file), |item| {
+            sender.blocking_send(item).map_err(|_| "Import was aborted".to_string())
+        })
+    });
+
+    let mut importer = Importer {
+        tx: pool.begin().await.expect("Couldn't start transaction!"),
+        summary: ImportSummary::default(),
+        cutoff: retention::cutoff(Utc::now()),
+        platforms: HashSet::new(),
+        projects: HashMap::new(),
+        actions: HashMap::new(),
+    };
+    while let Some(item) = receiver.recv().await {
+        importer.import(item).await?;
+    }
+    parser.await.expect("Import parser panicked!")?;
+
+    importer.tx.commit().await.expect("Couldn't apply transaction ._.");
+    let summary = importer.summary;
+    if summary.events.created > 0 {
+        stats::invalidate_today_cache();
+        records::rebuild_stat_records(pool).await;
+    }
+    info!("Imported export: {:?}", summary);
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use rocket::futures::StreamExt;
+
+    use super::*;
+    use crate::export;
+
+    fn parse(input: &str) -> Result<Vec<String>, String> {
+        let mut items = Vec::new();
+        parse_dump(input.as_bytes(), |item| {
+            items.push(match item {
+                ImportItem::Platform(platform) => platform.name,
+                ImportItem::Action(action) => action.name,
+                ImportItem::Project(project) => project.name,
+                ImportItem::Event(event) => event.action,
+                ImportItem::Aggregate(aggregate) => aggregate.action,
+            });
+            Ok(())
+        })?;
+        Ok(items)
+    }
+
+    #[test]
+    fn elements_are_handed_over_in_order() {
+        let items = parse(
+            r#"{"exported_at": "2024-05-01T10:00:00Z", "format": "pollux-export", "sections": {}, "version": 1,
+            "platforms": [{"name": "Github", "first_sync": "2024-01-01T00:00:00Z", "last_sync": null}],
+            "actions": [{"name": "commit"}],
+            "projects": [{"platform": "Github", "platform_project_id": 1, "pseudonymous": false, "name": "2tefan/pollux", "url": ""}],
+            "events": [{"platform": "Github", "platform_project_id": 1, "pseudonymous": false, "timestamp": "2024-05-01T10:00:00Z", "action": "commit"}]}"#,
+        );
+
+        assert_eq!(items, Ok(vec!["Github".to_string(), "commit".to_string(), "2tefan/pollux".to_string(), "commit".to_string()]));
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let err = parse(r#"{"format": "pollux-export", "version": 3, "events": []}"#).unwrap_err();
+        assert!(err.starts_with("Unsupported export version 3"), "{}", err);
+        assert!(parse(r#"{"format": "pollux-export", "version": 0, "events": []}"#).is_err());
+
+        let err = parse(r#"{"format": "pollux-export", "events": [], "version": 1}"#).unwrap_err();
+        assert!(err.starts_with("The export has to state its version"), "{}", err);
+
+        assert!(parse(r#"{"format": "pollux-export"}"#).is_err());
+        assert!(parse(r#"{"format": "mysqldump", "version": 1}"#).is_err());
+    }
+
+    #[test]
+    fn version_1_lacks_the_later_columns() {
+        let mut projects = Vec::new();
+        parse_dump(
+            r#"{"format": "pollux-export", "version": 1,
+            "projects": [{"platform": "Github", "platform_project_id": 1, "pseudonymous": false, "name": "2tefan/pollux", "url": ""}]}"#
+                .as_bytes(),
+            |item| {
+                if let ImportItem::Project(project) = item {
+                    projects.push(project);
+                }
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0].visibility, None);
+        assert_eq!(projects[0].unavailable_since, None);
+    }
+
+    #[test]
+    fn sink_errors_stop_parsing() {
+        let mut calls = 0;
+        let result = parse_dump(
+            r#"{"version": 1, "actions": [{"name": "commit"}, {"name": "comments"}]}"#.as_bytes(),
+            |_| {
+                calls += 1;
+                Err("Import was aborted".to_string())
+            },
+        );
+
+        assert!(result.unwrap_err().starts_with("Import was aborted"));
+        assert_eq!(calls, 1);
+    }
+
+    async fn export_to_file(pool: &Pool<MySql>, path: &PathBuf) -> serde_json::Value {
+        let exported_at = DateTime::from_timestamp(1_714_557_600, 0).unwrap();
+        let document = export::export(pool.clone(), exported_at).0.collect::<Vec<String>>().await.concat();
+        std::fs::write(path, &document).unwrap();
+        serde_json::from_str(&document).unwrap()
+    }
+
+    #[tokio::test]
+    async fn export_survives_a_round_trip() {
+        let (_container, pool) = crate::database::tests::initialize().await;
+        for statement in [
+            "INSERT INTO GitPlatforms (name, firstSync, lastSync) VALUES ('Github', '2024-01-01 00:00:00', '2024-05-02 00:00:00')",
+            "INSERT INTO GitActions (id, name) VALUES (1, 'commit'), (2, 'comments')",
+            "INSERT INTO GitProjects (id, name, url, platform, platform_project_id, pseudonymous) VALUES \
+                (1, '2tefan/pollux', 'https://github.com/2tefan/pollux', 'Github', 42, 0), \
+                (2, 'private-0123456789ab', '', 'Github', 42, 1)",
+            "UPDATE GitProjects SET visibility = 'private', unavailable_since = '2024-05-02 00:00:00' WHERE id = 2",
+            "INSERT INTO Events (id, timestamp) VALUES (1, '2024-05-01 10:00:00'), (2, '2024-05-01 11:00:00'), (3, '2024-05-01 11:00:00'), \
+                (4, '2024-05-01 12:00:00'), (5, '2024-05-01 12:00:00')",
+            // Two accounts pushing to the same project in the same second are two events
+            "INSERT INTO GitEvents (id, action_fk, project_fk, timestamp, account) VALUES (1, 1, 1, NULL, NULL), (2, 2, 1, NULL, NULL), \
+                (3, 1, 2, NULL, NULL), (4, 1, 1, '2024-05-01 12:00:00', 'alice'), (5, 1, 1, '2024-05-01 12:00:00', 'bob')",
+            "UPDATE GitEvents SET git_ref = 'main', target_type = 'Issue' WHERE id = 1",
+            "INSERT INTO DailyAggregates (platform, day, action_fk, event_count, commit_count) VALUES ('Github', '2023-01-01', 1, 4, 9)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        let path = std::env::temp_dir().join(format!("pollux-round-trip-{}.json", uuid::Uuid::new_v4()));
+        let exported = export_to_file(&pool, &path).await;
+
+        for table in ["Events", "DailyAggregates", "GitProjects", "GitActions", "GitPlatforms"] {
+            sqlx::query(&format!("DELETE FROM {}", table)).execute(&pool).await.unwrap();
+        }
+
+        let summary = import_file(&pool, path.clone()).await.unwrap();
+        assert_eq!(summary.platforms, ImportCounts { created: 1, skipped: 0 });
+        assert_eq!(summary.actions, ImportCounts { created: 2, skipped: 0 });
+        assert_eq!(summary.projects, ImportCounts { created: 2, skipped: 0 });
+        assert_eq!(summary.events, ImportCounts { created: 5, skipped: 0 });
+        assert_eq!(summary.daily_aggregates, ImportCounts { created: 1, skipped: 0 });
+
+        let summary = import_file(&pool, path.clone()).await.unwrap();
+        assert_eq!(summary.events, ImportCounts { created: 0, skipped: 5 });
+        assert_eq!(summary.projects, ImportCounts { created: 0, skipped: 2 });
+        // Adding them up again would count the pruned events twice
+        assert_eq!(summary.daily_aggregates, ImportCounts { created: 0, skipped: 1 });
+
+        assert_eq!(export_to_file(&pool, &path).await, exported);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn ingested_events_survive_a_round_trip() {
+        let (_container, pool) = crate::database::tests::initialize().await;
+        let entry = serde_json::json!({
+            "platform": "Fossil",
+            "project": {"external_id": "pollux", "name": "2tefan/pollux", "url": "https://fossil.example.com/pollux"},
+            "action": "commit",
+            "created_at": "2024-05-01T10:00:00Z",
+            "commit_count": 3,
+        });
+        let now = DateTime::from_timestamp(1_714_600_000, 0).unwrap();
+        assert_eq!(crate::ingest::ingest(&pool, vec![entry.clone()], now).await.created, 1);
+
+        let path = std::env::temp_dir().join(format!("pollux-ingest-round-trip-{}.json", uuid::Uuid::new_v4()));
+        let exported = export_to_file(&pool, &path).await;
+        for table in ["Events", "GitProjects", "GitActions", "GitPlatforms"] {
+            sqlx::query(&format!("DELETE FROM {}", table)).execute(&pool).await.unwrap();
+        }
+
+        let summary = import_file(&pool, path.clone()).await.unwrap();
+        assert_eq!(summary.projects, ImportCounts { created: 1, skipped: 0 });
+        assert_eq!(summary.events, ImportCounts { created: 1, skipped: 0 });
+        assert_eq!(export_to_file(&pool, &path).await, exported);
+        std::fs::remove_file(path).unwrap();
+
+        // The imported project is still found by its external id
+        let report = crate::ingest::ingest(&pool, vec![entry], now).await;
+        assert_eq!((report.created, report.skipped), (0, 1));
+    }
+}
@@ -0,0 +1,494 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::{MySql, Pool, Transaction};
+
+use crate::{
+    git_platform::{self, EventDetails, ACTIONS},
+    records, retention, stats,
+    visibility::ProjectVisibility,
+    KNOWN_PLATFORMS,
+};
+
+/// Length of `GitPlatforms.name` and `GitProjects.name`
+static MAX_NAME_LENGTH: usize = 100;
+/// Length of `GitProjects.url`
+static MAX_URL_LENGTH: usize = 500;
+static MAX_EXTERNAL_ID_LENGTH: usize = 255;
+
+/// One event pushed to `POST /api/v1/ingest`
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct IngestEvent {
+    /// Name of the forge, created on first use. Platforms pollux syncs itself are refused.
+    pub platform: String,
+    pub project: IngestProject,
+    /// One of the actions shared by all platforms, e.g. `commit` or `merge-request`
+    pub action: String,
+    pub created_at: DateTime<Utc>,
+    /// Only for commits, at least 1
+    #[serde(default)]
+    pub commit_count: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct IngestProject {
+    /// Id of the project on its platform, any string which doesn't change. In the rare case it collides
+    /// with the id of an already stored project, its entries are rejected.
+    pub external_id: String,
+    pub name: String,
+    /// http(s) url of the project, may be empty
+    pub url: String,
+    /// `public`, `internal` or `private`, unknown if missing
+    #[serde(default)]
+    pub visibility: Option<String>,
+}
+
+/// Outcome of an ingested batch, rejected entries don't keep the others from being inserted
+#[derive(Debug, Default, PartialEq, Serialize, JsonSchema)]
+pub struct IngestReport {
+    pub created: u64,
+    /// Events which already exist
+    pub skipped: u64,
+    pub rejected: Vec<IngestRejection>,
+}
+
+#[derive(Debug, PartialEq, Serialize, JsonSchema)]
+pub struct IngestRejection {
+    /// Position of the entry in the submitted array
+    pub index: usize,
+    pub error: String,
+}
+
+/// An entry which passed validation
+#[derive(Debug, PartialEq)]
+struct ValidEvent {
+    event: IngestEvent,
+    platform_project_id: u64,
+    visibility: Option<ProjectVisibility>,
+}
+
+/// Non-empty, without surrounding whitespace or control characters and at most `max_length` characters long
+fn check_text(field: &str, value: &str, max_length: usize) -> Result<(), String> {
+    if value.trim().is_empty() {
+        return Err(format!("{} must not be empty", field));
+    }
+    if value.trim() != value {
+        return Err(format!("{} must not start or end with whitespace", field));
+    }
+    if value.chars().any(char::is_control) {
+        return Err(format!("{} must not contain control characters", field));
+    }
+    if value.chars().count() > max_length {
+        return Err(format!("{} must not be longer than {} characters", field, max_length));
+    }
+    Ok(())
+}
+
+fn validate(entry: serde_json::Value, now: DateTime<Utc>) -> Result<ValidEvent, String> {
+    let event: IngestEvent = serde_json::from_value(entry).map_err(|err| err.to_string())?;
+
+    check_text("platform", &event.platform, MAX_NAME_LENGTH)?;
+    // Names are compared case-insensitively by the database
+    if KNOWN_PLATFORMS.iter().any(|known| known.eq_ignore_ascii_case(&event.platform)) {
+        return Err(format!("»{}« is synced by pollux itself, its events can't be ingested", event.platform));
+    }
+
+    let project = &event.project;
+    check_text("project.external_id", &project.external_id, MAX_EXTERNAL_ID_LENGTH)?;
+    check_text("project.name", &project.name, MAX_NAME_LENGTH)?;
+    if !project.url.is_empty() {
+        if project.url.chars().count() > MAX_URL_LENGTH {
+            return Err(format!("project.url must not be longer than {} characters", MAX_URL_LENGTH));
+        }
+        let is_web_url = Url::parse(&project.url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"));
+        if !is_web_url {
+            return Err(format!("project.url »{}« is no http(s) url", project.url));
+        }
+    }
+    let visibility = match &project.visibility {
+        Some(visibility) => Some(ProjectVisibility::parse(visibility).ok_or_else(|| {
+            format!("project.visibility »{}« is unknown (valid: public, internal, private)", visibility)
+        })?),
+        None => None,
+    };
+
+    if !ACTIONS.contains(&event.action.as_str()) {
+        return Err(format!("Unknown action »{}« (valid: {})", event.action, ACTIONS.join(", ")));
+    }
+    if event.created_at > now {
+        return Err(format!("created_at {} lies in the future", event.created_at.to_rfc3339()));
+    }
+    match event.commit_count {
+        Some(0) => return Err("commit_count must be at least 1".to_string()),
+        Some(_) if event.action != "commit" => return Err("commit_count is only allowed for commits".to_string()),
+        _ => {}
+    }
+
+    Ok(ValidEvent {
+        platform_project_id: git_platform::platform_project_id_from(&project.external_id),
+        visibility,
+        event,
+    })
+}
+
+struct Ingester {
+    tx: Transaction<'static, MySql>,
+    platforms: Vec<String>,
+    /// By platform and external id
+    projects: HashMap<(String, String), u64>,
+    actions: HashMap<String, u64>,
+}
+
+impl Ingester {
+    /// Rejects a project whose external id maps onto the `platform_project_id` of another one
+    async fn project(&mut self, valid: &ValidEvent) -> Result<u64, String> {
+        let platform = &valid.event.platform;
+        let external_id = &valid.event.project.external_id;
+        let key = (platform.clone(), external_id.clone());
+        if let Some(id) = self.projects.get(&key) {
+            return Ok(*id);
+        }
+
+        if !self.platforms.contains(platform) {
+            git_platform::set_platform_named(&mut self.tx, platform).await;
+            self.platforms.push(platform.clone());
+        }
+        let stored: Option<(u64, Option<String>)> = sqlx::query_as(
+            "SELECT id, external_id FROM GitProjects WHERE platform = ? AND platform_project_id = ? AND pseudonymous = 0",
+        )
+        .bind(platform)
+        .bind(valid.platform_project_id)
+        .fetch_optional(&mut *self.tx)
+        .await
+        .unwrap();
+
+        let id = match stored {
+            Some((_, Some(stored_id))) if stored_id != *external_id => {
+                return Err(format!(
+                    "project.external_id »{}« collides with the already stored »{}«, use a different one",
+                    external_id, stored_id
+                ));
+            }
+            Some((id, Some(_))) => id,
+            // Ingested before external ids were stored
+            Some((id, None)) => {
+                sqlx::query("UPDATE GitProjects SET external_id = ? WHERE id = ?")
+                    .bind(external_id)
+                    .bind(id)
+                    .execute(&mut *self.tx)
+                    .await
+                    .unwrap();
+                id
+            }
+            None => sqlx::query(
+                "INSERT INTO GitProjects (platform, platform_project_id, name, url, visibility, external_id) \
+                    VALUES ( ?, ?, ?, ?, ?, ? )",
+            )
+            .bind(platform)
+            .bind(valid.platform_project_id)
+            .bind(&valid.event.project.name)
+            .bind(&valid.event.project.url)
+            .bind(valid.visibility.as_ref().map(ProjectVisibility::as_str))
+            .bind(external_id)
+            .execute(&mut *self.tx)
+            .await
+            .unwrap()
+            .last_insert_id(),
+        };
+        self.projects.insert(key, id);
+        Ok(id)
+    }
+
+    async fn action(&mut self, name: &str) -> u64 {
+        if let Some(id) = self.actions.get(name) {
+            return *id;
+        }
+
+        let id = match git_platform::git_action_id(&mut self.tx, name).await {
+            Some(id) => id,
+            None => git_platform::add_git_action(&mut self.tx, name).await,
+        };
+        self.actions.insert(name.to_string(), id);
+        id
+    }
+
+    /// Whether the event was created, instead of already existing
+    async fn event(&mut self, valid: &ValidEvent) -> Result<bool, String> {
+        let project_id = self.project(valid).await?;
+        let action_id = self.action(&valid.event.action).await;
+
+        let created_at = valid.event.created_at;
+        let commit_count = valid.event.commit_count.map(u64::from);
+        let inserted =
+            git_platform::add_unique_event(&mut self.tx, created_at, action_id, project_id, commit_count, None, EventDetails::default())
+                .await;
+        Ok(inserted.is_some())
+    }
+}
+
+/// Validates every entry on its own and inserts the valid ones in one transaction, like the synced platforms do.
+/// Events which already exist are skipped, so a batch can be sent again. Events older than the retention are rejected.
+pub async fn ingest(pool: &Pool<MySql>, entries: Vec<serde_json::Value>, now: DateTime<Utc>) -> IngestReport {
+    let mut report = IngestReport::default();
+    let mut valid = Vec::new();
+    let cutoff = retention::cutoff(now);
+    for (index, entry) in entries.into_iter().enumerate() {
+        match validate(entry, now) {
+            // Pruned (and rolled up) already, it would be counted twice
+            Ok(event) if cutoff.is_some_and(|cutoff| event.event.created_at < cutoff) => report.rejected.push(IngestRejection {
+                index,
+                error: "created_at is older than the retention".to_string(),
+            }),
+            Ok(event) => valid.push((index, event)),
+            Err(error) => report.rejected.push(IngestRejection { index, error }),
+        }
+    }
+    if valid.is_empty() {
+        return report;
+    }
+
+    let mut ingester = Ingester {
+        tx: pool.begin().await.expect("Couldn't start transaction!"),
+        platforms: Vec::new(),
+        projects: HashMap::new(),
+        actions: HashMap::new(),
+    };
+    let mut inserted_at = Vec::new();
+    for (index, event) in &valid {
+        match ingester.event(event).await {
+            Ok(true) => {
+                inserted_at.push(event.event.created_at);
+                report.created += 1;
+            }
+            Ok(false) => report.skipped += 1,
+            Err(error) => report.rejected.push(IngestRejection { index: *index, error }),
+        }
+    }
+    ingester.tx.commit().await.expect("Couldn't apply transaction ._.");
+    report.rejected.sort_by_key(|rejection| rejection.index);
+
+    if report.created > 0 {
+        stats::invalidate_today_cache();
+        records::update_stat_records(pool, &inserted_at).await;
+    }
+    info!(
+        "Ingested {} new events, skipped {} existing and rejected {}",
+        report.created,
+        report.skipped,
+        report.rejected.len()
+    );
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap()
+    }
+
+    fn entry() -> serde_json::Value {
+        json!({
+            "platform": "Fossil",
+            "project": {"external_id": "pollux", "name": "2tefan/pollux", "url": "https://fossil.example.com/pollux"},
+            "action": "commit",
+            "created_at": "2024-05-01T10:00:00Z",
+            "commit_count": 3,
+        })
+    }
+
+    /// `entry()` with `pointer` set to `value`, or removed if it's `null`
+    fn entry_with(pointer: &str, value: serde_json::Value) -> serde_json::Value {
+        let mut entry = entry();
+        let (parent, field) = pointer.rsplit_once('/').unwrap();
+        let parent = entry.pointer_mut(parent).unwrap().as_object_mut().unwrap();
+        if value.is_null() {
+            parent.remove(field);
+        } else {
+            parent.insert(field.to_string(), value);
+        }
+        entry
+    }
+
+    fn rejection(entry: serde_json::Value) -> String {
+        validate(entry, now()).unwrap_err()
+    }
+
+    #[test]
+    fn valid_entries_are_accepted() {
+        let valid = validate(entry(), now()).unwrap();
+        assert_eq!(valid.event.platform, "Fossil");
+        assert_eq!(valid.event.commit_count, Some(3));
+        assert_eq!(valid.platform_project_id, git_platform::platform_project_id_from("pollux"));
+        assert_eq!(valid.visibility, None);
+
+        let valid = validate(entry_with("/project/visibility", json!("Private")), now()).unwrap();
+        assert_eq!(valid.visibility, Some(ProjectVisibility::Private));
+
+        // Optional or empty
+        assert!(validate(entry_with("/commit_count", json!(null)), now()).is_ok());
+        assert!(validate(entry_with("/project/url", json!("")), now()).is_ok());
+        assert!(validate(entry_with("/created_at", json!("2024-05-02T02:00:00+02:00")), now()).is_ok());
+        for action in ACTIONS.iter().filter(|action| **action != "commit") {
+            let mut entry = entry_with("/commit_count", json!(null));
+            entry["action"] = json!(action);
+            assert!(validate(entry, now()).is_ok(), "{}", action);
+        }
+    }
+
+    #[test]
+    fn malformed_entries_name_their_problem() {
+        for field in ["/platform", "/project", "/action", "/created_at", "/project/external_id", "/project/name", "/project/url"] {
+            let error = rejection(entry_with(field, json!(null)));
+            let name = field.rsplit('/').next().unwrap();
+            assert!(error.contains(&format!("missing field `{}`", name)), "{}: {}", field, error);
+        }
+        assert!(validate(json!(["Fossil"]), now()).is_err());
+        assert!(rejection(json!("Fossil")).contains("invalid type"));
+        assert!(rejection(entry_with("/commit_count", json!(-1))).contains("invalid value"));
+        assert!(rejection(entry_with("/commit_count", json!("3"))).contains("invalid type"));
+        assert!(rejection(entry_with("/project/external_id", json!(42))).contains("invalid type"));
+        assert!(rejection(entry_with("/author", json!("2tefan"))).contains("unknown field `author`"));
+        assert!(rejection(entry_with("/project/id", json!(1))).contains("unknown field `id`"));
+    }
+
+    #[test]
+    fn dates_have_to_be_rfc3339_and_not_in_the_future() {
+        for created_at in ["2024-05-01", "2024-05-01 10:00:00", "1714557600", "yesterday"] {
+            assert!(validate(entry_with("/created_at", json!(created_at)), now()).is_err(), "{}", created_at);
+        }
+        assert!(rejection(entry_with("/created_at", json!(1_714_557_600))).contains("invalid type"));
+        assert_eq!(
+            rejection(entry_with("/created_at", json!("2024-05-02T00:00:01Z"))),
+            "created_at 2024-05-02T00:00:01+00:00 lies in the future"
+        );
+    }
+
+    #[test]
+    fn texts_are_checked() {
+        assert_eq!(rejection(entry_with("/platform", json!(""))), "platform must not be empty");
+        assert_eq!(rejection(entry_with("/project/name", json!("   "))), "project.name must not be empty");
+        assert_eq!(
+            rejection(entry_with("/platform", json!(" Fossil"))),
+            "platform must not start or end with whitespace"
+        );
+        assert_eq!(
+            rejection(entry_with("/project/external_id", json!("pol\nlux"))),
+            "project.external_id must not contain control characters"
+        );
+        assert_eq!(
+            rejection(entry_with("/project/name", json!("x".repeat(101)))),
+            "project.name must not be longer than 100 characters"
+        );
+        // Characters, not bytes
+        assert!(validate(entry_with("/project/name", json!("ü".repeat(100))), now()).is_ok());
+        assert_eq!(
+            rejection(entry_with("/project/external_id", json!("1".repeat(256)))),
+            "project.external_id must not be longer than 255 characters"
+        );
+    }
+
+    #[test]
+    fn native_platforms_are_refused() {
+        for platform in KNOWN_PLATFORMS.iter().copied().chain(["gitea", "CODEBERG"]) {
+            let error = rejection(entry_with("/platform", json!(platform)));
+            assert!(error.contains("is synced by pollux itself"), "{}: {}", platform, error);
+        }
+    }
+
+    #[test]
+    fn urls_have_to_be_web_urls() {
+        for url in ["fossil.example.com/pollux", "ftp://fossil.example.com", "file:///srv/pollux", "javascript:alert(1)"] {
+            assert_eq!(
+                rejection(entry_with("/project/url", json!(url))),
+                format!("project.url »{}« is no http(s) url", url)
+            );
+        }
+        let long = format!("https://example.com/{}", "x".repeat(500));
+        assert_eq!(
+            rejection(entry_with("/project/url", json!(long))),
+            "project.url must not be longer than 500 characters"
+        );
+    }
+
+    #[test]
+    fn actions_and_commit_counts_are_checked() {
+        assert_eq!(
+            rejection(entry_with("/action", json!("PushEvent"))),
+            format!("Unknown action »PushEvent« (valid: {})", ACTIONS.join(", "))
+        );
+        assert_eq!(rejection(entry_with("/commit_count", json!(0))), "commit_count must be at least 1");
+        let mut review = entry();
+        review["action"] = json!("reviewed");
+        assert_eq!(rejection(review), "commit_count is only allowed for commits");
+        assert_eq!(
+            rejection(entry_with("/project/visibility", json!("secret"))),
+            "project.visibility »secret« is unknown (valid: public, internal, private)"
+        );
+    }
+
+    #[tokio::test]
+    async fn entries_are_inserted_once_and_rejected_individually() {
+        let (_container, pool) = crate::database::tests::initialize().await;
+        let mut other_project = entry_with("/project/external_id", json!("castor"));
+        other_project["project"]["name"] = json!("2tefan/castor");
+        let entries = vec![entry(), entry_with("/action", json!("push")), entry(), other_project];
+
+        let report = ingest(&pool, entries.clone(), now()).await;
+        assert_eq!(report.created, 2);
+        assert_eq!(report.skipped, 1);
+        assert_eq!(
+            report.rejected,
+            vec![IngestRejection {
+                index: 1,
+                error: format!("Unknown action »push« (valid: {})", ACTIONS.join(", ")),
+            }]
+        );
+
+        let report = ingest(&pool, entries, now()).await;
+        assert_eq!((report.created, report.skipped), (0, 3));
+
+        let platforms: Vec<String> = sqlx::query_scalar("SELECT name FROM GitPlatforms").fetch_all(&pool).await.unwrap();
+        assert_eq!(platforms, vec!["Fossil".to_string()]);
+        let projects: Vec<String> = sqlx::query_scalar("SELECT name FROM GitProjects ORDER BY name")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(projects, vec!["2tefan/castor".to_string(), "2tefan/pollux".to_string()]);
+    }
+
+    #[test]
+    fn external_ids_can_share_a_platform_project_id() {
+        // Found by trying, so the collision below is a real one
+        assert_eq!(git_platform::platform_project_id_from("repo-57926"), git_platform::platform_project_id_from("repo-105927"));
+    }
+
+    #[tokio::test]
+    async fn colliding_external_ids_are_rejected() {
+        let (_container, pool) = crate::database::tests::initialize().await;
+        let first = entry_with("/project/external_id", json!("repo-57926"));
+        let mut second = entry_with("/project/external_id", json!("repo-105927"));
+        second["project"]["name"] = json!("2tefan/castor");
+
+        let report = ingest(&pool, vec![first.clone(), second.clone()], now()).await;
+        assert_eq!(report.created, 1);
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected[0].index, 1);
+        assert!(report.rejected[0].error.contains("collides with the already stored »repo-57926«"), "{:?}", report.rejected);
+
+        // Also across batches
+        let report = ingest(&pool, vec![second, first], now()).await;
+        assert_eq!((report.created, report.skipped, report.rejected.len()), (0, 1, 1));
+
+        let projects: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM GitProjects").fetch_one(&pool).await.unwrap();
+        assert_eq!(projects, 1);
+    }
+}
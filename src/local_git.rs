@@ -0,0 +1,475 @@
+use crate::{
+    config::{self, ConfigError, EnvConfig},
+    credentials::CredentialCheck,
+    event_archive::{self, EventSource},
+    git_platform::{self, EventDetails, GitEventAPI, GitPlatform, GitProject, InsertEvents, NewEvent, PlatformError, Skipped, SyncCache, SyncResult},
+    pagination::FetchedEvents,
+    platform_registry::{BoxFuture, PlatformRunner},
+    project_filter::ProjectFilter,
+    retry::env_parse,
+    sync_runs::SyncReport,
+    visibility::ProjectVisibility,
+};
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::{DateTime, TimeZone, Utc};
+use git2::{BranchType, Oid, Repository, Sort};
+use log::warn;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use sqlx::{MySql, Transaction};
+use tokio::sync::Mutex;
+
+static LOCAL_GIT: OnceCell<Arc<Mutex<LocalGit>>> = OnceCell::new();
+/// Repositories have no feed to page through to its end, so the initial sync looks back this far
+static INITIAL_SYNC_DAYS: i64 = 90;
+/// Commits can be pushed a while after they were committed, so a bit before the last sync is walked again
+static FALLBACK_SYNC_OVERLAP_HOURS: i64 = 24;
+
+/// Commits of the user in a repository on this machine, committed in the same second
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LocalGitEvent {
+    /// Directory name without `.git`, matched by `ProjectFilter`
+    pub name: String,
+    pub path: String,
+    /// When it was committed, the closest a repository tells to when it was pushed
+    pub created: String,
+    /// First branch the commit was found on, `None` if it's only reachable from a detached `HEAD`
+    pub git_ref: Option<String>,
+    /// A rebased series is committed within a second, it would collapse into one event otherwise.
+    /// `None` for a single commit, events were archived without it at first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_count: Option<u64>,
+}
+
+impl GitEventAPI for LocalGitEvent {}
+
+#[derive(Debug)]
+pub struct LocalGit {
+    /// Glob patterns of repository directories, bare or not
+    repo_paths: Vec<String>,
+    /// Own commits are recognized by them, compared case-insensitively
+    author_emails: Vec<String>,
+    project_filter: ProjectFilter,
+    /// Commits this long before the last sync are walked again, e.g. ones pulled in after it
+    sync_overlap: chrono::Duration,
+}
+
+impl GitPlatform for LocalGit {
+    const GIT_PLATFORM_ID: &'static str = "LocalGit";
+    type GitEventAPI = LocalGitEvent;
+
+    fn init_from_env_vars() -> Result<Self, ConfigError> {
+        let mut env = EnvConfig::from_env();
+        let local_git = LocalGit {
+            repo_paths: parse_list(&env.required("POLLUX_LOCAL_REPO_PATHS")),
+            author_emails: parse_list(&env.required("POLLUX_LOCAL_AUTHOR_EMAILS")),
+            project_filter: ProjectFilter::from_env(),
+            sync_overlap: chrono::Duration::hours(env_parse(
+                "POLLUX_LOCAL_SYNC_OVERLAP_HOURS",
+                FALLBACK_SYNC_OVERLAP_HOURS,
+            )),
+        };
+        env.build(local_git)
+    }
+
+    async fn get_events(&mut self) -> Result<FetchedEvents<Self::GitEventAPI>, PlatformError> {
+        let since = match LocalGit::get_last_sync_timestamp().await {
+            Some(last_sync) => last_sync - self.sync_overlap,
+            None => {
+                info!("Initial run! Walking last {} days of local repositories...", INITIAL_SYNC_DAYS);
+                Utc::now() - chrono::Duration::days(INITIAL_SYNC_DAYS)
+            }
+        };
+        self.get_events_since(since).await
+    }
+
+    async fn update_provider(&mut self) -> Result<SyncResult, PlatformError> {
+        info!("Updating events from local repositories...");
+        let fetched = self.get_events().await?;
+        let result = self.insert_events(&fetched.events, EventSource::Fetched, true).await;
+        Ok(SyncResult {
+            truncated: fetched.truncated,
+            ..result
+        })
+    }
+
+    async fn insert_archived_events(&self, events: Vec<LocalGitEvent>) -> Result<SyncResult, PlatformError> {
+        Ok(self.insert_events(&events, EventSource::Archive, true).await)
+    }
+}
+
+impl PlatformRunner for LocalGit {
+    fn sync(&mut self) -> BoxFuture<'_, SyncReport> {
+        Box::pin(git_platform::sync_and_record(self))
+    }
+
+    fn check_credentials(&mut self) -> BoxFuture<'_, Vec<CredentialCheck>> {
+        // Nothing to validate, the repositories are read from disk
+        Box::pin(async { Vec::new() })
+    }
+
+    fn reprocess(&mut self) -> BoxFuture<'_, SyncReport> {
+        Box::pin(event_archive::reprocess(self))
+    }
+}
+
+impl InsertEvents for LocalGit {
+    async fn to_new_event<'e>(
+        &self,
+        tx: &mut Transaction<'static, MySql>,
+        cache: &mut SyncCache,
+        event: &'e LocalGitEvent,
+    ) -> Result<NewEvent<'e>, Skipped<'e>> {
+        let datetime = Self::parse_datetime(&event.created)?;
+        let action_name = Self::map_action_name("pushed").ok_or(Skipped::UnknownAction("pushed"))?;
+        let project_id = self.project_of(tx, cache, event).await;
+        let action_id = cache.action(tx, action_name).await;
+
+        Ok(NewEvent {
+            datetime,
+            action_id,
+            project_id,
+            commit_count: Some(event.commit_count.unwrap_or(1)),
+            account: None,
+            details: EventDetails {
+                git_ref: event.git_ref.as_deref(),
+                target_type: None,
+            },
+        })
+    }
+}
+
+/// Comma-separated values, blanks are ignored
+pub fn parse_list(input: &str) -> Vec<String> {
+    input
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl LocalGit {
+    /// Set up if `POLLUX_LOCAL_REPO_PATHS` and `POLLUX_LOCAL_AUTHOR_EMAILS` are given
+    /// Any of its settings is set, `init_from_env_vars` reports the missing ones
+    pub fn is_configured() -> bool {
+        ["POLLUX_LOCAL_REPO_PATHS", "POLLUX_LOCAL_AUTHOR_EMAILS"]
+            .iter()
+            .any(|name| config::var(name).is_some_and(|value| !value.trim().is_empty()))
+    }
+
+    /// `Ok(None)` if no local repositories are configured
+    pub fn get_or_init() -> Result<Option<Arc<Mutex<LocalGit>>>, ConfigError> {
+        if !LocalGit::is_configured() {
+            return Ok(None);
+        }
+        LOCAL_GIT
+            .get_or_try_init(|| Self::init_from_env_vars().map(|platform| Arc::new(Mutex::new(platform))))
+            .cloned()
+            .map(Some)
+    }
+
+    /// Own commits in all matching repositories since `since`. Walking is blocking, so it runs on its own thread.
+    pub async fn get_events_since(&self, since: DateTime<Utc>) -> Result<FetchedEvents<LocalGitEvent>, PlatformError> {
+        let repo_paths = self.repo_paths.clone();
+        let author_emails = self.author_emails.clone();
+        let project_filter = self.project_filter.clone();
+        let events = tokio::task::spawn_blocking(move || scan(&repo_paths, &author_emails, &project_filter, since))
+            .await
+            .map_err(|err| PlatformError::Request(format!("Walking local repositories failed: {}", err)))?;
+        Ok(FetchedEvents::complete(events))
+    }
+
+    /// Id of the `GitProjects` row, stored on the first event of the repository
+    async fn project_of(
+        &self,
+        tx: &mut Transaction<'static, MySql>,
+        cache: &mut SyncCache,
+        event: &LocalGitEvent,
+    ) -> u64 {
+        // Directories can share a name, their paths can't
+        let platform_project_id = git_platform::platform_project_id_from(&event.path);
+        if let Some(project) = cache.project(tx, platform_project_id, false).await {
+            return project.id;
+        }
+
+        let project = GitProject::reported(platform_project_id, &event.name, format!("file://{}", event.path));
+        // Nobody else can see them
+        self.write_project_to_db(tx, &project, Some(ProjectVisibility::Private)).await
+    }
+
+}
+
+/// Own commits in the repositories matching `repo_paths`. Repositories which can't be walked are skipped.
+fn scan(
+    repo_paths: &[String],
+    author_emails: &[String],
+    project_filter: &ProjectFilter,
+    since: DateTime<Utc>,
+) -> Vec<LocalGitEvent> {
+    let mut events = Vec::new();
+    let mut scanned = HashSet::new();
+    for pattern in repo_paths {
+        let paths = match glob::glob(pattern) {
+            Ok(paths) => paths,
+            Err(err) => {
+                warn!("Skipping POLLUX_LOCAL_REPO_PATHS pattern »{}«: {}", pattern, err);
+                continue;
+            }
+        };
+
+        for path in paths.flatten().filter(|path| path.is_dir()) {
+            // Patterns can overlap
+            let path = path.canonicalize().unwrap_or(path);
+            if !scanned.insert(path.clone()) {
+                continue;
+            }
+            let Ok(repo) = Repository::open(&path) else {
+                debug!("Skipping {}: not a git repository", path.display());
+                continue;
+            };
+            let name = project_name(&path);
+            if !project_filter.includes(LocalGit::GIT_PLATFORM_ID, &name) {
+                continue;
+            }
+
+            match scan_repository(&repo, author_emails, since) {
+                Ok(commits) => events.extend(group_by_second(&name, &path.display().to_string(), commits)),
+                Err(err) => warn!("Skipping {}: {}", path.display(), err),
+            }
+        }
+    }
+    events
+}
+
+/// One event per second with commits of the repository, events are unique per second.
+/// The branch of the newest commit is kept.
+fn group_by_second(name: &str, path: &str, commits: Vec<OwnCommit>) -> Vec<LocalGitEvent> {
+    let mut events: Vec<LocalGitEvent> = Vec::new();
+    let mut index_of: HashMap<String, usize> = HashMap::new();
+    for commit in commits {
+        let created = commit.committed.to_rfc3339();
+        if let Some(&index) = index_of.get(&created) {
+            let event = &mut events[index];
+            event.commit_count = Some(event.commit_count.unwrap_or(1) + 1);
+            continue;
+        }
+        index_of.insert(created.clone(), events.len());
+        events.push(LocalGitEvent {
+            name: name.to_string(),
+            path: path.to_string(),
+            created,
+            git_ref: commit.git_ref,
+            commit_count: None,
+        });
+    }
+    events
+}
+
+/// Directory name without `.git`, `/srv/git/pollux.git` and `/home/me/pollux` are both `pollux`
+fn project_name(path: &Path) -> String {
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    name.strip_suffix(".git").map(str::to_string).unwrap_or(name)
+}
+
+struct OwnCommit {
+    committed: DateTime<Utc>,
+    git_ref: Option<String>,
+}
+
+/// Own commits since `since`, on any branch or a detached `HEAD`.
+/// Commits are walked newest first and only until they are older than `since`.
+fn scan_repository(
+    repo: &Repository,
+    author_emails: &[String],
+    since: DateTime<Utc>,
+) -> Result<Vec<OwnCommit>, git2::Error> {
+    let mut tips: Vec<(Option<String>, Oid)> = Vec::new();
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        if let (Ok(Some(name)), Some(target)) = (branch.name(), branch.get().target()) {
+            tips.push((Some(name.to_string()), target));
+        }
+    }
+    // The checked out branch first, so commits on it are attributed to it
+    if let Ok(head) = repo.head() {
+        let head_branch = head.shorthand().map(str::to_string);
+        if repo.head_detached()? {
+            tips.extend(head.target().map(|target| (None, target)));
+        } else {
+            tips.sort_by_key(|(name, _)| *name != head_branch);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut commits = Vec::new();
+    for (git_ref, tip) in tips {
+        let mut walk = repo.revwalk()?;
+        walk.set_sorting(Sort::TIME)?;
+        walk.push(tip)?;
+        for oid in walk {
+            let oid = oid?;
+            let commit = repo.find_commit(oid)?;
+            let Some(committed) = Utc.timestamp_opt(commit.committer().when().seconds(), 0).single() else {
+                continue;
+            };
+            if committed < since {
+                break;
+            }
+            // Reachable from an earlier branch
+            if !seen.insert(oid) {
+                continue;
+            }
+
+            let author = commit.author();
+            let is_own = author
+                .email()
+                .is_some_and(|email| author_emails.iter().any(|own| own.eq_ignore_ascii_case(email)));
+            if is_own {
+                commits.push(OwnCommit {
+                    committed,
+                    git_ref: git_ref.clone(),
+                });
+            }
+        }
+    }
+    Ok(commits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Signature, Time};
+
+    fn since() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap()
+    }
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, day, hour, 0, 0).unwrap()
+    }
+
+    /// Commits on top of `reference`'s commit, or a root commit if it doesn't exist yet
+    fn commit(repo: &Repository, reference: &str, email: &str, time: DateTime<Utc>) -> Oid {
+        let signature = Signature::new("Someone", email, &Time::new(time.timestamp(), 0)).unwrap();
+        let tree = repo.find_tree(repo.treebuilder(None).unwrap().write().unwrap()).unwrap();
+        let parent = repo.refname_to_id(reference).ok().map(|oid| repo.find_commit(oid).unwrap());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(Some(reference), &signature, &signature, &time.to_rfc3339(), &tree, &parents)
+            .unwrap()
+    }
+
+    fn emails() -> Vec<String> {
+        vec!["2tefan@example.com".to_string()]
+    }
+
+    fn scan_dir(dir: &Path) -> Vec<LocalGitEvent> {
+        scan(&[format!("{}/*", dir.display())], &emails(), &ProjectFilter::default(), since())
+    }
+
+    #[test]
+    fn own_commits_since_the_last_sync_are_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("pollux.git")).unwrap();
+        commit(&repo, "refs/heads/main", "2tefan@example.com", at(1, 0) - chrono::Duration::days(1));
+        commit(&repo, "refs/heads/main", "2TEFAN@example.com", at(2, 8));
+        commit(&repo, "refs/heads/main", "colleague@example.com", at(3, 8));
+        commit(&repo, "refs/heads/main", "2tefan@example.com", at(4, 8));
+
+        let events = scan_dir(dir.path());
+        assert_eq!(
+            events.iter().map(|event| (event.name.as_str(), event.created.clone())).collect::<Vec<_>>(),
+            vec![("pollux", at(4, 8).to_rfc3339()), ("pollux", at(2, 8).to_rfc3339())]
+        );
+        assert!(events.iter().all(|event| event.git_ref.as_deref() == Some("main")));
+        assert_eq!(LocalGit::map_action_name("pushed"), Some("commit"));
+    }
+
+    #[test]
+    fn repositories_without_new_commits_have_no_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("old.git")).unwrap();
+        commit(&repo, "refs/heads/main", "2tefan@example.com", at(1, 0) - chrono::Duration::days(30));
+        // Without any commit or branch
+        Repository::init_bare(dir.path().join("empty.git")).unwrap();
+        // Not a repository at all
+        std::fs::create_dir(dir.path().join("notes")).unwrap();
+
+        assert_eq!(scan_dir(dir.path()), Vec::new());
+    }
+
+    #[test]
+    fn commits_on_several_branches_are_counted_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("pollux.git")).unwrap();
+        let base = commit(&repo, "refs/heads/main", "2tefan@example.com", at(2, 8));
+        repo.reference("refs/heads/feature", base, false, "branch").unwrap();
+        commit(&repo, "refs/heads/feature", "2tefan@example.com", at(3, 8));
+        repo.set_head("refs/heads/main").unwrap();
+
+        let mut refs: Vec<_> = scan_dir(dir.path()).into_iter().map(|event| event.git_ref).collect();
+        refs.sort();
+        assert_eq!(refs, vec![Some("feature".to_string()), Some("main".to_string())]);
+    }
+
+    #[test]
+    fn commits_of_a_detached_head_are_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path().join("checkout")).unwrap();
+        let base = commit(&repo, "refs/heads/main", "2tefan@example.com", at(2, 8));
+        repo.set_head_detached(base).unwrap();
+        commit(&repo, "HEAD", "2tefan@example.com", at(3, 8));
+
+        let events = scan_dir(dir.path());
+        assert_eq!(
+            events.iter().map(|event| (event.name.as_str(), event.git_ref.as_deref())).collect::<Vec<_>>(),
+            vec![("checkout", Some("main")), ("checkout", None)]
+        );
+    }
+
+    #[test]
+    fn rebased_commits_of_the_same_second_are_added_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("pollux.git")).unwrap();
+        commit(&repo, "refs/heads/main", "2tefan@example.com", at(2, 8));
+        // `git rebase` commits the whole series again within a second
+        for _ in 0..3 {
+            commit(&repo, "refs/heads/main", "2tefan@example.com", at(3, 8));
+        }
+
+        let events = scan_dir(dir.path());
+        assert_eq!(
+            events.iter().map(|event| (event.created.clone(), event.commit_count)).collect::<Vec<_>>(),
+            vec![(at(3, 8).to_rfc3339(), Some(3)), (at(2, 8).to_rfc3339(), None)]
+        );
+    }
+
+    #[test]
+    fn walking_stops_at_the_last_sync() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("huge.git")).unwrap();
+        for day in 0..500 {
+            commit(&repo, "refs/heads/main", "2tefan@example.com", at(1, 0) - chrono::Duration::days(500 - day));
+        }
+        commit(&repo, "refs/heads/main", "2tefan@example.com", at(2, 8));
+
+        assert_eq!(scan_dir(dir.path()).len(), 1);
+    }
+
+    #[test]
+    fn patterns_can_overlap_and_names_drop_the_suffix() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("pollux.git")).unwrap();
+        commit(&repo, "refs/heads/main", "2tefan@example.com", at(2, 8));
+
+        let patterns = [format!("{}/*", dir.path().display()), format!("{}/*.git", dir.path().display())];
+        let events = scan(&patterns, &emails(), &ProjectFilter::default(), since());
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].name, "pollux");
+        assert_eq!(parse_list(" a@example.com, ,b@example.com"), vec!["a@example.com", "b@example.com"]);
+    }
+}
@@ -0,0 +1,292 @@
+use crate::{
+    config::{self, ConfigError, EnvConfig},
+    credentials::CredentialCheck,
+    event_archive::{self, EventSource},
+    git_platform::{self, EventDetails, GitEventAPI, GitPlatform, GitProject, InsertEvents, NewEvent, PlatformError, Skipped, SyncCache, SyncResult},
+    pagination::FetchedEvents,
+    platform_registry::{BoxFuture, PlatformRunner},
+    sync_runs::SyncReport,
+    visibility::ProjectVisibility,
+};
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use sqlx::{MySql, Transaction};
+use tokio::sync::Mutex;
+
+static MOCK_PLATFORM: OnceCell<Arc<Mutex<MockPlatform>>> = OnceCell::new();
+static FALLBACK_MOCK_PLATFORM_SEED: u64 = 42;
+/// Like a platform's feed, the initial sync reaches back a while
+static INITIAL_SYNC_DAYS: u64 = 90;
+/// At most this many events per day, `0` to `MAX_EVENTS_PER_DAY` are generated
+static MAX_EVENTS_PER_DAY: u64 = 6;
+static PROJECTS: &[&str] = &["mock/pollux", "mock/castor", "mock/dashboard", "mock/dotfiles", "mock/website"];
+/// Named like GitHub's events, so they're mapped like any others, see `git_platform::map_action`
+static ACTIONS: &[&str] = &[
+    "PushEvent",
+    "PushEvent",
+    "PushEvent",
+    "PullRequestEvent",
+    "PullRequestReviewEvent",
+    "IssueCommentEvent",
+    "ReleaseEvent",
+];
+
+/// A generated event, see `MockPlatform::events_of_day`
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MockEvent {
+    pub project: String,
+    pub action: String,
+    pub created: DateTime<Utc>,
+    pub commit_count: Option<u64>,
+}
+
+impl GitEventAPI for MockEvent {}
+
+/// Syncs generated events instead of talking to a real platform, so pollux runs without any credentials.
+///
+/// Every day's events only depend on the seed and the day itself, so runs are reproducible and
+/// syncing a day again only finds duplicates.
+#[derive(Debug)]
+pub struct MockPlatform {
+    seed: u64,
+}
+
+impl GitPlatform for MockPlatform {
+    const GIT_PLATFORM_ID: &'static str = "Mock";
+    type GitEventAPI = MockEvent;
+
+    fn init_from_env_vars() -> Result<Self, ConfigError> {
+        let mut env = EnvConfig::from_env();
+        let mock = MockPlatform::new(env.parsed("POLLUX_MOCK_PLATFORM_SEED", FALLBACK_MOCK_PLATFORM_SEED));
+        env.build(mock)
+    }
+
+    async fn get_events(&mut self) -> Result<FetchedEvents<Self::GitEventAPI>, PlatformError> {
+        let since = match MockPlatform::get_last_sync_timestamp().await {
+            // Today is generated again, as it goes on
+            Some(last_sync) => last_sync.date_naive(),
+            None => {
+                info!("Initial run! Generating the last {} days of mock events...", INITIAL_SYNC_DAYS);
+                Utc::now().date_naive() - Days::new(INITIAL_SYNC_DAYS)
+            }
+        };
+        Ok(FetchedEvents::complete(self.events_between(since, Utc::now())))
+    }
+
+    async fn update_provider(&mut self) -> Result<SyncResult, PlatformError> {
+        info!("Updating events from the mock platform...");
+        let fetched = self.get_events().await?;
+        let result = self.insert_events(&fetched.events, EventSource::Fetched, true).await;
+        Ok(SyncResult {
+            truncated: fetched.truncated,
+            ..result
+        })
+    }
+
+    async fn insert_archived_events(&self, events: Vec<MockEvent>) -> Result<SyncResult, PlatformError> {
+        Ok(self.insert_events(&events, EventSource::Archive, true).await)
+    }
+}
+
+impl PlatformRunner for MockPlatform {
+    fn sync(&mut self) -> BoxFuture<'_, SyncReport> {
+        Box::pin(git_platform::sync_and_record(self))
+    }
+
+    fn check_credentials(&mut self) -> BoxFuture<'_, Vec<CredentialCheck>> {
+        // No credentials involved
+        Box::pin(async { Vec::new() })
+    }
+
+    fn reprocess(&mut self) -> BoxFuture<'_, SyncReport> {
+        Box::pin(event_archive::reprocess(self))
+    }
+}
+
+impl InsertEvents for MockPlatform {
+    async fn to_new_event<'e>(
+        &self,
+        tx: &mut Transaction<'static, MySql>,
+        cache: &mut SyncCache,
+        event: &'e MockEvent,
+    ) -> Result<NewEvent<'e>, Skipped<'e>> {
+        let action_name = Self::map_action_name(&event.action).ok_or(Skipped::UnknownAction(&event.action))?;
+        let project_id = self.project_of(tx, cache, event).await;
+        let action_id = cache.action(tx, action_name).await;
+
+        Ok(NewEvent {
+            datetime: event.created,
+            action_id,
+            project_id,
+            commit_count: event.commit_count,
+            account: None,
+            details: EventDetails::default(),
+        })
+    }
+}
+
+impl MockPlatform {
+    pub fn new(seed: u64) -> MockPlatform {
+        MockPlatform { seed }
+    }
+
+    /// `POLLUX_ENABLE_MOCK_PLATFORM=true`, never enabled by default
+    pub fn is_configured() -> bool {
+        config::var("POLLUX_ENABLE_MOCK_PLATFORM").is_some_and(|enabled| enabled.trim().eq_ignore_ascii_case("true"))
+    }
+
+    /// `Ok(None)` if the mock platform isn't enabled
+    pub fn get_or_init() -> Result<Option<Arc<Mutex<MockPlatform>>>, ConfigError> {
+        if !MockPlatform::is_configured() {
+            return Ok(None);
+        }
+        MOCK_PLATFORM
+            .get_or_try_init(|| Self::init_from_env_vars().map(|platform| Arc::new(Mutex::new(platform))))
+            .cloned()
+            .map(Some)
+    }
+
+    /// Events from the start of `since` until `until`, oldest first
+    pub fn events_between(&self, since: NaiveDate, until: DateTime<Utc>) -> Vec<MockEvent> {
+        since
+            .iter_days()
+            .take_while(|day| *day <= until.date_naive())
+            .flat_map(|day| self.events_of_day(day))
+            .filter(|event| event.created <= until)
+            .collect()
+    }
+
+    fn events_of_day(&self, day: NaiveDate) -> Vec<MockEvent> {
+        let start = day.and_hms_opt(0, 0, 0).unwrap().and_utc();
+        let mut random = SplitMix64(self.seed ^ start.timestamp() as u64);
+        let count = random.below(MAX_EVENTS_PER_DAY + 1);
+
+        let mut events: Vec<MockEvent> = (0..count)
+            .map(|_| {
+                let action = ACTIONS[random.below(ACTIONS.len() as u64) as usize];
+                MockEvent {
+                    project: PROJECTS[random.below(PROJECTS.len() as u64) as usize].to_string(),
+                    action: action.to_string(),
+                    created: start + chrono::Duration::seconds(random.below(24 * 60 * 60) as i64),
+                    commit_count: (action == "PushEvent").then(|| 1 + random.below(5)),
+                }
+            })
+            .collect();
+        events.sort_by_key(|event| event.created);
+        events
+    }
+
+    /// Id of the `GitProjects` row, stored on the first event of the project
+    async fn project_of(
+        &self,
+        tx: &mut Transaction<'static, MySql>,
+        cache: &mut SyncCache,
+        event: &MockEvent,
+    ) -> u64 {
+        let platform_project_id = git_platform::platform_project_id_from(&event.project);
+        if let Some(project) = cache.project(tx, platform_project_id, false).await {
+            return project.id;
+        }
+
+        let url = format!("https://mock.invalid/{}", event.project);
+        let project = GitProject::reported(platform_project_id, &event.project, url);
+        self.write_project_to_db(tx, &project, Some(ProjectVisibility::Public)).await
+    }
+
+}
+
+/// Small, seedable and good enough to make up events, see https://prng.di.unimi.it/splitmix64.c
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// `0..bound`, the slight bias doesn't matter for made up events
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use dotenv::dotenv;
+    use rocket::{http::Status, local::asynchronous::Client};
+
+    fn may(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, day).unwrap()
+    }
+
+    #[test]
+    fn events_are_reproducible_per_seed() {
+        let until = Utc.with_ymd_and_hms(2024, 5, 31, 0, 0, 0).unwrap();
+        let events = MockPlatform::new(7).events_between(may(1), until);
+
+        assert!(!events.is_empty());
+        assert_eq!(MockPlatform::new(7).events_between(may(1), until), events);
+        assert_ne!(MockPlatform::new(8).events_between(may(1), until), events);
+        // Days don't depend on when they're synced
+        let later = MockPlatform::new(7).events_between(may(10), until);
+        assert!(events.ends_with(&later));
+    }
+
+    #[test]
+    fn events_are_mapped_and_within_the_window() {
+        let until = Utc.with_ymd_and_hms(2024, 5, 10, 12, 0, 0).unwrap();
+        let events = MockPlatform::new(FALLBACK_MOCK_PLATFORM_SEED).events_between(may(1), until);
+
+        assert!(events.windows(2).all(|pair| pair[0].created <= pair[1].created));
+        assert!(events.iter().all(|event| event.created.date_naive() >= may(1) && event.created <= until));
+        assert!(events.iter().all(|event| git_platform::map_action(&event.action).is_some()));
+        assert!(events.iter().all(|event| (event.action == "PushEvent") == event.commit_count.is_some()));
+    }
+
+    #[tokio::test]
+    async fn synced_events_are_served_by_the_api() {
+        dotenv().ok();
+        let mut mock = MockPlatform::new(FALLBACK_MOCK_PLATFORM_SEED);
+        let result = mock.update_provider().await.unwrap();
+        assert!(result.fetched > 0);
+        assert_eq!(result.inserted + result.skipped_duplicates, result.fetched);
+
+        // Syncing the same days again only finds duplicates
+        let events = mock.events_between(Utc::now().date_naive() - Days::new(2), Utc::now());
+        let again = mock.insert_events(&events, EventSource::Fetched, true).await;
+        assert_eq!(again.inserted, 0);
+
+        let admin_config = crate::admin::AdminConfig { token: None, dev_mode: true };
+        let registry = Arc::new(crate::platform_registry::PlatformRegistry::default());
+        let client = Client::tracked(crate::build_rocket(admin_config, crate::clock::Clock::system(), registry))
+            .await
+            .unwrap();
+
+        let since = (Utc::now().date_naive() - Days::new(INITIAL_SYNC_DAYS)).to_string();
+        let response = client.get(format!("/api/v1/git-events?since={}", since)).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let events: serde_json::Value = response.into_json().await.unwrap();
+        let mock_event = events
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|event| event["project"]["platform"] == MockPlatform::GIT_PLATFORM_ID)
+            .expect("No mock event is served");
+        assert!(mock_event["project"]["name"].as_str().unwrap().starts_with("mock/"));
+        assert!(mock_event["timestamp"].is_string());
+
+        let response = client.get(format!("/api/v1/stats/daily?since={}", since)).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let days: serde_json::Value = response.into_json().await.unwrap();
+        assert!(days.as_array().unwrap().iter().any(|day| day["count"].as_u64() > Some(0)));
+    }
+}
@@ -0,0 +1,433 @@
+use schemars::{gen::SchemaGenerator, gen::SchemaSettings, JsonSchema};
+use serde_json::{json, Map, Value};
+
+#[cfg(any(feature = "github", feature = "gitlab"))]
+use crate::backfill::BackfillReport;
+use crate::{
+    api_v2::{EventV2, Page},
+    credentials::CredentialStatus,
+    badge::{DEFAULT_CALENDAR_CELL, DEFAULT_CALENDAR_WEEKS, MAX_BADGE_SCALE, MAX_CALENDAR_CELL, MAX_CALENDAR_WEEKS, MIN_CALENDAR_CELL},
+    import::ImportSummary,
+    platform_registry::PlatformStatus,
+    ingest::{IngestEvent, IngestReport},
+    projects::{DeleteReport, MergeReport, ProjectDetail},
+    purge::PurgeReport,
+    queries::GitEvents,
+    records::StatRecords,
+    stats::{DailyCount, PlatformBucket, TodayStats},
+    sync_jobs::SyncJob,
+    sync_runs::{SyncReport, SyncRun},
+    HealthResponse, ReadinessResponse, VersionResponse, DEFAULT_SYNC_RUNS_LIMIT,
+    MAX_SYNC_RUNS_LIMIT,
+};
+
+/// Builds the OpenAPI document. Routes are listed by hand, the schemas are generated
+/// from the response structs, so they can't drift from what the routes serialize.
+pub fn spec() -> Value {
+    let mut generator = SchemaSettings::openapi3().into_generator();
+
+    #[cfg_attr(not(any(feature = "github", feature = "gitlab")), allow(unused_mut))]
+    let mut paths = json!({
+        "/health": {
+            "get": operation("Health including a database check", &[], &[
+                ("200", "Healthy", schema::<HealthResponse>(&mut generator)),
+                ("503", "Degraded", schema::<HealthResponse>(&mut generator)),
+            ]),
+        },
+        "/livez": {
+            "get": operation("Liveness, answers as soon as the server is up", &[], &[
+                ("200", "Alive", schema::<HealthResponse>(&mut generator)),
+            ]),
+        },
+        "/readyz": {
+            "get": operation("Readiness: database reachable, migrations applied, platforms initialized", &[], &[
+                ("200", "Ready", schema::<ReadinessResponse>(&mut generator)),
+                ("503", "Not ready", schema::<ReadinessResponse>(&mut generator)),
+            ]),
+        },
+        "/api/v1/version": {
+            "get": operation("Build information", &[], &[
+                ("200", "Version", schema::<VersionResponse>(&mut generator)),
+            ]),
+        },
+        "/api/v1/git-events": {
+            "get": operation("All git events since a day", &[
+                since_parameter(),
+                query_parameter("count_only", "Only return the number of matching events", json!({"type": "boolean", "default": false})),
+                anonymize_parameter(),
+            ], &[
+                ("200", "Events ordered by timestamp, or `{\"count\": N}` with `count_only`", schema::<Vec<GitEvents>>(&mut generator)),
+                ("400", "Anonymization requested without a configured secret", Value::Null),
+            ]),
+            "head": {
+                "summary": "Number of matching events in the `X-Total` header",
+                "parameters": [since_parameter()],
+                "responses": {"200": {"description": "No body", "headers": {"X-Total": {"schema": {"type": "integer"}}}}},
+            },
+        },
+        "/api/v1/stats/daily": {
+            "get": operation("Number of events per day", &[
+                since_parameter(),
+                query_parameter("include", "Comma separated extras, `sources` adds where each count came from", json!({"type": "string"})),
+                weight_parameter(),
+                exclude_actions_parameter(),
+            ], &[
+                ("200", "Counts per day", schema::<Vec<DailyCount>>(&mut generator)),
+                ("400", "Unknown weight", text()),
+            ]),
+        },
+        "/api/v1/stats/today": {
+            "get": operation("Summary of the current local day", &[
+                query_parameter("tz", "IANA timezone, defaults to UTC", json!({"type": "string", "example": "Europe/Vienna"})),
+            ], &[
+                ("200", "Today", schema::<TodayStats>(&mut generator)),
+                ("400", "Unknown timezone", text()),
+            ]),
+        },
+        "/api/v1/stats/platforms/timeseries": {
+            "get": operation("Event counts per platform and time bucket, including platforms without activity", &[
+                since_parameter(),
+                query_parameter("granularity", "Bucket size, weeks start on Monday", json!({"type": "string", "enum": ["day", "week", "month"], "default": "day"})),
+                weight_parameter(),
+                exclude_actions_parameter(),
+            ], &[
+                ("200", "Buckets, oldest first", schema::<Vec<PlatformBucket>>(&mut generator)),
+                ("400", "Unknown granularity or weight", text()),
+            ]),
+        },
+        "/api/v1/calendar.png": {
+            "get": operation("Heatmap of daily counts as PNG, one column per week ending with the current one", &[
+                query_parameter("weeks", "Number of weeks", json!({"type": "integer", "minimum": 1, "maximum": MAX_CALENDAR_WEEKS, "default": DEFAULT_CALENDAR_WEEKS})),
+                query_parameter("cell", "Size of a day in pixels", json!({"type": "integer", "minimum": MIN_CALENDAR_CELL, "maximum": MAX_CALENDAR_CELL, "default": DEFAULT_CALENDAR_CELL})),
+                weight_parameter(),
+            ], &[
+                ("200", "Calendar", png()),
+                ("400", "Unknown weight", text()),
+            ]),
+        },
+        "/api/v1/badge.png": {
+            "get": operation("Badge with the number of events since a day as PNG", &[
+                since_parameter(),
+                weight_parameter(),
+                query_parameter("scale", "Multiplies the 20 pixel height", json!({"type": "integer", "minimum": 1, "maximum": MAX_BADGE_SCALE, "default": 1})),
+            ], &[
+                ("200", "Badge", png()),
+                ("400", "Unknown weight", text()),
+            ]),
+        },
+        "/api/v1/stats/records": {
+            "get": operation("All-time records", &[], &[
+                ("200", "Records by name", schema::<StatRecords>(&mut generator)),
+            ]),
+        },
+        "/api/v1/stats/records/rebuild": {
+            "post": admin(operation("Recompute all records from scratch", &[], &[
+                ("200", "Records by name", schema::<StatRecords>(&mut generator)),
+            ])),
+        },
+        "/api/v1/sync/runs": {
+            "get": operation("Past sync runs, newest first", &[
+                query_parameter(
+                    "limit",
+                    "Number of runs to return",
+                    json!({"type": "integer", "minimum": 0, "default": DEFAULT_SYNC_RUNS_LIMIT, "maximum": MAX_SYNC_RUNS_LIMIT}),
+                ),
+            ], &[
+                ("200", "Sync runs", schema::<Vec<SyncRun>>(&mut generator)),
+            ]),
+        },
+        "/api/v1/sync/status": {
+            "get": operation("Every platform compiled into this build and whether it's synced", &[], &[
+                ("200", "State per platform: enabled, disabled or not_configured, with its next scheduled sync", schema::<Vec<PlatformStatus>>(&mut generator)),
+            ]),
+        },
+        "/api/v1/sync/credentials": {
+            "get": operation("Tokens validated at startup, with their expiry", &[], &[
+                ("200", "Token per account", schema::<Vec<CredentialStatus>>(&mut generator)),
+            ]),
+        },
+        "/api/v1/sync/jobs/{id}": {
+            "get": operation("State of a job started by an asynchronous force-sync", &[
+                json!({"name": "id", "in": "path", "required": true, "schema": {"type": "string", "format": "uuid"}}),
+            ], &[
+                ("200", "Job", schema::<SyncJob>(&mut generator)),
+                ("404", "Unknown or expired job", Value::Null),
+            ]),
+        },
+        "/api/v1/force-sync": {
+            "get": admin(operation("Sync now", &[
+                query_parameter("platform", "Only sync this platform (case-insensitive)", json!({"type": "string", "example": "Github"})),
+                query_parameter("async", "Answer with 202 and a job right away", json!({"type": "boolean", "default": false})),
+                query_parameter("dry_run", "Only list the platforms which would be synced", json!({"type": "boolean", "default": false})),
+            ], &[
+                ("200", "Report per platform, or the platforms for a dry run", schema::<Vec<SyncReport>>(&mut generator)),
+                ("202", "Job started (or already running)", schema::<SyncJob>(&mut generator)),
+                ("400", "Unknown platform", text()),
+            ])),
+        },
+        "/api/v1/admin/archive/reprocess": {
+            "post": admin(operation("Run the archived events through the mapping again, inserting what isn't stored yet", &[
+                query_parameter("platform", "Only reprocess this platform (case-insensitive)", json!({"type": "string", "example": "Github"})),
+            ], &[
+                ("200", "Report per platform, archived events count as fetched", schema::<Vec<SyncReport>>(&mut generator)),
+                ("400", "Unknown platform", text()),
+            ])),
+        },
+        "/api/v1/admin/events": {
+            "delete": admin(operation("Delete all events between two days (both inclusive)", &[
+                required_date_parameter("since"),
+                required_date_parameter("until"),
+                query_parameter("platform", "Only purge events of this platform (case-insensitive)", json!({"type": "string", "example": "Github"})),
+                query_parameter("dry_run", "Only count the events which would be deleted", json!({"type": "boolean", "default": false})),
+            ], &[
+                ("200", "Number of (matching) deleted events", schema::<PurgeReport>(&mut generator)),
+                ("400", "Missing or invalid range or unknown platform", text()),
+            ])),
+        },
+        "/api/v1/admin/export": {
+            "get": admin(operation("Stream all platforms, actions, projects and events as one versioned JSON document", &[], &[
+                ("200", "Export, described by its own `format`, `version` and `sections` fields", json!({"application/json": {"schema": {"type": "object"}}})),
+            ])),
+        },
+        "/api/v1/admin/import": {
+            "post": admin(with_json_body(operation("Import an export, skipping rows which already exist", &[], &[
+                ("200", "Created and skipped rows per table", schema::<ImportSummary>(&mut generator)),
+                ("400", "Invalid export, unsupported version or unknown platform", text()),
+                ("413", "Export exceeds the import limit", text()),
+            ]))),
+        },
+        "/api/v1/ingest": {
+            "post": admin(with_body(
+                operation("Insert events of platforms pollux doesn't sync itself, validating every entry on its own", &[], &[
+                    ("200", "Created and skipped events, with the index and error of every rejected entry", schema::<IngestReport>(&mut generator)),
+                    ("400", "Body isn't a JSON array", text()),
+                ]),
+                schema::<Vec<IngestEvent>>(&mut generator),
+            )),
+        },
+        "/api/v1/admin/projects/{id}": {
+            "delete": admin(operation("Delete a project with all its events", &[path_parameter("id")], &[
+                ("200", "Affected rows", schema::<DeleteReport>(&mut generator)),
+                ("404", "Unknown project", text()),
+            ])),
+        },
+        "/api/v1/admin/projects/{id}/merge-into/{target_id}": {
+            "post": admin(operation("Move all events of a project to another one of the same platform, then delete it", &[
+                path_parameter("id"),
+                path_parameter("target_id"),
+            ], &[
+                ("200", "Affected rows", schema::<MergeReport>(&mut generator)),
+                ("404", "Unknown project", text()),
+                ("409", "Same project or projects of different platforms", text()),
+            ])),
+        },
+        "/api/v1/projects/{id}": {
+            "get": operation("Project metadata with first/last event, event counts per action and the most recent events", &[
+                path_parameter("id"),
+                anonymize_parameter(),
+            ], &[
+                ("200", "Project detail", schema::<ProjectDetail>(&mut generator)),
+                ("400", "Anonymization requested without a configured secret", Value::Null),
+                ("404", "Unknown project", Value::Null),
+            ]),
+        },
+        "/api/v2/git-events": {
+            "get": operation("Events with embedded project and action, paginated", &[
+                since_parameter(),
+                query_parameter("limit", "Page size, at most 1000", json!({"type": "integer", "minimum": 1, "maximum": 1000, "default": 100})),
+                query_parameter("offset", "Number of events to skip", json!({"type": "integer", "minimum": 0, "default": 0})),
+                anonymize_parameter(),
+            ], &[
+                ("200", "One page of events, oldest first", schema::<Page<EventV2>>(&mut generator)),
+                ("400", "Anonymization requested without a configured secret", Value::Null),
+            ]),
+        },
+        "/api/v1/openapi.json": {
+            "get": operation("This document", &[], &[
+                ("200", "OpenAPI document", json!({"application/json": {"schema": {"type": "object"}}})),
+            ]),
+        },
+    });
+    // Calendar backfills only exist for the platforms compiled into this build
+    #[cfg(feature = "github")]
+    {
+        paths["/api/v1/admin/backfill/github"] = json!({
+            "post": admin(operation("Import the Github contribution calendar as per-day counts, reaching further back than its events", &[
+                query_parameter("since", "First day to import, defaults to January 1st of last year", json!({"type": "string", "format": "date"})),
+                query_parameter("until", "Last day to import, defaults to and is capped at today", json!({"type": "string", "format": "date"})),
+            ], &[
+                ("200", "Imported days and their contributions", schema::<BackfillReport>(&mut generator)),
+                ("400", "Invalid range or Github not configured", text()),
+                ("502", "Github couldn't answer the contributions query", text()),
+            ])),
+        });
+    }
+    #[cfg(feature = "gitlab")]
+    {
+        paths["/api/v1/admin/backfill/gitlab"] = json!({
+            "post": admin(operation("Import the Gitlab contribution calendar as per-day counts, Gitlab only keeps it for the last year", &[
+                query_parameter("since", "First day to import, defaults to January 1st of last year", json!({"type": "string", "format": "date"})),
+                query_parameter("until", "Last day to import, defaults to and is capped at today", json!({"type": "string", "format": "date"})),
+            ], &[
+                ("200", "Imported days and their contributions", schema::<BackfillReport>(&mut generator)),
+                ("400", "Invalid range or Gitlab not configured", text()),
+                ("502", "Gitlab couldn't answer the calendar request", text()),
+            ])),
+        });
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "pollux",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": generator.take_definitions(),
+            "securitySchemes": {
+                "adminToken": {"type": "http", "scheme": "bearer"},
+            },
+        },
+    })
+}
+
+/// Registers the schema of `T` and returns a json response content referencing it
+fn schema<T: JsonSchema>(generator: &mut SchemaGenerator) -> Value {
+    json!({"application/json": {"schema": generator.subschema_for::<T>()}})
+}
+
+fn text() -> Value {
+    json!({"text/plain": {"schema": {"type": "string"}}})
+}
+
+fn png() -> Value {
+    json!({"image/png": {"schema": {"type": "string", "format": "binary"}}})
+}
+
+fn query_parameter(name: &str, description: &str, schema: Value) -> Value {
+    json!({"name": name, "in": "query", "required": false, "description": description, "schema": schema})
+}
+
+fn weight_parameter() -> Value {
+    query_parameter(
+        "weight",
+        "`commits` counts push events with their number of commits instead of once",
+        json!({"type": "string", "enum": ["events", "commits"], "default": "events"}),
+    )
+}
+
+fn exclude_actions_parameter() -> Value {
+    query_parameter(
+        "exclude_actions",
+        "Comma separated actions to leave out of the events, e.g. `starred,forked`",
+        json!({"type": "string", "example": "starred,forked"}),
+    )
+}
+
+fn anonymize_parameter() -> Value {
+    query_parameter(
+        "anonymize",
+        "Replace project names by stable aliases and omit urls, always on with `POLLUX_ANONYMIZE_PROJECTS`",
+        json!({"type": "boolean", "default": false}),
+    )
+}
+
+fn required_date_parameter(name: &str) -> Value {
+    json!({"name": name, "in": "query", "required": true, "schema": {"type": "string", "format": "date"}})
+}
+
+fn path_parameter(name: &str) -> Value {
+    json!({"name": name, "in": "path", "required": true, "schema": {"type": "integer", "minimum": 0}})
+}
+
+fn since_parameter() -> Value {
+    query_parameter(
+        "since",
+        "First day to include, defaults to 30 days ago",
+        json!({"type": "string", "format": "date", "example": "2024-05-01"}),
+    )
+}
+
+fn operation(summary: &str, parameters: &[Value], responses: &[(&str, &str, Value)]) -> Value {
+    let responses: Map<String, Value> = responses
+        .iter()
+        .map(|(status, description, content)| {
+            let mut response = json!({"description": description});
+            if !content.is_null() {
+                response["content"] = content.clone();
+            }
+            (status.to_string(), response)
+        })
+        .collect();
+
+    json!({"summary": summary, "parameters": parameters, "responses": responses})
+}
+
+fn with_json_body(mut operation: Value) -> Value {
+    operation["requestBody"] = json!({"required": true, "content": {"application/json": {"schema": {"type": "object"}}}});
+    operation
+}
+
+fn with_body(mut operation: Value, content: Value) -> Value {
+    operation["requestBody"] = json!({"required": true, "content": content});
+    operation
+}
+
+fn admin(mut operation: Value) -> Value {
+    operation["security"] = json!([{"adminToken": []}]);
+    operation["responses"]["401"] = json!({"description": "Missing admin token"});
+    operation["responses"]["403"] = json!({"description": "Invalid admin token, or no token configured outside dev mode"});
+    operation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_paths_are_documented() {
+        let spec: Value = serde_json::from_str(&spec().to_string()).unwrap();
+
+        for path in [
+            "/health",
+            "/livez",
+            "/readyz",
+            "/api/v1/git-events",
+            "/api/v1/force-sync",
+            "/api/v1/stats/daily",
+            "/api/v1/sync/runs",
+            "/api/v1/sync/status",
+            "/api/v1/sync/credentials",
+            "/api/v1/version",
+        ] {
+            assert!(spec["paths"][path].is_object(), "{} is missing", path);
+        }
+    }
+
+    #[test]
+    fn query_parameters_are_documented_with_formats() {
+        let spec = spec();
+
+        let since = &spec["paths"]["/api/v1/git-events"]["get"]["parameters"][0];
+        assert_eq!(since["name"], "since");
+        assert_eq!(since["schema"]["format"], "date");
+
+        let limit = &spec["paths"]["/api/v1/sync/runs"]["get"]["parameters"][0];
+        assert_eq!(limit["schema"]["maximum"], MAX_SYNC_RUNS_LIMIT);
+    }
+
+    #[test]
+    fn response_schemas_are_resolvable() {
+        let spec = spec();
+        let schemas = &spec["components"]["schemas"];
+
+        for name in ["HealthResponse", "GitEvents", "SyncReport", "SyncJob", "DailyCount", "IngestEvent", "IngestReport"] {
+            assert!(schemas[name].is_object(), "{} is missing", name);
+        }
+        assert_eq!(
+            spec["paths"]["/health"]["get"]["responses"]["200"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/HealthResponse"
+        );
+    }
+}
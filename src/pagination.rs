@@ -0,0 +1,269 @@
+use std::{collections::HashSet, fmt};
+
+use log::warn;
+use reqwest::{header::HeaderMap, Url};
+
+use crate::config;
+
+static FALLBACK_MAX_PAGES: usize = 100;
+/// Set by Azure DevOps while there are more results, passed back as `continuationToken`
+pub static CONTINUATION_TOKEN_HEADER: &str = "x-ms-continuationtoken";
+
+/// Link headers longer than this are not parsed at all
+#[cfg(any(feature = "github", feature = "gitlab"))]
+pub static MAX_LINK_HEADER_LENGTH: usize = 8 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaginationError {
+    /// The next page points to a page we already fetched during this run
+    Cycle(String),
+    /// More pages than allowed by `POLLUX_MAX_PAGES`
+    PageLimit(usize),
+    /// The link header exceeded `MAX_LINK_HEADER_LENGTH`
+    #[cfg(any(feature = "github", feature = "gitlab"))]
+    OversizedHeader(usize),
+    /// Still rejected with this status after `retries` rate-limited retries
+    RateLimited { status: u16, retries: u32 },
+    /// One of several accounts couldn't be fetched, the others were
+    #[cfg(feature = "github")]
+    AccountFailed { account: String, error: String },
+}
+
+impl fmt::Display for PaginationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaginationError::Cycle(url) => {
+                write!(f, "pagination cycle detected, next page {} was already fetched", url)
+            }
+            PaginationError::PageLimit(max_pages) => {
+                write!(f, "page limit of {} pages reached", max_pages)
+            }
+            #[cfg(any(feature = "github", feature = "gitlab"))]
+            PaginationError::OversizedHeader(length) => write!(
+                f,
+                "link header is {} bytes long, refusing to parse more than {} bytes",
+                length, MAX_LINK_HEADER_LENGTH
+            ),
+            PaginationError::RateLimited { status, retries } => {
+                write!(f, "still rate limited (HTTP {}) after {} retries", status, retries)
+            }
+            #[cfg(feature = "github")]
+            PaginationError::AccountFailed { account, error } => {
+                write!(f, "events of account {} couldn't be fetched: {}", account, error)
+            }
+        }
+    }
+}
+
+/// Events fetched from a platform, plus the reason if we had to stop early
+#[derive(Debug)]
+pub struct FetchedEvents<T> {
+    pub events: Vec<T>,
+    pub truncated: Option<PaginationError>,
+}
+
+impl<T> FetchedEvents<T> {
+    pub fn complete(events: Vec<T>) -> Self {
+        FetchedEvents {
+            events,
+            truncated: None,
+        }
+    }
+
+    pub fn truncated(events: Vec<T>, reason: PaginationError) -> Self {
+        warn!("Stopped fetching early: {}", reason);
+        FetchedEvents {
+            events,
+            truncated: Some(reason),
+        }
+    }
+}
+
+/// Keeps track of the pages fetched during a single run, so a misbehaving server
+/// can't keep us paginating forever.
+/// At most `max_pages` urls are remembered, so memory stays bounded.
+#[derive(Debug)]
+pub struct PaginationGuard {
+    visited: HashSet<String>,
+    max_pages: usize,
+}
+
+impl PaginationGuard {
+    pub fn new(max_pages: usize) -> Self {
+        PaginationGuard {
+            visited: HashSet::new(),
+            max_pages,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let max_pages = match config::var("POLLUX_MAX_PAGES")
+            .unwrap_or(FALLBACK_MAX_PAGES.to_string())
+            .parse::<usize>()
+        {
+            Ok(result) if result > 0 => result,
+            Ok(_) | Err(_) => {
+                warn!(
+                    "Unable to parse POLLUX_MAX_PAGES as a positive integer, using »{}« as a fallback",
+                    FALLBACK_MAX_PAGES
+                );
+                FALLBACK_MAX_PAGES
+            }
+        };
+        PaginationGuard::new(max_pages)
+    }
+
+    /// Registers the url of the page which is about to be fetched
+    pub fn visit(&mut self, url: &str) -> Result<(), PaginationError> {
+        if self.visited.contains(url) {
+            return Err(PaginationError::Cycle(url.to_string()));
+        }
+        if self.visited.len() >= self.max_pages {
+            return Err(PaginationError::PageLimit(self.max_pages));
+        }
+
+        self.visited.insert(url.to_string());
+        Ok(())
+    }
+}
+
+// Parse header like:
+// < link: <https://api.github.com/user/26086452/events?per_page=2&page=2>; rel="next", <https://api.github.com/user/26086452/events?per_page=2&page=6>; rel="last"
+#[cfg(any(feature = "github", feature = "gitlab"))]
+pub fn parse_header_for_next_page(header: &str) -> Result<Option<String>, PaginationError> {
+    if header.len() > MAX_LINK_HEADER_LENGTH {
+        return Err(PaginationError::OversizedHeader(header.len()));
+    }
+
+    for link in header.split(",") {
+        let parts: Vec<&str> = link.split(";").collect();
+
+        if parts.len() != 2 {
+            continue;
+        }
+
+        let rel_part = parts[1].trim();
+        if rel_part != r#"rel="next""# {
+            continue;
+        }
+
+        let url_part = parts[0].trim();
+        if !(url_part.starts_with("<") && url_part.ends_with(">")) {
+            continue;
+        }
+
+        return Ok(Some(url_part[1..url_part.len() - 1].to_string()));
+    }
+
+    Ok(None)
+}
+
+/// Token to fetch the following page with, `None` on the last one
+pub fn continuation_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(CONTINUATION_TOKEN_HEADER)?
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+}
+
+/// `url` with the query parameter `name` set to `value`, replacing an earlier value
+pub fn with_query_param(url: &str, name: &str, value: &str) -> Result<String, String> {
+    let mut url = Url::parse(url).map_err(|err| format!("»{}« isn't a valid url: {}", url, err))?;
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key != name)
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut().clear().extend_pairs(pairs).append_pair(name, value);
+    Ok(url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[cfg(any(feature = "github", feature = "gitlab"))]
+    #[test]
+    fn next_page_is_parsed_from_link_header() {
+        let header = r#"<https://api.github.com/user/26086452/events?per_page=2&page=2>; rel="next", <https://api.github.com/user/26086452/events?per_page=2&page=6>; rel="last""#;
+        assert_eq!(
+            parse_header_for_next_page(header),
+            Ok(Some(
+                "https://api.github.com/user/26086452/events?per_page=2&page=2".to_string()
+            ))
+        );
+    }
+
+    #[cfg(any(feature = "github", feature = "gitlab"))]
+    #[test]
+    fn last_page_has_no_next_page() {
+        let header = r#"<https://api.github.com/user/26086452/events?per_page=2&page=1>; rel="first""#;
+        assert_eq!(parse_header_for_next_page(header), Ok(None));
+    }
+
+    #[cfg(any(feature = "github", feature = "gitlab"))]
+    #[test]
+    fn oversized_link_header_is_rejected() {
+        let header = format!(
+            r#"<https://api.github.com/{}>; rel="next""#,
+            "a".repeat(MAX_LINK_HEADER_LENGTH)
+        );
+        assert!(matches!(
+            parse_header_for_next_page(&header),
+            Err(PaginationError::OversizedHeader(_))
+        ));
+    }
+
+    #[test]
+    fn revisiting_a_page_is_a_cycle() {
+        let mut guard = PaginationGuard::new(10);
+        assert!(guard.visit("https://example.com?page=1").is_ok());
+        assert!(guard.visit("https://example.com?page=2").is_ok());
+        assert_eq!(
+            guard.visit("https://example.com?page=1"),
+            Err(PaginationError::Cycle("https://example.com?page=1".to_string()))
+        );
+    }
+
+    #[test]
+    fn page_limit_bounds_the_visited_set() {
+        let mut guard = PaginationGuard::new(3);
+        for page in 1..=3 {
+            assert!(guard.visit(&format!("https://example.com?page={}", page)).is_ok());
+        }
+        assert_eq!(
+            guard.visit("https://example.com?page=4"),
+            Err(PaginationError::PageLimit(3))
+        );
+        assert_eq!(guard.visited.len(), 3);
+    }
+
+    #[test]
+    fn continuation_token_is_read_from_the_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(continuation_token(&headers), None);
+
+        headers.insert(CONTINUATION_TOKEN_HEADER, HeaderValue::from_static(" "));
+        assert_eq!(continuation_token(&headers), None);
+
+        headers.insert(CONTINUATION_TOKEN_HEADER, HeaderValue::from_static("a1b2;c3"));
+        assert_eq!(continuation_token(&headers), Some("a1b2;c3".to_string()));
+    }
+
+    #[test]
+    fn query_param_is_replaced() {
+        assert_eq!(
+            with_query_param("https://dev.azure.com/org/_apis/projects?$top=2", "continuationToken", "a1b2;c3"),
+            Ok("https://dev.azure.com/org/_apis/projects?%24top=2&continuationToken=a1b2%3Bc3".to_string())
+        );
+        assert_eq!(
+            with_query_param("https://dev.azure.com/org/_apis/projects?$skip=2&$top=2", "$skip", "4"),
+            Ok("https://dev.azure.com/org/_apis/projects?%24top=2&%24skip=4".to_string())
+        );
+        assert!(with_query_param("not a url", "$skip", "4").is_err());
+    }
+}
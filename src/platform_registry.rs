@@ -0,0 +1,503 @@
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use rocket::futures::future::join_all;
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+#[cfg(feature = "github")]
+use crate::github::Github;
+#[cfg(feature = "gitlab")]
+use crate::gitlab::Gitlab;
+use crate::{
+    azure_devops::AzureDevops,
+    bitbucket::Bitbucket,
+    config::{self, ConfigError},
+    credentials::CredentialCheck,
+    find_platform,
+    git_platform::{self, GitPlatform},
+    gitea::{Codeberg, Gitea},
+    local_git::LocalGit,
+    mock_platform::MockPlatform,
+    panic_message,
+    project_refresh::RefreshReport,
+    sourcehut::Sourcehut,
+    sync_runs::SyncReport,
+    KNOWN_PLATFORMS,
+};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Object-safe wrapper around the async methods of a platform, so the sync loop can
+/// handle all of them alike instead of naming every `GitPlatform` on its own
+pub trait PlatformRunner: Send {
+    /// `git_platform::sync_and_record`, boxed
+    fn sync(&mut self) -> BoxFuture<'_, SyncReport>;
+
+    /// One check per account, see `credentials::validate_all`
+    fn check_credentials(&mut self) -> BoxFuture<'_, Vec<CredentialCheck>>;
+
+    /// `event_archive::reprocess`, boxed
+    fn reprocess(&mut self) -> BoxFuture<'_, SyncReport>;
+
+    /// `project_refresh::refresh`, boxed. `None` for platforms which can't look up their projects.
+    fn refresh_projects(&mut self) -> BoxFuture<'_, Option<RefreshReport>> {
+        Box::pin(async { None })
+    }
+
+    /// Syncs the shared platform, which stays locked for the whole sync by default. Platforms
+    /// syncing a copy of themselves instead only lock it to take the copy and to update it after.
+    fn sync_shared(runner: Arc<Mutex<Self>>) -> BoxFuture<'static, SyncReport>
+    where
+        Self: Sized + 'static,
+    {
+        Box::pin(async move { runner.lock().await.sync().await })
+    }
+
+    /// Refreshes the projects of the shared platform, like `sync_shared`
+    fn refresh_shared(runner: Arc<Mutex<Self>>) -> BoxFuture<'static, Option<RefreshReport>>
+    where
+        Self: Sized + 'static,
+    {
+        Box::pin(async move { runner.lock().await.refresh_projects().await })
+    }
+}
+
+/// `PlatformRunner::sync_shared` of a registered platform
+type SharedSync = Arc<dyn Fn() -> BoxFuture<'static, SyncReport> + Send + Sync>;
+/// `PlatformRunner::refresh_shared` of a registered platform
+type SharedRefresh = Arc<dyn Fn() -> BoxFuture<'static, Option<RefreshReport>> + Send + Sync>;
+
+#[derive(Clone)]
+struct RegisteredPlatform {
+    name: &'static str,
+    runner: Arc<Mutex<dyn PlatformRunner>>,
+    sync: SharedSync,
+    refresh: SharedRefresh,
+    /// Held while syncing, the runner itself might not be locked meanwhile
+    syncing: Arc<Mutex<()>>,
+}
+
+/// Whether a platform compiled into this build is synced
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PlatformState {
+    Enabled,
+    /// Left out of `POLLUX_PLATFORMS`
+    Disabled,
+    /// Its env vars aren't set
+    NotConfigured,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct PlatformStatus {
+    pub platform: &'static str,
+    pub state: PlatformState,
+    /// When it's synced next, once it's scheduled, see `sync_schedule`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+/// The configured platforms, initialized once at startup and managed by Rocket
+#[derive(Clone, Default)]
+pub struct PlatformRegistry {
+    platforms: Vec<RegisteredPlatform>,
+    statuses: Vec<PlatformStatus>,
+    next_runs: Arc<std::sync::Mutex<HashMap<&'static str, DateTime<Utc>>>>,
+}
+
+impl PlatformRegistry {
+    /// Initializes the platforms compiled into this build, leaving out the ones which aren't configured.
+    /// `POLLUX_PLATFORMS` selects platforms explicitly, every one of them has to be configured then.
+    /// What's wrong with the configuration of any platform is reported at once.
+    pub fn from_env() -> Result<PlatformRegistry, ConfigError> {
+        let selection = match config::var("POLLUX_PLATFORMS") {
+            Some(input) if !input.trim().is_empty() => {
+                Some(parse_selection(&input).map_err(|err| ConfigError::invalid("POLLUX_PLATFORMS", err))?)
+            }
+            _ => None,
+        };
+        let selection = selection.as_deref();
+
+        let mut registry = PlatformRegistry::default();
+        let mut errors = ConfigError::default();
+        let results = [
+            #[cfg(feature = "github")]
+            registry.register_selected(selection, Github::GIT_PLATFORM_ID, Github::get_or_init),
+            #[cfg(feature = "gitlab")]
+            registry.register_selected(selection, Gitlab::GIT_PLATFORM_ID, Gitlab::get_or_init),
+            registry.register_selected(selection, Gitea::GIT_PLATFORM_ID, Gitea::get_or_init),
+            registry.register_selected(selection, Codeberg::GIT_PLATFORM_ID, Codeberg::get_or_init),
+            registry.register_selected(selection, Bitbucket::GIT_PLATFORM_ID, Bitbucket::get_or_init),
+            registry.register_selected(selection, Sourcehut::GIT_PLATFORM_ID, Sourcehut::get_or_init),
+            registry.register_selected(selection, AzureDevops::GIT_PLATFORM_ID, AzureDevops::get_or_init),
+            registry.register_selected(selection, LocalGit::GIT_PLATFORM_ID, LocalGit::get_or_init),
+            registry.register_selected(selection, MockPlatform::GIT_PLATFORM_ID, MockPlatform::get_or_init),
+        ];
+        for result in results {
+            errors.merge(result.err().unwrap_or_default());
+        }
+
+        if errors.is_empty() {
+            registry.ensure_not_empty().map_err(ConfigError::other)?;
+            Ok(registry)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Only initializes the platform if it's selected (or nothing is selected explicitly)
+    fn register_selected<P: PlatformRunner + 'static>(
+        &mut self,
+        selection: Option<&[&'static str]>,
+        name: &'static str,
+        init: impl FnOnce() -> Result<Option<Arc<Mutex<P>>>, ConfigError>,
+    ) -> Result<(), ConfigError> {
+        if selection.is_some_and(|selected| !selected.contains(&name)) {
+            info!("{} disabled by POLLUX_PLATFORMS, skipping", name);
+            self.statuses.push(PlatformStatus {
+                platform: name,
+                state: PlatformState::Disabled,
+                next_run: None,
+            });
+            return Ok(());
+        }
+
+        match init()? {
+            Some(runner) => self.register(name, runner),
+            None if selection.is_some() => {
+                return Err(ConfigError::other(format!(
+                    "{} is listed in POLLUX_PLATFORMS, but not configured!",
+                    name
+                )));
+            }
+            None => {
+                info!("{} not configured, skipping", name);
+                self.statuses.push(PlatformStatus {
+                    platform: name,
+                    state: PlatformState::NotConfigured,
+                    next_run: None,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Platforms are synced and reported in the order they were registered
+    pub fn register<P: PlatformRunner + 'static>(&mut self, name: &'static str, runner: Arc<Mutex<P>>) {
+        let (shared, refreshed) = (runner.clone(), runner.clone());
+        self.platforms.push(RegisteredPlatform {
+            name,
+            runner,
+            sync: Arc::new(move || P::sync_shared(shared.clone())),
+            refresh: Arc::new(move || P::refresh_shared(refreshed.clone())),
+            syncing: Arc::new(Mutex::new(())),
+        });
+        self.statuses.push(PlatformStatus {
+            platform: name,
+            state: PlatformState::Enabled,
+            next_run: None,
+        });
+    }
+
+    /// Nothing would ever be synced without a single platform, so pollux refuses to start
+    fn ensure_not_empty(&self) -> Result<(), String> {
+        if self.platforms.is_empty() {
+            return Err(format!(
+                "No platform is configured! Set the env vars of at least one of {}, see .env.template",
+                KNOWN_PLATFORMS.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// Every platform compiled into this build, including the ones which aren't synced
+    pub fn statuses(&self) -> Vec<PlatformStatus> {
+        let next_runs = self.next_runs.lock().unwrap();
+        self.statuses
+            .iter()
+            .map(|status| PlatformStatus {
+                next_run: next_runs.get(status.platform).copied(),
+                ..status.clone()
+            })
+            .collect()
+    }
+
+    /// Shared between clones, like the runners
+    pub fn set_next_run(&self, platform: &'static str, next_run: DateTime<Utc>) {
+        self.next_runs.lock().unwrap().insert(platform, next_run);
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.platforms.iter().map(|platform| platform.name).collect()
+    }
+
+    /// Syncs the registered platforms out of `platforms` concurrently. Every platform runs in
+    /// its own task, so a panic doesn't take down the others (or the cron job).
+    pub async fn sync(&self, platforms: &[&str]) -> Vec<SyncReport> {
+        let started_at = Utc::now();
+
+        let syncs = self
+            .platforms
+            .iter()
+            .filter(|platform| platforms.contains(&platform.name))
+            .map(|platform| async move {
+                let (sync, syncing) = (platform.sync.clone(), platform.syncing.clone());
+                let sync = tokio::spawn(async move {
+                    let _syncing = syncing.lock().await;
+                    sync().await
+                });
+                match sync.await {
+                    Ok(report) => report,
+                    Err(err) => {
+                        let message = panic_message(err);
+                        error!("Syncing {} failed: {}", platform.name, message);
+                        git_platform::record_sync_run(platform.name, started_at, Err(message)).await
+                    }
+                }
+            });
+        join_all(syncs).await
+    }
+
+    /// Reprocesses the archived events of the registered platforms out of `platforms`, like `sync`.
+    /// Waits for running syncs of a platform, so both don't insert the same events at once.
+    pub async fn reprocess(&self, platforms: &[&str]) -> Vec<SyncReport> {
+        let started_at = Utc::now();
+
+        let reprocessing = self
+            .platforms
+            .iter()
+            .filter(|platform| platforms.contains(&platform.name))
+            .map(|platform| async move {
+                let (runner, syncing) = (platform.runner.clone(), platform.syncing.clone());
+                let reprocess = tokio::spawn(async move {
+                    let _syncing = syncing.lock().await;
+                    runner.lock().await.reprocess().await
+                });
+                match reprocess.await {
+                    Ok(report) => report,
+                    Err(err) => {
+                        let message = panic_message(err);
+                        error!("Reprocessing {} failed: {}", platform.name, message);
+                        SyncReport::from(&git_platform::sync_run(platform.name, started_at, Err(message)))
+                    }
+                }
+            });
+        join_all(reprocessing).await
+    }
+
+    /// Refreshes the projects of `platform` which are due, in its own task like `sync`
+    pub async fn refresh_projects(&self, platform: &str) -> Option<RefreshReport> {
+        let registered = self.platforms.iter().find(|registered| registered.name == platform)?;
+        let refresh = registered.refresh.clone();
+        match tokio::spawn(async move { refresh().await }).await {
+            Ok(report) => report,
+            Err(err) => {
+                error!("Refreshing the projects of {} failed: {}", platform, panic_message(err));
+                None
+            }
+        }
+    }
+
+    /// Waits for running syncs, no platform starts a new one until the guards are dropped
+    pub async fn pause_syncs(&self) -> Vec<OwnedMutexGuard<()>> {
+        let mut paused = Vec::new();
+        for platform in &self.platforms {
+            paused.push(platform.syncing.clone().lock_owned().await);
+        }
+        paused
+    }
+
+    pub async fn check_credentials(&self) -> Vec<CredentialCheck> {
+        let mut checks = Vec::new();
+        for platform in &self.platforms {
+            checks.extend(platform.runner.lock().await.check_credentials().await);
+        }
+        checks
+    }
+}
+
+/// Comma-separated and case-insensitive, e.g. `gitlab,codeberg`
+pub fn parse_selection(input: &str) -> Result<Vec<&'static str>, String> {
+    input
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| find_platform(name).map_err(|err| format!("Invalid POLLUX_PLATFORMS: {}", err)))
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Answers every sync with an empty report, without touching the network or the database
+    pub(crate) struct FakePlatform {
+        name: &'static str,
+        pub(crate) syncs: u32,
+        pub(crate) reprocessed: u32,
+    }
+
+    impl FakePlatform {
+        fn report(&self) -> SyncReport {
+            SyncReport {
+                platform: self.name.to_string(),
+                events_fetched: 0,
+                events_inserted: 0,
+                skipped_unknown_action: 0,
+                skipped_duplicates: 0,
+                skipped_project_errors: 0,
+                skipped_expired: 0,
+                skipped_other: 0,
+                duration_ms: 0,
+                truncated: false,
+                error: None,
+                rate_limited: false,
+                rate_limit_remaining: None,
+                rate_limit_reset_at: None,
+            }
+        }
+    }
+
+    impl PlatformRunner for FakePlatform {
+        fn sync(&mut self) -> BoxFuture<'_, SyncReport> {
+            self.syncs += 1;
+            Box::pin(async { self.report() })
+        }
+
+        fn check_credentials(&mut self) -> BoxFuture<'_, Vec<CredentialCheck>> {
+            Box::pin(async { Vec::new() })
+        }
+
+        fn reprocess(&mut self) -> BoxFuture<'_, SyncReport> {
+            self.reprocessed += 1;
+            Box::pin(async { self.report() })
+        }
+    }
+
+    pub(crate) fn fake_registry(names: &[&'static str]) -> PlatformRegistry {
+        let mut registry = PlatformRegistry::default();
+        for name in names {
+            registry.register(name, Arc::new(Mutex::new(FakePlatform { name, syncs: 0, reprocessed: 0 })));
+        }
+        registry
+    }
+
+    #[tokio::test]
+    async fn all_requested_platforms_are_synced_in_order() {
+        let registry = fake_registry(&["Github", "Gitlab"]);
+
+        let reports = registry.sync(&["Gitlab", "Github"]).await;
+        let platforms: Vec<&str> = reports.iter().map(|report| report.platform.as_str()).collect();
+        assert_eq!(platforms, vec!["Github", "Gitlab"]);
+    }
+
+    #[tokio::test]
+    async fn unregistered_platforms_are_skipped() {
+        let registry = fake_registry(&["Github", "Gitlab"]);
+
+        let reports = registry.sync(&["Gitlab", "Gitea"]).await;
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].platform, "Gitlab");
+        assert!(registry.sync(&[]).await.is_empty());
+    }
+
+    #[test]
+    fn an_empty_registry_is_refused() {
+        let error = fake_registry(&[]).ensure_not_empty().unwrap_err();
+        assert!(error.contains("No platform is configured"), "{}", error);
+        assert!(fake_registry(&["Gitlab"]).ensure_not_empty().is_ok());
+    }
+
+    pub(crate) fn fake(name: &'static str) -> Option<Arc<Mutex<FakePlatform>>> {
+        Some(Arc::new(Mutex::new(FakePlatform { name, syncs: 0, reprocessed: 0 })))
+    }
+
+    /// Like leaving `name` out of `POLLUX_PLATFORMS`
+    pub(crate) fn disable(registry: &mut PlatformRegistry, name: &'static str) {
+        registry.register_selected(Some(&[]), name, || Ok(fake(name))).unwrap();
+    }
+
+    #[test]
+    fn selection_is_parsed_case_insensitive() {
+        assert_eq!(parse_selection("gitea, CODEBERG,"), Ok(vec!["Gitea", "Codeberg"]));
+
+        let error = parse_selection("gitea,launchpad").unwrap_err();
+        assert!(error.contains("»launchpad«"), "{}", error);
+        assert!(error.contains("Known platforms are: "), "{}", error);
+    }
+
+    #[test]
+    fn only_selected_platforms_are_initialized() {
+        let selection: &[&str] = &["Codeberg"];
+        let mut registry = PlatformRegistry::default();
+
+        registry
+            .register_selected(Some(selection), "Gitea", || -> Result<Option<Arc<Mutex<FakePlatform>>>, ConfigError> {
+                panic!("Gitea isn't selected")
+            })
+            .unwrap();
+        registry.register_selected(Some(selection), "Codeberg", || Ok(fake("Codeberg"))).unwrap();
+
+        assert_eq!(registry.names(), vec!["Codeberg"]);
+        assert_eq!(
+            registry.statuses(),
+            vec![
+                PlatformStatus {
+                    platform: "Gitea",
+                    state: PlatformState::Disabled,
+                    next_run: None,
+                },
+                PlatformStatus {
+                    platform: "Codeberg",
+                    state: PlatformState::Enabled,
+                    next_run: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn selected_platforms_have_to_be_configured() {
+        let mut registry = PlatformRegistry::default();
+
+        let error = registry
+            .register_selected(Some(&["Gitea"]), "Gitea", || Ok(None::<Arc<Mutex<FakePlatform>>>))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Gitea is listed in POLLUX_PLATFORMS"), "{}", error);
+
+        // Without a selection, unconfigured platforms are skipped
+        registry.register_selected(None, "Gitea", || Ok(None::<Arc<Mutex<FakePlatform>>>)).unwrap();
+        assert!(registry.names().is_empty());
+        assert_eq!(registry.statuses()[0].state, PlatformState::NotConfigured);
+    }
+
+    #[tokio::test]
+    async fn registered_runners_are_shared_between_clones() {
+        let fake = Arc::new(Mutex::new(FakePlatform { name: "Github", syncs: 0, reprocessed: 0 }));
+        let mut registry = PlatformRegistry::default();
+        registry.register("Github", fake.clone());
+
+        registry.clone().sync(&["Github"]).await;
+        registry.sync(&["Github"]).await;
+        assert_eq!(fake.lock().await.syncs, 2);
+    }
+
+    #[tokio::test]
+    async fn only_requested_platforms_are_reprocessed() {
+        let (github, gitlab) = (fake("Github").unwrap(), fake("Gitlab").unwrap());
+        let mut registry = PlatformRegistry::default();
+        registry.register("Github", github.clone());
+        registry.register("Gitlab", gitlab.clone());
+
+        let reports = registry.reprocess(&["Gitlab"]).await;
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].platform, "Gitlab");
+        assert_eq!((github.lock().await.reprocessed, gitlab.lock().await.reprocessed), (0, 1));
+        // Reprocessing isn't a sync
+        assert_eq!(gitlab.lock().await.syncs, 0);
+    }
+}
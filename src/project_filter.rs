@@ -0,0 +1,201 @@
+use log::debug;
+
+use crate::config;
+
+/// A glob-style pattern matched against `platform/name`, e.g. `Gitlab/secret-org/*`.
+/// `*` matches any number of characters (including `/`), `?` exactly one.
+/// Matching ignores case, as both platforms treat names case-insensitively.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    source: String,
+    chars: Vec<char>,
+}
+
+impl Pattern {
+    pub fn new(source: &str) -> Pattern {
+        let source = source.trim().to_string();
+        Pattern {
+            chars: source.to_lowercase().chars().collect(),
+            source,
+        }
+    }
+
+    pub fn matches(&self, input: &str) -> bool {
+        let input: Vec<char> = input.to_lowercase().chars().collect();
+
+        // Iterative wildcard matching, backtracking to the last `*` on a mismatch
+        let (mut pattern_index, mut input_index) = (0, 0);
+        let mut last_star: Option<(usize, usize)> = None;
+        while input_index < input.len() {
+            match self.chars.get(pattern_index) {
+                Some('*') => {
+                    last_star = Some((pattern_index, input_index));
+                    pattern_index += 1;
+                }
+                Some(&expected) if expected == '?' || expected == input[input_index] => {
+                    pattern_index += 1;
+                    input_index += 1;
+                }
+                _ => match last_star {
+                    Some((star_index, star_input_index)) => {
+                        pattern_index = star_index + 1;
+                        input_index = star_input_index + 1;
+                        last_star = Some((star_index, star_input_index + 1));
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        self.chars[pattern_index..].iter().all(|char| *char == '*')
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// Comma-separated patterns, empty ones are ignored
+pub fn parse_patterns(input: &str) -> Vec<Pattern> {
+    input
+        .split(',')
+        .filter(|pattern| !pattern.trim().is_empty())
+        .map(Pattern::new)
+        .collect()
+}
+
+/// Decides which projects are ingested at all, before anything of them is written.
+/// The denylist always wins, a non-empty allowlist lets only matching projects through.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProjectFilter {
+    allow: Vec<Pattern>,
+    deny: Vec<Pattern>,
+}
+
+impl ProjectFilter {
+    pub fn new(allow: Vec<Pattern>, deny: Vec<Pattern>) -> ProjectFilter {
+        ProjectFilter { allow, deny }
+    }
+
+    /// `POLLUX_PROJECT_ALLOWLIST` and `POLLUX_PROJECT_DENYLIST`, both unset lets everything through
+    pub fn from_env() -> ProjectFilter {
+        let patterns = |name: &str| config::var(name).map(|input| parse_patterns(&input)).unwrap_or_default();
+        ProjectFilter::new(patterns("POLLUX_PROJECT_ALLOWLIST"), patterns("POLLUX_PROJECT_DENYLIST"))
+    }
+
+    /// The rule excluding the project, `None` if it is ingested
+    pub fn rejection(&self, platform: &str, name: &str) -> Option<String> {
+        let project = format!("{}/{}", platform, name);
+
+        if let Some(pattern) = self.deny.iter().find(|pattern| pattern.matches(&project)) {
+            return Some(format!("denylist pattern »{}«", pattern));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|pattern| pattern.matches(&project)) {
+            return Some("no allowlist pattern".to_string());
+        }
+        None
+    }
+
+    /// Like `rejection`, logging skipped projects
+    pub fn includes(&self, platform: &str, name: &str) -> bool {
+        match self.rejection(platform, name) {
+            Some(rule) => {
+                debug!("Skipping project {}/{}, it matches {}", platform, name, rule);
+                false
+            }
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal_patterns_match_exactly() {
+        let pattern = Pattern::new("Github/2tefan/pollux");
+        assert!(pattern.matches("Github/2tefan/pollux"));
+        assert!(pattern.matches("github/2TEFAN/Pollux"));
+        assert!(!pattern.matches("Github/2tefan/pollux-old"));
+        assert!(!pattern.matches("Github/2tefan/pollu"));
+        assert!(!pattern.matches("Gitlab/2tefan/pollux"));
+    }
+
+    #[test]
+    fn star_matches_any_characters() {
+        let pattern = Pattern::new("Gitlab/secret-org/*");
+        assert!(pattern.matches("Gitlab/secret-org/plans"));
+        assert!(pattern.matches("Gitlab/secret-org/nested/group/repo"));
+        assert!(pattern.matches("Gitlab/secret-org/"));
+        assert!(!pattern.matches("Gitlab/secret-org"));
+        assert!(!pattern.matches("Gitlab/other-org/plans"));
+
+        assert!(Pattern::new("*").matches(""));
+        assert!(Pattern::new("*/*-archive").matches("Github/2tefan/pollux-archive"));
+        assert!(!Pattern::new("*/*-archive").matches("Github/2tefan/pollux-archived"));
+        assert!(Pattern::new("Github/*/dotfiles*").matches("Github/2tefan/dotfiles-work"));
+        // Needs backtracking: the first `b` isn't the one matching the end
+        assert!(Pattern::new("*b*c").matches("abxbyc"));
+        assert!(Pattern::new("a**b").matches("ab"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        let pattern = Pattern::new("Github/2tefan/repo-?");
+        assert!(pattern.matches("Github/2tefan/repo-1"));
+        assert!(!pattern.matches("Github/2tefan/repo-"));
+        assert!(!pattern.matches("Github/2tefan/repo-10"));
+    }
+
+    #[test]
+    fn patterns_are_comma_separated() {
+        assert_eq!(
+            parse_patterns(" Github/2tefan/*, ,Gitlab/secret-org/* ,"),
+            vec![Pattern::new("Github/2tefan/*"), Pattern::new("Gitlab/secret-org/*")]
+        );
+        assert_eq!(parse_patterns(""), Vec::new());
+        assert_eq!(Pattern::new(" Github/* ").to_string(), "Github/*");
+    }
+
+    #[test]
+    fn empty_filter_includes_everything() {
+        let filter = ProjectFilter::default();
+        assert_eq!(filter.rejection("Github", "2tefan/pollux"), None);
+        assert!(filter.includes("Gitlab", "secret-org/plans"));
+    }
+
+    #[test]
+    fn denylist_excludes_matches() {
+        let filter = ProjectFilter::new(Vec::new(), parse_patterns("Gitlab/secret-org/*"));
+        assert_eq!(
+            filter.rejection("Gitlab", "secret-org/plans").as_deref(),
+            Some("denylist pattern »Gitlab/secret-org/*«")
+        );
+        assert_eq!(filter.rejection("Github", "secret-org/plans"), None);
+        assert_eq!(filter.rejection("Gitlab", "2tefan/pollux"), None);
+    }
+
+    #[test]
+    fn allowlist_only_includes_matches() {
+        let filter = ProjectFilter::new(parse_patterns("Github/2tefan/*"), Vec::new());
+        assert_eq!(filter.rejection("Github", "2tefan/pollux"), None);
+        assert_eq!(
+            filter.rejection("Github", "someone/else").as_deref(),
+            Some("no allowlist pattern")
+        );
+        assert!(!filter.includes("Gitlab", "2tefan/pollux"));
+    }
+
+    #[test]
+    fn denylist_wins_over_allowlist() {
+        let filter = ProjectFilter::new(parse_patterns("Github/2tefan/*"), parse_patterns("Github/2tefan/secret-*"));
+        assert!(filter.includes("Github", "2tefan/pollux"));
+        assert_eq!(
+            filter.rejection("Github", "2tefan/secret-plans").as_deref(),
+            Some("denylist pattern »Github/2tefan/secret-*«")
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, info, warn};
+use sqlx::{prelude::FromRow, MySql, Pool, Transaction};
+
+use crate::{
+    config::{ConfigError, EnvConfig},
+    database,
+    git_platform::{GitPlatform, PlatformError},
+};
+
+static REFRESH_DAYS: &str = "POLLUX_PROJECT_REFRESH_DAYS";
+static FALLBACK_REFRESH_DAYS: u32 = 7;
+
+/// How often the metadata of every project is looked up again, `None` if it never is (`0`)
+pub fn interval_from_env() -> Result<Option<Duration>, ConfigError> {
+    interval(EnvConfig::from_env())
+}
+
+fn interval(mut env: EnvConfig) -> Result<Option<Duration>, ConfigError> {
+    let days: u32 = env.parsed(REFRESH_DAYS, FALLBACK_REFRESH_DAYS);
+    env.build((days > 0).then(|| Duration::days(days.into())))
+}
+
+/// `refreshed_at` of projects stored without asking their platform, see `mark_unconfirmed`
+fn unconfirmed() -> DateTime<Utc> {
+    DateTime::UNIX_EPOCH
+}
+
+/// Lets the next refresh look up `project_id` right away, even if refreshes are turned off otherwise.
+/// For projects stored without asking their platform, which can't tell if they're still there.
+#[cfg_attr(not(feature = "github"), allow(dead_code))]
+pub async fn mark_unconfirmed(tx: &mut Transaction<'static, MySql>, project_id: u64) {
+    sqlx::query("UPDATE GitProjects SET refreshed_at = ? WHERE id = ?")
+        .bind(unconfirmed().format("%Y-%m-%d %H:%M:%S").to_string())
+        .bind(project_id)
+        .execute(&mut **tx)
+        .await
+        .unwrap();
+}
+
+/// What the platform reports about a project now
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectMetadata {
+    pub name: String,
+    pub url: String,
+    /// `None` for platforms which don't tell, the stored one is kept then
+    pub visibility: Option<String>,
+}
+
+/// Platforms which can look up a project by its `platform_project_id`
+pub trait ProjectLookup: GitPlatform {
+    /// `Ok(None)` if the project is gone (or we lost access to it)
+    async fn lookup_project(&mut self, platform_project_id: u64) -> Result<Option<ProjectMetadata>, PlatformError>;
+}
+
+/// A row of `GitProjects` due for a refresh
+#[derive(Debug, Clone, PartialEq, FromRow)]
+struct StoredProject {
+    id: u64,
+    platform_project_id: u64,
+    name: String,
+    url: String,
+    visibility: Option<String>,
+    unavailable: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Refresh {
+    Unchanged,
+    Updated(ProjectMetadata),
+    /// Gone since this refresh, projects which were gone before stay `Unchanged`
+    Unavailable,
+}
+
+/// What changed about `stored`, given what its platform reported
+fn compare(stored: &StoredProject, found: Option<ProjectMetadata>) -> Refresh {
+    let Some(found) = found else {
+        return if stored.unavailable { Refresh::Unchanged } else { Refresh::Unavailable };
+    };
+
+    let visibility_changed = found.visibility.is_some() && found.visibility != stored.visibility;
+    if stored.unavailable || found.name != stored.name || found.url != stored.url || visibility_changed {
+        Refresh::Updated(found)
+    } else {
+        Refresh::Unchanged
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct RefreshReport {
+    pub checked: u64,
+    /// Renamed, moved, their visibility changed or they're available again
+    pub updated: u64,
+    pub unavailable: u64,
+    /// Looked up again after the next interval
+    pub failed: u64,
+    /// Rate limited, the remaining projects are refreshed by the next run
+    pub rate_limited: bool,
+}
+
+/// Refreshes the projects of `P` not refreshed for `POLLUX_PROJECT_REFRESH_DAYS`, and the unconfirmed
+/// ones (see `mark_unconfirmed`) also without periodic refreshes. Called after every scheduled sync, usually there's nothing to do.
+pub async fn refresh<P: ProjectLookup>(platform: &mut P) -> RefreshReport {
+    let due_before = match interval_from_env() {
+        Ok(Some(interval)) => Utc::now() - interval,
+        // Only the projects nobody asked the platform about yet
+        Ok(None) => unconfirmed() + Duration::seconds(1),
+        Err(_) => return RefreshReport::default(),
+    };
+    let db = database::Database::get_or_init().await;
+    let pool = db.get_pool().await;
+
+    let report = refresh_due(&pool, platform, due_before).await;
+    if report.checked > 0 {
+        info!(
+            "Refreshed {} projects of {}: {} updated, {} unavailable, {} failed{}",
+            report.checked,
+            P::GIT_PLATFORM_ID,
+            report.updated,
+            report.unavailable,
+            report.failed,
+            if report.rate_limited { ", stopped by the rate limit" } else { "" }
+        );
+    }
+    report
+}
+
+/// Looks up the projects of `P` refreshed before `due_before`, least recently refreshed first
+pub async fn refresh_due<P: ProjectLookup>(pool: &Pool<MySql>, platform: &mut P, due_before: DateTime<Utc>) -> RefreshReport {
+    // Pseudonymous projects stand in for private ones, their names must not be looked up
+    let projects: Vec<StoredProject> = sqlx::query_as(
+        "SELECT id, platform_project_id, name, url, visibility, unavailable_since IS NOT NULL AS unavailable \
+            FROM GitProjects WHERE platform = ? AND pseudonymous = 0 AND refreshed_at < ? ORDER BY refreshed_at, id",
+    )
+    .bind(P::GIT_PLATFORM_ID)
+    .bind(due_before.format("%Y-%m-%d %H:%M:%S").to_string())
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    let mut report = RefreshReport::default();
+    for project in projects {
+        let now = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let found = match platform.lookup_project(project.platform_project_id).await {
+            Ok(found) => found,
+            Err(PlatformError::RateLimited { .. }) => {
+                warn!("{} is rate limiting project lookups, refreshing the rest later", P::GIT_PLATFORM_ID);
+                report.rate_limited = true;
+                break;
+            }
+            Err(err) => {
+                warn!("Unable to refresh project {} of {}: {}", project.name, P::GIT_PLATFORM_ID, err);
+                report.failed += 1;
+                mark_refreshed(pool, project.id, &now).await;
+                continue;
+            }
+        };
+
+        report.checked += 1;
+        match compare(&project, found) {
+            Refresh::Unchanged => mark_refreshed(pool, project.id, &now).await,
+            Refresh::Updated(metadata) => {
+                debug!("Project {} of {} is now {} ({})", project.name, P::GIT_PLATFORM_ID, metadata.name, metadata.url);
+                report.updated += 1;
+                sqlx::query(
+                    "UPDATE GitProjects SET name = ?, url = ?, visibility = COALESCE(?, visibility), \
+                        unavailable_since = NULL, refreshed_at = ?, updated_at = ? WHERE id = ?",
+                )
+                .bind(metadata.name)
+                .bind(metadata.url)
+                .bind(metadata.visibility)
+                .bind(&now)
+                .bind(&now)
+                .bind(project.id)
+                .execute(pool)
+                .await
+                .unwrap();
+            }
+            Refresh::Unavailable => {
+                warn!("Project {} of {} is gone, flagging it as unavailable", project.name, P::GIT_PLATFORM_ID);
+                report.unavailable += 1;
+                sqlx::query(
+                    "UPDATE GitProjects SET unavailable_since = ?, refreshed_at = ?, updated_at = ? WHERE id = ?",
+                )
+                .bind(&now)
+                .bind(&now)
+                .bind(&now)
+                .bind(project.id)
+                .execute(pool)
+                .await
+                .unwrap();
+            }
+        }
+    }
+    report
+}
+
+async fn mark_refreshed(pool: &Pool<MySql>, project_id: u64, now: &str) {
+    sqlx::query("UPDATE GitProjects SET refreshed_at = ? WHERE id = ?")
+        .bind(now)
+        .bind(project_id)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &'static [(&'static str, &'static str)]) -> EnvConfig {
+        EnvConfig::from(|name| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string()))
+    }
+
+    fn stored(unavailable: bool) -> StoredProject {
+        StoredProject {
+            id: 1,
+            platform_project_id: 7,
+            name: "2tefan/dotfiles".to_string(),
+            url: "https://git.example.com/2tefan/dotfiles".to_string(),
+            visibility: Some("public".to_string()),
+            unavailable,
+        }
+    }
+
+    fn found(name: &str, visibility: Option<&str>) -> Option<ProjectMetadata> {
+        Some(ProjectMetadata {
+            name: name.to_string(),
+            url: format!("https://git.example.com/{}", name),
+            visibility: visibility.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn projects_are_refreshed_weekly_by_default() {
+        assert_eq!(interval(env(&[])).unwrap(), Some(Duration::days(7)));
+        assert_eq!(interval(env(&[("POLLUX_PROJECT_REFRESH_DAYS", "0")])).unwrap(), None);
+
+        let message = interval(env(&[("POLLUX_PROJECT_REFRESH_DAYS", "weekly")])).unwrap_err().to_string();
+        assert!(message.contains("POLLUX_PROJECT_REFRESH_DAYS is invalid"), "{}", message);
+    }
+
+    #[test]
+    fn only_changed_metadata_is_updated() {
+        assert_eq!(compare(&stored(false), found("2tefan/dotfiles", Some("public"))), Refresh::Unchanged);
+        // Platforms which don't report it keep the stored one
+        assert_eq!(compare(&stored(false), found("2tefan/dotfiles", None)), Refresh::Unchanged);
+
+        for changed in [found("2tefan/configs", Some("public")), found("2tefan/dotfiles", Some("private"))] {
+            assert_eq!(compare(&stored(false), changed.clone()), Refresh::Updated(changed.unwrap()));
+        }
+        let restored = found("2tefan/dotfiles", Some("public"));
+        assert_eq!(compare(&stored(true), restored.clone()), Refresh::Updated(restored.unwrap()));
+    }
+
+    #[test]
+    fn gone_projects_are_flagged_once() {
+        assert_eq!(compare(&stored(false), None), Refresh::Unavailable);
+        assert_eq!(compare(&stored(true), None), Refresh::Unchanged);
+    }
+}
@@ -0,0 +1,401 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use sqlx::{prelude::FromRow, MySql, Pool, Transaction};
+
+use crate::{stats, visibility::Pseudonymizer};
+
+#[derive(Debug, PartialEq)]
+pub enum ProjectError {
+    NotFound(u64),
+    SameProject(u64),
+    CrossPlatform {
+        source: (u64, String),
+        target: (u64, String),
+    },
+}
+
+impl fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectError::NotFound(id) => write!(f, "There is no project with id {}", id),
+            ProjectError::SameProject(id) => write!(f, "Can't merge project {} into itself", id),
+            ProjectError::CrossPlatform { source, target } => write!(
+                f,
+                "Can't merge project {} ({}) into project {} ({}) of another platform",
+                source.0, source.1, target.0, target.1
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, JsonSchema)]
+pub struct DeleteReport {
+    pub project_id: u64,
+    pub events_deleted: u64,
+    pub projects_deleted: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize, JsonSchema)]
+pub struct MergeReport {
+    pub source_id: u64,
+    pub target_id: u64,
+    pub events_moved: u64,
+    pub projects_deleted: u64,
+}
+
+/// Number of events returned in `ProjectDetail::recent_events`
+static RECENT_EVENTS_LIMIT: u32 = 20;
+
+#[derive(Debug, PartialEq, FromRow, Serialize, JsonSchema)]
+pub struct ActionCount {
+    pub action: String,
+    pub count: i64,
+}
+
+#[derive(Debug, PartialEq, FromRow, Serialize, JsonSchema)]
+pub struct ProjectEvent {
+    pub timestamp: DateTime<Utc>,
+    pub action: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, JsonSchema)]
+pub struct ProjectDetail {
+    pub id: u64,
+    pub name: String,
+    /// `None` if the project isn't available on its platform anymore
+    pub url: Option<String>,
+    pub platform: String,
+    pub pseudonymous: bool,
+    pub first_event_at: Option<DateTime<Utc>>,
+    pub last_event_at: Option<DateTime<Utc>>,
+    pub event_count: i64,
+    /// Sorted by count, most frequent first
+    pub actions: Vec<ActionCount>,
+    /// Newest first
+    pub recent_events: Vec<ProjectEvent>,
+}
+
+impl ProjectDetail {
+    /// Replaces the name by the project's alias and drops the url, all stats stay accurate
+    pub fn anonymize(&mut self, pseudonymizer: &Pseudonymizer) {
+        self.name = pseudonymizer.project_alias(self.id);
+        self.url = Some(String::new());
+    }
+}
+
+/// Metadata and stats of one project, `None` if there is no project with this id
+pub async fn get_project_detail(pool: &Pool<MySql>, project_id: u64) -> Option<ProjectDetail> {
+    let (project, totals, actions, recent_events) = tokio::join!(
+        sqlx::query_as::<_, (String, Option<String>, String, bool)>(
+            "SELECT name, IF(unavailable_since IS NULL, url, NULL), platform, pseudonymous FROM GitProjects WHERE id = ?"
+        )
+        .bind(project_id)
+        .fetch_optional(pool),
+        sqlx::query_as::<_, (Option<DateTime<Utc>>, Option<DateTime<Utc>>, i64)>(
+            "SELECT MIN(evt.timestamp), MAX(evt.timestamp), COUNT(evt.id) FROM Events AS evt \
+            INNER JOIN GitEvents AS gevt ON evt.id = gevt.id \
+            WHERE gevt.project_fk = ?"
+        )
+        .bind(project_id)
+        .fetch_one(pool),
+        sqlx::query_as::<_, ActionCount>(
+            "SELECT gact.name AS action, COUNT(1) AS count FROM GitEvents AS gevt \
+            INNER JOIN GitActions AS gact ON gevt.action_fk = gact.id \
+            WHERE gevt.project_fk = ? \
+            GROUP BY gact.name \
+            ORDER BY count DESC, gact.name"
+        )
+        .bind(project_id)
+        .fetch_all(pool),
+        sqlx::query_as::<_, ProjectEvent>(
+            "SELECT evt.timestamp, gact.name AS action FROM Events AS evt \
+            INNER JOIN GitEvents AS gevt ON evt.id = gevt.id \
+            INNER JOIN GitActions AS gact ON gevt.action_fk = gact.id \
+            WHERE gevt.project_fk = ? \
+            ORDER BY evt.timestamp DESC, evt.id DESC \
+            LIMIT ?"
+        )
+        .bind(project_id)
+        .bind(RECENT_EVENTS_LIMIT)
+        .fetch_all(pool),
+    );
+
+    let (name, url, platform, pseudonymous) = project.unwrap()?;
+    let (first_event_at, last_event_at, event_count) = totals.unwrap();
+    Some(ProjectDetail {
+        id: project_id,
+        name,
+        url,
+        platform,
+        pseudonymous,
+        first_event_at,
+        last_event_at,
+        event_count,
+        actions: actions.unwrap(),
+        recent_events: recent_events.unwrap(),
+    })
+}
+
+async fn get_platform(tx: &mut Transaction<'static, MySql>, project_id: u64) -> Result<String, ProjectError> {
+    sqlx::query_scalar("SELECT platform FROM GitProjects WHERE id = ? FOR UPDATE")
+        .bind(project_id)
+        .fetch_optional(&mut **tx)
+        .await
+        .unwrap()
+        .ok_or(ProjectError::NotFound(project_id))
+}
+
+async fn delete_project_row(tx: &mut Transaction<'static, MySql>, project_id: u64) -> u64 {
+    sqlx::query("DELETE FROM GitProjects WHERE id = ?")
+        .bind(project_id)
+        .execute(&mut **tx)
+        .await
+        .unwrap()
+        .rows_affected()
+}
+
+/// GitEvents cascade from both Events and GitProjects, but Events would stay behind
+async fn delete_events_of(tx: &mut Transaction<'static, MySql>, project_id: u64) -> u64 {
+    sqlx::query("DELETE evt FROM Events AS evt, GitEvents AS gevt WHERE evt.id = gevt.id AND gevt.project_fk = ?")
+        .bind(project_id)
+        .execute(&mut **tx)
+        .await
+        .unwrap()
+        .rows_affected()
+}
+
+/// Deletes a project with all its events
+pub async fn delete_project(pool: &Pool<MySql>, project_id: u64) -> Result<DeleteReport, ProjectError> {
+    let mut tx = pool.begin().await.expect("Couldn't start transaction!");
+    get_platform(&mut tx, project_id).await?;
+
+    let events_deleted = delete_events_of(&mut tx, project_id).await;
+    let projects_deleted = delete_project_row(&mut tx, project_id).await;
+
+    tx.commit().await.expect("Couldn't apply transaction ._.");
+    stats::invalidate_today_cache();
+    info!("Deleted project {} with {} events", project_id, events_deleted);
+
+    Ok(DeleteReport {
+        project_id,
+        events_deleted,
+        projects_deleted,
+    })
+}
+
+/// Moves all events of `source_id` over to `target_id` and deletes `source_id` afterwards
+pub async fn merge_projects(pool: &Pool<MySql>, source_id: u64, target_id: u64) -> Result<MergeReport, ProjectError> {
+    if source_id == target_id {
+        return Err(ProjectError::SameProject(source_id));
+    }
+
+    let mut tx = pool.begin().await.expect("Couldn't start transaction!");
+    let source_platform = get_platform(&mut tx, source_id).await?;
+    let target_platform = get_platform(&mut tx, target_id).await?;
+    if source_platform != target_platform {
+        return Err(ProjectError::CrossPlatform {
+            source: (source_id, source_platform),
+            target: (target_id, target_platform),
+        });
+    }
+
+    // Events the target has already stay behind, see `GitEvents_UNIQUE`
+    let events_moved = sqlx::query("UPDATE IGNORE GitEvents SET project_fk = ? WHERE project_fk = ?")
+        .bind(target_id)
+        .bind(source_id)
+        .execute(&mut *tx)
+        .await
+        .unwrap()
+        .rows_affected();
+    let duplicates_dropped = delete_events_of(&mut tx, source_id).await;
+    let projects_deleted = delete_project_row(&mut tx, source_id).await;
+
+    tx.commit().await.expect("Couldn't apply transaction ._.");
+    info!(
+        "Merged project {} into {}, moved {} events and dropped {} duplicates",
+        source_id, target_id, events_moved, duplicates_dropped
+    );
+
+    Ok(MergeReport {
+        source_id,
+        target_id,
+        events_moved,
+        projects_deleted,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn seed() -> (testcontainers::ContainerAsync<testcontainers::GenericImage>, Pool<MySql>) {
+        let (container, pool) = crate::database::tests::initialize().await;
+        for statement in [
+            "INSERT INTO GitPlatforms (name, firstSync) VALUES ('Github', NOW()), ('Gitlab', NOW())",
+            "INSERT INTO GitActions (id, name) VALUES (1, 'commit')",
+            "INSERT INTO GitProjects (id, name, url, platform, platform_project_id) VALUES \
+                (1, '2tefan/pollux', '', 'Github', 1), \
+                (2, '2tefan/pollux-renamed', '', 'Github', 2), \
+                (3, '2tefan/castor', '', 'Gitlab', 3)",
+            "INSERT INTO Events (id, timestamp) VALUES \
+                (1, '2024-05-01 10:00:00'), (2, '2024-05-02 10:00:00'), (3, '2024-05-03 10:00:00'), (4, '2024-05-04 10:00:00')",
+            "INSERT INTO GitEvents (id, action_fk, project_fk) VALUES (1, 1, 1), (2, 1, 1), (3, 1, 2), (4, 1, 3)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        (container, pool)
+    }
+
+    #[test]
+    fn anonymized_detail_leaks_no_name() {
+        let pseudonymizer = Pseudonymizer::new("secret");
+        let mut detail = ProjectDetail {
+            id: 1,
+            name: "2tefan/pollux".to_string(),
+            url: Some("https://github.com/2tefan/pollux".to_string()),
+            platform: "Github".to_string(),
+            pseudonymous: false,
+            first_event_at: DateTime::from_timestamp(1_714_557_600, 0),
+            last_event_at: DateTime::from_timestamp(1_714_557_600, 0),
+            event_count: 1,
+            actions: vec![ActionCount {
+                action: "commit".to_string(),
+                count: 1,
+            }],
+            recent_events: vec![],
+        };
+        detail.anonymize(&pseudonymizer);
+
+        let payload = serde_json::to_string(&detail).unwrap();
+        assert!(!payload.contains("pollux"), "{}", payload);
+        assert_eq!(detail.name, pseudonymizer.project_alias(1));
+        assert_eq!(detail.event_count, 1);
+    }
+
+    async fn count(pool: &Pool<MySql>, table: &str) -> i64 {
+        sqlx::query_scalar(&format!("SELECT COUNT(1) FROM {}", table))
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn deleting_a_project_removes_its_events() {
+        let (_container, pool) = seed().await;
+
+        assert_eq!(
+            delete_project(&pool, 1).await,
+            Ok(DeleteReport {
+                project_id: 1,
+                events_deleted: 2,
+                projects_deleted: 1,
+            })
+        );
+        assert_eq!(count(&pool, "Events").await, 2);
+        assert_eq!(count(&pool, "GitEvents").await, 2);
+        assert_eq!(count(&pool, "GitProjects").await, 2);
+
+        assert_eq!(delete_project(&pool, 1).await, Err(ProjectError::NotFound(1)));
+    }
+
+    #[tokio::test]
+    async fn merging_repoints_events() {
+        let (_container, pool) = seed().await;
+
+        assert_eq!(
+            merge_projects(&pool, 1, 2).await,
+            Ok(MergeReport {
+                source_id: 1,
+                target_id: 2,
+                events_moved: 2,
+                projects_deleted: 1,
+            })
+        );
+        let on_target: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM GitEvents WHERE project_fk = 2")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(on_target, 3);
+        assert_eq!(count(&pool, "Events").await, 4);
+    }
+
+    #[tokio::test]
+    async fn merging_drops_events_the_target_has_already() {
+        let (_container, pool) = seed().await;
+        sqlx::query("UPDATE GitEvents SET timestamp = '2024-05-01 10:00:00' WHERE id IN (1, 3)")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(merge_projects(&pool, 1, 2).await.unwrap().events_moved, 1);
+        assert_eq!(count(&pool, "Events").await, 3);
+        assert_eq!(count(&pool, "GitEvents").await, 3);
+    }
+
+    #[tokio::test]
+    async fn projects_of_different_platforms_are_not_merged() {
+        let (_container, pool) = seed().await;
+
+        assert!(matches!(
+            merge_projects(&pool, 1, 3).await,
+            Err(ProjectError::CrossPlatform { .. })
+        ));
+        assert_eq!(merge_projects(&pool, 1, 1).await, Err(ProjectError::SameProject(1)));
+        assert_eq!(count(&pool, "GitProjects").await, 3);
+        assert_eq!(count(&pool, "GitEvents").await, 4);
+    }
+
+    #[tokio::test]
+    async fn project_detail_contains_stats() {
+        let (_container, pool) = seed().await;
+        sqlx::query("INSERT INTO GitActions (id, name) VALUES (2, 'comments')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE GitEvents SET action_fk = 2 WHERE id = 1")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let detail = get_project_detail(&pool, 1).await.unwrap();
+        assert_eq!(detail.name, "2tefan/pollux");
+        assert_eq!(detail.platform, "Github");
+        assert_eq!(detail.first_event_at, DateTime::from_timestamp(1_714_557_600, 0));
+        assert_eq!(detail.last_event_at, DateTime::from_timestamp(1_714_644_000, 0));
+        assert_eq!(detail.event_count, 2);
+        assert_eq!(
+            detail.actions,
+            vec![
+                ActionCount {
+                    action: "comments".to_string(),
+                    count: 1
+                },
+                ActionCount {
+                    action: "commit".to_string(),
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(
+            detail.recent_events.iter().map(|event| event.action.as_str()).collect::<Vec<_>>(),
+            vec!["commit", "comments"]
+        );
+
+        assert_eq!(get_project_detail(&pool, 42).await, None);
+    }
+
+    #[tokio::test]
+    async fn project_without_events_has_empty_stats() {
+        let (_container, pool) = seed().await;
+        sqlx::query("DELETE FROM Events WHERE id = 4").execute(&pool).await.unwrap();
+
+        let detail = get_project_detail(&pool, 3).await.unwrap();
+        assert_eq!(detail.event_count, 0);
+        assert_eq!(detail.first_event_at, None);
+        assert!(detail.actions.is_empty());
+        assert!(detail.recent_events.is_empty());
+    }
+}
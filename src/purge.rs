@@ -0,0 +1,161 @@
+use chrono::{Days, NaiveDate};
+use schemars::JsonSchema;
+use serde::Serialize;
+use sqlx::{MySql, Pool};
+
+use crate::stats;
+
+/// Shared by the dry run and the actual purge, binds start, end and the platform twice
+static MATCHING_EVENTS: &str = "FROM Events AS evt \
+    INNER JOIN GitEvents AS gevt ON evt.id = gevt.id \
+    INNER JOIN GitProjects AS proj ON gevt.project_fk = proj.id \
+    WHERE evt.timestamp >= ? AND evt.timestamp < ? AND (? IS NULL OR proj.platform = ?)";
+
+/// Events to purge: every day from `since` up to and including `until`, optionally limited to one platform
+#[derive(Debug, PartialEq)]
+pub struct PurgeRange {
+    pub since: NaiveDate,
+    pub until: NaiveDate,
+    pub platform: Option<&'static str>,
+}
+
+impl PurgeRange {
+    /// Both dates are required here, a typo must not fall back to some default range
+    pub fn parse(since: &str, until: &str, platform: Option<&'static str>) -> Result<PurgeRange, String> {
+        let parse = |name: &str, input: &str| {
+            NaiveDate::parse_from_str(input, "%Y-%m-%d")
+                .map_err(|err| format!("Couldn't parse {} »{}« as a date (YYYY-MM-DD): {}", name, input, err))
+        };
+        let since = parse("since", since)?;
+        let until = parse("until", until)?;
+        if until < since {
+            return Err(format!("until ({}) is before since ({})", until, since));
+        }
+
+        Ok(PurgeRange { since, until, platform })
+    }
+
+    fn bounds(&self) -> (String, String) {
+        let end = self.until.checked_add_days(Days::new(1)).unwrap_or(NaiveDate::MAX);
+        (
+            self.since.format("%Y-%m-%d 00:00:00").to_string(),
+            end.format("%Y-%m-%d 00:00:00").to_string(),
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, JsonSchema)]
+pub struct PurgeReport {
+    pub events_deleted: u64,
+    pub dry_run: bool,
+}
+
+/// Deletes the matching `Events` rows, their `GitEvents` rows are removed by the foreign key cascade
+pub async fn purge_events(pool: &Pool<MySql>, range: &PurgeRange, dry_run: bool) -> PurgeReport {
+    let (start, end) = range.bounds();
+    let mut tx = pool.begin().await.expect("Couldn't start transaction!");
+
+    let matching: i64 = sqlx::query_scalar(&format!("SELECT COUNT(1) {}", MATCHING_EVENTS))
+    .bind(&start)
+    .bind(&end)
+    .bind(range.platform)
+    .bind(range.platform)
+    .fetch_one(&mut *tx)
+    .await
+    .unwrap();
+
+    if dry_run {
+        tx.rollback().await.expect("Couldn't roll back transaction!");
+        return PurgeReport {
+            events_deleted: matching as u64,
+            dry_run,
+        };
+    }
+
+    let events_deleted = sqlx::query(&format!("DELETE evt {}", MATCHING_EVENTS))
+    .bind(&start)
+    .bind(&end)
+    .bind(range.platform)
+    .bind(range.platform)
+    .execute(&mut *tx)
+    .await
+    .unwrap()
+    .rows_affected();
+
+    tx.commit().await.expect("Couldn't apply transaction ._.");
+    stats::invalidate_today_cache();
+    info!(
+        "Purged {} events between {} and {} ({})",
+        events_deleted,
+        range.since,
+        range.until,
+        range.platform.unwrap_or("all platforms")
+    );
+
+    PurgeReport { events_deleted, dry_run }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn until_is_inclusive() {
+        let range = PurgeRange::parse("2024-05-01", "2024-05-02", None).unwrap();
+        assert_eq!(
+            range.bounds(),
+            ("2024-05-01 00:00:00".to_string(), "2024-05-03 00:00:00".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_ranges_are_rejected() {
+        assert!(PurgeRange::parse("yesterday", "2024-05-02", None).is_err());
+        assert!(PurgeRange::parse("2024-05-03", "2024-05-02", None).is_err());
+        assert!(PurgeRange::parse("2024-05-02", "2024-05-02", Some("Github")).is_ok());
+    }
+
+    #[tokio::test]
+    async fn only_events_in_range_are_purged() {
+        let (_container, pool) = crate::database::tests::initialize().await;
+        for statement in [
+            "INSERT INTO GitPlatforms (name, firstSync) VALUES ('Github', NOW()), ('Gitlab', NOW())",
+            "INSERT INTO GitActions (id, name) VALUES (1, 'commit')",
+            "INSERT INTO GitProjects (id, name, url, platform, platform_project_id) VALUES \
+                (1, '2tefan/pollux', '', 'Github', 1), (2, '2tefan/castor', '', 'Gitlab', 2)",
+            "INSERT INTO Events (id, timestamp) VALUES \
+                (1, '2024-04-30 23:59:59'), (2, '2024-05-01 00:00:00'), (3, '2024-05-02 23:59:59'), \
+                (4, '2024-05-03 00:00:00'), (5, '2024-05-02 12:00:00')",
+            "INSERT INTO GitEvents (id, action_fk, project_fk) VALUES (1, 1, 1), (2, 1, 1), (3, 1, 1), (4, 1, 1), (5, 1, 2)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+
+        let range = PurgeRange::parse("2024-05-01", "2024-05-02", Some("Github")).unwrap();
+        assert_eq!(
+            purge_events(&pool, &range, true).await,
+            PurgeReport {
+                events_deleted: 2,
+                dry_run: true
+            }
+        );
+        assert_eq!(
+            purge_events(&pool, &range, false).await,
+            PurgeReport {
+                events_deleted: 2,
+                dry_run: false
+            }
+        );
+
+        let events: Vec<u64> = sqlx::query_scalar("SELECT id FROM Events ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(events, vec![1, 4, 5]);
+        let git_events: Vec<u64> = sqlx::query_scalar("SELECT id FROM GitEvents ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(git_events, events);
+    }
+}
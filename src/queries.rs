@@ -0,0 +1,312 @@
+//! Reads events back for the APIs, independent of the platform they were synced from
+
+use chrono::{DateTime, NaiveDate, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, MySql, Pool, QueryBuilder};
+
+use crate::visibility::Pseudonymizer;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct EventProject {
+    pub id: u64,
+    pub name: String,
+    /// `null` if the project was already deleted when its events were synced
+    pub url: Option<String>,
+    pub platform: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct GitEvents {
+    timestamp: DateTime<Utc>,
+    project_name: String,
+    action: String,
+    platform: String,
+    url: String,
+    /// Only known for push events
+    commit_count: Option<u32>,
+    /// Branch or tag of GitLab push events, omitted otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    git_ref: Option<String>,
+    /// What a GitLab event acted on, e.g. `Issue` or `MergeRequest` (also for comments on them), omitted otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target_type: Option<String>,
+    /// `null` if the project is missing, then the flat project fields above are empty
+    #[serde(default)]
+    project: Option<EventProject>,
+}
+
+/// Shared by all API versions, which map it into their own shape.
+/// Project columns come from a LEFT JOIN, so a missing project doesn't fail the whole query.
+#[derive(Debug, Clone, FromRow)]
+pub struct GitEventRow {
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    pub action_id: u64,
+    pub action: String,
+    pub commit_count: Option<u32>,
+    pub git_ref: Option<String>,
+    pub target_type: Option<String>,
+    pub project_id: Option<u64>,
+    pub project_name: Option<String>,
+    pub platform: Option<String>,
+    pub url: Option<String>,
+}
+
+impl From<GitEventRow> for GitEvents {
+    fn from(row: GitEventRow) -> Self {
+        let project = match (row.project_id, row.project_name, row.platform) {
+            (Some(id), Some(name), Some(platform)) => Some(EventProject {
+                id,
+                name,
+                url: row.url,
+                platform,
+            }),
+            _ => None,
+        };
+
+        GitEvents {
+            timestamp: row.timestamp,
+            project_name: project.as_ref().map(|project| project.name.clone()).unwrap_or_default(),
+            action: row.action,
+            platform: project.as_ref().map(|project| project.platform.clone()).unwrap_or_default(),
+            url: project.as_ref().and_then(|project| project.url.clone()).unwrap_or_default(),
+            commit_count: row.commit_count,
+            git_ref: row.git_ref,
+            target_type: row.target_type,
+            project,
+        }
+    }
+}
+
+impl GitEvents {
+    /// Replaces the project name by its alias and drops the url, everything else stays accurate
+    pub fn anonymize(&mut self, pseudonymizer: &Pseudonymizer) {
+        let Some(project) = self.project.as_mut() else {
+            return;
+        };
+        project.name = pseudonymizer.project_alias(project.id);
+        project.url = Some(String::new());
+        self.project_name = project.name.clone();
+        self.url = String::new();
+        // Branch names tend to tell as much as the project name
+        self.git_ref = None;
+    }
+}
+
+/// Filters of event listings, shared with the counts so both always agree
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EventFilter {
+    /// Exclusive
+    pub since: NaiveDate,
+}
+
+impl EventFilter {
+    fn query<'a>(&self, select: &str) -> QueryBuilder<'a, MySql> {
+        let mut query = QueryBuilder::new(select);
+        query.push(
+            " FROM Events AS evt \
+            INNER JOIN GitEvents AS gevt ON evt.id = gevt.id \
+            INNER JOIN GitActions AS gact ON gevt.action_fk = gact.id \
+            LEFT JOIN GitProjects AS gpro ON gevt.project_fk = gpro.id \
+            WHERE evt.timestamp > ",
+        );
+        query.push_bind(self.since);
+        query
+    }
+}
+
+/// Matching events, oldest first. `page` is (limit, offset), all events without it.
+pub async fn fetch_event_rows(pool: &Pool<MySql>, filter: EventFilter, page: Option<(u32, u32)>) -> Vec<GitEventRow> {
+    let (limit, offset) = page.unwrap_or((u32::MAX, 0));
+    let mut query = filter.query(
+        r#"
+            SELECT
+                evt.id as id,
+                evt.timestamp as timestamp,
+                gact.id as action_id,
+                gact.name as action,
+                gevt.commit_count as commit_count,
+                gevt.git_ref as git_ref,
+                gevt.target_type as target_type,
+                gpro.id as project_id,
+                gpro.name as project_name,
+                gpro.platform as platform,
+                IF(gpro.unavailable_since IS NULL, gpro.url, NULL) as url"#,
+    );
+    query.push(" ORDER BY evt.timestamp, evt.id LIMIT ");
+    query.push_bind(limit);
+    query.push(" OFFSET ");
+    query.push_bind(offset);
+
+    query.build_query_as::<GitEventRow>().fetch_all(pool).await.unwrap()
+}
+
+pub async fn count_events(pool: &Pool<MySql>, filter: EventFilter) -> u64 {
+    let count: i64 = filter
+        .query("SELECT COUNT(1)")
+        .build_query_scalar()
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    count.max(0) as u64
+}
+
+/// All matching events with their project embedded, oldest first
+pub async fn query_git_events(pool: &Pool<MySql>, filter: EventFilter) -> Vec<GitEvents> {
+    fetch_event_rows(pool, filter, None)
+        .await
+        .into_iter()
+        .map(GitEvents::from)
+        .collect()
+}
+
+/// `(?, ?, ...)` for an `IN` clause
+pub(crate) fn push_ids(query: &mut QueryBuilder<'_, MySql>, ids: &[u64]) {
+    query.push("(");
+    let mut separated = query.separated(", ");
+    for id in ids {
+        separated.push_bind(*id);
+    }
+    separated.push_unseparated(")");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_project_serializes_as_null() {
+        let event = GitEvents::from(GitEventRow {
+            id: 1,
+            timestamp: DateTime::from_timestamp(0, 0).unwrap(),
+            action_id: 1,
+            action: "commit".to_string(),
+            commit_count: None,
+            git_ref: None,
+            target_type: None,
+            project_id: None,
+            project_name: None,
+            platform: None,
+            url: None,
+        });
+
+        let json = serde_json::to_value(event).unwrap();
+        assert_eq!(json["project"], serde_json::Value::Null);
+        assert_eq!(json["project_name"], "");
+    }
+
+    #[test]
+    fn git_ref_is_only_serialized_when_known() {
+        let row = |git_ref: Option<&str>| GitEventRow {
+            id: 1,
+            timestamp: DateTime::from_timestamp(0, 0).unwrap(),
+            action_id: 1,
+            action: "commit".to_string(),
+            commit_count: Some(1),
+            git_ref: git_ref.map(str::to_string),
+            target_type: None,
+            project_id: None,
+            project_name: None,
+            platform: None,
+            url: None,
+        };
+
+        let json = serde_json::to_value(GitEvents::from(row(Some("main")))).unwrap();
+        assert_eq!(json["git_ref"], "main");
+        let json = serde_json::to_value(GitEvents::from(row(None))).unwrap();
+        assert!(json.get("git_ref").is_none(), "{}", json);
+    }
+
+    #[test]
+    fn anonymized_events_leak_no_project_name() {
+        let row = GitEventRow {
+            id: 1,
+            timestamp: DateTime::from_timestamp(1_714_557_600, 0).unwrap(),
+            action_id: 1,
+            action: "commit".to_string(),
+            commit_count: Some(3),
+            git_ref: Some("feature/secret-client".to_string()),
+            target_type: Some("MergeRequest".to_string()),
+            project_id: Some(5),
+            project_name: Some("2tefan/pollux".to_string()),
+            platform: Some("Gitlab".to_string()),
+            url: Some("https://gitlab.com/2tefan/pollux".to_string()),
+        };
+        let pseudonymizer = Pseudonymizer::new("secret");
+        let mut event = GitEvents::from(row.clone());
+        event.anonymize(&pseudonymizer);
+
+        let payload = serde_json::to_string(&event).unwrap();
+        assert!(!payload.contains("pollux"), "{}", payload);
+        assert!(!payload.contains("secret-client"), "{}", payload);
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["project_name"], pseudonymizer.project_alias(5));
+        assert_eq!(json["project"]["name"], pseudonymizer.project_alias(5));
+        // Only names change
+        let plain = serde_json::to_value(GitEvents::from(row)).unwrap();
+        for field in ["timestamp", "action", "platform", "commit_count", "target_type"] {
+            assert_eq!(json[field], plain[field], "{}", field);
+        }
+        assert_eq!(json["project"]["id"], 5);
+    }
+
+    #[tokio::test]
+    async fn events_embed_their_project() {
+        let (_container, pool) = crate::database::tests::initialize().await;
+        for statement in [
+            "INSERT INTO GitPlatforms (name, firstSync) VALUES ('Gitlab', NOW())",
+            "INSERT INTO GitActions (id, name) VALUES (1, 'commit')",
+            "INSERT INTO GitProjects (id, name, url, platform, platform_project_id) VALUES \
+                (5, '2tefan/pollux', 'https://gitlab.com/2tefan/pollux', 'Gitlab', 42)",
+            "INSERT INTO Events (id, timestamp) VALUES (1, '2024-05-01 10:00:00')",
+            "INSERT INTO GitEvents (id, action_fk, project_fk, commit_count) VALUES (1, 1, 5, 3)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+
+        let filter = EventFilter {
+            since: NaiveDate::from_ymd_opt(2024, 4, 30).unwrap(),
+        };
+        let events = query_git_events(&pool, filter).await;
+        let json = serde_json::to_value(&events).unwrap();
+        assert_eq!(
+            json[0]["project"],
+            serde_json::json!({
+                "id": 5,
+                "name": "2tefan/pollux",
+                "url": "https://gitlab.com/2tefan/pollux",
+                "platform": "Gitlab"
+            })
+        );
+        assert_eq!(json[0]["project_name"], "2tefan/pollux");
+        assert_eq!(json[0]["commit_count"], 3);
+    }
+
+    #[tokio::test]
+    async fn counts_match_listings() {
+        let (_container, pool) = crate::database::tests::initialize().await;
+        for statement in [
+            "INSERT INTO GitPlatforms (name, firstSync) VALUES ('Github', NOW())",
+            "INSERT INTO GitActions (id, name) VALUES (1, 'commit')",
+            "INSERT INTO GitProjects (id, name, url, platform, platform_project_id) VALUES (1, '2tefan/pollux', '', 'Github', 1)",
+            "INSERT INTO Events (id, timestamp) VALUES \
+                (1, '2024-04-30 10:00:00'), (2, '2024-05-01 10:00:00'), (3, '2024-05-02 10:00:00'), (4, '2024-05-03 10:00:00')",
+            "INSERT INTO GitEvents (id, action_fk, project_fk) VALUES (1, 1, 1), (2, 1, 1), (3, 1, 1), (4, 1, 1)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+
+        for day in [1, 30] {
+            let filter = EventFilter {
+                since: NaiveDate::from_ymd_opt(2024, 4, day).unwrap(),
+            };
+            let listed = query_git_events(&pool, filter).await.len() as u64;
+            assert_eq!(count_events(&pool, filter).await, listed);
+        }
+        let everything = EventFilter {
+            since: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+        };
+        assert_eq!(count_events(&pool, everything).await, 4);
+    }
+}
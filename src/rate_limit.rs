@@ -0,0 +1,284 @@
+use std::{net::IpAddr, num::NonZeroU32, sync::Arc, time::Duration};
+
+use governor::{clock::Clock, DefaultKeyedRateLimiter, Quota, RateLimiter};
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::{uri::Origin, Header, Method, Status},
+    response::Responder,
+    Build, Data, Orbit, Request, Response, Rocket,
+};
+
+use crate::config::{ConfigError, EnvConfig};
+
+static FALLBACK_RATE_LIMIT_PER_MINUTE: u32 = 120;
+static FALLBACK_RATE_LIMIT_BURST: u32 = 30;
+/// Buckets of clients which stayed away this long are full again and get dropped
+static CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+/// Probes of orchestrators must never be throttled
+static EXEMPT_PATHS: [&str; 3] = ["/health", "/livez", "/readyz"];
+static RATE_LIMITED_PATH: &str = "/rate-limited";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitConfig {
+    /// `None` disables rate limiting
+    pub quota: Option<Quota>,
+    /// Use the last address of `X-Forwarded-For`, the one the reverse proxy appended. Only safe behind a proxy.
+    pub trust_proxy: bool,
+}
+
+impl RateLimitConfig {
+    pub fn new(per_minute: u32, burst: u32, trust_proxy: bool) -> RateLimitConfig {
+        let quota = NonZeroU32::new(per_minute).map(|per_minute| {
+            Quota::per_minute(per_minute).allow_burst(NonZeroU32::new(burst).unwrap_or(NonZeroU32::MIN))
+        });
+        RateLimitConfig { quota, trust_proxy }
+    }
+
+    pub fn from_env() -> Result<RateLimitConfig, ConfigError> {
+        RateLimitConfig::from(EnvConfig::from_env())
+    }
+
+    fn from(mut env: EnvConfig) -> Result<RateLimitConfig, ConfigError> {
+        let config = RateLimitConfig::new(
+            env.parsed("POLLUX_RATE_LIMIT_PER_MINUTE", FALLBACK_RATE_LIMIT_PER_MINUTE),
+            env.parsed("POLLUX_RATE_LIMIT_BURST", FALLBACK_RATE_LIMIT_BURST),
+            env.parsed("POLLUX_TRUST_PROXY", false),
+        );
+        env.build(config)
+    }
+}
+
+/// Token bucket per client IP, answering `429 Too Many Requests` once a client's bucket is empty.
+/// Limited requests are rerouted to an internal route, so their handlers never run.
+pub struct RateLimit {
+    limiter: Option<Arc<DefaultKeyedRateLimiter<IpAddr>>>,
+    trust_proxy: bool,
+}
+
+impl RateLimit {
+    pub fn new(config: RateLimitConfig) -> RateLimit {
+        RateLimit {
+            limiter: config.quota.map(|quota| Arc::new(RateLimiter::keyed(quota))),
+            trust_proxy: config.trust_proxy,
+        }
+    }
+
+    fn client_ip(&self, request: &Request<'_>) -> Option<IpAddr> {
+        let forwarded = self
+            .trust_proxy
+            .then(|| request.headers().get_one("X-Forwarded-For"))
+            .flatten()
+            // Earlier entries come from the client and can't be trusted
+            .and_then(|header| header.rsplit(',').next())
+            .and_then(|client| client.trim().parse().ok());
+
+        forwarded.or_else(|| request.remote().map(|remote| remote.ip()))
+    }
+
+    /// Seconds until the client may try again, `None` if the request is allowed
+    fn check(&self, request: &Request<'_>) -> Option<u64> {
+        let limiter = self.limiter.as_ref()?;
+        if EXEMPT_PATHS.contains(&request.uri().path().as_str()) {
+            return None;
+        }
+        let ip = self.client_ip(request)?;
+
+        match limiter.check_key(&ip) {
+            Ok(()) => None,
+            Err(not_until) => {
+                let wait = not_until.wait_time_from(limiter.clock().now());
+                debug!("Rate limited {} for {:?}", ip, wait);
+                // Retry-After only knows whole seconds, rounding down would invite another 429
+                Some(wait.as_secs() + u64::from(wait.subsec_nanos() > 0))
+            }
+        }
+    }
+}
+
+/// Set by the fairing on requests it rerouted
+struct RetryAfter(Option<u64>);
+
+struct TooManyRequests;
+
+impl<'r> Responder<'r, 'static> for TooManyRequests {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        let retry_after = request.local_cache(|| RetryAfter(None)).0.unwrap_or(1);
+        Response::build()
+            .status(Status::TooManyRequests)
+            .header(Header::new("Retry-After", retry_after.to_string()))
+            .ok()
+    }
+}
+
+#[get("/rate-limited")]
+fn rate_limited() -> TooManyRequests {
+    TooManyRequests
+}
+
+#[rocket::async_trait]
+impl Fairing for RateLimit {
+    fn info(&self) -> Info {
+        Info {
+            name: "Per-IP rate limit",
+            kind: Kind::Ignite | Kind::Liftoff | Kind::Request,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        Ok(rocket.mount("/", routes![rate_limited]))
+    }
+
+    async fn on_liftoff(&self, _: &Rocket<Orbit>) {
+        let Some(limiter) = self.limiter.clone() else {
+            return;
+        };
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+            loop {
+                interval.tick().await;
+                limiter.retain_recent();
+                limiter.shrink_to_fit();
+            }
+        });
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        if let Some(retry_after) = self.check(request) {
+            request.local_cache(|| RetryAfter(Some(retry_after)));
+            request.set_method(Method::Get);
+            request.set_uri(Origin::parse(RATE_LIMITED_PATH).unwrap());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::local::asynchronous::Client;
+
+    use super::*;
+
+    #[get("/ping")]
+    fn ping() -> &'static str {
+        "pong"
+    }
+
+    #[get("/livez")]
+    fn livez() -> &'static str {
+        "ok"
+    }
+
+    async fn client(config: RateLimitConfig) -> Client {
+        let rocket = rocket::build()
+            .attach(RateLimit::new(config))
+            .mount("/", routes![ping, livez]);
+        Client::untracked(rocket).await.unwrap()
+    }
+
+    async fn status(client: &Client, path: &str, ip: &str) -> (Status, Option<String>) {
+        let response = client
+            .get(path)
+            .remote(format!("{}:4242", ip).parse().unwrap())
+            .dispatch()
+            .await;
+        (
+            response.status(),
+            response.headers().get_one("Retry-After").map(str::to_string),
+        )
+    }
+
+    fn env(vars: &'static [(&'static str, &'static str)]) -> EnvConfig {
+        EnvConfig::from(|name| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string()))
+    }
+
+    #[test]
+    fn invalid_limits_are_reported_instead_of_replaced() {
+        assert_eq!(RateLimitConfig::from(env(&[])), Ok(RateLimitConfig::new(120, 30, false)));
+        assert_eq!(
+            RateLimitConfig::from(env(&[("POLLUX_RATE_LIMIT_PER_MINUTE", "0"), ("POLLUX_TRUST_PROXY", "true")])),
+            Ok(RateLimitConfig::new(0, 30, true))
+        );
+
+        let message = RateLimitConfig::from(env(&[("POLLUX_RATE_LIMIT_BURST", "-1"), ("POLLUX_TRUST_PROXY", "1")]))
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("POLLUX_RATE_LIMIT_BURST is invalid"), "{}", message);
+        assert!(message.contains("POLLUX_TRUST_PROXY is invalid"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn burst_past_the_limit_is_rejected() {
+        let client = client(RateLimitConfig::new(1, 3, false)).await;
+
+        for _ in 0..3 {
+            assert_eq!(status(&client, "/ping", "10.0.0.1").await.0, Status::Ok);
+        }
+        let (status_code, retry_after) = status(&client, "/ping", "10.0.0.1").await;
+        assert_eq!(status_code, Status::TooManyRequests);
+        let retry_after: u64 = retry_after.unwrap().parse().unwrap();
+        assert!((1..=60).contains(&retry_after), "{}", retry_after);
+
+        // Other clients and health probes aren't affected
+        assert_eq!(status(&client, "/ping", "10.0.0.2").await.0, Status::Ok);
+        assert_eq!(status(&client, "/livez", "10.0.0.1").await.0, Status::Ok);
+    }
+
+    #[tokio::test]
+    async fn client_recovers_after_the_window() {
+        let mut config = RateLimitConfig::new(1, 1, false);
+        config.quota = Quota::with_period(Duration::from_millis(200));
+        let client = client(config).await;
+
+        assert_eq!(status(&client, "/ping", "10.0.0.1").await.0, Status::Ok);
+        assert_eq!(status(&client, "/ping", "10.0.0.1").await.0, Status::TooManyRequests);
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(status(&client, "/ping", "10.0.0.1").await.0, Status::Ok);
+    }
+
+    #[tokio::test]
+    async fn forwarded_for_is_only_used_behind_a_trusted_proxy() {
+        for trust_proxy in [false, true] {
+            let client = client(RateLimitConfig::new(1, 1, trust_proxy)).await;
+
+            for forwarded_for in ["203.0.113.1", "198.51.100.7, 203.0.113.2"] {
+                let response = client
+                    .get("/ping")
+                    .remote("10.0.0.1:4242".parse().unwrap())
+                    .header(Header::new("X-Forwarded-For", forwarded_for))
+                    .dispatch()
+                    .await;
+                // Behind a proxy both are different clients, otherwise it's the same one twice
+                let expected = if trust_proxy || forwarded_for == "203.0.113.1" {
+                    Status::Ok
+                } else {
+                    Status::TooManyRequests
+                };
+                assert_eq!(response.status(), expected, "trust_proxy={}", trust_proxy);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn spoofed_forwarded_for_entries_share_the_real_clients_bucket() {
+        let client = client(RateLimitConfig::new(1, 1, true)).await;
+
+        let mut statuses = Vec::new();
+        for spoofed in ["198.51.100.1", "198.51.100.2"] {
+            let response = client
+                .get("/ping")
+                .remote("10.0.0.1:4242".parse().unwrap())
+                .header(Header::new("X-Forwarded-For", format!("{}, 203.0.113.1", spoofed)))
+                .dispatch()
+                .await;
+            statuses.push(response.status());
+        }
+        assert_eq!(statuses, [Status::Ok, Status::TooManyRequests]);
+    }
+
+    #[test]
+    fn zero_disables_rate_limiting() {
+        assert_eq!(RateLimitConfig::new(0, 10, false).quota, None);
+        assert!(RateLimitConfig::new(60, 0, false).quota.is_some());
+    }
+}
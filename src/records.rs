@@ -0,0 +1,406 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, Duration, DurationRound, NaiveDateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use sqlx::{MySql, Pool};
+
+/// Events closer to each other than this belong to the same session
+static SESSION_GAP_MINUTES: i64 = 120;
+
+/// Extra range around new events which is scanned when updating the records incrementally.
+/// Sessions reaching further than that past the new events are underestimated until the next rebuild.
+static SESSION_LOOKAROUND_DAYS: i64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordKind {
+    BusiestDay,
+    BusiestWeek,
+    BusiestHour,
+    LongestSession,
+}
+
+impl RecordKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordKind::BusiestDay => "busiest_day",
+            RecordKind::BusiestWeek => "busiest_week",
+            RecordKind::BusiestHour => "busiest_hour",
+            RecordKind::LongestSession => "longest_session",
+        }
+    }
+
+    pub fn parse(input: &str) -> Option<RecordKind> {
+        [
+            RecordKind::BusiestDay,
+            RecordKind::BusiestWeek,
+            RecordKind::BusiestHour,
+            RecordKind::LongestSession,
+        ]
+        .into_iter()
+        .find(|kind| kind.as_str() == input)
+    }
+}
+
+/// A single record. `value` is the number of events, or the length in minutes for sessions.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct StatRecord {
+    pub value: u64,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+}
+
+impl StatRecord {
+    /// Higher values win, ties are broken by the earliest occurrence
+    fn beats(&self, other: &StatRecord) -> bool {
+        self.value > other.value || (self.value == other.value && self.started_at < other.started_at)
+    }
+}
+
+pub type StatRecords = BTreeMap<RecordKind, StatRecord>;
+
+fn start_of_day(timestamp: DateTime<Utc>) -> DateTime<Utc> {
+    timestamp.duration_trunc(Duration::days(1)).unwrap()
+}
+
+fn start_of_week(timestamp: DateTime<Utc>) -> DateTime<Utc> {
+    start_of_day(timestamp) - Duration::days(timestamp.weekday().num_days_from_monday() as i64)
+}
+
+fn start_of_hour(timestamp: DateTime<Utc>) -> DateTime<Utc> {
+    timestamp.duration_trunc(Duration::hours(1)).unwrap()
+}
+
+/// Returns the bucket with the most events, the earliest one on ties
+fn busiest_bucket(
+    timestamps: &[DateTime<Utc>],
+    bucket_start: fn(DateTime<Utc>) -> DateTime<Utc>,
+    bucket_length: Duration,
+) -> Option<StatRecord> {
+    let mut buckets: BTreeMap<DateTime<Utc>, u64> = BTreeMap::new();
+    for timestamp in timestamps {
+        *buckets.entry(bucket_start(*timestamp)).or_default() += 1;
+    }
+
+    let mut busiest: Option<StatRecord> = None;
+    for (started_at, value) in buckets {
+        let candidate = StatRecord {
+            value,
+            started_at,
+            ended_at: started_at + bucket_length,
+        };
+        if busiest.as_ref().is_none_or(|busiest| candidate.beats(busiest)) {
+            busiest = Some(candidate);
+        }
+    }
+    busiest
+}
+
+fn longest_session(sorted_timestamps: &[DateTime<Utc>]) -> Option<StatRecord> {
+    let mut longest: Option<StatRecord> = None;
+    let mut session: Option<(DateTime<Utc>, DateTime<Utc>)> = None;
+
+    let mut finish = |(started_at, ended_at): (DateTime<Utc>, DateTime<Utc>)| {
+        let candidate = StatRecord {
+            value: (ended_at - started_at).num_minutes() as u64,
+            started_at,
+            ended_at,
+        };
+        if longest.as_ref().is_none_or(|longest| candidate.beats(longest)) {
+            longest = Some(candidate);
+        }
+    };
+
+    for timestamp in sorted_timestamps {
+        session = match session {
+            Some((started_at, ended_at)) if *timestamp - ended_at <= Duration::minutes(SESSION_GAP_MINUTES) => {
+                Some((started_at, *timestamp))
+            }
+            Some(finished) => {
+                finish(finished);
+                Some((*timestamp, *timestamp))
+            }
+            None => Some((*timestamp, *timestamp)),
+        };
+    }
+    if let Some(finished) = session {
+        finish(finished);
+    }
+
+    longest
+}
+
+/// Computes all records from the given event timestamps (in any order)
+pub fn compute_records(timestamps: &[DateTime<Utc>]) -> StatRecords {
+    let mut sorted = timestamps.to_vec();
+    sorted.sort();
+
+    [
+        (RecordKind::BusiestDay, busiest_bucket(&sorted, start_of_day, Duration::days(1))),
+        (RecordKind::BusiestWeek, busiest_bucket(&sorted, start_of_week, Duration::weeks(1))),
+        (RecordKind::BusiestHour, busiest_bucket(&sorted, start_of_hour, Duration::hours(1))),
+        (RecordKind::LongestSession, longest_session(&sorted)),
+    ]
+    .into_iter()
+    .filter_map(|(kind, record)| Some((kind, record?)))
+    .collect()
+}
+
+/// Merges candidates into the current records, returning the kinds which changed
+pub fn merge_records(current: &mut StatRecords, candidates: StatRecords) -> Vec<RecordKind> {
+    let mut changed = Vec::new();
+    for (kind, candidate) in candidates {
+        let replaces = match current.get(&kind) {
+            Some(existing) => candidate.beats(existing),
+            None => true,
+        };
+        if replaces {
+            current.insert(kind, candidate);
+            changed.push(kind);
+        }
+    }
+    changed
+}
+
+async fn get_event_timestamps(
+    pool: &Pool<MySql>,
+    range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+) -> Vec<DateTime<Utc>> {
+    // Bounds of MySQL's DATETIME
+    let (from, to) = range.unwrap_or((
+        "1000-01-01T00:00:00Z".parse().unwrap(),
+        "9999-12-31T23:59:59Z".parse().unwrap(),
+    ));
+    let rows: Vec<(NaiveDateTime,)> = sqlx::query_as(
+        r#"
+            SELECT evt.timestamp
+            FROM
+                Events AS evt,
+                GitEvents AS gevt
+            WHERE evt.timestamp >= ?
+            AND   evt.timestamp < ?
+            AND   evt.id = gevt.id
+            "#,
+    )
+    .bind(from.naive_utc())
+    .bind(to.naive_utc())
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    rows.into_iter().map(|(timestamp,)| timestamp.and_utc()).collect()
+}
+
+pub async fn get_stat_records(pool: &Pool<MySql>) -> StatRecords {
+    let rows: Vec<(String, u32, NaiveDateTime, NaiveDateTime)> =
+        sqlx::query_as("SELECT record, value, started_at, ended_at FROM StatRecords")
+            .fetch_all(pool)
+            .await
+            .unwrap();
+
+    rows.into_iter()
+        .filter_map(|(record, value, started_at, ended_at)| {
+            let Some(kind) = RecordKind::parse(&record) else {
+                warn!("Ignoring unknown stat record »{}«", record);
+                return None;
+            };
+            Some((
+                kind,
+                StatRecord {
+                    value: value as u64,
+                    started_at: started_at.and_utc(),
+                    ended_at: ended_at.and_utc(),
+                },
+            ))
+        })
+        .collect()
+}
+
+async fn store_stat_records(pool: &Pool<MySql>, records: &StatRecords, kinds: &[RecordKind]) {
+    for kind in kinds {
+        let record = &records[kind];
+        sqlx::query("REPLACE INTO StatRecords (record, value, started_at, ended_at, updated_at) VALUES ( ?, ?, ?, ?, ? )")
+            .bind(kind.as_str())
+            .bind(record.value.min(u32::MAX as u64) as u32)
+            .bind(record.started_at.format("%Y-%m-%d %H:%M:%S").to_string())
+            .bind(record.ended_at.format("%Y-%m-%d %H:%M:%S").to_string())
+            .bind(Utc::now().format("%Y-%m-%d %H:%M:%S").to_string())
+            .execute(pool)
+            .await
+            .unwrap();
+        info!("New record for {}: {:?}", kind.as_str(), record);
+    }
+}
+
+/// Updates the records after new events were inserted, only looking at the weeks they fall into
+pub async fn update_stat_records(pool: &Pool<MySql>, inserted: &[DateTime<Utc>]) {
+    let (Some(first), Some(last)) = (inserted.iter().min(), inserted.iter().max()) else {
+        return;
+    };
+
+    let from = start_of_week(*first).min(*first - Duration::days(SESSION_LOOKAROUND_DAYS));
+    let to = (start_of_week(*last) + Duration::weeks(1)).max(*last + Duration::days(SESSION_LOOKAROUND_DAYS));
+    let candidates = compute_records(&get_event_timestamps(pool, Some((from, to))).await);
+
+    let mut records = get_stat_records(pool).await;
+    let changed = merge_records(&mut records, candidates);
+    store_stat_records(pool, &records, &changed).await;
+}
+
+/// Recomputes all records from scratch
+pub async fn rebuild_stat_records(pool: &Pool<MySql>) -> StatRecords {
+    info!("Rebuilding stat records...");
+    let records = compute_records(&get_event_timestamps(pool, None).await);
+
+    sqlx::query("DELETE FROM StatRecords").execute(pool).await.unwrap();
+    let kinds: Vec<RecordKind> = records.keys().copied().collect();
+    store_stat_records(pool, &records, &kinds).await;
+
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(input: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(input).unwrap().with_timezone(&Utc)
+    }
+
+    fn seed() -> Vec<DateTime<Utc>> {
+        vec![
+            // Wednesday: 3 events in the same hour, one session of 3h 30min
+            at("2024-05-01T09:00:00Z"),
+            at("2024-05-01T09:10:00Z"),
+            at("2024-05-01T09:20:00Z"),
+            at("2024-05-01T11:00:00Z"),
+            at("2024-05-01T12:30:00Z"),
+            // Thursday
+            at("2024-05-02T20:00:00Z"),
+            // Monday of the next week: 5 events, ties the busiest day later
+            at("2024-05-06T08:00:00Z"),
+            at("2024-05-06T13:00:00Z"),
+            at("2024-05-06T18:00:00Z"),
+            at("2024-05-06T23:00:00Z"),
+            at("2024-05-06T23:30:00Z"),
+        ]
+    }
+
+    #[test]
+    fn records_are_computed_from_events() {
+        let records = compute_records(&seed());
+
+        assert_eq!(
+            records[&RecordKind::BusiestDay],
+            StatRecord {
+                value: 5,
+                started_at: at("2024-05-01T00:00:00Z"),
+                ended_at: at("2024-05-02T00:00:00Z"),
+            }
+        );
+        assert_eq!(records[&RecordKind::BusiestWeek].value, 6);
+        assert_eq!(records[&RecordKind::BusiestWeek].started_at, at("2024-04-29T00:00:00Z"));
+        assert_eq!(records[&RecordKind::BusiestHour].value, 3);
+        assert_eq!(records[&RecordKind::BusiestHour].started_at, at("2024-05-01T09:00:00Z"));
+        assert_eq!(
+            records[&RecordKind::LongestSession],
+            StatRecord {
+                value: 210,
+                started_at: at("2024-05-01T09:00:00Z"),
+                ended_at: at("2024-05-01T12:30:00Z"),
+            }
+        );
+    }
+
+    #[test]
+    fn ties_are_broken_by_earliest_occurrence() {
+        let records = compute_records(&seed());
+        // 2024-05-06 has 5 events as well
+        assert_eq!(records[&RecordKind::BusiestDay].started_at, at("2024-05-01T00:00:00Z"));
+
+        let mut current = records.clone();
+        let later_tie = compute_records(&seed()[6..]);
+        assert_eq!(later_tie[&RecordKind::BusiestDay].value, 5);
+        assert!(!merge_records(&mut current, later_tie).contains(&RecordKind::BusiestDay));
+        assert_eq!(current, records);
+    }
+
+    #[test]
+    fn record_breaking_day_replaces_the_record() {
+        let mut records = compute_records(&seed());
+
+        let new_day: Vec<DateTime<Utc>> = (0..6)
+            .map(|hour| at("2024-06-10T08:00:00Z") + Duration::hours(hour * 3))
+            .collect();
+        let changed = merge_records(&mut records, compute_records(&new_day));
+
+        assert_eq!(changed, vec![RecordKind::BusiestDay]);
+        assert_eq!(records[&RecordKind::BusiestDay].value, 6);
+        assert_eq!(records[&RecordKind::BusiestDay].started_at, at("2024-06-10T00:00:00Z"));
+        assert_eq!(records[&RecordKind::BusiestWeek].value, 6);
+        assert_eq!(records[&RecordKind::BusiestWeek].started_at, at("2024-04-29T00:00:00Z"));
+    }
+
+    #[test]
+    fn no_events_no_records() {
+        assert!(compute_records(&[]).is_empty());
+    }
+
+    #[test]
+    fn records_are_serialized_by_name() {
+        let records = compute_records(&[at("2024-05-01T09:00:00Z")]);
+        let json = serde_json::to_value(&records).unwrap();
+        assert_eq!(json["busiest_hour"]["value"], 1);
+        assert_eq!(json["longest_session"]["value"], 0);
+    }
+
+    #[tokio::test]
+    async fn records_are_maintained_in_database() {
+        let (_container, pool) = crate::database::tests::initialize().await;
+        sqlx::query("INSERT INTO GitPlatforms (name, firstSync) VALUES ('Github', NOW())")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO GitActions (id, name) VALUES (1, 'commit')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO GitProjects (id, name, url, platform, platform_project_id) VALUES (1, 'pollux', '', 'Github', 1)")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let insert = |timestamps: Vec<DateTime<Utc>>| {
+            let pool = pool.clone();
+            async move {
+                for timestamp in timestamps.iter() {
+                    let id = sqlx::query("INSERT INTO Events (timestamp) VALUES ( ? )")
+                        .bind(timestamp.naive_utc())
+                        .execute(&pool)
+                        .await
+                        .unwrap()
+                        .last_insert_id();
+                    sqlx::query("INSERT INTO GitEvents (id, action_fk, project_fk) VALUES ( ?, 1, 1 )")
+                        .bind(id)
+                        .execute(&pool)
+                        .await
+                        .unwrap();
+                }
+                update_stat_records(&pool, &timestamps).await;
+            }
+        };
+
+        insert(seed()).await;
+        assert_eq!(get_stat_records(&pool).await, compute_records(&seed()));
+        assert_eq!(rebuild_stat_records(&pool).await, compute_records(&seed()));
+
+        let new_day: Vec<DateTime<Utc>> = (0..6)
+            .map(|hour| at("2024-06-10T08:00:00Z") + Duration::hours(hour * 3))
+            .collect();
+        insert(new_day).await;
+        let records = get_stat_records(&pool).await;
+        assert_eq!(records[&RecordKind::BusiestDay].value, 6);
+        assert_eq!(records[&RecordKind::BusiestDay].started_at, at("2024-06-10T00:00:00Z"));
+    }
+}
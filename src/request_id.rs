@@ -0,0 +1,158 @@
+use std::{fmt, time::Instant};
+
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::Header,
+    request::{FromRequest, Outcome},
+    Data, Request, Response,
+};
+use uuid::Uuid;
+
+static REQUEST_ID_HEADER: &str = "X-Request-Id";
+/// Longer incoming ids are replaced, they'd only bloat every log line
+static MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Id of the current request, either taken from `X-Request-Id` or generated.
+/// Handlers can take it as a request guard to include it in their log lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestId(pub String);
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH && id.bytes().all(|byte| byte.is_ascii_graphic())
+}
+
+/// What the access log needs from `on_request`, before other fairings (e.g. the rate limit) rewrite the request
+struct RequestStart {
+    id: RequestId,
+    started_at: Instant,
+    method: String,
+    path: String,
+}
+
+fn request_start<'r>(request: &'r Request<'_>) -> &'r RequestStart {
+    request.local_cache(|| {
+        let id = request
+            .headers()
+            .get_one(REQUEST_ID_HEADER)
+            .filter(|id| is_valid_request_id(id))
+            .map(str::to_string)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        RequestStart {
+            id: RequestId(id),
+            started_at: Instant::now(),
+            method: request.method().to_string(),
+            path: request.uri().path().to_string(),
+        }
+    })
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestId {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(request_start(request).id.clone())
+    }
+}
+
+/// Assigns every request an id, echoes it in `X-Request-Id` and writes one access log line per request
+pub struct RequestIds;
+
+#[rocket::async_trait]
+impl Fairing for RequestIds {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request ids and access log",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request_start(request);
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let start = request_start(request);
+        response.set_header(Header::new(REQUEST_ID_HEADER, start.id.0.clone()));
+
+        // Rocket's info! can't take a target, the access log gets its own so it can be filtered
+        log::info!(
+            target: "pollux::access",
+            "request_id={} method={} path={} status={} duration_ms={}",
+            start.id,
+            start.method,
+            start.path,
+            response.status().code,
+            start.started_at.elapsed().as_millis()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{http::Status, local::asynchronous::Client};
+
+    use super::*;
+
+    #[get("/echo")]
+    fn echo(id: RequestId) -> String {
+        id.0
+    }
+
+    async fn client() -> Client {
+        Client::untracked(rocket::build().attach(RequestIds).mount("/", routes![echo]))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn generated_id_is_echoed() {
+        let client = client().await;
+
+        let response = client.get("/echo").dispatch().await;
+        let header = response.headers().get_one(REQUEST_ID_HEADER).unwrap().to_string();
+        assert!(Uuid::parse_str(&header).is_ok(), "{}", header);
+        // Handlers see the same id
+        assert_eq!(response.into_string().await.unwrap(), header);
+
+        let other = client.get("/echo").dispatch().await;
+        assert_ne!(other.headers().get_one(REQUEST_ID_HEADER).unwrap(), header);
+    }
+
+    #[tokio::test]
+    async fn supplied_id_is_reused() {
+        let client = client().await;
+
+        let response = client
+            .get("/echo")
+            .header(Header::new(REQUEST_ID_HEADER, "upstream-42"))
+            .dispatch()
+            .await;
+        assert_eq!(response.headers().get_one(REQUEST_ID_HEADER), Some("upstream-42"));
+        assert_eq!(response.into_string().await.unwrap(), "upstream-42");
+
+        // Also for requests no route answered
+        let response = client
+            .get("/missing")
+            .header(Header::new(REQUEST_ID_HEADER, "upstream-43"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(response.headers().get_one(REQUEST_ID_HEADER), Some("upstream-43"));
+    }
+
+    #[test]
+    fn unusable_ids_are_replaced() {
+        assert!(is_valid_request_id("7d3c2f1e-0000-4000-8000-000000000000"));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("with space"));
+        assert!(!is_valid_request_id(&"x".repeat(MAX_REQUEST_ID_LENGTH + 1)));
+    }
+}
@@ -0,0 +1,306 @@
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, info};
+use once_cell::sync::Lazy;
+use sqlx::{MySql, Pool, QueryBuilder};
+use tokio::sync::Mutex;
+
+use crate::{
+    config::{ConfigError, EnvConfig},
+    database,
+    platform_registry::PlatformRegistry,
+    queries::push_ids,
+    stats,
+};
+
+static RETENTION_DAYS: &str = "POLLUX_RETENTION_DAYS";
+/// Events deleted per statement, so pruning doesn't lock `Events` for long
+static BATCH_SIZE: u64 = 1000;
+/// Pruning piggybacks on the syncs, but only runs once in a while
+static PRUNE_INTERVAL_HOURS: i64 = 24;
+
+/// When the events were pruned last, shared by the sync tasks of all platforms
+static LAST_PRUNED: Lazy<Mutex<Option<DateTime<Utc>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Events older than `POLLUX_RETENTION_DAYS` are deleted, see `prune_if_due`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionConfig {
+    pub max_age: Duration,
+    /// Roll the events up into `DailyAggregates` first, so the calendar keeps counting them
+    pub rollup: bool,
+    /// Delete projects left without any events afterwards
+    pub prune_projects: bool,
+}
+
+impl RetentionConfig {
+    /// `None` without `POLLUX_RETENTION_DAYS`, events are kept forever then
+    pub fn from_env() -> Result<Option<RetentionConfig>, ConfigError> {
+        RetentionConfig::from(EnvConfig::from_env())
+    }
+
+    fn from(mut env: EnvConfig) -> Result<Option<RetentionConfig>, ConfigError> {
+        if env.optional(RETENTION_DAYS).is_none() {
+            return env.build(None);
+        }
+        // Unparsable values are reported by `parsed` already
+        let days: u32 = env.parsed(RETENTION_DAYS, 1);
+        if days == 0 {
+            env.invalid(RETENTION_DAYS, "has to be at least 1 day, leave it unset to keep events forever");
+        }
+        let config = RetentionConfig {
+            max_age: Duration::days(days.into()),
+            rollup: env.parsed("POLLUX_RETENTION_ROLLUP", true),
+            prune_projects: env.parsed("POLLUX_RETENTION_PRUNE_PROJECTS", false),
+        };
+        env.build(Some(config))
+    }
+}
+
+/// Events which happened before this are pruned, and not inserted again by syncs fetching them.
+/// `None` if events are kept forever.
+pub fn cutoff(now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    // Checked at startup already, see `config::check`
+    let config = RetentionConfig::from_env().ok()??;
+    Some(now - config.max_age)
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct PruneReport {
+    pub events_deleted: u64,
+    /// Statements it took, at most `BATCH_SIZE` events each
+    pub batches: u64,
+    pub projects_deleted: u64,
+}
+
+/// Prunes the events if `POLLUX_RETENTION_DAYS` is set and they weren't pruned for a day.
+/// Called after every scheduled sync, the syncs of all platforms are held off meanwhile.
+pub async fn prune_if_due(registry: &PlatformRegistry) {
+    let Some(config) = RetentionConfig::from_env().ok().flatten() else {
+        return;
+    };
+    // Another platform's sync task is pruning already
+    let Ok(mut last_pruned) = LAST_PRUNED.try_lock() else {
+        return;
+    };
+    let now = Utc::now();
+    if last_pruned.is_some_and(|last_pruned| now - last_pruned < Duration::hours(PRUNE_INTERVAL_HOURS)) {
+        return;
+    }
+
+    let _paused = registry.pause_syncs().await;
+    let db = database::Database::get_or_init().await;
+    let pool = db.get_pool().await;
+    let report = prune(&pool, now - config.max_age, &config, BATCH_SIZE).await;
+    *last_pruned = Some(now);
+
+    info!(
+        "Pruned {} events older than {} days in {} batches{}, and {} projects without events",
+        report.events_deleted,
+        config.max_age.num_days(),
+        report.batches,
+        if config.rollup { " (rolled up into DailyAggregates)" } else { "" },
+        report.projects_deleted
+    );
+}
+
+/// Deletes the events before `cutoff`, at most `batch_size` per transaction
+pub async fn prune(pool: &Pool<MySql>, cutoff: DateTime<Utc>, config: &RetentionConfig, batch_size: u64) -> PruneReport {
+    let cutoff = cutoff.format("%Y-%m-%d %H:%M:%S").to_string();
+    let mut report = PruneReport::default();
+    loop {
+        let deleted = prune_batch(pool, &cutoff, config.rollup, batch_size).await;
+        if deleted == 0 {
+            break;
+        }
+        report.events_deleted += deleted;
+        report.batches += 1;
+        debug!("Pruned a batch of {} events", deleted);
+        if deleted < batch_size {
+            break;
+        }
+    }
+
+    if config.prune_projects {
+        report.projects_deleted =
+            sqlx::query("DELETE FROM GitProjects WHERE NOT EXISTS (SELECT 1 FROM GitEvents WHERE project_fk = GitProjects.id)")
+                .execute(pool)
+                .await
+                .unwrap()
+                .rows_affected();
+    }
+    if report.events_deleted > 0 {
+        stats::invalidate_today_cache();
+    }
+    report
+}
+
+/// Rolls up and deletes the oldest `batch_size` events before `cutoff` in one transaction,
+/// so an interrupted prune never counts an event twice. Returns how many were deleted.
+async fn prune_batch(pool: &Pool<MySql>, cutoff: &str, rollup: bool, batch_size: u64) -> u64 {
+    let mut tx = pool.begin().await.expect("Couldn't start transaction!");
+    let ids: Vec<u64> = sqlx::query_scalar("SELECT id FROM Events WHERE timestamp < ? ORDER BY timestamp, id LIMIT ? FOR UPDATE")
+        .bind(cutoff)
+        .bind(batch_size)
+        .fetch_all(&mut *tx)
+        .await
+        .unwrap();
+    if ids.is_empty() {
+        return 0;
+    }
+
+    if rollup {
+        // Grouped in a derived table, the update can't refer to a grouped select otherwise
+        let mut aggregates = QueryBuilder::<MySql>::new(
+            "INSERT INTO DailyAggregates (platform, day, action_fk, event_count, commit_count) \
+                SELECT * FROM ( \
+                    SELECT gpro.platform, DATE(evt.timestamp) AS day, gevt.action_fk, \
+                        COUNT(1) AS events, SUM(COALESCE(gevt.commit_count, 1)) AS commits \
+                    FROM Events AS evt, GitEvents AS gevt, GitProjects AS gpro \
+                    WHERE evt.id = gevt.id AND gevt.project_fk = gpro.id AND evt.id IN ",
+        );
+        push_ids(&mut aggregates, &ids);
+        aggregates.push(
+            " GROUP BY gpro.platform, DATE(evt.timestamp), gevt.action_fk \
+                ) AS batch \
+                ON DUPLICATE KEY UPDATE event_count = event_count + batch.events, commit_count = commit_count + batch.commits",
+        );
+        aggregates.build().execute(&mut *tx).await.unwrap();
+    }
+
+    // Their `GitEvents` rows are removed by the foreign key cascade
+    let mut events = QueryBuilder::<MySql>::new("DELETE FROM Events WHERE id IN ");
+    push_ids(&mut events, &ids);
+    let deleted = events.build().execute(&mut *tx).await.unwrap().rows_affected();
+
+    tx.commit().await.expect("Couldn't apply transaction ._.");
+    deleted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::Weight;
+    use chrono::{NaiveDate, TimeZone};
+
+    fn env(vars: &'static [(&'static str, &'static str)]) -> EnvConfig {
+        EnvConfig::from(|name| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string()))
+    }
+
+    #[test]
+    fn events_are_kept_forever_by_default() {
+        assert_eq!(RetentionConfig::from(env(&[("POLLUX_RETENTION_ROLLUP", "false")])), Ok(None));
+        assert_eq!(
+            RetentionConfig::from(env(&[("POLLUX_RETENTION_DAYS", "730")])),
+            Ok(Some(RetentionConfig {
+                max_age: Duration::days(730),
+                rollup: true,
+                prune_projects: false,
+            }))
+        );
+    }
+
+    #[test]
+    fn invalid_retentions_are_reported() {
+        let message = RetentionConfig::from(env(&[("POLLUX_RETENTION_DAYS", "0")])).unwrap_err().to_string();
+        assert!(message.contains("POLLUX_RETENTION_DAYS is invalid: has to be at least 1 day"), "{}", message);
+
+        let message = RetentionConfig::from(env(&[("POLLUX_RETENTION_DAYS", "two years"), ("POLLUX_RETENTION_ROLLUP", "yes")]))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(message.matches("POLLUX_RETENTION_DAYS is invalid").count(), 1, "{}", message);
+        assert!(message.contains("POLLUX_RETENTION_ROLLUP is invalid"), "{}", message);
+    }
+
+    fn config(rollup: bool, prune_projects: bool) -> RetentionConfig {
+        RetentionConfig {
+            max_age: Duration::days(730),
+            rollup,
+            prune_projects,
+        }
+    }
+
+    /// Five old events of two projects, and a recent one of a third project
+    async fn seed() -> (testcontainers::ContainerAsync<testcontainers::GenericImage>, Pool<MySql>) {
+        let (container, pool) = crate::database::tests::initialize().await;
+        for statement in [
+            "INSERT INTO GitPlatforms (name, firstSync) VALUES ('Github', NOW()), ('Gitlab', NOW())",
+            "INSERT INTO GitActions (id, name) VALUES (1, 'commit'), (2, 'starred')",
+            "INSERT INTO GitProjects (id, name, url, platform, platform_project_id) VALUES \
+                (1, '2tefan/pollux', '', 'Github', 1), (2, '2tefan/castor', '', 'Gitlab', 2), \
+                (3, '2tefan/dotfiles', '', 'Github', 3)",
+            "INSERT INTO Events (id, timestamp) VALUES \
+                (1, '2022-05-01 10:00:00'), (2, '2022-05-01 12:00:00'), (3, '2022-05-01 13:00:00'), \
+                (4, '2022-05-02 10:00:00'), (5, '2022-05-02 11:00:00'), (6, '2024-05-01 10:00:00')",
+            "INSERT INTO GitEvents (id, action_fk, project_fk, commit_count) VALUES \
+                (1, 1, 1, 3), (2, 1, 1, NULL), (3, 2, 1, NULL), (4, 1, 2, 2), (5, 1, 2, 4), (6, 1, 3, 1)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        (container, pool)
+    }
+
+    fn cutoff() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    async fn remaining_events(pool: &Pool<MySql>) -> Vec<u64> {
+        sqlx::query_scalar("SELECT id FROM GitEvents ORDER BY id").fetch_all(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn events_are_pruned_in_batches() {
+        let (_container, pool) = seed().await;
+
+        let report = prune(&pool, cutoff(), &config(false, false), 2).await;
+        assert_eq!(
+            report,
+            PruneReport {
+                events_deleted: 5,
+                batches: 3,
+                projects_deleted: 0,
+            }
+        );
+        assert_eq!(remaining_events(&pool).await, vec![6]);
+        let aggregates: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM DailyAggregates").fetch_one(&pool).await.unwrap();
+        assert_eq!(aggregates, 0);
+
+        // Nothing left to prune
+        assert_eq!(prune(&pool, cutoff(), &config(false, false), 2).await, PruneReport::default());
+    }
+
+    #[tokio::test]
+    async fn pruned_events_are_still_counted_per_day() {
+        let (_container, pool) = seed().await;
+        let since = NaiveDate::from_ymd_opt(2022, 1, 1).unwrap();
+        let before = (
+            stats::get_event_counts_per_day(&pool, since, Weight::Events, &[]).await,
+            stats::get_event_counts_per_day(&pool, since, Weight::Commits, &[]).await,
+            stats::get_event_counts_per_day(&pool, since, Weight::Events, &["starred".to_string()]).await,
+        );
+
+        // Batches which split a day are added up
+        let report = prune(&pool, cutoff(), &config(true, false), 2).await;
+        assert_eq!(report.events_deleted, 5);
+        assert_eq!(remaining_events(&pool).await, vec![6]);
+
+        let after = (
+            stats::get_event_counts_per_day(&pool, since, Weight::Events, &[]).await,
+            stats::get_event_counts_per_day(&pool, since, Weight::Commits, &[]).await,
+            stats::get_event_counts_per_day(&pool, since, Weight::Events, &["starred".to_string()]).await,
+        );
+        assert_eq!(after, before);
+        let day = |day| ("Github".to_string(), NaiveDate::from_ymd_opt(2022, 5, day).unwrap());
+        assert_eq!(after.0.get(&day(1)), Some(&3));
+        assert_eq!(after.1.get(&day(1)), Some(&5));
+        assert_eq!(after.2.get(&day(1)), Some(&2));
+    }
+
+    #[tokio::test]
+    async fn projects_without_events_are_pruned_on_request() {
+        let (_container, pool) = seed().await;
+
+        let report = prune(&pool, cutoff(), &config(true, true), BATCH_SIZE).await;
+        assert_eq!((report.events_deleted, report.batches, report.projects_deleted), (5, 1, 2));
+        let projects: Vec<u64> = sqlx::query_scalar("SELECT id FROM GitProjects").fetch_all(&pool).await.unwrap();
+        assert_eq!(projects, vec![3]);
+    }
+}
@@ -0,0 +1,299 @@
+use std::{future::Future, time::Duration};
+
+use governor::Jitter;
+use log::warn;
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+use crate::{
+    config,
+    http_client::{HttpClient, HttpError, HttpRequest, HttpResponse},
+};
+
+static FALLBACK_RETRY_ATTEMPTS: u32 = 3;
+static FALLBACK_RETRY_BASE_DELAY_MS: u64 = 500;
+static FALLBACK_RETRY_MAX_DELAY_SECS: u64 = 30;
+
+/// Parses the env var `name`, warning about and falling back to `fallback` if it isn't valid
+pub fn env_parse<T: std::str::FromStr + std::fmt::Display>(name: &str, fallback: T) -> T
+where
+    T::Err: std::fmt::Display,
+{
+    match config::var(name) {
+        Some(input) => match input.parse::<T>() {
+            Ok(result) => result,
+            Err(err) => {
+                warn!("Unable to parse {} »{}«, using »{}« as a fallback: {}", name, input, fallback, err);
+                fallback
+            }
+        },
+        None => fallback,
+    }
+}
+
+/// Retries requests which failed for reasons likely gone a moment later: connection problems,
+/// timeouts and 5xx responses. 4xx responses are the client's fault and returned right away.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Including the first one, `1` disables retrying
+    pub attempts: u32,
+    /// Delay before the first retry, doubled for every further one
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> RetryPolicy {
+        RetryPolicy {
+            attempts: env_parse("POLLUX_HTTP_RETRY_ATTEMPTS", FALLBACK_RETRY_ATTEMPTS).max(1),
+            base_delay: Duration::from_millis(env_parse(
+                "POLLUX_HTTP_RETRY_BASE_DELAY_MS",
+                FALLBACK_RETRY_BASE_DELAY_MS,
+            )),
+            max_delay: Duration::from_secs(env_parse("POLLUX_HTTP_RETRY_MAX_DELAY_SECS", FALLBACK_RETRY_MAX_DELAY_SECS)),
+        }
+    }
+
+    /// Delay before retry number `retry` (starting at 1), without jitter
+    fn backoff(&self, retry: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(retry - 1))
+            .min(self.max_delay)
+    }
+
+    /// Sends the request built by `request` until it gets an answer which isn't a 5xx or all attempts are used up.
+    /// The last answer or error is returned as is.
+    pub async fn send(&self, platform: &str, request: impl Fn() -> RequestBuilder) -> Result<Response, reqwest::Error> {
+        self.retrying(platform, || request().send()).await
+    }
+
+    /// Like `send`, for the requests of an `HttpClient`
+    #[cfg_attr(not(any(feature = "github", feature = "gitlab")), allow(dead_code))]
+    pub async fn send_http(
+        &self,
+        platform: &str,
+        client: &dyn HttpClient,
+        request: &HttpRequest,
+    ) -> Result<HttpResponse, HttpError> {
+        self.retrying(platform, || client.send(request.clone())).await
+    }
+
+    async fn retrying<A: Attempt, F: Future<Output = A>>(&self, platform: &str, send: impl Fn() -> F) -> A {
+        let mut attempt = 1;
+        loop {
+            let result = send().await;
+            let reason = match result.retry_reason() {
+                Some(reason) if attempt < self.attempts => reason,
+                _ => return result,
+            };
+
+            // Spread out the retries, in case the platform is just recovering
+            let backoff = self.backoff(attempt);
+            let wait = Jitter::up_to(backoff / 2) + backoff;
+            warn!(
+                "Request to {} failed ({}), retrying in {:?} ({}/{})",
+                platform,
+                reason,
+                wait,
+                attempt,
+                self.attempts - 1
+            );
+            tokio::time::sleep(wait).await;
+            attempt += 1;
+        }
+    }
+
+    /// Like `send`, but 429 responses are retried as well, up to `rate_limit_retries` times with the same backoff.
+    /// For platforms which don't say how long to wait, the last 429 is returned once the retries are used up.
+    pub async fn send_rate_limited(
+        &self,
+        platform: &str,
+        rate_limit_retries: u32,
+        request: impl Fn() -> RequestBuilder,
+    ) -> Result<Response, reqwest::Error> {
+        let mut retry = 0;
+        loop {
+            let response = self.send(platform, &request).await?;
+            if response.status() != StatusCode::TOO_MANY_REQUESTS || retry == rate_limit_retries {
+                return Ok(response);
+            }
+
+            retry += 1;
+            let backoff = self.backoff(retry);
+            let wait = Jitter::up_to(backoff / 2) + backoff;
+            warn!(
+                "{} answered {}, retrying in {:?} ({}/{})",
+                platform,
+                response.status(),
+                wait,
+                retry,
+                rate_limit_retries
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+fn is_transient(err: &reqwest::Error) -> bool {
+    err.is_connect() || err.is_timeout()
+}
+
+/// An answer to a request, or why there is none
+trait Attempt {
+    /// Why the request is worth another try, `None` if it's returned as is
+    fn retry_reason(&self) -> Option<String>;
+}
+
+impl Attempt for Result<Response, reqwest::Error> {
+    fn retry_reason(&self) -> Option<String> {
+        match self {
+            Ok(response) => response.status().is_server_error().then(|| response.status().to_string()),
+            Err(err) => is_transient(err).then(|| err.to_string()),
+        }
+    }
+}
+
+impl Attempt for Result<HttpResponse, HttpError> {
+    fn retry_reason(&self) -> Option<String> {
+        match self {
+            Ok(response) => response.status.is_server_error().then(|| response.status.to_string()),
+            Err(err) => err.transient.then(|| err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let policy = RetryPolicy {
+            attempts: 10,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(3),
+        };
+        let delays: Vec<_> = (1..=5).map(|retry| policy.backoff(retry)).collect();
+        assert_eq!(
+            delays,
+            [500, 1000, 2000, 3000, 3000].map(Duration::from_millis).to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried_until_they_pass() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/events"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .with_priority(1)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/events"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = reqwest::Client::new();
+        let url = format!("{}/events", server.uri());
+        let response = policy().send("Github", || client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn last_server_error_is_returned_once_attempts_are_used_up() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let client = reqwest::Client::new();
+        let response = policy().send("Github", || client.get(server.uri())).await.unwrap();
+        assert_eq!(response.status(), 500);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = reqwest::Client::new();
+        let response = policy().send("Gitlab", || client.get(server.uri())).await.unwrap();
+        assert_eq!(response.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn connection_errors_are_retried() {
+        // Nothing listens on the port anymore once the listener is dropped
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+
+        let client = reqwest::Client::new();
+        let url = format!("http://127.0.0.1:{}/events", port);
+        let result = policy()
+            .send("Gitlab", || {
+                attempts.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                client.get(&url)
+            })
+            .await;
+        assert!(result.unwrap_err().is_connect());
+        assert_eq!(attempts.into_inner(), 3);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_are_retried_with_backoff() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429))
+            .up_to_n_times(2)
+            .with_priority(1)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = reqwest::Client::new();
+        let response = policy().send_rate_limited("Bitbucket", 2, || client.get(server.uri())).await.unwrap();
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn last_rate_limit_is_returned_once_retries_are_used_up() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let client = reqwest::Client::new();
+        let response = policy().send_rate_limited("Bitbucket", 1, || client.get(server.uri())).await.unwrap();
+        assert_eq!(response.status(), 429);
+    }
+}
@@ -0,0 +1,383 @@
+use std::{future::Future, time::Instant};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::{
+    client::{ClientError, PolluxClient},
+    sync_runs::{SyncRun, SyncRunStatus},
+};
+
+static FALLBACK_MAX_SYNC_AGE_HOURS: i64 = 25;
+static SYNC_RUNS_TO_INSPECT: u32 = 50;
+static DAYS_TO_QUERY: i64 = 7;
+
+static USAGE: &str =
+    "Usage: pollux smoke-test --base-url <url> [--admin-token <token>] [--max-sync-age-hours <hours>]";
+
+#[derive(Debug, PartialEq)]
+pub struct SmokeTestOptions {
+    pub base_url: String,
+    pub admin_token: Option<String>,
+    pub max_sync_age: Duration,
+}
+
+impl SmokeTestOptions {
+    pub fn from_args(args: &[String]) -> Result<SmokeTestOptions, String> {
+        let mut base_url = None;
+        let mut admin_token = None;
+        let mut max_sync_age = Duration::hours(FALLBACK_MAX_SYNC_AGE_HOURS);
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("Missing value for {}\n{}", arg, USAGE))
+            };
+            match arg.as_str() {
+                "--base-url" => base_url = Some(value()?),
+                "--admin-token" => admin_token = Some(value()?),
+                "--max-sync-age-hours" => {
+                    let input = value()?;
+                    max_sync_age = match input.parse::<u32>() {
+                        Ok(hours) => Duration::hours(hours.into()),
+                        Err(err) => return Err(format!("Invalid --max-sync-age-hours »{}«: {}", input, err)),
+                    };
+                }
+                _ => return Err(format!("Unknown argument »{}«\n{}", arg, USAGE)),
+            }
+        }
+
+        Ok(SmokeTestOptions {
+            base_url: base_url.ok_or_else(|| format!("--base-url is required\n{}", USAGE))?,
+            admin_token,
+            max_sync_age,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub passed: bool,
+    pub latency_ms: u128,
+    pub detail: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SmokeReport {
+    pub checks: Vec<CheckResult>,
+}
+
+impl SmokeReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+
+    async fn check<F>(&mut self, name: &'static str, check: F)
+    where
+        F: Future<Output = Result<String, String>>,
+    {
+        let started = Instant::now();
+        let result = check.await;
+        let latency_ms = started.elapsed().as_millis();
+
+        let (passed, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+        self.checks.push(CheckResult {
+            name,
+            passed,
+            latency_ms,
+            detail,
+        });
+    }
+}
+
+/// The newest run of every platform has to be recent and must not have failed
+fn check_sync_freshness(
+    runs: &[SyncRun],
+    platforms: &[String],
+    now: DateTime<Utc>,
+    max_age: Duration,
+) -> Result<String, String> {
+    let mut problems = Vec::new();
+    for platform in platforms {
+        // Runs are listed newest first
+        match runs.iter().find(|run| &run.platform == platform) {
+            None => problems.push(format!("{}: no sync runs", platform)),
+            Some(run) if run.status == SyncRunStatus::Failed.as_str() => problems.push(format!(
+                "{}: last sync failed: {}",
+                platform,
+                run.error_message.as_deref().unwrap_or("unknown error")
+            )),
+            Some(run) if now - run.finished_at > max_age => problems.push(format!(
+                "{}: last sync finished at {}, more than {} hours ago",
+                platform,
+                run.finished_at,
+                max_age.num_hours()
+            )),
+            Some(_) => {}
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(format!("all {} platforms synced recently", platforms.len()))
+    } else {
+        Err(problems.join("; "))
+    }
+}
+
+fn describe(err: ClientError) -> String {
+    err.to_string()
+}
+
+/// Exercises a live deployment read-only
+pub async fn run(client: &PolluxClient, max_sync_age: Duration) -> SmokeReport {
+    let mut report = SmokeReport::default();
+    let since = (Utc::now() - Duration::days(DAYS_TO_QUERY)).date_naive();
+
+    report
+        .check("health", async {
+            let health = client.health().await.map_err(describe)?;
+            if health.status == "ok" {
+                Ok("ok".to_string())
+            } else {
+                Err(format!("{}: {:?}", health.status, health.checks))
+            }
+        })
+        .await;
+
+    let mut platforms = Vec::new();
+    report
+        .check("version", async {
+            let version = client.version().await.map_err(describe)?;
+            platforms = version.platforms;
+            Ok(format!("{} ({})", version.version, version.commit))
+        })
+        .await;
+
+    report
+        .check("platforms", async {
+            if platforms.is_empty() {
+                Err("no platforms compiled in".to_string())
+            } else {
+                Ok(platforms.join(", "))
+            }
+        })
+        .await;
+
+    report
+        .check("sync freshness", async {
+            let runs = client.sync_runs(SYNC_RUNS_TO_INSPECT).await.map_err(describe)?;
+            check_sync_freshness(&runs, &platforms, Utc::now(), max_sync_age)
+        })
+        .await;
+
+    report
+        .check("events", async {
+            let events = client.git_events(since).await.map_err(describe)?;
+            Ok(format!("{} events since {}", events.len(), since))
+        })
+        .await;
+
+    report
+        .check("daily stats", async {
+            let days = client.daily_stats(since).await.map_err(describe)?;
+            Ok(format!("{} days with events since {}", days.len(), since))
+        })
+        .await;
+
+    report
+        .check("force-sync dry run", async {
+            if !client.has_admin_token() {
+                return Ok("skipped, no --admin-token given".to_string());
+            }
+            let platforms = client.force_sync_dry_run().await.map_err(describe)?;
+            Ok(format!("would sync {}", platforms.join(", ")))
+        })
+        .await;
+
+    report
+}
+
+pub fn print_report(report: &SmokeReport) {
+    for check in report.checks.iter() {
+        println!(
+            "{} {:<20} {:>6} ms  {}",
+            if check.passed { "PASS" } else { "FAIL" },
+            check.name,
+            check.latency_ms,
+            check.detail
+        );
+    }
+    let failed = report.checks.iter().filter(|check| !check.passed).count();
+    println!("{} of {} checks failed", failed, report.checks.len());
+}
+
+/// Entry point of `pollux smoke-test`, returns whether all checks passed
+pub async fn run_cli(args: &[String]) -> bool {
+    let options = match SmokeTestOptions::from_args(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            return false;
+        }
+    };
+
+    let client = PolluxClient::new(&options.base_url, options.admin_token);
+    let report = run(&client, options.max_sync_age).await;
+    print_report(&report);
+    report.passed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{admin::AdminConfig, force_sync, health, platform_registry::tests::fake_registry, sync_jobs::SyncJobs, version};
+    use std::sync::Arc;
+
+    impl SmokeReport {
+        fn get(&self, name: &str) -> Option<&CheckResult> {
+            self.checks.iter().find(|check| check.name == name)
+        }
+    }
+
+    fn args(input: &[&str]) -> Vec<String> {
+        input.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn run_at(platform: &str, finished_at: DateTime<Utc>, status: SyncRunStatus) -> SyncRun {
+        SyncRun {
+            id: 1,
+            platform: platform.to_string(),
+            started_at: finished_at,
+            finished_at,
+            duration_ms: 0,
+            events_fetched: 0,
+            events_inserted: 0,
+            skipped_unknown_action: 0,
+            skipped_duplicates: 0,
+            skipped_project_errors: 0,
+            skipped_expired: 0,
+            skipped_other: 0,
+            status: status.as_str().to_string(),
+            error_message: None,
+            rate_limit_remaining: None,
+            rate_limit_reset_at: None,
+        }
+    }
+
+    #[test]
+    fn options_are_parsed_from_args() {
+        assert_eq!(
+            SmokeTestOptions::from_args(&args(&[
+                "--base-url",
+                "https://pollux.example.com",
+                "--admin-token",
+                "secret",
+                "--max-sync-age-hours",
+                "6"
+            ])),
+            Ok(SmokeTestOptions {
+                base_url: "https://pollux.example.com".to_string(),
+                admin_token: Some("secret".to_string()),
+                max_sync_age: Duration::hours(6),
+            })
+        );
+        assert!(SmokeTestOptions::from_args(&args(&[])).is_err());
+        assert!(SmokeTestOptions::from_args(&args(&["--base-url"])).is_err());
+        assert!(SmokeTestOptions::from_args(&args(&["--base-url", "x", "--verbose"])).is_err());
+    }
+
+    #[test]
+    fn stale_or_failed_syncs_are_reported() {
+        let now = Utc::now();
+        let platforms = args(&["Github", "Gitlab"]);
+        let max_age = Duration::hours(25);
+
+        let runs = vec![
+            run_at("Github", now - Duration::hours(1), SyncRunStatus::Success),
+            run_at("Gitlab", now - Duration::hours(2), SyncRunStatus::Truncated),
+        ];
+        assert!(check_sync_freshness(&runs, &platforms, now, max_age).is_ok());
+
+        let runs = vec![
+            run_at("Github", now - Duration::hours(1), SyncRunStatus::Failed),
+            run_at("Github", now - Duration::hours(3), SyncRunStatus::Success),
+            run_at("Gitlab", now - Duration::hours(30), SyncRunStatus::Success),
+        ];
+        let err = check_sync_freshness(&runs, &platforms, now, max_age).unwrap_err();
+        assert!(err.contains("Github: last sync failed"), "{}", err);
+        assert!(err.contains("Gitlab: last sync finished"), "{}", err);
+
+        assert!(check_sync_freshness(&[], &platforms, now, max_age).is_err());
+    }
+
+    #[tokio::test]
+    async fn smoke_test_runs_against_in_process_instance() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = rocket::Config {
+            port,
+            address: std::net::Ipv4Addr::LOCALHOST.into(),
+            log_level: rocket::config::LogLevel::Off,
+            ..rocket::Config::debug_default()
+        };
+
+        // Only routes which don't need a database, so the outcome doesn't depend on the environment
+        let rocket = rocket::custom(config)
+            .manage(AdminConfig {
+                token: Some("secret".to_string()),
+                dev_mode: false,
+            })
+            .manage(Arc::new(SyncJobs::default()))
+            .manage(Arc::new(fake_registry(&["Github", "Gitlab"])))
+            .mount("/", routes![health])
+            .mount("/api/v1", routes![version, force_sync])
+            .ignite()
+            .await
+            .unwrap();
+        let shutdown = rocket.shutdown();
+        tokio::spawn(rocket.launch());
+
+        let base_url = format!("http://127.0.0.1:{}", port);
+        let client = PolluxClient::new(&base_url, Some("secret".to_string()));
+        for _ in 0..50 {
+            if client.version().await.is_ok() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+
+        let report = run(&client, Duration::hours(25)).await;
+        assert!(!report.passed());
+        assert_eq!(report.checks.len(), 7);
+
+        let passed: Vec<&str> = report
+            .checks
+            .iter()
+            .filter(|check| check.passed)
+            .map(|check| check.name)
+            .collect();
+        assert_eq!(passed, vec!["version", "platforms", "force-sync dry run"]);
+        assert!(report.get("health").unwrap().detail.contains("degraded"));
+        assert_eq!(report.get("platforms").unwrap().detail, "Github, Gitlab");
+        assert_eq!(
+            report.get("force-sync dry run").unwrap().detail,
+            "would sync Github, Gitlab"
+        );
+        assert!(report.get("events").unwrap().detail.contains("404"));
+
+        let client = PolluxClient::new(&base_url, Some("guess".to_string()));
+        let report = run(&client, Duration::hours(25)).await;
+        assert!(!report.get("force-sync dry run").unwrap().passed);
+
+        shutdown.notify();
+    }
+}
@@ -0,0 +1,646 @@
+use crate::{
+    config::{self, ConfigError, EnvConfig},
+    credentials::{CredentialCheck, TokenInfo},
+    event_archive::{self, EventSource},
+    git_platform::{self, EventDetails, GitEventAPI, GitPlatform, GitProject, InsertEvents, NewEvent, PlatformError, Skipped, SyncCache, SyncResult},
+    graphql_client, http_client,
+    pagination::{FetchedEvents, PaginationGuard},
+    platform_registry::{BoxFuture, PlatformRunner},
+    project_filter::ProjectFilter,
+    retry::{env_parse, RetryPolicy},
+    sync_runs::SyncReport,
+    visibility::ProjectVisibility,
+};
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use once_cell::sync::OnceCell;
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+use sqlx::{MySql, Transaction};
+use tokio::sync::Mutex;
+
+static SOURCEHUT: OnceCell<Arc<Mutex<Sourcehut>>> = OnceCell::new();
+static FALLBACK_SOURCEHUT_GIT_URL: &str = "https://git.sr.ht";
+/// Sourcehut has no feed to page through to its end, so the initial sync looks back this far
+static INITIAL_SYNC_DAYS: i64 = 90;
+/// Commits can be pushed a while after they were committed, so a bit before the last sync is fetched again
+static FALLBACK_SYNC_OVERLAP_HOURS: i64 = 24;
+
+static REPOSITORIES_QUERY: &str = "query($username: String!, $cursor: Cursor) { \
+    user(username: $username) { canonicalName email \
+    repositories(cursor: $cursor) { results { id name visibility updated } cursor } } }";
+static LOG_QUERY: &str = "query($id: Int!, $cursor: Cursor) { \
+    repository(id: $id) { log(cursor: $cursor) { results { id author { email } committer { time } } cursor } } }";
+static ME_QUERY: &str = "query { me { canonicalName } }";
+
+/// A page of any listing, `cursor` is `null` on the last one
+#[derive(Debug, Deserialize)]
+struct SourcehutCursor<T> {
+    results: Vec<T>,
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RepositoriesData {
+    user: Option<SourcehutUser>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SourcehutUser {
+    /// With the tilde, like `~2tefan`
+    canonical_name: String,
+    /// Own commits are recognized by it
+    email: Option<String>,
+    repositories: SourcehutCursor<SourcehutRepo>,
+}
+
+/// One of `user.repositories`
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourcehutRepo {
+    pub id: u64,
+    pub name: String,
+    /// `PUBLIC`, `UNLISTED` or `PRIVATE`
+    pub visibility: String,
+    pub updated: String,
+}
+
+impl SourcehutRepo {
+    /// Unlisted repositories are only known to whoever got the link, so they count as private
+    pub fn visibility(&self) -> ProjectVisibility {
+        match self.visibility.as_str() {
+            "PUBLIC" => ProjectVisibility::Public,
+            _ => ProjectVisibility::Private,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LogData {
+    repository: Option<SourcehutRepoLog>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SourcehutRepoLog {
+    log: SourcehutCursor<SourcehutCommit>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SourcehutCommit {
+    author: SourcehutSignature,
+    committer: SourcehutSignature,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SourcehutSignature {
+    #[serde(default)]
+    email: String,
+    #[serde(default)]
+    time: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MeData {
+    me: SourcehutMe,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SourcehutMe {
+    canonical_name: String,
+}
+
+/// A commit of the user on the default branch, Sourcehut has no feed of pushes
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourcehutEvent {
+    pub repo: SourcehutRepo,
+    /// e.g. `~2tefan/pollux`, matched by `ProjectFilter`
+    pub full_name: String,
+    /// When it was committed, the closest Sourcehut tells to when it was pushed
+    pub created: String,
+}
+
+impl GitEventAPI for SourcehutEvent {}
+
+#[derive(Debug)]
+pub struct Sourcehut {
+    token: String,
+    /// Without the tilde
+    username: String,
+    /// Web url of git.sr.ht without a trailing slash, the GraphQL API lives under `/query`
+    git_url: String,
+    /// Unlisted repositories count as private, see `SourcehutRepo::visibility`
+    include_visibilities: Vec<ProjectVisibility>,
+    project_filter: ProjectFilter,
+    retry_policy: RetryPolicy,
+    /// Repositories updated since the last sync minus this have their log read
+    sync_overlap: chrono::Duration,
+}
+
+impl GitPlatform for Sourcehut {
+    const GIT_PLATFORM_ID: &'static str = "Sourcehut";
+    type GitEventAPI = SourcehutEvent;
+
+    fn init_from_env_vars() -> Result<Self, ConfigError> {
+        let mut env = EnvConfig::from_env();
+        let sourcehut = Sourcehut {
+            token: env.secret("SOURCEHUT_TOKEN"),
+            username: env
+                .required("SOURCEHUT_USERNAME")
+                .trim_start_matches('~')
+                .to_string(),
+            git_url: config::var("SOURCEHUT_GIT_URL")
+                .unwrap_or(FALLBACK_SOURCEHUT_GIT_URL.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            include_visibilities: Sourcehut::include_visibilities_from_env(),
+            project_filter: ProjectFilter::from_env(),
+            retry_policy: RetryPolicy::from_env(),
+            sync_overlap: chrono::Duration::hours(env_parse(
+                "POLLUX_SOURCEHUT_SYNC_OVERLAP_HOURS",
+                FALLBACK_SYNC_OVERLAP_HOURS,
+            )),
+        };
+        env.build(sourcehut)
+    }
+
+    async fn get_events(&mut self) -> Result<FetchedEvents<Self::GitEventAPI>, PlatformError> {
+        let since = match Sourcehut::get_last_sync_timestamp().await {
+            Some(last_sync) => last_sync - self.sync_overlap,
+            None => {
+                info!("Initial run! Fetching last {} days from Sourcehut...", INITIAL_SYNC_DAYS);
+                Utc::now() - chrono::Duration::days(INITIAL_SYNC_DAYS)
+            }
+        };
+        self.get_events_since(since).await
+    }
+
+    async fn update_provider(&mut self) -> Result<SyncResult, PlatformError> {
+        info!("Updating events from Sourcehut ({})...", self.git_url);
+        let fetched = self.get_events().await?;
+        let result = self.insert_events(&fetched.events, EventSource::Fetched, true).await;
+        Ok(SyncResult {
+            truncated: fetched.truncated,
+            ..result
+        })
+    }
+
+    async fn insert_archived_events(&self, events: Vec<SourcehutEvent>) -> Result<SyncResult, PlatformError> {
+        Ok(self.insert_events(&events, EventSource::Archive, true).await)
+    }
+}
+
+impl PlatformRunner for Sourcehut {
+    fn sync(&mut self) -> BoxFuture<'_, SyncReport> {
+        Box::pin(git_platform::sync_and_record(self))
+    }
+
+    fn check_credentials(&mut self) -> BoxFuture<'_, Vec<CredentialCheck>> {
+        Box::pin(async { vec![Sourcehut::validate_credentials(self).await] })
+    }
+
+    fn reprocess(&mut self) -> BoxFuture<'_, SyncReport> {
+        Box::pin(event_archive::reprocess(self))
+    }
+}
+
+impl InsertEvents for Sourcehut {
+    async fn to_new_event<'e>(
+        &self,
+        tx: &mut Transaction<'static, MySql>,
+        cache: &mut SyncCache,
+        event: &'e SourcehutEvent,
+    ) -> Result<NewEvent<'e>, Skipped<'e>> {
+        let datetime = Self::parse_datetime(&event.created)?;
+        let action_name = Self::map_action_name("pushed").ok_or(Skipped::UnknownAction("pushed"))?;
+        let project_id = self.project_of(tx, cache, event).await;
+        let action_id = cache.action(tx, action_name).await;
+
+        // Commits are synced one by one
+        Ok(NewEvent {
+            datetime,
+            action_id,
+            project_id,
+            commit_count: Some(1),
+            account: None,
+            details: EventDetails::default(),
+        })
+    }
+}
+
+impl Sourcehut {
+    /// Set up if `SOURCEHUT_TOKEN` and `SOURCEHUT_USERNAME` are given
+    /// Any of its credentials is set, `init_from_env_vars` reports the missing ones
+    pub fn is_configured() -> bool {
+        ["SOURCEHUT_TOKEN", "SOURCEHUT_TOKEN_FILE", "SOURCEHUT_USERNAME"]
+            .iter()
+            .any(|name| config::var(name).is_some_and(|value| !value.trim().is_empty()))
+    }
+
+    /// `Ok(None)` if Sourcehut isn't configured
+    pub fn get_or_init() -> Result<Option<Arc<Mutex<Sourcehut>>>, ConfigError> {
+        if !Sourcehut::is_configured() {
+            return Ok(None);
+        }
+        SOURCEHUT
+            .get_or_try_init(|| Self::init_from_env_vars().map(|platform| Arc::new(Mutex::new(platform))))
+            .cloned()
+            .map(Some)
+    }
+
+    /// `POLLUX_SOURCEHUT_INCLUDE_VISIBILITIES`, only public repositories by default
+    fn include_visibilities_from_env() -> Vec<ProjectVisibility> {
+        let Some(input) = config::var("POLLUX_SOURCEHUT_INCLUDE_VISIBILITIES") else {
+            return vec![ProjectVisibility::Public];
+        };
+
+        match ProjectVisibility::parse_set(&input) {
+            Ok(visibilities) => visibilities,
+            Err(err) => {
+                warn!(
+                    "Unable to parse POLLUX_SOURCEHUT_INCLUDE_VISIBILITIES »{}«, using »public« as a fallback: {}",
+                    input, err
+                );
+                vec![ProjectVisibility::Public]
+            }
+        }
+    }
+
+    fn api_url(&self) -> String {
+        format!("{}/query", self.git_url)
+    }
+
+    async fn query<T: serde::de::DeserializeOwned>(
+        &self,
+        client: &reqwest::Client,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> Result<T, PlatformError> {
+        let authorize = |request: RequestBuilder| request.bearer_auth(&self.token);
+        graphql_client::post(client, &self.retry_policy, Self::GIT_PLATFORM_ID, &self.api_url(), authorize, query, variables)
+            .await
+            .map_err(|err| match err {
+                PlatformError::Status { status: 401, message } => PlatformError::Status {
+                    status: 401,
+                    message: format!("SOURCEHUT_TOKEN is invalid or expired ({})", message),
+                },
+                err => err,
+            })
+    }
+
+    pub async fn validate_credentials(&self) -> CredentialCheck {
+        let client = http_client::platform_client();
+        let outcome = self
+            .query::<MeData>(&client, ME_QUERY, serde_json::json!({}))
+            .await
+            .map(|data| TokenInfo {
+                username: data.me.canonical_name,
+                scopes: None,
+                expires_at: None,
+            })
+            .map_err(|err| err.to_string());
+        CredentialCheck {
+            platform: Self::GIT_PLATFORM_ID,
+            env_var: "SOURCEHUT_TOKEN".to_string(),
+            outcome,
+        }
+    }
+
+    /// Own commits in all repositories of the user changed since `since`
+    pub async fn get_events_since(&self, since: DateTime<Utc>) -> Result<FetchedEvents<SourcehutEvent>, PlatformError> {
+        let client = http_client::platform_client();
+        let mut events = Vec::new();
+        let mut pagination = PaginationGuard::from_env();
+        let mut cursor: Option<String> = None;
+        loop {
+            if let Err(err) = pagination.visit(&format!("repositories:{}", cursor.as_deref().unwrap_or_default())) {
+                return Ok(FetchedEvents::truncated(events, err));
+            }
+            info!("Getting repositories of ~{} from Sourcehut... ({})", self.username, self.api_url());
+            let data: RepositoriesData = self
+                .query(
+                    &client,
+                    REPOSITORIES_QUERY,
+                    serde_json::json!({"username": self.username, "cursor": cursor}),
+                )
+                .await?;
+            let user = data.user.ok_or_else(|| {
+                PlatformError::InvalidResponse(format!("Sourcehut doesn't know the user ~{}", self.username))
+            })?;
+            let Some(email) = user.email.as_deref() else {
+                warn!("Sourcehut doesn't tell the email of {}, so no commits are recognized", user.canonical_name);
+                return Ok(FetchedEvents::complete(events));
+            };
+
+            for repo in user.repositories.results {
+                let full_name = format!("{}/{}", user.canonical_name, repo.name);
+                // Repositories aren't sorted, each one is looked at
+                if !is_recent(&repo.updated, since) {
+                    continue;
+                }
+                if !self.project_filter.includes(Self::GIT_PLATFORM_ID, &full_name) {
+                    continue;
+                }
+                if !self.include_visibilities.contains(&repo.visibility()) {
+                    debug!("Skipping repository {}: its visibility isn't included", full_name);
+                    continue;
+                }
+
+                let mut commits = self.get_commit_times(&client, &repo, email, since).await?;
+                events.extend(commits.events.drain(..).map(|created| SourcehutEvent {
+                    repo: repo.clone(),
+                    full_name: full_name.clone(),
+                    created,
+                }));
+                if let Some(reason) = commits.truncated {
+                    return Ok(FetchedEvents::truncated(events, reason));
+                }
+            }
+
+            match user.repositories.cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(FetchedEvents::complete(events)),
+            }
+        }
+    }
+
+    /// Commit times of `email` on the default branch. The log is newest first, so it stops at a page
+    /// without any commit since `since`.
+    async fn get_commit_times(
+        &self,
+        client: &reqwest::Client,
+        repo: &SourcehutRepo,
+        email: &str,
+        since: DateTime<Utc>,
+    ) -> Result<FetchedEvents<String>, PlatformError> {
+        let mut times = Vec::new();
+        let mut pagination = PaginationGuard::from_env();
+        let mut cursor: Option<String> = None;
+        loop {
+            if let Err(err) = pagination.visit(&format!("log:{}:{}", repo.id, cursor.as_deref().unwrap_or_default())) {
+                return Ok(FetchedEvents::truncated(times, err));
+            }
+            debug!("Getting commits of {} from Sourcehut...", repo.name);
+            let data: LogData = self
+                .query(client, LOG_QUERY, serde_json::json!({"id": repo.id, "cursor": cursor}))
+                .await?;
+            // Empty repositories have no log
+            let Some(repository) = data.repository else {
+                return Ok(FetchedEvents::complete(times));
+            };
+
+            let recent: Vec<SourcehutCommit> = repository
+                .log
+                .results
+                .into_iter()
+                .filter(|commit| is_recent(&commit.committer.time, since))
+                .collect();
+            if recent.is_empty() {
+                return Ok(FetchedEvents::complete(times));
+            }
+            times.extend(
+                recent
+                    .into_iter()
+                    .filter(|commit| commit.author.email.eq_ignore_ascii_case(email))
+                    .map(|commit| commit.committer.time),
+            );
+
+            match repository.log.cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(FetchedEvents::complete(times)),
+            }
+        }
+    }
+
+    /// Id of the `GitProjects` row, stored on the first event of the repository
+    async fn project_of(
+        &self,
+        tx: &mut Transaction<'static, MySql>,
+        cache: &mut SyncCache,
+        event: &SourcehutEvent,
+    ) -> u64 {
+        if let Some(project) = cache.project(tx, event.repo.id, false).await {
+            return project.id;
+        }
+
+        let url = format!("{}/{}", self.git_url, event.full_name);
+        let project = GitProject::reported(event.repo.id, &event.full_name, url);
+        self.write_project_to_db(tx, &project, Some(event.repo.visibility())).await
+    }
+
+}
+
+/// Whether the RFC3339 `time` isn't older than `since`, unparseable times are
+fn is_recent(time: &str, since: DateTime<Utc>) -> bool {
+    time.parse::<DateTime<Utc>>().is_ok_and(|time| time >= since)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use wiremock::{
+        matchers::{body_partial_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn sourcehut_for_tests(git_url: String) -> Sourcehut {
+        Sourcehut {
+            token: "token".to_string(),
+            username: "2tefan".to_string(),
+            git_url,
+            include_visibilities: vec![ProjectVisibility::Public],
+            project_filter: ProjectFilter::default(),
+            retry_policy: RetryPolicy {
+                attempts: 1,
+                base_delay: std::time::Duration::from_millis(1),
+                max_delay: std::time::Duration::from_millis(1),
+            },
+            sync_overlap: chrono::Duration::hours(FALLBACK_SYNC_OVERLAP_HOURS),
+        }
+    }
+
+    fn since() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap()
+    }
+
+    fn repo(id: u64, name: &str, visibility: &str, updated: &str) -> serde_json::Value {
+        serde_json::json!({"id": id, "name": name, "visibility": visibility, "updated": updated})
+    }
+
+    fn commit(email: &str, time: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": "2b9f0c4e",
+            "author": {"email": email},
+            "committer": {"time": time},
+        })
+    }
+
+    /// Answers the query whose variables contain `variables`
+    async fn answer(server: &MockServer, variables: serde_json::Value, data: serde_json::Value) {
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(header("authorization", "Bearer token"))
+            .and(body_partial_json(serde_json::json!({"variables": variables})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"data": data})))
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    fn repositories(repos: Vec<serde_json::Value>, cursor: Option<&str>) -> serde_json::Value {
+        serde_json::json!({"user": {
+            "canonicalName": "~2tefan",
+            "email": "2tefan@example.com",
+            "repositories": {"results": repos, "cursor": cursor},
+        }})
+    }
+
+    fn log(commits: Vec<serde_json::Value>, cursor: Option<&str>) -> serde_json::Value {
+        serde_json::json!({"repository": {"log": {"results": commits, "cursor": cursor}}})
+    }
+
+    #[test]
+    fn unlisted_repositories_count_as_private() {
+        let visibility = |visibility: &str| SourcehutRepo {
+            visibility: visibility.to_string(),
+            ..Default::default()
+        }
+        .visibility();
+        assert_eq!(visibility("PUBLIC"), ProjectVisibility::Public);
+        assert_eq!(visibility("UNLISTED"), ProjectVisibility::Private);
+        assert_eq!(visibility("PRIVATE"), ProjectVisibility::Private);
+        assert_eq!(Sourcehut::map_action_name("pushed"), Some("commit"));
+    }
+
+    #[tokio::test]
+    async fn repositories_are_paged_by_cursor() {
+        let server = MockServer::start().await;
+        answer(
+            &server,
+            serde_json::json!({"username": "2tefan", "cursor": null}),
+            repositories(
+                vec![
+                    repo(1, "pollux", "PUBLIC", "2024-05-03T18:40:12Z"),
+                    repo(2, "secrets", "PRIVATE", "2024-05-03T18:40:12Z"),
+                ],
+                Some("page-2"),
+            ),
+        )
+        .await;
+        answer(
+            &server,
+            serde_json::json!({"username": "2tefan", "cursor": "page-2"}),
+            repositories(
+                vec![
+                    repo(3, "castor", "PUBLIC", "2024-05-02T08:00:00Z"),
+                    repo(4, "archive", "PUBLIC", "2023-01-01T00:00:00Z"),
+                ],
+                None,
+            ),
+        )
+        .await;
+        answer(
+            &server,
+            serde_json::json!({"id": 1, "cursor": null}),
+            log(vec![commit("2tefan@example.com", "2024-05-03T18:40:12Z")], None),
+        )
+        .await;
+        answer(
+            &server,
+            serde_json::json!({"id": 3, "cursor": null}),
+            log(vec![commit("2tefan@example.com", "2024-05-02T08:00:00Z")], None),
+        )
+        .await;
+
+        let fetched = sourcehut_for_tests(server.uri()).get_events_since(since()).await.unwrap();
+        assert_eq!(fetched.truncated, None);
+        assert_eq!(
+            fetched.events.iter().map(|event| event.full_name.as_str()).collect::<Vec<_>>(),
+            vec!["~2tefan/pollux", "~2tefan/castor"]
+        );
+    }
+
+    #[tokio::test]
+    async fn commits_are_paged_by_cursor_until_they_are_older_than_the_last_sync() {
+        let server = MockServer::start().await;
+        answer(
+            &server,
+            serde_json::json!({"username": "2tefan", "cursor": null}),
+            repositories(vec![repo(1, "pollux", "PUBLIC", "2024-05-03T18:40:12Z")], None),
+        )
+        .await;
+        answer(
+            &server,
+            serde_json::json!({"id": 1, "cursor": null}),
+            log(
+                vec![
+                    commit("2tefan@example.com", "2024-05-03T18:40:12Z"),
+                    commit("colleague@example.com", "2024-05-03T10:00:00Z"),
+                ],
+                Some("log-2"),
+            ),
+        )
+        .await;
+        answer(
+            &server,
+            serde_json::json!({"id": 1, "cursor": "log-2"}),
+            log(
+                vec![
+                    commit("2Tefan@Example.com", "2024-05-02T09:00:00+02:00"),
+                    commit("2tefan@example.com", "2024-04-30T23:00:00Z"),
+                ],
+                Some("log-3"),
+            ),
+        )
+        .await;
+        answer(
+            &server,
+            serde_json::json!({"id": 1, "cursor": "log-3"}),
+            log(vec![commit("2tefan@example.com", "2024-04-29T12:00:00Z")], Some("log-4")),
+        )
+        .await;
+
+        let fetched = sourcehut_for_tests(server.uri()).get_events_since(since()).await.unwrap();
+        assert_eq!(
+            fetched.events.iter().map(|event| event.created.as_str()).collect::<Vec<_>>(),
+            vec!["2024-05-03T18:40:12Z", "2024-05-02T09:00:00+02:00"]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_errors_fail_the_sync() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"data": null, "errors": [{"message": "Access denied for scope REPOSITORIES:RO"}]}"#),
+            )
+            .mount(&server)
+            .await;
+
+        let err = sourcehut_for_tests(server.uri()).get_events_since(since()).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Sourcehut couldn't answer the query: Access denied for scope REPOSITORIES:RO"
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_token_is_named() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(401).set_body_string(r#"{"errors": [{"message": "Invalid token"}]}"#))
+            .mount(&server)
+            .await;
+
+        let check = sourcehut_for_tests(server.uri()).validate_credentials().await;
+        assert_eq!(
+            check.outcome.unwrap_err(),
+            "SOURCEHUT_TOKEN is invalid or expired (Sourcehut answered 401 Unauthorized)"
+        );
+    }
+}
@@ -0,0 +1,694 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use log::warn;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::{MySql, Pool};
+
+use crate::config;
+
+static FALLBACK_MERGE_STRATEGY: MergeStrategy = MergeStrategy::PreferCalendar;
+
+/// How long a computed "today" summary is served from the cache
+static TODAY_CACHE_TTL_SECONDS: i64 = 30;
+
+/// Cached "today" summaries keyed by timezone and local date, with the time they were computed
+type TodayCache = HashMap<(Tz, NaiveDate), (DateTime<Utc>, TodayStats)>;
+
+/// Cleared whenever new events were inserted
+static TODAY_CACHE: Lazy<Mutex<TodayCache>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// How per-day counts are merged when both the contribution calendar and
+/// the precise events have data for the same platform and day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeStrategy {
+    PreferCalendar,
+    PreferEvents,
+    Max,
+}
+
+impl MergeStrategy {
+    pub fn parse(input: &str) -> Option<MergeStrategy> {
+        match input.to_ascii_lowercase().as_str() {
+            "prefer_calendar" => Some(MergeStrategy::PreferCalendar),
+            "prefer_events" => Some(MergeStrategy::PreferEvents),
+            "max" => Some(MergeStrategy::Max),
+            _ => None,
+        }
+    }
+
+    pub fn from_env() -> MergeStrategy {
+        let Some(input) = config::var("POLLUX_DAILY_MERGE_STRATEGY") else {
+            return FALLBACK_MERGE_STRATEGY;
+        };
+
+        match MergeStrategy::parse(&input) {
+            Some(strategy) => strategy,
+            None => {
+                warn!(
+                    "Unable to parse POLLUX_DAILY_MERGE_STRATEGY »{}« (valid: prefer_calendar, prefer_events, max), using {:?} as a fallback",
+                    input, FALLBACK_MERGE_STRATEGY
+                );
+                FALLBACK_MERGE_STRATEGY
+            }
+        }
+    }
+}
+
+/// What a single event contributes to aggregated counts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Weight {
+    Events,
+    /// Push events count with their number of commits, all other events as one
+    Commits,
+}
+
+impl Weight {
+    pub fn parse(input: &str) -> Option<Weight> {
+        match input.to_ascii_lowercase().as_str() {
+            "events" => Some(Weight::Events),
+            "commits" => Some(Weight::Commits),
+            _ => None,
+        }
+    }
+
+    /// Aggregate over `GitEvents AS gevt` rows
+    fn sql_count(&self) -> &'static str {
+        match self {
+            Weight::Events => "COUNT(1)",
+            Weight::Commits => "CAST(SUM(COALESCE(gevt.commit_count, 1)) AS SIGNED)",
+        }
+    }
+
+    /// Aggregate over `DailyAggregates AS agg` rows
+    fn sql_aggregated_count(&self) -> &'static str {
+        match self {
+            Weight::Events => "CAST(SUM(agg.event_count) AS SIGNED)",
+            Weight::Commits => "CAST(SUM(agg.commit_count) AS SIGNED)",
+        }
+    }
+}
+
+/// Leaves out events of the given action names, e.g. `starred` for people who consider them noise.
+/// Binds one placeholder per action.
+fn sql_exclude_actions(action_column: &str, exclude_actions: &[String]) -> String {
+    if exclude_actions.is_empty() {
+        return String::new();
+    }
+    format!(
+        "AND {} NOT IN (SELECT id FROM GitActions WHERE name IN ({}))",
+        action_column,
+        vec!["?"; exclude_actions.len()].join(", ")
+    )
+}
+
+/// Where the count of a single platform on a single day came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum DaySource {
+    Calendar,
+    Events,
+    MergedMax,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DailyCount {
+    pub date: NaiveDate,
+    pub count: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sources: Option<BTreeMap<String, DaySource>>,
+}
+
+/// Counts keyed by (platform, day)
+pub type PlatformDayCounts = BTreeMap<(String, NaiveDate), u64>;
+
+fn merge_single(calendar: Option<u64>, events: Option<u64>, strategy: MergeStrategy) -> Option<(u64, DaySource)> {
+    match (calendar, events) {
+        (None, None) => None,
+        (Some(calendar), None) => Some((calendar, DaySource::Calendar)),
+        (None, Some(events)) => Some((events, DaySource::Events)),
+        (Some(calendar), Some(events)) => Some(match strategy {
+            MergeStrategy::PreferCalendar => (calendar, DaySource::Calendar),
+            MergeStrategy::PreferEvents => (events, DaySource::Events),
+            MergeStrategy::Max => (calendar.max(events), DaySource::MergedMax),
+        }),
+    }
+}
+
+/// Merges calendar and event derived counts per platform and day, then sums up the platforms per day.
+/// The result doesn't depend on the order in which the counts were collected.
+pub fn merge_daily_counts(
+    calendar: &PlatformDayCounts,
+    events: &PlatformDayCounts,
+    strategy: MergeStrategy,
+    include_sources: bool,
+) -> Vec<DailyCount> {
+    let keys: BTreeSet<&(String, NaiveDate)> = calendar.keys().chain(events.keys()).collect();
+
+    let mut days: BTreeMap<NaiveDate, DailyCount> = BTreeMap::new();
+    for key in keys {
+        let Some((count, source)) =
+            merge_single(calendar.get(key).copied(), events.get(key).copied(), strategy)
+        else {
+            continue;
+        };
+
+        let (platform, date) = key;
+        let day = days.entry(*date).or_insert_with(|| DailyCount {
+            date: *date,
+            count: 0,
+            sources: include_sources.then(BTreeMap::new),
+        });
+        day.count += count;
+        if let Some(sources) = day.sources.as_mut() {
+            sources.insert(platform.clone(), source);
+        }
+    }
+
+    days.into_values().collect()
+}
+
+fn to_platform_day_counts(rows: Vec<(String, NaiveDate, i64)>) -> PlatformDayCounts {
+    rows.into_iter()
+        .map(|(platform, day, count)| ((platform, day), count.max(0) as u64))
+        .collect()
+}
+
+pub async fn get_event_counts_per_day(
+    pool: &Pool<MySql>,
+    since: NaiveDate,
+    weight: Weight,
+    exclude_actions: &[String],
+) -> PlatformDayCounts {
+    let sql = format!(
+        r#"
+            SELECT
+                gpro.platform AS platform,
+                DATE(evt.timestamp) AS day,
+                {} AS count
+            FROM
+                Events AS evt,
+                GitEvents AS gevt,
+                GitProjects AS gpro
+            WHERE evt.timestamp >= ?
+            AND   evt.id = gevt.id
+            AND   gevt.project_fk = gpro.id
+            {}
+            GROUP BY gpro.platform, DATE(evt.timestamp)
+            "#,
+        weight.sql_count(),
+        sql_exclude_actions("gevt.action_fk", exclude_actions)
+    );
+    let mut query = sqlx::query_as(&sql).bind(since);
+    for action in exclude_actions {
+        query = query.bind(action);
+    }
+    let rows: Vec<(String, NaiveDate, i64)> = query.fetch_all(pool).await.unwrap();
+
+    let mut counts = to_platform_day_counts(rows);
+    add_counts(&mut counts, get_aggregated_counts_per_day(pool, since, weight, exclude_actions).await);
+    counts
+}
+
+/// Counts of the events pruned by `retention`, see `DailyAggregates`
+async fn get_aggregated_counts_per_day(
+    pool: &Pool<MySql>,
+    since: NaiveDate,
+    weight: Weight,
+    exclude_actions: &[String],
+) -> PlatformDayCounts {
+    let sql = format!(
+        "SELECT agg.platform, agg.day, {} AS count FROM DailyAggregates AS agg WHERE agg.day >= ? {} \
+            GROUP BY agg.platform, agg.day",
+        weight.sql_aggregated_count(),
+        sql_exclude_actions("agg.action_fk", exclude_actions)
+    );
+    let mut query = sqlx::query_as(&sql).bind(since);
+    for action in exclude_actions {
+        query = query.bind(action);
+    }
+    let rows: Vec<(String, NaiveDate, i64)> = query.fetch_all(pool).await.unwrap();
+
+    to_platform_day_counts(rows)
+}
+
+/// Days which are in both are added up, e.g. the remaining events of the day the retention cut through
+fn add_counts(counts: &mut PlatformDayCounts, other: PlatformDayCounts) {
+    for (key, count) in other {
+        *counts.entry(key).or_default() += count;
+    }
+}
+
+pub async fn get_calendar_counts_per_day(pool: &Pool<MySql>, since: NaiveDate) -> PlatformDayCounts {
+    let rows: Vec<(String, NaiveDate, i64)> = sqlx::query_as(
+        "SELECT platform, day, CAST(count AS SIGNED) AS count FROM ContributionCalendar WHERE day >= ?",
+    )
+    .bind(since)
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    to_platform_day_counts(rows)
+}
+
+pub async fn get_daily_counts(
+    pool: &Pool<MySql>,
+    since: NaiveDate,
+    strategy: MergeStrategy,
+    include_sources: bool,
+    weight: Weight,
+    exclude_actions: &[String],
+) -> Vec<DailyCount> {
+    // The calendar only knows totals, so excluded actions are only left out of the events
+    let (calendar, events) = tokio::join!(
+        get_calendar_counts_per_day(pool, since),
+        get_event_counts_per_day(pool, since, weight, exclude_actions)
+    );
+
+    merge_daily_counts(&calendar, &events, strategy, include_sources)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct TodayStats {
+    /// The local date the counts belong to, so clients can detect the rollover at midnight
+    pub date: NaiveDate,
+    pub timezone: String,
+    pub count: u64,
+    /// Push events count with their number of commits, all other events as one
+    pub commit_weighted_count: u64,
+    pub distinct_projects: u64,
+    pub latest_event_at: Option<DateTime<Utc>>,
+}
+
+/// Returns the local date of `now` in `tz` and the bounds of that day in UTC (start inclusive, end exclusive)
+pub fn local_day_bounds(now: DateTime<Utc>, tz: Tz) -> (NaiveDate, DateTime<Utc>, DateTime<Utc>) {
+    let date = now.with_timezone(&tz).date_naive();
+    let start_of = |date: NaiveDate| {
+        // Days starting with a DST gap have no local midnight, so take the first hour which exists
+        (0..24)
+            .find_map(|hour| tz.from_local_datetime(&date.and_hms_opt(hour, 0, 0).unwrap()).earliest())
+            .unwrap()
+            .with_timezone(&Utc)
+    };
+
+    (date, start_of(date), start_of(date + Duration::days(1)))
+}
+
+fn to_today_stats(date: NaiveDate, tz: Tz, row: (i64, i64, i64, Option<NaiveDateTime>)) -> TodayStats {
+    let (count, commit_weighted_count, distinct_projects, latest_event_at) = row;
+    TodayStats {
+        date,
+        timezone: tz.name().to_string(),
+        count: count.max(0) as u64,
+        commit_weighted_count: commit_weighted_count.max(0) as u64,
+        distinct_projects: distinct_projects.max(0) as u64,
+        latest_event_at: latest_event_at.map(|timestamp| timestamp.and_utc()),
+    }
+}
+
+fn get_cached_today_stats(tz: Tz, date: NaiveDate, now: DateTime<Utc>) -> Option<TodayStats> {
+    let cache = TODAY_CACHE.lock().unwrap();
+    cache
+        .get(&(tz, date))
+        .filter(|(computed_at, _)| now - *computed_at < Duration::seconds(TODAY_CACHE_TTL_SECONDS))
+        .map(|(_, stats)| stats.clone())
+}
+
+fn cache_today_stats(tz: Tz, now: DateTime<Utc>, stats: &TodayStats) {
+    let mut cache = TODAY_CACHE.lock().unwrap();
+    // Entries of past days are never requested again
+    cache.retain(|(_, date), _| *date >= stats.date - Duration::days(1));
+    cache.insert((tz, stats.date), (now, stats.clone()));
+}
+
+/// Drops all cached "today" summaries, called after new events were written
+pub fn invalidate_today_cache() {
+    TODAY_CACHE.lock().unwrap().clear();
+}
+
+pub async fn get_today_stats(pool: &Pool<MySql>, now: DateTime<Utc>, tz: Tz) -> TodayStats {
+    let (date, start, end) = local_day_bounds(now, tz);
+    if let Some(stats) = get_cached_today_stats(tz, date, now) {
+        return stats;
+    }
+
+    let row: (i64, i64, i64, Option<NaiveDateTime>) = sqlx::query_as(
+        r#"
+            SELECT
+                COUNT(1) AS count,
+                CAST(COALESCE(SUM(COALESCE(gevt.commit_count, 1)), 0) AS SIGNED) AS commit_weighted_count,
+                COUNT(DISTINCT gevt.project_fk) AS distinct_projects,
+                MAX(evt.timestamp) AS latest_event_at
+            FROM
+                Events AS evt,
+                GitEvents AS gevt
+            WHERE evt.timestamp >= ?
+            AND   evt.timestamp < ?
+            AND   evt.id = gevt.id
+            "#,
+    )
+    .bind(start.format("%Y-%m-%d %H:%M:%S").to_string())
+    .bind(end.format("%Y-%m-%d %H:%M:%S").to_string())
+    .fetch_one(pool)
+    .await
+    .unwrap();
+
+    let stats = to_today_stats(date, tz, row);
+    cache_today_stats(tz, now, &stats);
+    stats
+}
+
+/// Size of the time buckets of aggregated stats. Weeks start on Monday.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Granularity {
+    Day,
+    Week,
+    Month,
+}
+
+impl Granularity {
+    pub fn parse(input: &str) -> Option<Granularity> {
+        match input.to_ascii_lowercase().as_str() {
+            "day" => Some(Granularity::Day),
+            "week" => Some(Granularity::Week),
+            "month" => Some(Granularity::Month),
+            _ => None,
+        }
+    }
+
+    /// First day of the bucket containing `date`
+    pub fn bucket_start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Granularity::Day => date,
+            Granularity::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            Granularity::Month => date.with_day(1).unwrap(),
+        }
+    }
+
+    /// First day of the bucket after the one starting at `bucket_start`
+    pub fn next_bucket(&self, bucket_start: NaiveDate) -> NaiveDate {
+        match self {
+            Granularity::Day => bucket_start + Duration::days(1),
+            Granularity::Week => bucket_start + Duration::weeks(1),
+            Granularity::Month => bucket_start + Months::new(1),
+        }
+    }
+
+    /// SQL expression computing the same bucket start as `bucket_start` for a DATETIME column
+    fn sql_bucket_start(&self, column: &str) -> String {
+        match self {
+            Granularity::Day => format!("DATE({})", column),
+            Granularity::Week => format!("DATE_SUB(DATE({0}), INTERVAL WEEKDAY({0}) DAY)", column),
+            Granularity::Month => format!("CAST(DATE_FORMAT({}, '%Y-%m-01') AS DATE)", column),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PlatformBucket {
+    pub start: NaiveDate,
+    /// Every platform is listed, with 0 if it had no activity
+    pub counts: BTreeMap<String, u64>,
+}
+
+/// Sums up `counts` into all buckets from the one containing `since` up to the one containing `until`,
+/// so every series has a value for every bucket
+pub fn fill_platform_buckets(
+    counts: &PlatformDayCounts,
+    platforms: &[&str],
+    granularity: Granularity,
+    since: NaiveDate,
+    until: NaiveDate,
+) -> Vec<PlatformBucket> {
+    let mut buckets: BTreeMap<NaiveDate, BTreeMap<String, u64>> = BTreeMap::new();
+    let mut start = granularity.bucket_start(since);
+    while start <= until {
+        buckets.insert(start, platforms.iter().map(|platform| (platform.to_string(), 0)).collect());
+        start = granularity.next_bucket(start);
+    }
+
+    for ((platform, day), count) in counts {
+        if let Some(bucket) = buckets.get_mut(&granularity.bucket_start(*day)) {
+            *bucket.entry(platform.clone()).or_default() += count;
+        }
+    }
+
+    buckets
+        .into_iter()
+        .map(|(start, counts)| PlatformBucket { start, counts })
+        .collect()
+}
+
+/// Event counts per platform and bucket, starting with the (complete) bucket containing `since`
+pub async fn get_platform_timeseries(
+    pool: &Pool<MySql>,
+    platforms: &[&str],
+    granularity: Granularity,
+    since: NaiveDate,
+    until: NaiveDate,
+    weight: Weight,
+    exclude_actions: &[String],
+) -> Vec<PlatformBucket> {
+    let sql = format!(
+        r#"
+            SELECT
+                gpro.platform AS platform,
+                {0} AS bucket,
+                {1} AS count
+            FROM
+                Events AS evt,
+                GitEvents AS gevt,
+                GitProjects AS gpro
+            WHERE evt.timestamp >= ?
+            AND   evt.id = gevt.id
+            AND   gevt.project_fk = gpro.id
+            {2}
+            GROUP BY gpro.platform, {0}
+            "#,
+        granularity.sql_bucket_start("evt.timestamp"),
+        weight.sql_count(),
+        sql_exclude_actions("gevt.action_fk", exclude_actions)
+    );
+    let mut query = sqlx::query_as(&sql).bind(granularity.bucket_start(since));
+    for action in exclude_actions {
+        query = query.bind(action);
+    }
+    let rows: Vec<(String, NaiveDate, i64)> = query.fetch_all(pool).await.unwrap();
+
+    // Bucketed by `fill_platform_buckets` along with the events
+    let mut counts = to_platform_day_counts(rows);
+    let aggregated = get_aggregated_counts_per_day(pool, granularity.bucket_start(since), weight, exclude_actions).await;
+    add_counts(&mut counts, aggregated);
+    fill_platform_buckets(&counts, platforms, granularity, since, until)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, day).unwrap()
+    }
+
+    fn counts(entries: &[(&str, u32, u64)]) -> PlatformDayCounts {
+        entries
+            .iter()
+            .map(|(platform, d, count)| ((platform.to_string(), day(*d)), *count))
+            .collect()
+    }
+
+    #[test]
+    fn excluded_actions_get_one_placeholder_each() {
+        assert_eq!(sql_exclude_actions("gevt.action_fk", &[]), "");
+        assert_eq!(
+            sql_exclude_actions("gevt.action_fk", &["starred".to_string(), "forked".to_string()]),
+            "AND gevt.action_fk NOT IN (SELECT id FROM GitActions WHERE name IN (?, ?))"
+        );
+    }
+
+    #[test]
+    fn merge_strategy_is_parsed() {
+        assert_eq!(MergeStrategy::parse("prefer_calendar"), Some(MergeStrategy::PreferCalendar));
+        assert_eq!(MergeStrategy::parse("PREFER_EVENTS"), Some(MergeStrategy::PreferEvents));
+        assert_eq!(MergeStrategy::parse("max"), Some(MergeStrategy::Max));
+        assert_eq!(MergeStrategy::parse("min"), None);
+    }
+
+    #[test]
+    fn conflicting_counts_are_merged_per_strategy() {
+        let calendar = counts(&[("Github", 1, 10)]);
+        let events = counts(&[("Github", 1, 4)]);
+
+        let expected = [
+            (MergeStrategy::PreferCalendar, 10, DaySource::Calendar),
+            (MergeStrategy::PreferEvents, 4, DaySource::Events),
+            (MergeStrategy::Max, 10, DaySource::MergedMax),
+        ];
+        for (strategy, count, source) in expected {
+            let result = merge_daily_counts(&calendar, &events, strategy, true);
+            assert_eq!(
+                result,
+                vec![DailyCount {
+                    date: day(1),
+                    count,
+                    sources: Some(BTreeMap::from([("Github".to_string(), source)])),
+                }],
+                "{:?}",
+                strategy
+            );
+        }
+    }
+
+    #[test]
+    fn max_strategy_takes_events_when_they_are_higher() {
+        let calendar = counts(&[("Github", 1, 2)]);
+        let events = counts(&[("Github", 1, 7)]);
+
+        let result = merge_daily_counts(&calendar, &events, MergeStrategy::Max, false);
+        assert_eq!(result[0].count, 7);
+    }
+
+    #[test]
+    fn platforms_are_merged_independently() {
+        let calendar = counts(&[("Github", 1, 10), ("Github", 2, 3)]);
+        let events = counts(&[("Github", 1, 4), ("Gitlab", 1, 5), ("Gitlab", 3, 1)]);
+
+        for strategy in [MergeStrategy::PreferCalendar, MergeStrategy::PreferEvents, MergeStrategy::Max] {
+            let result = merge_daily_counts(&calendar, &events, strategy, false);
+            let github_first_day = if strategy == MergeStrategy::PreferEvents { 4 } else { 10 };
+            assert_eq!(
+                result.iter().map(|day| (day.date, day.count)).collect::<Vec<_>>(),
+                vec![(day(1), github_first_day + 5), (day(2), 3), (day(3), 1)],
+                "{:?}",
+                strategy
+            );
+        }
+    }
+
+    #[test]
+    fn sources_are_only_serialized_when_requested() {
+        let calendar = counts(&[("Github", 1, 10)]);
+        let events = counts(&[("Github", 1, 4), ("Gitlab", 1, 5)]);
+
+        let without_sources = merge_daily_counts(&calendar, &events, MergeStrategy::Max, false);
+        assert_eq!(
+            serde_json::to_value(&without_sources).unwrap(),
+            serde_json::json!([{"date": "2024-05-01", "count": 15}])
+        );
+
+        let with_sources = merge_daily_counts(&calendar, &events, MergeStrategy::Max, true);
+        assert_eq!(
+            serde_json::to_value(&with_sources).unwrap(),
+            serde_json::json!([{
+                "date": "2024-05-01",
+                "count": 15,
+                "sources": {"Github": "merged-max", "Gitlab": "events"}
+            }])
+        );
+    }
+
+    fn utc(input: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(input).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn local_day_rolls_over_at_local_midnight() {
+        let tz: Tz = "Europe/Vienna".parse().unwrap();
+
+        // 23:59 and 00:00 local time (UTC+2 in summer)
+        let (date, start, end) = local_day_bounds(utc("2024-05-01T21:59:00Z"), tz);
+        assert_eq!(date, day(1));
+        assert_eq!(start, utc("2024-04-30T22:00:00Z"));
+        assert_eq!(end, utc("2024-05-01T22:00:00Z"));
+
+        let (date, start, _) = local_day_bounds(utc("2024-05-01T22:00:00Z"), tz);
+        assert_eq!(date, day(2));
+        assert_eq!(start, utc("2024-05-01T22:00:00Z"));
+    }
+
+    #[test]
+    fn dst_switch_shortens_the_local_day() {
+        let tz: Tz = "Europe/Vienna".parse().unwrap();
+
+        let (_, start, end) = local_day_bounds(utc("2024-03-31T12:00:00Z"), tz);
+        assert_eq!(end - start, Duration::hours(23));
+    }
+
+    #[test]
+    fn empty_day_has_no_latest_event() {
+        let stats = to_today_stats(day(1), Tz::UTC, (0, 0, 0, None));
+        assert_eq!(
+            serde_json::to_value(stats).unwrap(),
+            serde_json::json!({
+                "date": "2024-05-01",
+                "timezone": "UTC",
+                "count": 0,
+                "commit_weighted_count": 0,
+                "distinct_projects": 0,
+                "latest_event_at": null
+            })
+        );
+    }
+
+    #[test]
+    fn today_cache_expires_and_is_invalidated() {
+        // A timezone no other test uses, so the shared cache doesn't interfere
+        let tz: Tz = "Pacific/Chatham".parse().unwrap();
+        let now = utc("2024-05-01T10:00:00Z");
+        let (date, _, _) = local_day_bounds(now, tz);
+        let stats = to_today_stats(date, tz, (3, 3, 1, None));
+
+        cache_today_stats(tz, now, &stats);
+        assert_eq!(get_cached_today_stats(tz, date, now + Duration::seconds(5)), Some(stats));
+        assert_eq!(
+            get_cached_today_stats(tz, date, now + Duration::seconds(TODAY_CACHE_TTL_SECONDS)),
+            None
+        );
+
+        invalidate_today_cache();
+        assert_eq!(get_cached_today_stats(tz, date, now), None);
+    }
+
+    #[test]
+    fn buckets_follow_the_granularity() {
+        // 2024-05-08 is a Wednesday
+        assert_eq!(Granularity::Day.bucket_start(day(8)), day(8));
+        assert_eq!(Granularity::Week.bucket_start(day(8)), day(6));
+        assert_eq!(Granularity::Week.bucket_start(day(6)), day(6));
+        assert_eq!(Granularity::Month.bucket_start(day(8)), day(1));
+        assert_eq!(Granularity::Month.next_bucket(day(1)), NaiveDate::from_ymd_opt(2024, 6, 1).unwrap());
+        assert_eq!(Granularity::parse("Week"), Some(Granularity::Week));
+        assert_eq!(Granularity::parse("year"), None);
+    }
+
+    #[test]
+    fn idle_platforms_are_listed_with_zero() {
+        let counts = counts(&[("Github", 6, 2), ("Github", 8, 3), ("Gitlab", 14, 1)]);
+
+        let buckets = fill_platform_buckets(&counts, &["Github", "Gitlab"], Granularity::Week, day(8), day(21));
+        assert_eq!(
+            buckets,
+            vec![
+                PlatformBucket {
+                    start: day(6),
+                    counts: BTreeMap::from([("Github".to_string(), 5), ("Gitlab".to_string(), 0)]),
+                },
+                PlatformBucket {
+                    start: day(13),
+                    counts: BTreeMap::from([("Github".to_string(), 0), ("Gitlab".to_string(), 1)]),
+                },
+                PlatformBucket {
+                    start: day(20),
+                    counts: BTreeMap::from([("Github".to_string(), 0), ("Gitlab".to_string(), 0)]),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::sync_runs::SyncReport;
+
+/// Finished jobs are forgotten after this long
+static JOB_EXPIRY_MINUTES: i64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum SyncJobState {
+    Queued,
+    Running,
+    Done { reports: Vec<SyncReport> },
+    Failed { error: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct SyncJob {
+    pub id: String,
+    pub platforms: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub state: SyncJobState,
+}
+
+impl SyncJob {
+    fn is_active(&self) -> bool {
+        matches!(self.state, SyncJobState::Queued | SyncJobState::Running)
+    }
+}
+
+/// In-memory registry of force-sync jobs started with `?async=true`, managed as Rocket state
+#[derive(Debug, Default)]
+pub struct SyncJobs {
+    jobs: Mutex<HashMap<String, SyncJob>>,
+}
+
+impl SyncJobs {
+    /// Queues a new job, unless there is already one queued or running.
+    /// Returns the job and whether it was newly created.
+    pub fn submit(&self, platforms: &[&str]) -> (SyncJob, bool) {
+        let mut jobs = self.jobs.lock().unwrap();
+        Self::expire(&mut jobs, Utc::now());
+
+        if let Some(active) = jobs.values().find(|job| job.is_active()) {
+            debug!("Sync job {} is still active, not starting another one", active.id);
+            return (active.clone(), false);
+        }
+
+        let job = SyncJob {
+            id: Uuid::new_v4().to_string(),
+            platforms: platforms.iter().map(|platform| platform.to_string()).collect(),
+            created_at: Utc::now(),
+            finished_at: None,
+            state: SyncJobState::Queued,
+        };
+        jobs.insert(job.id.clone(), job.clone());
+        (job, true)
+    }
+
+    pub fn update(&self, id: &str, state: SyncJobState) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.get_mut(id) {
+            if !matches!(state, SyncJobState::Queued | SyncJobState::Running) {
+                job.finished_at = Some(Utc::now());
+            }
+            job.state = state;
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<SyncJob> {
+        let mut jobs = self.jobs.lock().unwrap();
+        Self::expire(&mut jobs, Utc::now());
+        jobs.get(id).cloned()
+    }
+
+    fn expire(jobs: &mut HashMap<String, SyncJob>, now: DateTime<Utc>) {
+        jobs.retain(|_, job| match job.finished_at {
+            Some(finished_at) => now - finished_at < Duration::minutes(JOB_EXPIRY_MINUTES),
+            None => true,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active_job_is_reused() {
+        let jobs = SyncJobs::default();
+
+        let (first, created) = jobs.submit(&["Github", "Gitlab"]);
+        assert!(created);
+        assert_eq!(first.state, SyncJobState::Queued);
+
+        jobs.update(&first.id, SyncJobState::Running);
+        let (second, created) = jobs.submit(&["Gitlab"]);
+        assert!(!created);
+        assert_eq!(second.id, first.id);
+        assert_eq!(second.state, SyncJobState::Running);
+    }
+
+    #[test]
+    fn new_job_is_started_after_the_last_one_finished() {
+        let jobs = SyncJobs::default();
+
+        let (first, _) = jobs.submit(&["Github"]);
+        jobs.update(&first.id, SyncJobState::Done { reports: Vec::new() });
+
+        let (second, created) = jobs.submit(&["Github"]);
+        assert!(created);
+        assert_ne!(second.id, first.id);
+        assert!(jobs.get(&first.id).unwrap().finished_at.is_some());
+    }
+
+    #[test]
+    fn finished_jobs_expire() {
+        let jobs = SyncJobs::default();
+
+        let (job, _) = jobs.submit(&["Github"]);
+        jobs.update(
+            &job.id,
+            SyncJobState::Failed {
+                error: "boom".to_string(),
+            },
+        );
+
+        let mut map = jobs.jobs.lock().unwrap();
+        SyncJobs::expire(&mut map, Utc::now() + Duration::minutes(JOB_EXPIRY_MINUTES - 1));
+        assert!(map.contains_key(&job.id));
+        SyncJobs::expire(&mut map, Utc::now() + Duration::minutes(JOB_EXPIRY_MINUTES + 1));
+        assert!(!map.contains_key(&job.id));
+    }
+
+    #[test]
+    fn job_state_is_serialized_flat() {
+        let job = SyncJob {
+            id: "42".to_string(),
+            platforms: vec!["Github".to_string()],
+            created_at: DateTime::from_timestamp(0, 0).unwrap(),
+            finished_at: None,
+            state: SyncJobState::Failed {
+                error: "boom".to_string(),
+            },
+        };
+
+        assert_eq!(
+            serde_json::to_value(job).unwrap(),
+            serde_json::json!({
+                "id": "42",
+                "platforms": ["Github"],
+                "created_at": "1970-01-01T00:00:00Z",
+                "finished_at": null,
+                "state": "failed",
+                "error": "boom"
+            })
+        );
+    }
+}
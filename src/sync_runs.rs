@@ -0,0 +1,388 @@
+use chrono::{DateTime, Utc};
+use log::{trace, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, MySql, Pool};
+
+use crate::{config, database};
+
+static FALLBACK_SYNC_RUNS_RETENTION: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncRunStatus {
+    Success,
+    /// Stopped fetching early, e.g. because of a pagination cycle
+    Truncated,
+    /// Gave up after the platform kept rejecting requests because of its rate limits
+    RateLimited,
+    Failed,
+}
+
+impl SyncRunStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncRunStatus::Success => "success",
+            SyncRunStatus::Truncated => "truncated",
+            SyncRunStatus::RateLimited => "rate_limited",
+            SyncRunStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Rate limit headroom of a platform's API, as reported by its last response
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitStatus {
+    pub remaining: u32,
+    pub reset_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewSyncRun {
+    pub platform: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub events_fetched: u32,
+    pub events_inserted: u32,
+    pub skipped_unknown_action: u32,
+    pub skipped_duplicates: u32,
+    pub skipped_project_errors: u32,
+    pub skipped_expired: u32,
+    pub skipped_other: u32,
+    pub status: SyncRunStatus,
+    pub error_message: Option<String>,
+    pub rate_limit: Option<RateLimitStatus>,
+}
+
+impl NewSyncRun {
+    pub fn duration_ms(&self) -> u32 {
+        (self.finished_at - self.started_at)
+            .num_milliseconds()
+            .clamp(0, u32::MAX.into()) as u32
+    }
+}
+
+/// Outcome of a single sync of one platform, as returned by `force-sync` (and `archive/reprocess`)
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct SyncReport {
+    pub platform: String,
+    pub events_fetched: u32,
+    pub events_inserted: u32,
+    /// Fetched, but skipped because their action is unknown
+    pub skipped_unknown_action: u32,
+    /// Fetched, but already stored
+    pub skipped_duplicates: u32,
+    /// Fetched, but their project couldn't be resolved
+    pub skipped_project_errors: u32,
+    /// Fetched, but older than the retention, see `retention`
+    pub skipped_expired: u32,
+    /// Fetched, but left out on purpose or unusable, e.g. filtered out or with an unparseable date
+    pub skipped_other: u32,
+    pub duration_ms: u32,
+    pub truncated: bool,
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub rate_limited: bool,
+    /// Requests left until `rate_limit_reset_at`, only for platforms reporting it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit_remaining: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit_reset_at: Option<DateTime<Utc>>,
+}
+
+impl From<&NewSyncRun> for SyncReport {
+    fn from(run: &NewSyncRun) -> Self {
+        SyncReport {
+            platform: run.platform.clone(),
+            events_fetched: run.events_fetched,
+            events_inserted: run.events_inserted,
+            skipped_unknown_action: run.skipped_unknown_action,
+            skipped_duplicates: run.skipped_duplicates,
+            skipped_project_errors: run.skipped_project_errors,
+            skipped_expired: run.skipped_expired,
+            skipped_other: run.skipped_other,
+            duration_ms: run.duration_ms(),
+            truncated: run.status == SyncRunStatus::Truncated,
+            error: run.error_message.clone(),
+            rate_limited: run.status == SyncRunStatus::RateLimited,
+            rate_limit_remaining: run.rate_limit.map(|rate_limit| rate_limit.remaining),
+            rate_limit_reset_at: run.rate_limit.map(|rate_limit| rate_limit.reset_at),
+        }
+    }
+}
+
+#[derive(Debug, FromRow, Serialize, Deserialize, JsonSchema)]
+pub struct SyncRun {
+    pub id: u64,
+    pub platform: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub duration_ms: u32,
+    pub events_fetched: u32,
+    pub events_inserted: u32,
+    pub skipped_unknown_action: u32,
+    pub skipped_duplicates: u32,
+    pub skipped_project_errors: u32,
+    pub skipped_expired: u32,
+    pub skipped_other: u32,
+    pub status: String,
+    pub error_message: Option<String>,
+    pub rate_limit_remaining: Option<u32>,
+    pub rate_limit_reset_at: Option<DateTime<Utc>>,
+}
+
+/// Number of rows kept in `SyncRuns`, older ones get pruned after every insert.
+/// `0` disables pruning.
+pub fn get_retention() -> u64 {
+    match config::var("POLLUX_SYNC_RUNS_RETENTION")
+        .unwrap_or(FALLBACK_SYNC_RUNS_RETENTION.to_string())
+        .parse::<u64>()
+    {
+        Ok(result) => result,
+        Err(err) => {
+            warn!(
+                "Unable to parse POLLUX_SYNC_RUNS_RETENTION, using »{}« as a fallback: {}",
+                FALLBACK_SYNC_RUNS_RETENTION, err
+            );
+            FALLBACK_SYNC_RUNS_RETENTION
+        }
+    }
+}
+
+pub async fn record_sync_run(run: &NewSyncRun) {
+    let db = database::Database::get_or_init().await;
+    let pool = db.get_pool().await;
+
+    let id = insert_sync_run(&pool, run).await;
+    trace!("Inserted SyncRun ({}) id: {}", run.platform, id);
+
+    let pruned = prune_sync_runs(&pool, get_retention()).await;
+    if pruned > 0 {
+        debug!("Pruned {} old sync runs", pruned);
+    }
+}
+
+pub async fn insert_sync_run(pool: &Pool<MySql>, run: &NewSyncRun) -> u64 {
+    sqlx::query(
+        "INSERT INTO SyncRuns \
+            (platform, started_at, finished_at, duration_ms, events_fetched, events_inserted, skipped_unknown_action, \
+            skipped_duplicates, skipped_project_errors, skipped_expired, skipped_other, status, error_message, \
+            rate_limit_remaining, rate_limit_reset_at) \
+            VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )",
+    )
+    .bind(&run.platform)
+    .bind(run.started_at.format("%Y-%m-%d %H:%M:%S").to_string())
+    .bind(run.finished_at.format("%Y-%m-%d %H:%M:%S").to_string())
+    .bind(run.duration_ms())
+    .bind(run.events_fetched)
+    .bind(run.events_inserted)
+    .bind(run.skipped_unknown_action)
+    .bind(run.skipped_duplicates)
+    .bind(run.skipped_project_errors)
+    .bind(run.skipped_expired)
+    .bind(run.skipped_other)
+    .bind(run.status.as_str())
+    .bind(&run.error_message)
+    .bind(run.rate_limit.map(|rate_limit| rate_limit.remaining))
+    .bind(run.rate_limit.map(|rate_limit| rate_limit.reset_at.format("%Y-%m-%d %H:%M:%S").to_string()))
+    .execute(pool)
+    .await
+    .unwrap()
+    .last_insert_id()
+}
+
+/// Deletes everything but the newest `keep` sync runs
+pub async fn prune_sync_runs(pool: &Pool<MySql>, keep: u64) -> u64 {
+    if keep == 0 {
+        return 0;
+    }
+
+    let oldest_kept: Option<u64> =
+        sqlx::query_scalar("SELECT id FROM SyncRuns ORDER BY id DESC LIMIT 1 OFFSET ?")
+            .bind(keep - 1)
+            .fetch_optional(pool)
+            .await
+            .unwrap();
+
+    let Some(oldest_kept) = oldest_kept else {
+        return 0;
+    };
+
+    sqlx::query("DELETE FROM SyncRuns WHERE id < ?")
+        .bind(oldest_kept)
+        .execute(pool)
+        .await
+        .unwrap()
+        .rows_affected()
+}
+
+pub async fn get_sync_runs(pool: &Pool<MySql>, limit: u32) -> Vec<SyncRun> {
+    sqlx::query_as::<_, SyncRun>(
+        r#"
+            SELECT
+                id,
+                platform,
+                started_at,
+                finished_at,
+                duration_ms,
+                events_fetched,
+                events_inserted,
+                skipped_unknown_action,
+                skipped_duplicates,
+                skipped_project_errors,
+                skipped_expired,
+                skipped_other,
+                status,
+                error_message,
+                rate_limit_remaining,
+                rate_limit_reset_at
+            FROM SyncRuns
+            ORDER BY started_at DESC, id DESC
+            LIMIT ?
+            "#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::initialize;
+    use chrono::TimeZone;
+
+    fn sync_run(platform: &str, started_at: DateTime<Utc>) -> NewSyncRun {
+        NewSyncRun {
+            platform: platform.to_string(),
+            started_at,
+            finished_at: started_at + chrono::Duration::milliseconds(1500),
+            events_fetched: 12,
+            events_inserted: 3,
+            skipped_unknown_action: 1,
+            skipped_duplicates: 8,
+            skipped_project_errors: 0,
+            skipped_expired: 0,
+            skipped_other: 0,
+            status: SyncRunStatus::Success,
+            error_message: None,
+            rate_limit: None,
+        }
+    }
+
+    #[test]
+    fn duration_is_calculated_in_milliseconds() {
+        let run = sync_run("Gitlab", Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap());
+        assert_eq!(run.duration_ms(), 1500);
+    }
+
+    #[test]
+    fn sync_report_is_serialized_as_json() {
+        let run = NewSyncRun {
+            status: SyncRunStatus::Failed,
+            error_message: Some("Couldn't fetch events".to_string()),
+            ..sync_run("Github", Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap())
+        };
+
+        assert_eq!(
+            serde_json::to_value(SyncReport::from(&run)).unwrap(),
+            serde_json::json!({
+                "platform": "Github",
+                "events_fetched": 12,
+                "events_inserted": 3,
+                "skipped_unknown_action": 1,
+                "skipped_duplicates": 8,
+                "skipped_project_errors": 0,
+                "skipped_expired": 0,
+                "skipped_other": 0,
+                "duration_ms": 1500,
+                "truncated": false,
+                "error": "Couldn't fetch events"
+            })
+        );
+
+        let run = NewSyncRun {
+            rate_limit: Some(RateLimitStatus {
+                remaining: 42,
+                reset_at: Utc.with_ymd_and_hms(2024, 5, 1, 1, 0, 0).unwrap(),
+            }),
+            ..run
+        };
+        let json = serde_json::to_value(SyncReport::from(&run)).unwrap();
+        assert_eq!(json["rate_limit_remaining"], 42);
+        assert_eq!(json["rate_limit_reset_at"], "2024-05-01T01:00:00Z");
+
+        let run = NewSyncRun {
+            status: SyncRunStatus::RateLimited,
+            ..run
+        };
+        assert_eq!(serde_json::to_value(SyncReport::from(&run)).unwrap()["rate_limited"], true);
+    }
+
+    #[tokio::test]
+    async fn sync_runs_are_listed_newest_first() {
+        let (_container, pool) = initialize().await;
+
+        let first = Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        insert_sync_run(&pool, &sync_run("Gitlab", first)).await;
+        insert_sync_run(&pool, &sync_run("Github", first + chrono::Duration::hours(1))).await;
+        insert_sync_run(
+            &pool,
+            &NewSyncRun {
+                status: SyncRunStatus::Failed,
+                error_message: Some("Couldn't fetch events".to_string()),
+                ..sync_run("Gitlab", first + chrono::Duration::hours(2))
+            },
+        )
+        .await;
+
+        let runs = get_sync_runs(&pool, 2).await;
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].platform, "Gitlab");
+        assert_eq!(runs[0].status, "failed");
+        assert_eq!(runs[0].error_message.as_deref(), Some("Couldn't fetch events"));
+        assert_eq!(runs[1].platform, "Github");
+        assert_eq!(runs[1].duration_ms, 1500);
+        assert_eq!(runs[1].events_fetched, 12);
+        assert_eq!(runs[1].events_inserted, 3);
+        assert_eq!(runs[1].skipped_duplicates, 8);
+        assert_eq!(runs[1].rate_limit_remaining, None);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_headroom_is_recorded() {
+        let (_container, pool) = initialize().await;
+
+        let reset_at = Utc.with_ymd_and_hms(2024, 5, 1, 1, 0, 0).unwrap();
+        insert_sync_run(
+            &pool,
+            &NewSyncRun {
+                rate_limit: Some(RateLimitStatus { remaining: 42, reset_at }),
+                ..sync_run("Github", Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap())
+            },
+        )
+        .await;
+
+        let runs = get_sync_runs(&pool, 1).await;
+        assert_eq!(runs[0].rate_limit_remaining, Some(42));
+        assert_eq!(runs[0].rate_limit_reset_at, Some(reset_at));
+    }
+
+    #[tokio::test]
+    async fn old_sync_runs_are_pruned() {
+        let (_container, pool) = initialize().await;
+
+        let first = Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        for hour in 0..5 {
+            insert_sync_run(&pool, &sync_run("Gitlab", first + chrono::Duration::hours(hour))).await;
+        }
+
+        assert_eq!(prune_sync_runs(&pool, 0).await, 0);
+        assert_eq!(prune_sync_runs(&pool, 3).await, 2);
+        assert_eq!(prune_sync_runs(&pool, 3).await, 0);
+
+        let runs = get_sync_runs(&pool, 50).await;
+        assert_eq!(runs.len(), 3);
+        assert_eq!(runs[2].started_at, first + chrono::Duration::hours(2));
+    }
+}
@@ -0,0 +1,131 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use tokio::{task::JoinHandle, time::sleep};
+
+use crate::{
+    config::{ConfigError, EnvConfig},
+    platform_registry::PlatformRegistry,
+    retention,
+};
+
+static RESYNC_TIMEOUT_HOURS: &str = "POLLUX_RESYNC_TIMEOUT_HOURS";
+
+/// Overrides `POLLUX_RESYNC_TIMEOUT_HOURS` for one platform, e.g. `POLLUX_RESYNC_TIMEOUT_HOURS_GITLAB`
+fn platform_setting(platform: &str) -> String {
+    format!("{}_{}", RESYNC_TIMEOUT_HOURS, platform.to_uppercase())
+}
+
+/// How long each of `platforms` waits between its syncs
+pub fn intervals_from_env(platforms: &[&'static str]) -> Result<Vec<(&'static str, Duration)>, ConfigError> {
+    intervals(EnvConfig::from_env(), platforms)
+}
+
+fn intervals(mut env: EnvConfig, platforms: &[&'static str]) -> Result<Vec<(&'static str, Duration)>, ConfigError> {
+    let fallback = hours(&mut env, RESYNC_TIMEOUT_HOURS);
+    let mut intervals = Vec::new();
+    for platform in platforms {
+        match hours(&mut env, &platform_setting(platform)).or(fallback) {
+            Some(hours) => intervals.push((*platform, Duration::from_secs(hours * 3600))),
+            // Only needed if a platform doesn't have its own
+            None => env.missing(RESYNC_TIMEOUT_HOURS),
+        }
+    }
+    env.build(intervals)
+}
+
+/// At least one, syncing all the time would only hit the rate limits
+fn hours(env: &mut EnvConfig, name: &str) -> Option<u64> {
+    env.optional(name)?;
+    match env.parsed(name, 0) {
+        0 => {
+            env.invalid(name, "has to be at least 1 hour");
+            None
+        }
+        hours => Some(hours),
+    }
+}
+
+/// Syncs every platform in its own task on its own schedule, the first time right away.
+/// A slow platform doesn't hold up the others this way.
+pub fn spawn(registry: Arc<PlatformRegistry>, intervals: Vec<(&'static str, Duration)>) -> Vec<JoinHandle<()>> {
+    intervals
+        .into_iter()
+        .map(|(platform, interval)| {
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                loop {
+                    info!("Crontime ✨ ({})", platform);
+                    registry.sync(&[platform]).await;
+                    registry.refresh_projects(platform).await;
+                    retention::prune_if_due(&registry).await;
+
+                    let next_run = chrono::Duration::from_std(interval).map(|interval| Utc::now() + interval);
+                    if let Ok(next_run) = next_run {
+                        registry.set_next_run(platform, next_run);
+                    }
+                    sleep(interval).await;
+                }
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform_registry::tests::fake;
+
+    fn env(vars: &'static [(&'static str, &'static str)]) -> EnvConfig {
+        EnvConfig::from(|name| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string()))
+    }
+
+    #[test]
+    fn platforms_fall_back_to_the_global_interval() {
+        let env = env(&[("POLLUX_RESYNC_TIMEOUT_HOURS", "1"), ("POLLUX_RESYNC_TIMEOUT_HOURS_GITLAB", "12")]);
+        assert_eq!(
+            intervals(env, &["Github", "Gitlab"]).unwrap(),
+            vec![("Github", Duration::from_secs(3600)), ("Gitlab", Duration::from_secs(12 * 3600))]
+        );
+    }
+
+    #[test]
+    fn the_global_interval_is_only_required_without_an_own_one() {
+        let own_ones = env(&[("POLLUX_RESYNC_TIMEOUT_HOURS_GITHUB", "2"), ("POLLUX_RESYNC_TIMEOUT_HOURS_GITLAB", "12")]);
+        assert!(intervals(own_ones, &["Github", "Gitlab"]).is_ok());
+
+        let message = intervals(env(&[("POLLUX_RESYNC_TIMEOUT_HOURS_GITLAB", "12")]), &["Github", "Gitlab", "Gitea"])
+            .unwrap_err()
+            .to_string();
+        assert_eq!(message.matches("POLLUX_RESYNC_TIMEOUT_HOURS is missing").count(), 1, "{}", message);
+    }
+
+    #[test]
+    fn intervals_have_to_be_whole_hours() {
+        let env = env(&[("POLLUX_RESYNC_TIMEOUT_HOURS", "0"), ("POLLUX_RESYNC_TIMEOUT_HOURS_GITLAB", "half")]);
+        let message = intervals(env, &["Gitlab"]).unwrap_err().to_string();
+        assert!(message.contains("POLLUX_RESYNC_TIMEOUT_HOURS is invalid: has to be at least 1 hour"), "{}", message);
+        assert!(message.contains("POLLUX_RESYNC_TIMEOUT_HOURS_GITLAB is invalid"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn platforms_are_synced_on_their_own_cadence() {
+        let (github, gitlab) = (fake("Github").unwrap(), fake("Gitlab").unwrap());
+        let mut registry = PlatformRegistry::default();
+        registry.register("Github", github.clone());
+        registry.register("Gitlab", gitlab.clone());
+        let registry = Arc::new(registry);
+
+        let tasks = spawn(
+            registry.clone(),
+            vec![("Github", Duration::from_millis(20)), ("Gitlab", Duration::from_millis(200))],
+        );
+        sleep(Duration::from_millis(500)).await;
+        tasks.iter().for_each(JoinHandle::abort);
+
+        let (github_syncs, gitlab_syncs) = (github.lock().await.syncs, gitlab.lock().await.syncs);
+        assert!((2..=3).contains(&gitlab_syncs), "{}", gitlab_syncs);
+        assert!(github_syncs >= 2 * gitlab_syncs, "{} vs. {}", github_syncs, gitlab_syncs);
+        assert!(registry.statuses().iter().all(|status| status.next_run.is_some()));
+    }
+}
@@ -0,0 +1,224 @@
+use hmac::{Hmac, Mac};
+#[cfg(feature = "github")]
+use log::warn;
+use sha2::Sha256;
+
+#[cfg(feature = "github")]
+use crate::config::{self, EnvConfig};
+
+#[cfg(feature = "github")]
+static FALLBACK_VISIBILITY_POLICY: VisibilityPolicy = VisibilityPolicy::Include;
+
+/// What happens with events of private projects
+#[cfg(feature = "github")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VisibilityPolicy {
+    /// Stored like public events, including project name and url
+    Include,
+    /// Not stored at all
+    Exclude,
+    /// Stored under a pseudonymous project, see `Pseudonymizer`
+    Anonymized,
+}
+
+#[cfg(feature = "github")]
+impl VisibilityPolicy {
+    pub fn parse(input: &str) -> Option<VisibilityPolicy> {
+        match input.to_ascii_lowercase().as_str() {
+            "include" => Some(VisibilityPolicy::Include),
+            "exclude" => Some(VisibilityPolicy::Exclude),
+            "anonymized" => Some(VisibilityPolicy::Anonymized),
+            _ => None,
+        }
+    }
+
+    pub fn from_env() -> VisibilityPolicy {
+        let Some(input) = config::var("POLLUX_PRIVATE_EVENTS") else {
+            return FALLBACK_VISIBILITY_POLICY;
+        };
+
+        match VisibilityPolicy::parse(&input) {
+            Some(policy) => policy,
+            None => {
+                warn!(
+                    "Unable to parse POLLUX_PRIVATE_EVENTS »{}« (valid: include, exclude, anonymized), using {:?} as a fallback",
+                    input, FALLBACK_VISIBILITY_POLICY
+                );
+                FALLBACK_VISIBILITY_POLICY
+            }
+        }
+    }
+}
+
+/// Visibility of a project as reported by GitLab
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectVisibility {
+    Public,
+    /// Visible to every signed-in user of the instance
+    Internal,
+    Private,
+}
+
+impl ProjectVisibility {
+    pub fn parse(input: &str) -> Option<ProjectVisibility> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "public" => Some(ProjectVisibility::Public),
+            "internal" => Some(ProjectVisibility::Internal),
+            "private" => Some(ProjectVisibility::Private),
+            _ => None,
+        }
+    }
+
+    /// Comma-separated like `public,internal`, at least one is required
+    pub fn parse_set(input: &str) -> Result<Vec<ProjectVisibility>, String> {
+        let mut visibilities = Vec::new();
+        for part in input.split(',').filter(|part| !part.trim().is_empty()) {
+            let visibility = ProjectVisibility::parse(part)
+                .ok_or_else(|| format!("unknown visibility »{}« (valid: public, internal, private)", part.trim()))?;
+            if !visibilities.contains(&visibility) {
+                visibilities.push(visibility);
+            }
+        }
+
+        if visibilities.is_empty() {
+            return Err("no visibility given".to_string());
+        }
+        Ok(visibilities)
+    }
+
+    /// As stored in `GitProjects.visibility`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProjectVisibility::Public => "public",
+            ProjectVisibility::Internal => "internal",
+            ProjectVisibility::Private => "private",
+        }
+    }
+}
+
+#[cfg(feature = "github")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pseudonym {
+    pub id: u64,
+    pub name: String,
+}
+
+/// Derives stable pseudonyms for private projects from a keyed hash of their platform id,
+/// so counts per project stay meaningful without revealing any names.
+///
+/// Rotating `POLLUX_PSEUDONYM_SECRET` creates new pseudonyms: events synced afterwards end up
+/// in new pseudonymous projects, the old ones are kept but never extended again.
+#[derive(Clone)]
+pub struct Pseudonymizer {
+    secret: Vec<u8>,
+}
+
+impl std::fmt::Debug for Pseudonymizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pseudonymizer").finish_non_exhaustive()
+    }
+}
+
+impl Pseudonymizer {
+    pub fn new(secret: &str) -> Pseudonymizer {
+        Pseudonymizer {
+            secret: secret.as_bytes().to_vec(),
+        }
+    }
+
+    /// Only needed when `POLLUX_PRIVATE_EVENTS` is anonymized
+    #[cfg(feature = "github")]
+    pub fn from_env(env: &mut EnvConfig) -> Pseudonymizer {
+        Pseudonymizer::new(&env.secret("POLLUX_PSEUDONYM_SECRET"))
+    }
+
+    fn digest(&self, input: &str) -> [u8; 32] {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any size");
+        mac.update(input.as_bytes());
+        mac.finalize().into_bytes().into()
+    }
+
+    #[cfg(feature = "github")]
+    pub fn pseudonym(&self, platform: &str, platform_project_id: u64) -> Pseudonym {
+        let digest = self.digest(&format!("{}:{}", platform, platform_project_id));
+
+        // platform_project_id is an unsigned 32 bit column
+        let id = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) as u64;
+        let name = digest[..6].iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+
+        Pseudonym {
+            id,
+            name: format!("private-{}", name),
+        }
+    }
+
+    /// Name shown instead of a stored project in anonymized responses, e.g. `project-2873401`.
+    /// Keyed on the database id, so it can't collide with the platform pseudonyms above.
+    pub fn project_alias(&self, project_id: u64) -> String {
+        let digest = self.digest(&format!("project:{}", project_id));
+        format!("project-{}", u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "github")]
+    #[test]
+    fn visibility_policy_is_parsed() {
+        assert_eq!(VisibilityPolicy::parse("include"), Some(VisibilityPolicy::Include));
+        assert_eq!(VisibilityPolicy::parse("EXCLUDE"), Some(VisibilityPolicy::Exclude));
+        assert_eq!(VisibilityPolicy::parse("anonymized"), Some(VisibilityPolicy::Anonymized));
+        assert_eq!(VisibilityPolicy::parse("hidden"), None);
+    }
+
+    #[test]
+    fn visibility_sets_are_parsed() {
+        assert_eq!(ProjectVisibility::parse_set("public"), Ok(vec![ProjectVisibility::Public]));
+        assert_eq!(
+            ProjectVisibility::parse_set(" Public, private ,public,"),
+            Ok(vec![ProjectVisibility::Public, ProjectVisibility::Private])
+        );
+        for input in ["", " , ", "public,secret"] {
+            assert!(ProjectVisibility::parse_set(input).is_err(), "{}", input);
+        }
+    }
+
+    #[cfg(feature = "github")]
+    #[test]
+    fn pseudonyms_are_stable_within_a_secret() {
+        let pseudonymizer = Pseudonymizer::new("secret");
+
+        assert_eq!(
+            pseudonymizer.pseudonym("Github", 42),
+            Pseudonymizer::new("secret").pseudonym("Github", 42)
+        );
+        assert_ne!(pseudonymizer.pseudonym("Github", 42), pseudonymizer.pseudonym("Github", 43));
+        assert_ne!(pseudonymizer.pseudonym("Github", 42), pseudonymizer.pseudonym("Gitlab", 42));
+    }
+
+    #[cfg(feature = "github")]
+    #[test]
+    fn rotating_the_secret_creates_new_pseudonyms() {
+        assert_ne!(
+            Pseudonymizer::new("secret").pseudonym("Github", 42),
+            Pseudonymizer::new("rotated").pseudonym("Github", 42)
+        );
+    }
+
+    #[test]
+    fn project_aliases_are_deterministic() {
+        let pseudonymizer = Pseudonymizer::new("secret");
+
+        assert_eq!(pseudonymizer.project_alias(7), Pseudonymizer::new("secret").project_alias(7));
+        assert_ne!(pseudonymizer.project_alias(7), pseudonymizer.project_alias(8));
+        assert_ne!(pseudonymizer.project_alias(7), Pseudonymizer::new("rotated").project_alias(7));
+        assert!(pseudonymizer.project_alias(7).starts_with("project-"));
+    }
+
+    #[test]
+    fn secret_is_not_debug_printed() {
+        assert!(!format!("{:?}", Pseudonymizer::new("secret")).contains("secret"));
+    }
+}